@@ -0,0 +1,96 @@
+#![feature(test)]
+extern crate test;
+
+// Micro-benchmarks for the inner loops of the renderer. Run them with `cargo +nightly bench`, and
+// compare before and after any change to the math kernels.
+
+use test::{black_box, Bencher};
+use toytracer::ray::{Intersection, Ray};
+use toytracer::shapes::{Group, Plane, Shape, Sphere};
+use toytracer::transform::Tr;
+use toytracer::{p, v};
+
+/// A transformation like one a scene would actually use, so nothing is trivially zero.
+fn transform() -> Tr {
+    Tr::new()
+        .scale(1.5, 0.5, 2.0)
+        .rotate_y(0.7)
+        .rotate_x(-0.3)
+        .translate(1.0, 2.0, -3.0)
+}
+
+#[bench]
+fn matrix_inverse(b: &mut Bencher) {
+    let m = transform().matrix();
+    b.iter(|| black_box(m).inverse());
+}
+
+#[bench]
+fn matrix_mul(b: &mut Bencher) {
+    let (m, n) = (transform().matrix(), transform().inverse().matrix());
+    b.iter(|| black_box(m) * black_box(n));
+}
+
+#[bench]
+fn matrix_mul_tuple(b: &mut Bencher) {
+    let m = transform().matrix();
+    let t = p!(1, -2, 3);
+    b.iter(|| black_box(m) * black_box(t));
+}
+
+#[bench]
+fn sphere_intersect(b: &mut Bencher) {
+    let s = Sphere::default().with_transform(transform());
+    let r = Ray::new(p!(0, 2, -10), v!(0.1, 0, 1).normalize());
+    b.iter(|| s.intersect_with(black_box(r)));
+}
+
+#[bench]
+fn sphere_miss(b: &mut Bencher) {
+    let s = Sphere::default().with_transform(transform());
+    let r = Ray::new(p!(0, 20, -10), v!(0, 0, 1));
+    b.iter(|| s.intersect_with(black_box(r)));
+}
+
+#[bench]
+fn plane_intersect(b: &mut Bencher) {
+    let pl = Plane::default().with_transform(transform());
+    let r = Ray::new(p!(0, 10, -10), v!(0, -1, 1).normalize());
+    b.iter(|| pl.intersect_with(black_box(r)));
+}
+
+#[bench]
+fn prepare_computations(b: &mut Bencher) {
+    let s = Sphere::default().with_transform(transform()).as_object();
+    let r = Ray::new(p!(0, 2, -10), v!(0.1, 0, 1).normalize());
+    let xs: Vec<Intersection> = s.intersect_with(r);
+    let hit = xs[0].clone();
+    b.iter(|| hit.prepare_computations(black_box(r), Some(&xs)));
+}
+
+/// A grid of spheres, so that most rays only pass near a few of them.
+fn sphere_grid() -> Group {
+    let spheres = (0..400)
+        .map(|i| {
+            let (x, z) = ((i % 20) as f64 * 3.0, (i / 20) as f64 * 3.0);
+            Sphere::default()
+                .with_transform(Tr::new().translate(x, 0.0, z))
+                .as_object()
+        })
+        .collect();
+    Group::new(spheres)
+}
+
+#[bench]
+fn flat_group_intersect(b: &mut Bencher) {
+    let g = sphere_grid();
+    let r = Ray::new(p!(3, 10, 3.5), v!(0, -1, 0));
+    b.iter(|| g.intersect_with(black_box(r)));
+}
+
+#[bench]
+fn divided_group_intersect(b: &mut Bencher) {
+    let g = sphere_grid().divide(8);
+    let r = Ray::new(p!(3, 10, 3.5), v!(0, -1, 0));
+    b.iter(|| g.intersect_with(black_box(r)));
+}
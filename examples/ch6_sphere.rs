@@ -4,7 +4,7 @@ use std::env;
 use std::fs::write;
 use toytracer::canvas::Canvas;
 use toytracer::color::Color;
-use toytracer::light::{lighting, Material, PointLight};
+use toytracer::light::{lighting, Material, PointLight, ShadingInput};
 use toytracer::ray::{hit, Ray};
 use toytracer::shapes::{Shape, Sphere};
 use toytracer::transform::Tr;
@@ -34,7 +34,7 @@ fn main() {
 
     // Create a sphere at the origin with a radius of 1.0. Also give it some material.
     let s = Sphere::default()
-        .with_transform(Tr::default().scale(1.0, 1.0, 1.0).into())
+        .with_transform(Tr::default().scale(1.0, 1.0, 1.0))
         .with_material(
             Material::default()
                 .with_color(Color::new(1.0, 0.2, 1.0))
@@ -58,23 +58,19 @@ fn main() {
             let ray = Ray::new(ray_origin, (ray_end - ray_origin).normalize());
             let eyev = -ray.direction();
 
-            match hit(&s.intersect_with(ray)) {
-                Some(h) => {
-                    let p = ray.position_at(h.t());
-                    let normalv = h.object().normal_at(p);
+            if let Some(h) = hit(&s.intersect_with(ray)) {
+                let p = ray.position_at(h.t());
+                let normalv = h.object().normal_at(p);
 
-                    let color = lighting(
-                        h.material(),
-                        &Sphere::default(),
-                        light,
-                        p,
-                        eyev,
-                        normalv,
-                        false,
-                    );
-                    canvas.write_to(j, i, color);
-                }
-                _ => (),
+                let color = lighting(ShadingInput::new(
+                    &h.material(),
+                    &Sphere::default(),
+                    &light,
+                    p,
+                    eyev,
+                    normalv,
+                ));
+                canvas.write_to(j, i, color);
             }
         }
     }
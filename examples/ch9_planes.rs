@@ -8,7 +8,6 @@ use toytracer::light::{Material, PointLight};
 use toytracer::patterns::Checkers;
 use toytracer::shapes::{Plane, Sphere};
 use toytracer::transform::{view_transform, Tr};
-use toytracer::tuple::{Point, Vector};
 use toytracer::world::World;
 use toytracer::{file_exists, p, pad_filepath, v};
 
@@ -0,0 +1,192 @@
+use crate::canvas::Canvas;
+use crate::color::Color;
+use rayon::prelude::*;
+
+/// A running sum of the samples taken at each pixel, along with how many there were. Samples are
+/// accumulated here, and only averaged into a [`Canvas`] for display or export, so a pixel can
+/// take any number of samples without the average drifting from repeated rounding. The sum of the
+/// squares of the samples' luminance is kept too, to tell how noisy each pixel still is.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Accumulator {
+    width: usize,
+    height: usize,
+    sums: Vec<Color>,
+    square_sums: Vec<f64>,
+    counts: Vec<u32>,
+}
+
+impl Accumulator {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            sums: vec![Color::black(); width * height],
+            square_sums: vec![0.0; width * height],
+            counts: vec![0; width * height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Adds a sample to some pixel. Like [`Canvas::write_to`], samples beyond the buffer are
+    /// ignored.
+    pub fn add(&mut self, x: usize, y: usize, c: Color) {
+        if x >= self.width || y >= self.height {
+            return;
+        }
+        let idx = y * self.width + x;
+        self.sums[idx] = self.sums[idx] + c;
+        self.square_sums[idx] += c.luminance().powi(2);
+        self.counts[idx] += 1;
+    }
+
+    /// Adds one more sample to every pixel, in parallel. The function is given the pixel's
+    /// coordinates and how many samples it already has, and gives the new sample.
+    pub fn add_pass(&mut self, f: impl Fn(usize, usize, u32) -> Color + Sync) {
+        let width = self.width;
+        self.sums
+            .par_iter_mut()
+            .zip(self.square_sums.par_iter_mut())
+            .zip(self.counts.par_iter_mut())
+            .enumerate()
+            .for_each(|(idx, ((sum, square_sum), count))| {
+                let c = f(idx % width, idx / width, *count);
+                *sum = *sum + c;
+                *square_sum += c.luminance().powi(2);
+                *count += 1;
+            });
+    }
+
+    /// The number of samples taken at some pixel.
+    pub fn count(&self, x: usize, y: usize) -> u32 {
+        self.counts[y * self.width + x]
+    }
+
+    /// The average of the samples at some pixel, or black if there are none.
+    pub fn mean(&self, x: usize, y: usize) -> Color {
+        let idx = y * self.width + x;
+        mean(self.sums[idx], self.counts[idx])
+    }
+
+    /// Estimates how far the average luminance at some pixel might still be from the true value,
+    /// as the variance of the mean of its samples. This shrinks as more samples are taken, so it
+    /// shows where more samples would help the most. Pixels with fewer than two samples have no
+    /// estimate, and give zero.
+    pub fn variance(&self, x: usize, y: usize) -> f64 {
+        let idx = y * self.width + x;
+        variance(self.sums[idx], self.square_sums[idx], self.counts[idx])
+    }
+
+    /// The variance of every pixel as a grayscale image. See [`Accumulator::variance`].
+    pub fn resolve_variance(&self) -> Canvas {
+        let mut image = Canvas::new(self.width, self.height);
+        for (idx, px) in image.pixels_mut().iter_mut().enumerate() {
+            let v = variance(self.sums[idx], self.square_sums[idx], self.counts[idx]);
+            *px = Color::new(v, v, v);
+        }
+        image
+    }
+
+    /// Averages every pixel's samples into an image.
+    pub fn resolve(&self) -> Canvas {
+        let mut image = Canvas::new(self.width, self.height);
+        for (px, (sum, count)) in image
+            .pixels_mut()
+            .iter_mut()
+            .zip(self.sums.iter().zip(&self.counts))
+        {
+            *px = mean(*sum, *count);
+        }
+        image
+    }
+}
+
+fn mean(sum: Color, count: u32) -> Color {
+    if count == 0 {
+        return Color::black();
+    }
+    sum * (1.0 / count as f64)
+}
+
+fn variance(sum: Color, square_sum: f64, count: u32) -> f64 {
+    if count < 2 {
+        return 0.0;
+    }
+    let n = count as f64;
+    let mean = sum.luminance() / n;
+    // The sample variance, then divided by n again for the variance of the mean. Rounding can
+    // leave a tiny negative for pixels with no noise at all.
+    ((square_sum - n * mean * mean) / (n - 1.0) / n).max(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Accumulator;
+    use crate::color::Color;
+
+    #[test]
+    fn averages_samples_per_pixel() {
+        let mut acc = Accumulator::new(2, 1);
+        acc.add(0, 0, Color::new(1.0, 0.0, 0.0));
+        acc.add(0, 0, Color::new(0.0, 0.5, 0.0));
+        acc.add(5, 5, Color::white());
+        assert_eq!(acc.count(0, 0), 2);
+        assert_eq!(acc.count(1, 0), 0);
+        assert_eq!(acc.mean(0, 0), Color::new(0.5, 0.25, 0.0));
+
+        let image = acc.resolve();
+        assert_eq!(image.pixel_at(0, 0), Color::new(0.5, 0.25, 0.0));
+        assert_eq!(image.pixel_at(1, 0), Color::black());
+    }
+
+    #[test]
+    fn passes_sample_every_pixel() {
+        let mut acc = Accumulator::new(3, 2);
+        for _ in 0..4 {
+            acc.add_pass(|x, y, n| Color::new(x as f64, y as f64, n as f64));
+        }
+        assert_eq!(acc.count(2, 1), 4);
+        assert_eq!(acc.mean(2, 1), Color::new(2.0, 1.0, 1.5));
+    }
+
+    #[test]
+    fn many_samples_keep_their_precision() {
+        let mut acc = Accumulator::new(1, 1);
+        for _ in 0..100_000 {
+            acc.add(0, 0, Color::new(0.1, 0.2, 0.3));
+        }
+        let c = acc.mean(0, 0);
+        assert!((c.r() - 0.1).abs() < 1e-9);
+        assert!((c.b() - 0.3).abs() < 1e-9);
+        // Values too bright for a display survive until the very end.
+        acc.add_pass(|_, _, _| Color::new(1e6, 0.0, 0.0));
+        assert!(acc.resolve().pixel_at(0, 0).r() > 9.0);
+    }
+
+    #[test]
+    fn variance_of_noisy_pixels() {
+        let mut acc = Accumulator::new(2, 1);
+        for i in 0..4 {
+            acc.add_pass(|x, _, _| match x {
+                0 => Color::new(0.5, 0.5, 0.5),
+                _ => [Color::black(), Color::white()][i % 2],
+            });
+        }
+        assert_eq!(acc.variance(0, 0), 0.0);
+        // Samples of 0, 1, 0, 1 have a variance of 1/3, so their mean has a variance of 1/12.
+        assert!((acc.variance(1, 0) - 1.0 / 12.0).abs() < 1e-9);
+        let image = acc.resolve_variance();
+        assert_eq!(image.pixel_at(0, 0), Color::black());
+        assert!((image.pixel_at(1, 0).g() - 1.0 / 12.0).abs() < 1e-9);
+
+        let mut acc = Accumulator::new(1, 1);
+        acc.add(0, 0, Color::white());
+        assert_eq!(acc.variance(0, 0), 0.0);
+    }
+}
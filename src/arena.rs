@@ -0,0 +1,191 @@
+use crate::ray::Intersection;
+use crate::shapes::{Group, Object};
+use crate::tuple::Vector;
+use crate::world::World;
+use std::cell::RefCell;
+
+/// The most buffers of one kind kept on a thread. Anything handed back beyond this is freed, so a
+/// one-off burst of deep recursion doesn't pin its memory forever.
+const MAX_POOLED: usize = 64;
+
+/// Spare buffers of one kind, kept on a single thread.
+#[derive(Debug)]
+pub(crate) struct Pool<T> {
+    free: Vec<Vec<T>>,
+}
+
+impl<T> Pool<T> {
+    const fn new() -> Self {
+        Self { free: Vec::new() }
+    }
+
+    fn take(&mut self) -> Vec<T> {
+        self.free.pop().unwrap_or_default()
+    }
+
+    fn give(&mut self, mut buf: Vec<T>) {
+        // Buffers which never grew are not worth keeping.
+        if buf.capacity() > 0 && self.free.len() < MAX_POOLED {
+            buf.clear();
+            self.free.push(buf);
+        }
+    }
+
+    /// Makes sure there are at least `count` spare buffers, each with room for `capacity` items.
+    fn warm(&mut self, count: usize, capacity: usize) {
+        for buf in &mut self.free {
+            buf.reserve(capacity);
+        }
+        while self.free.len() < count.min(MAX_POOLED) {
+            self.free.push(Vec::with_capacity(capacity));
+        }
+    }
+}
+
+/// Things which have a pool of buffers on every thread.
+pub(crate) trait Pooled: Sized + 'static {
+    fn with_pool<R>(f: impl FnOnce(&mut Pool<Self>) -> R) -> R;
+}
+
+macro_rules! pooled {
+    ($($name:ident: $t:ty,)*) => {
+        $(
+        thread_local! {
+            static $name: RefCell<Pool<$t>> = const { RefCell::new(Pool::new()) };
+        }
+
+        impl Pooled for $t {
+            fn with_pool<R>(f: impl FnOnce(&mut Pool<Self>) -> R) -> R {
+                $name.with(|p| f(&mut p.borrow_mut()))
+            }
+        }
+        )*
+    };
+}
+
+pooled! {
+    // The intersections found along a ray.
+    INTERSECTIONS: Intersection,
+    // The objects a ray is inside of, when working out refractive indices.
+    CONTAINERS: Object,
+    // The directions of the rays a rough surface spreads its reflection over.
+    SAMPLES: Vector,
+}
+
+/// Takes an empty buffer from this thread's pool, or makes a new one if the pool is empty.
+pub(crate) fn take<T: Pooled>() -> Vec<T> {
+    T::with_pool(|p| p.take())
+}
+
+/// Hands a buffer back to this thread's pool, to be reused by the next [`take`].
+pub(crate) fn give<T: Pooled>(buf: Vec<T>) {
+    T::with_pool(|p| p.give(buf))
+}
+
+/// The number of spare buffers of some kind on this thread.
+#[cfg(test)]
+fn spare<T: Pooled>() -> usize {
+    T::with_pool(|p| p.free.len())
+}
+
+/// How many groups deep the deepest object in a list is.
+fn depth(objects: &[Object]) -> usize {
+    objects
+        .iter()
+        .filter_map(|o| o.as_any().downcast_ref::<Group>())
+        .map(|g| 1 + depth(g.children()))
+        .max()
+        .unwrap_or(0)
+}
+
+/// Fills this thread's pools with buffers big enough for rays through some world, so that even
+/// the first rays traced don't have to allocate. Every ray that may be in flight at once, i.e.
+/// one per bounce plus a shadow ray, gets its own buffers, along with one for every level of
+/// groups it goes down through.
+pub fn prewarm_thread(world: &World) {
+    let count = world.settings.max_bounce() as usize + 2;
+    // Most shapes are entered and left once.
+    let capacity = 2 * world.objects.len();
+    Intersection::with_pool(|p| p.warm(count * (1 + depth(&world.objects)), capacity));
+    Object::with_pool(|p| p.warm(count, world.objects.len()));
+    let samples = world
+        .settings
+        .scale_samples(world.settings.glossy_samples());
+    Vector::with_pool(|p| p.warm(count, samples as usize));
+}
+
+/// Like [`prewarm_thread`], but for the current thread and every thread which renders.
+pub fn prewarm(world: &World) {
+    prewarm_thread(world);
+    rayon::broadcast(|_| prewarm_thread(world));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{give, prewarm_thread, spare, take, MAX_POOLED};
+    use crate::ray::{Intersection, Ray};
+    use crate::shapes::{Group, Object, Sphere};
+    use crate::transform::Tr;
+    use crate::tuple::Vector;
+    use crate::world::World;
+    use crate::{p, v};
+
+    #[test]
+    fn buffers_are_reused() {
+        let mut buf = take::<Object>();
+        buf.extend([Sphere::default().as_object(), Sphere::default().as_object()]);
+        let ptr = buf.as_ptr();
+        give(buf);
+        let buf = take::<Object>();
+        assert!(buf.is_empty());
+        assert_eq!(buf.as_ptr(), ptr);
+    }
+
+    #[test]
+    fn pool_is_bounded() {
+        for _ in 0..MAX_POOLED + 10 {
+            give(Vec::<Object>::with_capacity(4));
+        }
+        assert_eq!(spare::<Object>(), MAX_POOLED);
+    }
+
+    #[test]
+    fn prewarmed_rays_reuse_buffers() {
+        let w = World::default();
+        prewarm_thread(&w);
+        let before = spare::<Intersection>();
+        assert!(before >= w.settings.max_bounce() as usize + 2);
+        assert!(spare::<Vector>() >= w.settings.max_bounce() as usize + 2);
+
+        let next = take::<Intersection>();
+        let ptr = next.as_ptr();
+        give(next);
+
+        let r = Ray::new(p!(0, 0, -5), v!(0, 0, 1));
+        let xs = r.when_intersect_world(&w);
+        assert_eq!(xs.len(), 4);
+        assert_eq!(xs.as_ptr(), ptr);
+        drop(xs);
+        // The lists of the shapes which were hit are kept as well.
+        assert!(spare::<Intersection>() >= before);
+    }
+
+    #[test]
+    fn groups_hand_back_their_childrens_lists() {
+        let balls = (0..8)
+            .map(|i| {
+                Sphere::default()
+                    .with_transform(Tr::new().translate(0.0, 0.0, 3.0 * i as f64))
+                    .as_object()
+            })
+            .collect();
+        let w = World::new().with_objects(vec![Group::new(balls).divide(2).as_object()]);
+        let r = Ray::new(p!(0, 0, -5), v!(0, 0, 1));
+        assert_eq!(spare::<Intersection>(), 0);
+        let xs = r.when_intersect_world(&w);
+        assert_eq!(xs.len(), 16);
+        // The lists made going down through the groups came back, not just the world's own.
+        drop(xs);
+        assert!(spare::<Intersection>() > 1);
+    }
+}
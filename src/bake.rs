@@ -0,0 +1,140 @@
+use crate::canvas::Canvas;
+use crate::color::Color;
+use crate::mesh::Mesh;
+use crate::transform::Tr;
+use crate::tuple::{Point, Vector};
+use crate::world::World;
+use rayon::prelude::*;
+
+/// Renders how much light falls on a mesh into a lightmap, an image laid out by the mesh's UVs,
+/// so that the lighting can be painted back onto the model elsewhere, e.g. in a game engine. The
+/// light at each texel is measured as in [`World::incident_light`], in shades of grey.
+///
+/// The mesh is placed by `transform`. It does not have to be in the world, but if it isn't, it
+/// can't cast shadows on itself. U runs left to right across the image and v from bottom to top;
+/// texels which no face covers stay black. Gives None if the mesh has no UVs.
+pub fn bake_lightmap(
+    world: &World,
+    mesh: &Mesh,
+    transform: Tr,
+    width: usize,
+    height: usize,
+) -> Option<Canvas> {
+    let uvs = mesh.uvs()?;
+    let m = transform.matrix();
+    let norm = transform.inverse().matrix().transpose();
+    let bias = world.settings.bias_for(transform);
+
+    // First find the point on the surface under every covered texel, then light them all in
+    // parallel.
+    let mut samples: Vec<(usize, Point, Vector)> = vec![];
+    for (f, face) in mesh.faces().iter().enumerate() {
+        let [a, b, c] = face.map(|i| m * mesh.vertices()[i]);
+        let normals = match mesh.normals() {
+            Some(ns) => ns[f],
+            None => [mesh.face_normal(f); 3],
+        };
+        let corners = uvs[f].map(|(u, v)| (u * width as f64, (1.0 - v) * height as f64));
+        let (x0, x1) = span(corners.map(|c| c.0), width);
+        let (y0, y1) = span(corners.map(|c| c.1), height);
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let Some([wa, wb, wc]) = barycentric(corners, x as f64 + 0.5, y as f64 + 0.5)
+                else {
+                    continue;
+                };
+                let p = a + (b - a) * wb + (c - a) * wc;
+                let n = (norm * (normals[0] * wa + normals[1] * wb + normals[2] * wc)).normalize();
+                samples.push((y * width + x, p + n * bias, n));
+            }
+        }
+    }
+
+    let light: Vec<(usize, f64)> = samples
+        .into_par_iter()
+        .map(|(i, p, n)| (i, world.incident_light_at(p, n)))
+        .collect();
+    let mut image = Canvas::new(width, height);
+    for (i, l) in light {
+        image.pixels_mut()[i] = Color::new(l, l, l);
+    }
+    Some(image)
+}
+
+/// The range of texels which some coordinates could touch along one axis of the image.
+fn span(coords: [f64; 3], len: usize) -> (usize, usize) {
+    let lo = coords.iter().copied().fold(f64::INFINITY, f64::min);
+    let hi = coords.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let lo = lo.floor().clamp(0.0, len as f64) as usize;
+    let hi = hi.ceil().clamp(0.0, len as f64) as usize;
+    (lo, hi)
+}
+
+/// Finds how much each corner of a 2D triangle contributes to some point, if the point is inside
+/// it.
+fn barycentric([a, b, c]: [(f64, f64); 3], x: f64, y: f64) -> Option<[f64; 3]> {
+    let (v0, v1, v2) = (
+        (b.0 - a.0, b.1 - a.1),
+        (c.0 - a.0, c.1 - a.1),
+        (x - a.0, y - a.1),
+    );
+    let d = v0.0 * v1.1 - v1.0 * v0.1;
+    if d.abs() < f64::EPSILON {
+        return None;
+    }
+    let wb = (v2.0 * v1.1 - v1.0 * v2.1) / d;
+    let wc = (v0.0 * v2.1 - v2.0 * v0.1) / d;
+    let w = [1.0 - wb - wc, wb, wc];
+    w.iter().all(|&w| w >= -1e-9).then_some(w)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::bake_lightmap;
+    use crate::color::Color;
+    use crate::light::PointLight;
+    use crate::mesh::Mesh;
+    use crate::p;
+    use crate::shapes::Sphere;
+    use crate::transform::Tr;
+    use crate::world::World;
+    use std::f64::consts::PI;
+
+    /// A unit square on the floor, with its UVs following x and z.
+    fn floor() -> Mesh {
+        Mesh::new(
+            vec![p!(0, 0, 0), p!(1, 0, 0), p!(1, 0, 1), p!(0, 0, 1)],
+            vec![[0, 1, 2], [0, 2, 3]],
+        )
+        .with_uvs(vec![
+            [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0)],
+            [(0.0, 0.0), (1.0, 1.0), (0.0, 1.0)],
+        ])
+    }
+
+    #[test]
+    fn baking_light_and_shadow() {
+        // A small ball hangs over the corner near u = v = 0.2.
+        let ball = Sphere::default()
+            .with_transform(Tr::new().scale(0.1, 0.1, 0.1).translate(0.25, 1.0, 0.25))
+            .as_object();
+        let w = World::new()
+            .with_objects(vec![ball])
+            .with_light(PointLight::new(p!(0.5, 10, 0.5), Color::white()));
+        let map = bake_lightmap(&w, &floor(), Tr::new(), 8, 8).unwrap();
+        assert!(map.pixel_at(4, 4).luminance() > 0.99);
+        assert_eq!(map.pixel_at(1, 6), Color::black());
+        assert!(map.pixel_at(6, 1).luminance() > 0.99);
+
+        // Turned upside down, the floor faces away from the light.
+        let flipped = Tr::new().rotate_x(PI).translate(0.0, 0.5, 1.0);
+        let map = bake_lightmap(&w, &floor(), flipped, 8, 8).unwrap();
+        assert_eq!(map.pixel_at(4, 4), Color::black());
+    }
+
+    #[test]
+    fn meshes_need_uvs() {
+        let mesh = Mesh::new(vec![p!(0, 0, 0), p!(1, 0, 0), p!(1, 0, 1)], vec![[0, 1, 2]]);
+        assert!(bake_lightmap(&World::new(), &mesh, Tr::new(), 4, 4).is_none());
+    }
+}
@@ -0,0 +1,325 @@
+use crate::light::Material;
+use crate::mesh::Mesh;
+use crate::shapes::Group;
+use crate::tuple::{Point, Vector};
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// The most rows of triangles a patch is ever cut into along either direction.
+const MAX_DIVISIONS: usize = 64;
+
+/// A bicubic Bézier patch: a smooth curved surface pulled towards a 4 by 4 grid of control
+/// points, like the patches the Utah teapot is made of. The surface passes through the four
+/// corner points, and is parameterized by u along each row of the grid and v down the columns,
+/// both from 0 to 1.
+///
+/// Patches are rendered by cutting them into smooth triangles, with normals taken from the
+/// surface itself rather than the triangles.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BezierPatch {
+    /// Indexed by v, then u.
+    points: [[Point; 4]; 4],
+}
+
+/// The cubic Bernstein polynomials at some t.
+fn bernstein(t: f64) -> [f64; 4] {
+    let s = 1.0 - t;
+    [s * s * s, 3.0 * t * s * s, 3.0 * t * t * s, t * t * t]
+}
+
+/// The derivatives of the cubic Bernstein polynomials at some t.
+fn bernstein_slope(t: f64) -> [f64; 4] {
+    let s = 1.0 - t;
+    [
+        -3.0 * s * s,
+        3.0 * s * s - 6.0 * t * s,
+        6.0 * t * s - 3.0 * t * t,
+        3.0 * t * t,
+    ]
+}
+
+impl BezierPatch {
+    /// Creates a patch from its control points, given row by row.
+    pub fn new(points: [[Point; 4]; 4]) -> Self {
+        Self { points }
+    }
+
+    pub fn points(&self) -> &[[Point; 4]; 4] {
+        &self.points
+    }
+
+    /// Sums the control points weighted by `wu` along each row and `wv` down each column.
+    fn weigh(&self, wu: [f64; 4], wv: [f64; 4]) -> Vector {
+        let mut res = Vector::new(0.0, 0.0, 0.0);
+        for (row, a) in self.points.iter().zip(wv) {
+            for (p, b) in row.iter().zip(wu) {
+                res = res + (*p - Point::origin()) * (a * b);
+            }
+        }
+        res
+    }
+
+    /// The point on the surface at some (u, v).
+    pub fn point_at(&self, u: f64, v: f64) -> Point {
+        Point::origin() + self.weigh(bernstein(u), bernstein(v))
+    }
+
+    /// The normal of the surface at some (u, v), pointing along the cross product of the
+    /// directions of increasing u and increasing v.
+    ///
+    /// Where an edge of the patch is squashed into a single point, as at the top of the teapot's
+    /// lid, the surface has no normal of its own, so the normal is taken from just inside the
+    /// patch instead.
+    pub fn normal_at(&self, u: f64, v: f64) -> Vector {
+        const NUDGE: f64 = 1e-4;
+        let n = self.raw_normal(u, v);
+        if n.magnitude() > 1e-12 {
+            return n.normalize();
+        }
+        let inward = |t: f64| if t < 0.5 { t + NUDGE } else { t - NUDGE };
+        self.raw_normal(inward(u), inward(v)).normalize()
+    }
+
+    fn raw_normal(&self, u: f64, v: f64) -> Vector {
+        let du = self.weigh(bernstein_slope(u), bernstein(v));
+        let dv = self.weigh(bernstein(u), bernstein_slope(v));
+        du.cross(dv)
+    }
+
+    /// How many rows of triangles to cut this patch into along each direction, so that the
+    /// triangles are never further than `tolerance` from the surface. Flat patches need only a
+    /// single row, while tightly curved ones get up to 64.
+    pub fn divisions(&self, tolerance: f64) -> usize {
+        // Cutting a cubic curve into n straight pieces strays from it by at most 3/4 of its
+        // largest second difference of control points, over n squared. A patch can stray that
+        // far along u and along v at once, so it gets half the tolerance for each.
+        let second = |a: Point, b: Point, c: Point| ((a - b) - (b - c)).magnitude();
+        let mut bend: f64 = 0.0;
+        for i in 0..4 {
+            for j in 0..2 {
+                let row = &self.points[i];
+                bend = bend.max(second(row[j], row[j + 1], row[j + 2]));
+                let col = |k: usize| self.points[k][i];
+                bend = bend.max(second(col(j), col(j + 1), col(j + 2)));
+            }
+        }
+        let n = (1.5 * bend / tolerance.max(f64::MIN_POSITIVE))
+            .sqrt()
+            .ceil();
+        (n as usize).clamp(1, MAX_DIVISIONS)
+    }
+
+    /// Cuts this patch into an n by n grid of quads, each split into two triangles, with the
+    /// surface's normals and UVs at every corner.
+    pub fn tessellate(&self, n: usize) -> Mesh {
+        tessellate_all(&[(*self, n.max(1))])
+    }
+
+    /// Like [`BezierPatch::tessellate`], but cut as finely as needed to stay within `tolerance`
+    /// of the surface. See [`BezierPatch::divisions`].
+    pub fn tessellate_adaptive(&self, tolerance: f64) -> Mesh {
+        self.tessellate(self.divisions(tolerance))
+    }
+}
+
+/// Cuts each patch into a grid of triangles, with its own number of divisions, and puts them all
+/// into one mesh.
+fn tessellate_all(patches: &[(BezierPatch, usize)]) -> Mesh {
+    let (mut vertices, mut faces, mut normals, mut uvs) = (vec![], vec![], vec![], vec![]);
+    for &(patch, n) in patches {
+        let base = vertices.len();
+        let mut corner_normals = vec![];
+        let mut corner_uvs = vec![];
+        for j in 0..=n {
+            for i in 0..=n {
+                let (u, v) = (i as f64 / n as f64, j as f64 / n as f64);
+                vertices.push(patch.point_at(u, v));
+                corner_normals.push(patch.normal_at(u, v));
+                corner_uvs.push((u, v));
+            }
+        }
+        let at = |i: usize, j: usize| j * (n + 1) + i;
+        for j in 0..n {
+            for i in 0..n {
+                // Wound so that the faces point the same way as the surface's normals.
+                for face in [
+                    [at(i, j), at(i, j + 1), at(i + 1, j)],
+                    [at(i + 1, j), at(i, j + 1), at(i + 1, j + 1)],
+                ] {
+                    faces.push(face.map(|k| base + k));
+                    normals.push(face.map(|k| corner_normals[k]));
+                    uvs.push(face.map(|k| corner_uvs[k]));
+                }
+            }
+        }
+    }
+    Mesh::new(vertices, faces)
+        .with_normals(normals)
+        .with_uvs(uvs)
+}
+
+/// Cuts every patch as finely as it needs to stay within `tolerance` of the surface, and gathers
+/// the triangles into a group, e.g. to render the whole teapot.
+pub fn patches_to_group(patches: &[BezierPatch], tolerance: f64, material: &Material) -> Group {
+    let cuts: Vec<(BezierPatch, usize)> = patches
+        .iter()
+        .map(|p| (*p, p.divisions(tolerance)))
+        .collect();
+    tessellate_all(&cuts).to_group(material)
+}
+
+/// Represents some problem reading patches from a BPT file.
+#[derive(Debug, PartialEq)]
+pub enum ErrParseBpt {
+    InvalidNumber(String),
+    /// When a patch is not bicubic. Holds its degrees along u and v.
+    UnsupportedDegree(usize, usize),
+    /// When the file ends before all the patches it promises.
+    Truncated,
+}
+
+impl Display for ErrParseBpt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use ErrParseBpt::*;
+        match self {
+            InvalidNumber(n) => write!(f, "Invalid number in BPT file; n={n}"),
+            UnsupportedDegree(u, v) => {
+                write!(f, "Only bicubic patches are supported; degree={u}x{v}")
+            }
+            Truncated => write!(f, "BPT file is truncated"),
+        }
+    }
+}
+
+/// The patches in a BPT file, the plain text format the Utah teapot is often passed around in.
+/// The file starts with the number of patches; each patch is then its degrees along u and v,
+/// which must both be 3, followed by its 16 control points as x, y and z, row by row.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BezierPatches(pub Vec<BezierPatch>);
+
+impl FromStr for BezierPatches {
+    type Err = ErrParseBpt;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tokens = s.split_whitespace();
+        let mut next =
+            || -> Result<&str, ErrParseBpt> { tokens.next().ok_or(ErrParseBpt::Truncated) };
+        let count = |t: &str| {
+            t.parse::<usize>()
+                .map_err(|_| ErrParseBpt::InvalidNumber(t.to_string()))
+        };
+        let coord = |t: &str| {
+            t.parse::<f64>()
+                .map_err(|_| ErrParseBpt::InvalidNumber(t.to_string()))
+        };
+
+        let n = count(next()?)?;
+        let mut patches = Vec::with_capacity(n.min(4096));
+        for _ in 0..n {
+            let (du, dv) = (count(next()?)?, count(next()?)?);
+            if (du, dv) != (3, 3) {
+                return Err(ErrParseBpt::UnsupportedDegree(du, dv));
+            }
+            let mut points = [[Point::origin(); 4]; 4];
+            for row in &mut points {
+                for p in row.iter_mut() {
+                    *p = Point::new(coord(next()?)?, coord(next()?)?, coord(next()?)?);
+                }
+            }
+            patches.push(BezierPatch::new(points));
+        }
+        Ok(Self(patches))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{patches_to_group, BezierPatch, BezierPatches, ErrParseBpt};
+    use crate::light::Material;
+    use crate::ray::Ray;
+    use crate::shapes::Shape;
+    use crate::tuple::Point;
+    use crate::{assert_f64_eq, p, v, EPSILON};
+
+    /// A patch over the square from -1.5 to 1.5 in the xz plane, with its middle control points
+    /// raised to `bump`.
+    fn hill(bump: f64) -> BezierPatch {
+        let mut points = [[Point::origin(); 4]; 4];
+        for (j, row) in points.iter_mut().enumerate() {
+            for (i, p) in row.iter_mut().enumerate() {
+                let middle = (1..=2).contains(&i) && (1..=2).contains(&j);
+                let y = if middle { bump } else { 0.0 };
+                *p = Point::new(i as f64 - 1.5, y, j as f64 - 1.5);
+            }
+        }
+        BezierPatch::new(points)
+    }
+
+    #[test]
+    fn points_and_normals_on_a_patch() {
+        let flat = hill(0.0);
+        assert_eq!(flat.point_at(0.0, 0.0), p!(-1.5, 0, -1.5));
+        assert_eq!(flat.point_at(0.5, 0.5), p!(0, 0, 0));
+        assert_eq!(flat.normal_at(0.3, 0.8), v!(0, -1, 0));
+
+        // The middle of a hill is raised by nine sixteenths of its middle control points.
+        let h = hill(4.0);
+        assert_f64_eq!(h.point_at(0.5, 0.5).y(), 2.25, EPSILON);
+        assert_eq!(h.normal_at(0.5, 0.5), v!(0, -1, 0));
+        assert!(h.normal_at(0.1, 0.5).x() > 0.0);
+
+        // A patch with a whole edge squashed to a point still has normals along that edge.
+        let mut points = *hill(1.0).points();
+        points[0] = [p!(0, 2, 0); 4];
+        let n = BezierPatch::new(points).normal_at(0.5, 0.0);
+        assert!(n.magnitude() > 0.5);
+    }
+
+    #[test]
+    fn tessellating_as_finely_as_needed() {
+        assert_eq!(hill(0.0).divisions(0.01), 1);
+        assert_eq!(hill(0.0).tessellate_adaptive(0.01).faces().len(), 2);
+        let (coarse, fine) = (hill(4.0).divisions(0.1), hill(4.0).divisions(0.001));
+        assert!(1 < coarse && coarse < fine, "coarse={coarse}, fine={fine}");
+
+        let mesh = hill(4.0).tessellate(4);
+        assert_eq!(mesh.vertices().len(), 25);
+        assert_eq!(mesh.faces().len(), 32);
+        assert_eq!(mesh.uvs().unwrap()[0][0], (0.0, 0.0));
+        // The triangles face the same way as the surface.
+        let normals = mesh.normals().unwrap();
+        for (f, n) in normals.iter().enumerate() {
+            assert!(mesh.face_normal(f).dot(n[0]) > 0.0);
+        }
+    }
+
+    #[test]
+    fn rendering_patches_as_triangles() {
+        let g = patches_to_group(&[hill(4.0)], 0.01, &Material::default());
+        let xs = g.intersect_with(Ray::new(p!(0, 10, 0), v!(0, -1, 0)));
+        assert_eq!(xs.len(), 1);
+        assert_f64_eq!(xs[0].t(), 7.75, 0.01);
+    }
+
+    #[test]
+    fn reading_bpt_files() {
+        let points: String = (0..16).map(|i| format!("{i} 0 {}\n", i * 2)).collect();
+        let src = format!("2\n3 3\n{points}3 3\n{points}");
+        let BezierPatches(patches) = src.parse().unwrap();
+        assert_eq!(patches.len(), 2);
+        assert_eq!(patches[1].points()[1][2], p!(6, 0, 12));
+
+        assert_eq!(
+            format!("1\n2 3\n{points}").parse::<BezierPatches>(),
+            Err(ErrParseBpt::UnsupportedDegree(2, 3))
+        );
+        assert_eq!(
+            "1\n3 3\n0 0 0".parse::<BezierPatches>(),
+            Err(ErrParseBpt::Truncated)
+        );
+        assert_eq!(
+            "x".parse::<BezierPatches>(),
+            Err(ErrParseBpt::InvalidNumber("x".to_string()))
+        );
+    }
+}
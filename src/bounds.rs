@@ -0,0 +1,281 @@
+use crate::ray::Ray;
+use crate::transform::Tr;
+use crate::tuple::{Point, Vector};
+use crate::EPSILON;
+
+/// An axis aligned box, given by its two opposite corners.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bounds {
+    min: Point,
+    max: Point,
+}
+
+impl Bounds {
+    /// Creates a box spanning two corners, which may be given in any order.
+    pub fn new(a: Point, b: Point) -> Self {
+        Self {
+            min: Point::new(a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z())),
+            max: Point::new(a.x().max(b.x()), a.y().max(b.y()), a.z().max(b.z())),
+        }
+    }
+
+    /// A box containing nothing at all. Adding anything to it gives that thing's box.
+    pub fn empty() -> Self {
+        Self {
+            min: Point::new(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+            max: Point::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+        }
+    }
+
+    /// A box containing all of space, for shapes which go on forever.
+    pub fn infinite() -> Self {
+        Self {
+            min: Point::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+            max: Point::new(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x() > self.max.x() || self.min.y() > self.max.y() || self.min.z() > self.max.z()
+    }
+
+    /// Whether the box goes on forever along any axis.
+    pub fn is_infinite(&self) -> bool {
+        !self.is_empty()
+            && [self.min, self.max]
+                .iter()
+                .any(|p| !(p.x().is_finite() && p.y().is_finite() && p.z().is_finite()))
+    }
+
+    pub fn min(&self) -> Point {
+        self.min
+    }
+
+    pub fn max(&self) -> Point {
+        self.max
+    }
+
+    /// The length of the box along each axis.
+    pub fn size(&self) -> Vector {
+        self.max - self.min
+    }
+
+    pub fn center(&self) -> Point {
+        self.min + self.size() * 0.5
+    }
+
+    /// Checks if a point is inside the box, or on its surface.
+    pub fn contains(&self, p: Point) -> bool {
+        (self.min.x()..=self.max.x()).contains(&p.x())
+            && (self.min.y()..=self.max.y()).contains(&p.y())
+            && (self.min.z()..=self.max.z()).contains(&p.z())
+    }
+
+    /// Checks if another box is entirely inside this one.
+    pub fn contains_box(&self, other: &Bounds) -> bool {
+        other.is_empty() || (self.contains(other.min) && self.contains(other.max))
+    }
+
+    /// The smallest box containing both this box and another.
+    pub fn union(&self, other: &Bounds) -> Bounds {
+        Self {
+            min: Point::new(
+                self.min.x().min(other.min.x()),
+                self.min.y().min(other.min.y()),
+                self.min.z().min(other.min.z()),
+            ),
+            max: Point::new(
+                self.max.x().max(other.max.x()),
+                self.max.y().max(other.max.y()),
+                self.max.z().max(other.max.z()),
+            ),
+        }
+    }
+
+    /// The smallest axis aligned box containing this box after some transformation. Boxes which
+    /// are infinite along any axis stay infinite along every axis, since a rotation could swing
+    /// the infinite part anywhere.
+    pub fn transform(&self, t: Tr) -> Bounds {
+        if self.is_empty() {
+            return *self;
+        }
+        if self.is_infinite() {
+            return Bounds::infinite();
+        }
+        let m = t.matrix();
+        let (a, b) = (self.min, self.max);
+        [
+            Point::new(a.x(), a.y(), a.z()),
+            Point::new(a.x(), a.y(), b.z()),
+            Point::new(a.x(), b.y(), a.z()),
+            Point::new(a.x(), b.y(), b.z()),
+            Point::new(b.x(), a.y(), a.z()),
+            Point::new(b.x(), a.y(), b.z()),
+            Point::new(b.x(), b.y(), a.z()),
+            Point::new(b.x(), b.y(), b.z()),
+        ]
+        .into_iter()
+        .fold(Bounds::empty(), |acc, p| {
+            let p = m * p;
+            acc.union(&Bounds { min: p, max: p })
+        })
+    }
+
+    /// Checks if a ray passes through this box at all, whether ahead of or behind its origin.
+    pub fn intersects(&self, r: Ray) -> bool {
+        self.clip(r).is_some()
+    }
+
+    /// Finds where a ray enters and leaves this box, as distances along the ray which may be
+    /// negative or infinite. Gives None if the ray misses the box.
+    pub fn clip(&self, r: Ray) -> Option<(f64, f64)> {
+        if self.is_empty() {
+            return None;
+        }
+        let (o, d) = (r.origin(), r.direction());
+        let mut tmin = f64::NEG_INFINITY;
+        let mut tmax = f64::INFINITY;
+        for (o, d, lo, hi) in [
+            (o.x(), d.x(), self.min.x(), self.max.x()),
+            (o.y(), d.y(), self.min.y(), self.max.y()),
+            (o.z(), d.z(), self.min.z(), self.max.z()),
+        ] {
+            if d.abs() < EPSILON {
+                // Parallel to this pair of planes, so the ray is either always between them or
+                // never.
+                if o < lo || o > hi {
+                    return None;
+                }
+                continue;
+            }
+            let (t0, t1) = ((lo - o) / d, (hi - o) / d);
+            tmin = tmin.max(t0.min(t1));
+            tmax = tmax.min(t0.max(t1));
+        }
+        (tmin <= tmax).then_some((tmin, tmax))
+    }
+
+    /// Cuts the box in half across its longest side.
+    pub fn split(&self) -> (Bounds, Bounds) {
+        let size = self.size();
+        let (mut left_max, mut right_min) = (self.max, self.min);
+        if size.x() >= size.y() && size.x() >= size.z() {
+            let mid = self.min.x() + size.x() / 2.0;
+            left_max = Point::new(mid, left_max.y(), left_max.z());
+            right_min = Point::new(mid, right_min.y(), right_min.z());
+        } else if size.y() >= size.z() {
+            let mid = self.min.y() + size.y() / 2.0;
+            left_max = Point::new(left_max.x(), mid, left_max.z());
+            right_min = Point::new(right_min.x(), mid, right_min.z());
+        } else {
+            let mid = self.min.z() + size.z() / 2.0;
+            left_max = Point::new(left_max.x(), left_max.y(), mid);
+            right_min = Point::new(right_min.x(), right_min.y(), mid);
+        }
+        (
+            Bounds::new(self.min, left_max),
+            Bounds::new(right_min, self.max),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Bounds;
+    use crate::ray::Ray;
+    use crate::transform::Tr;
+    use crate::tuple::Point;
+    use crate::{p, v};
+    use std::f64::consts::{FRAC_PI_2, FRAC_PI_4, SQRT_2};
+
+    #[test]
+    fn corners_in_any_order() {
+        let b = Bounds::new(p!(1, -2, 3), p!(-1, 2, -3));
+        assert_eq!(b.min(), p!(-1, -2, -3));
+        assert_eq!(b.max(), p!(1, 2, 3));
+        assert_eq!(b.size(), v!(2, 4, 6));
+        assert_eq!(b.center(), p!(0, 0, 0));
+        assert!(b.contains(p!(1, 0, -3)));
+        assert!(!b.contains(p!(1.5, 0, 0)));
+    }
+
+    #[test]
+    fn union_of_boxes() {
+        let a = Bounds::new(p!(-5, -2, 0), p!(7, 4, 4));
+        let b = Bounds::new(p!(8, -7, -2), p!(14, 2, 8));
+        let u = a.union(&b);
+        assert_eq!(u.min(), p!(-5, -7, -2));
+        assert_eq!(u.max(), p!(14, 4, 8));
+        assert_eq!(Bounds::empty().union(&a), a);
+        assert!(Bounds::empty().is_empty());
+        assert!(u.contains_box(&a) && u.contains_box(&b));
+        assert!(!a.contains_box(&b));
+    }
+
+    #[test]
+    fn transforming_a_box() {
+        let b = Bounds::new(p!(-1, -1, -1), p!(1, 1, 1));
+        let t = b.transform(Tr::new().rotate_y(FRAC_PI_4).rotate_x(FRAC_PI_4));
+        assert!((t.min().x() + SQRT_2).abs() < 1e-5);
+        assert!((t.min().y() + 1.70711).abs() < 1e-5);
+        assert!((t.max().z() - 1.70711).abs() < 1e-5);
+        assert!(Bounds::infinite()
+            .transform(Tr::new().translate(1.0, 0.0, 0.0))
+            .is_infinite());
+
+        // A floor stood up on its side becomes a wall, which a flat box can't hold.
+        let floor = Bounds::new(
+            Point::new(f64::NEG_INFINITY, 0.0, f64::NEG_INFINITY),
+            Point::new(f64::INFINITY, 0.0, f64::INFINITY),
+        );
+        let wall = floor.transform(Tr::new().rotate_z(FRAC_PI_2));
+        assert!(wall.contains(p!(0, 100, 0)));
+    }
+
+    #[test]
+    fn ray_against_box() {
+        let b = Bounds::new(p!(5, -2, 0), p!(11, 4, 7));
+        let tests = vec![
+            (p!(15, 1, 2), v!(-1, 0, 0), true),
+            (p!(-5, -1, 4), v!(1, 0, 0), true),
+            (p!(7, 6, 5), v!(0, -1, 0), true),
+            (p!(8, 2, 12), v!(0, 0, -1), true),
+            (p!(6, 0, 5), v!(0, 0, 1), true),
+            (p!(9, -1, -8), v!(2, 4, 6), false),
+            (p!(8, 3, -4), v!(6, 2, 4), false),
+            (p!(8, 7, -4), v!(4, 6, 2), false),
+            (p!(12, 5, 4), v!(0, 0, 1), false),
+        ];
+        for (origin, direction, want) in tests {
+            let r = Ray::new(origin, direction.normalize());
+            assert_eq!(b.intersects(r), want, "{origin:?} {direction:?}");
+        }
+        assert!(!Bounds::empty().intersects(Ray::new(p!(0, 0, 0), v!(0, 0, 1))));
+        assert!(Bounds::infinite().intersects(Ray::new(p!(0, 0, 0), v!(0, 0, 1))));
+    }
+
+    #[test]
+    fn clipping_a_ray() {
+        let b = Bounds::new(p!(-1, -1, -1), p!(1, 1, 1));
+        assert_eq!(
+            b.clip(Ray::new(p!(0, 0, -5), v!(0, 0, 1))),
+            Some((4.0, 6.0))
+        );
+        assert_eq!(
+            b.clip(Ray::new(p!(0, 0, 0), v!(0, 2, 0))),
+            Some((-0.5, 0.5))
+        );
+        assert_eq!(b.clip(Ray::new(p!(0, 2, -5), v!(0, 0, 1))), None);
+    }
+
+    #[test]
+    fn splitting_a_box() {
+        let b = Bounds::new(p!(-1, -2, -3), p!(9, 5.5, 3));
+        let (l, r) = b.split();
+        assert_eq!(l.max(), p!(4, 5.5, 3));
+        assert_eq!(r.min(), p!(4, -2, -3));
+        let (l, r) = Bounds::new(p!(-1, -2, -3), p!(5, 8, 3)).split();
+        assert_eq!(l.max(), p!(5, 3, 3));
+        assert_eq!(r.min(), p!(-1, 3, -3));
+    }
+}
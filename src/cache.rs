@@ -0,0 +1,202 @@
+use crate::yaml::{self, ErrParseYaml, ResolvedScene, Scene, SCHEMA_VERSION};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::fmt::Display;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+
+/// Bumped whenever the layout of the cache file changes.
+const CACHE_VERSION: u32 = 8;
+
+/// What actually gets written to a cache file. The versions, the hash of the YAML source and the
+/// hashes of the meshes in the scene are used to decide if a cache file is stale.
+#[derive(Serialize, Deserialize)]
+struct CacheFile<S> {
+    cache_version: u32,
+    schema_version: u32,
+    source_hash: u64,
+    scene: S,
+}
+
+/// Represents some problem with loading a scene through the cache.
+#[derive(Debug)]
+pub enum ErrSceneCache {
+    Io(std::io::Error),
+    Yaml(ErrParseYaml),
+    Encode(String),
+}
+
+impl Display for ErrSceneCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use ErrSceneCache::*;
+        match self {
+            Io(e) => write!(f, "Could not access scene files; {e}"),
+            Yaml(e) => write!(f, "{e}"),
+            Encode(e) => write!(f, "Could not encode scene cache; {e}"),
+        }
+    }
+}
+
+impl From<std::io::Error> for ErrSceneCache {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<ErrParseYaml> for ErrSceneCache {
+    fn from(e: ErrParseYaml) -> Self {
+        Self::Yaml(e)
+    }
+}
+
+/// Where the cache for some scene file lives.
+pub fn cache_path(scene_path: &str) -> String {
+    format!("{scene_path}.cache")
+}
+
+fn hash_source(src: &str) -> u64 {
+    let mut h = DefaultHasher::new();
+    src.hash(&mut h);
+    h.finish()
+}
+
+pub(crate) fn hash_bytes(bytes: &[u8]) -> u64 {
+    let mut h = DefaultHasher::new();
+    bytes.hash(&mut h);
+    h.finish()
+}
+
+/// Encodes a resolved scene, tagging it with the source it was parsed from.
+pub fn encode(src: &str, scene: &ResolvedScene) -> Result<Vec<u8>, ErrSceneCache> {
+    let file = CacheFile {
+        cache_version: CACHE_VERSION,
+        schema_version: SCHEMA_VERSION,
+        source_hash: hash_source(src),
+        scene,
+    };
+    // Field names have to be kept, otherwise the untagged enums in the scene can't be decoded.
+    rmp_serde::to_vec_named(&file).map_err(|e| ErrSceneCache::Encode(e.to_string()))
+}
+
+/// Decodes a cached scene. Returns None if the cache is corrupt, or if it was not produced from
+/// the given source by this version of the ray tracer. It is also stale if any of the meshes it
+/// holds, found relative to `dir`, have changed since.
+pub fn decode(src: &str, dir: &Path, bytes: &[u8]) -> Option<ResolvedScene> {
+    let file: CacheFile<ResolvedScene> = rmp_serde::from_slice(bytes).ok()?;
+    if file.cache_version != CACHE_VERSION
+        || file.schema_version != SCHEMA_VERSION
+        || file.source_hash != hash_source(src)
+    {
+        return None;
+    }
+    let fresh = file
+        .scene
+        .mesh_hashes()
+        .all(|(path, hash)| fs::read(dir.join(path)).is_ok_and(|bytes| hash_bytes(&bytes) == hash));
+    fresh.then_some(file.scene)
+}
+
+/// Loads the scene at some path. If there is an up-to-date cache next to the scene file it is used
+/// instead of parsing the YAML again; otherwise the scene is parsed and the cache is rewritten.
+/// Meshes are looked up relative to the scene file, and are kept in the cache already divided into
+/// bounding volume hierarchies. A cache which can't be written is left alone.
+pub fn load_scene(path: &str) -> Result<Scene, ErrSceneCache> {
+    let src = fs::read_to_string(path)?;
+    let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+    let cache = cache_path(path);
+    if let Some(scene) = fs::read(&cache).ok().and_then(|b| decode(&src, dir, &b)) {
+        return Ok(scene.build_in(dir)?);
+    }
+    let mut scene = yaml::resolve_scene(&src)?;
+    scene.load_meshes(dir)?;
+    // The cache only saves time, so failing to write it, e.g. beside a read-only scene, is fine.
+    if let Ok(bytes) = encode(&src, &scene) {
+        let _ = fs::write(&cache, bytes);
+    }
+    Ok(scene.build_in(dir)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cache_path, decode, encode, load_scene};
+    use crate::yaml::resolve_scene;
+    use std::path::Path;
+
+    const SPEC: &str = include_str!("spec.yml");
+
+    #[test]
+    fn round_trip_through_cache() {
+        let bytes = encode(SPEC, &resolve_scene(SPEC).unwrap()).unwrap();
+        let got = decode(SPEC, Path::new(""), &bytes).expect("decodes the cache");
+        assert_eq!(got, resolve_scene(SPEC).unwrap());
+
+        let scene = got.build().unwrap();
+        assert_eq!(scene.world.objects.len(), 2);
+    }
+
+    #[test]
+    fn stale_cache_is_ignored() {
+        let bytes = encode(SPEC, &resolve_scene(SPEC).unwrap()).unwrap();
+        let edited = SPEC.replace("width: 100", "width: 200");
+        assert!(decode(&edited, Path::new(""), &bytes).is_none());
+        assert!(decode(SPEC, Path::new(""), &bytes[..bytes.len() / 2]).is_none());
+    }
+
+    /// Writes a scene scattering a triangle from a mesh file beside it, into a fresh directory.
+    fn scatter_scene(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("toytracer-scene-{name}"));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("tri.obj"), "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n").unwrap();
+        let src = format!(
+            r#"{SPEC}
+scatter:
+  - source: tri.obj
+    count: 3
+    region: {{ min: [0, 0, 0], max: [1, 0, 1] }}
+"#
+        );
+        let path = dir.join("scene.yml");
+        std::fs::write(&path, src).unwrap();
+        path
+    }
+
+    #[test]
+    fn meshes_are_found_next_to_the_scene() {
+        let path = scatter_scene("dir");
+        let path = path.to_str().unwrap();
+
+        // Once parsing the YAML, and once from the cache it leaves behind.
+        for _ in 0..2 {
+            let scene = load_scene(path).expect("finds the mesh beside the scene");
+            assert_eq!(scene.world.objects.len(), 3);
+        }
+    }
+
+    #[test]
+    fn cache_holds_meshes_until_they_change() {
+        let path = scatter_scene("mesh-hash");
+        let dir = path.parent().unwrap();
+        let path = path.to_str().unwrap();
+        load_scene(path).unwrap();
+        let src = std::fs::read_to_string(path).unwrap();
+        let bytes = std::fs::read(cache_path(path)).unwrap();
+        let cached = decode(&src, dir, &bytes).expect("cache is fresh");
+        assert_eq!(cached.mesh_hashes().count(), 1);
+
+        std::fs::write(dir.join("tri.obj"), "v 0 0 0\nv 2 0 0\nv 0 2 0\nf 1 2 3\n").unwrap();
+        assert!(decode(&src, dir, &bytes).is_none());
+        let scene = load_scene(path).unwrap();
+        assert_eq!(scene.world.objects.len(), 3);
+    }
+
+    #[test]
+    fn unwritable_cache_is_skipped() {
+        let path = scatter_scene("unwritable");
+        let path = path.to_str().unwrap();
+        // A directory in the way makes writing the cache fail.
+        std::fs::create_dir_all(cache_path(path)).unwrap();
+        assert!(load_scene(path).is_ok());
+    }
+}
@@ -1,11 +1,22 @@
+use crate::accumulator::Accumulator;
+use crate::arena;
 use crate::canvas::Canvas;
+use crate::color::Color;
+use crate::deep::DeepImage;
+use crate::matte::ObjectMatte;
+use crate::motion::MotionVectors;
+use crate::post::{draw_ink, preview_expose};
 use crate::ray::Ray;
-use crate::transform::{view_transform, Tr};
-use crate::tuple::Point;
-use crate::world::World;
-use crate::{yaml, MAX_BOUNCE};
+use crate::rng::Rng;
+use crate::tiles::{tiles, Tile};
+use crate::transform::{self, Tr};
+use crate::tuple::{Point, Vector};
+use crate::world::{PassMode, ShadingPass, World};
+use crate::yaml;
 use rayon::prelude::*;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::time::{Duration, Instant};
 
 #[derive(Deserialize, Debug, PartialEq)]
 #[serde(from = "crate::yaml::CameraRepr")]
@@ -16,73 +27,448 @@ pub struct Camera {
     transform: Tr,
     /// Inverse of the transform.
     inv_transform: Tr,
+    /// Where every ray starts from, in world space.
+    origin: Point,
 
     half_width: f64,
     half_height: f64,
     /// The width of one square pixel.
     pixel_size: f64,
+    projection: Projection,
+}
+
+/// A one line summary, like `160x120 perspective camera at (0, 1.5, -5), 60° field of view`.
+impl std::fmt::Display for Camera {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // The origin comes out of inverting the view transformation, so tidy up its rounding.
+        let tidy = |x: f64| (x * 1e5).round() / 1e5 + 0.0;
+        let o = self.origin;
+        write!(
+            f,
+            "{}x{} {} camera at ({}, {}, {}), {}° field of view",
+            self.hsize,
+            self.vsize,
+            match self.projection {
+                Projection::Perspective => "perspective",
+                Projection::Stereographic => "stereographic",
+            },
+            tidy(o.x()),
+            tidy(o.y()),
+            tidy(o.z()),
+            self.field_of_view.to_degrees().round()
+        )
+    }
+}
+
+/// How the camera maps directions onto its image.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Projection {
+    /// The usual pinhole camera. Straight lines stay straight, but the field of view has to be
+    /// less than half a turn.
+    #[default]
+    Perspective,
+    /// Projects the whole sphere of directions onto the image from the point directly behind the
+    /// camera. The field of view may go up to (but not reach) a full turn; pointed at the ground
+    /// with a field of view of around 300 degrees, this gives the "little planet" look.
+    Stereographic,
+}
+
+impl Projection {
+    /// Half the extent of the image plane for some field of view.
+    fn half_view(&self, field_of_view: f64) -> f64 {
+        match self {
+            Projection::Perspective => (field_of_view / 2.0).tan(),
+            Projection::Stereographic => (field_of_view / 4.0).tan(),
+        }
+    }
+
+    /// The direction, in camera space, of a point on the image plane.
+    fn direction(&self, x: f64, y: f64) -> Vector {
+        match self {
+            Projection::Perspective => Vector::new(x, y, -1.0),
+            Projection::Stereographic => {
+                let r2 = x * x + y * y;
+                Vector::new(2.0 * x, 2.0 * y, r2 - 1.0) * (1.0 / (r2 + 1.0))
+            }
+        }
+    }
+
+    /// The point on the image plane which some direction in camera space goes through, undoing
+    /// [`Projection::direction`]. Gives None for directions the projection can't show.
+    fn image_point(&self, d: Vector) -> Option<(f64, f64)> {
+        match self {
+            Projection::Perspective if d.z() < 0.0 => Some((d.x() / -d.z(), d.y() / -d.z())),
+            Projection::Perspective => None,
+            Projection::Stereographic => {
+                let d = d.normalize();
+                let below = 1.0 - d.z();
+                (below > 1e-12).then(|| (d.x() / below, d.y() / below))
+            }
+        }
+    }
+}
+
+/// What a progressive render managed to do within its budget.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderStats {
+    pub samples_per_pixel: usize,
+    pub elapsed: Duration,
 }
 
 impl From<yaml::CameraRepr> for Camera {
     fn from(r: yaml::CameraRepr) -> Self {
-        Self::new(r.width, r.height, r.field_of_view).with_transform(view_transform(
-            r.from.into(),
-            r.to.into(),
-            r.up.into(),
-        ))
+        Self::new(r.width, r.height, r.field_of_view)
+            .with_projection(r.projection)
+            .with_transform(r.view_transform())
     }
 }
 
 impl Camera {
     pub fn new(hsize: usize, vsize: usize, field_of_view: f64) -> Self {
-        let half_view = (field_of_view / 2.0).tan();
-        let aspect = hsize as f64 / vsize as f64;
-        let (half_width, half_height);
-        if aspect >= 1.0 {
-            half_width = half_view;
-            half_height = half_view / aspect;
-        } else {
-            half_width = half_view * aspect;
-            half_height = half_view;
-        }
-        let pixel_size = (2.0 * half_width) / hsize as f64;
-        Self {
+        let mut res = Self {
             hsize,
             vsize,
             field_of_view,
             transform: Tr::default(),
             inv_transform: Tr::default(),
-            half_width,
-            half_height,
-            pixel_size,
+            origin: Point::origin(),
+            half_width: 0.0,
+            half_height: 0.0,
+            pixel_size: 0.0,
+            projection: Projection::default(),
+        };
+        res.compute_pixel_size();
+        res
+    }
+
+    pub fn with_projection(mut self, projection: Projection) -> Self {
+        self.projection = projection;
+        self.compute_pixel_size();
+        self
+    }
+
+    /// Works out the size of the image plane, and of each pixel on it.
+    fn compute_pixel_size(&mut self) {
+        let half_view = self.projection.half_view(self.field_of_view);
+        let aspect = self.hsize as f64 / self.vsize as f64;
+        if aspect >= 1.0 {
+            self.half_width = half_view;
+            self.half_height = half_view / aspect;
+        } else {
+            self.half_width = half_view * aspect;
+            self.half_height = half_view;
         }
+        self.pixel_size = (2.0 * self.half_width) / self.hsize as f64;
     }
 
     /// Finds the ray which will go through a pixel on the camera's screen.
     fn ray_for_pixel(&self, x: usize, y: usize) -> Ray {
-        let xoffset = (x as f64 + 0.5) * self.pixel_size;
-        let yoffset = (y as f64 + 0.5) * self.pixel_size;
+        self.ray_for_subpixel(x, y, 0.5, 0.5)
+    }
+
+    /// Finds the ray which will go through some point within a pixel, where (0, 0) is the pixel's
+    /// top left corner and (1, 1) is its bottom right.
+    fn ray_for_subpixel(&self, x: usize, y: usize, dx: f64, dy: f64) -> Ray {
+        let xoffset = (x as f64 + dx) * self.pixel_size;
+        let yoffset = (y as f64 + dy) * self.pixel_size;
 
         let world_x = self.half_width - xoffset;
         let world_y = self.half_height - yoffset;
 
-        let transform = self.inv_transform.matrix();
-        let pixel = transform * Point::new(world_x, world_y, -1.0);
-        let origin = transform * Point::origin();
-        let direction = (pixel - origin).normalize();
+        // The transformation is affine, so the direction can be transformed on its own, without
+        // going through the point on the image plane.
+        let direction = self.inv_transform.matrix() * self.projection.direction(world_x, world_y);
+        Ray::new(self.origin, direction.normalize())
+    }
 
-        Ray::new(origin, direction)
+    pub fn with_transform(self, transform: Tr) -> Self {
+        self.try_with_transform(transform)
+            .expect("camera transformation should be invertible")
     }
 
-    pub fn with_transform(mut self, transform: Tr) -> Self {
+    /// Like `with_transform`, but fails instead of panicking if the transformation is singular,
+    /// e.g. for a camera looking straight along its up vector.
+    pub fn try_with_transform(mut self, transform: Tr) -> Result<Self, transform::Error> {
+        self.inv_transform = transform.try_inverse()?;
         self.transform = transform;
-        self.inv_transform = self.transform.inverse();
-        self
+        self.origin = self.inv_transform.matrix() * Point::origin();
+        Ok(self)
+    }
+
+    /// Finds where some point in world space lands on the image, in pixels from the top left
+    /// corner of the image, so that the center of pixel (x, y) is at (x + 0.5, y + 0.5). Points
+    /// the camera can't see, like those behind a perspective camera, give None. Points outside
+    /// the field of view land off the image.
+    pub fn project(&self, p: Point) -> Option<(f64, f64)> {
+        // In camera space, the camera sits at the origin.
+        let d = self.transform.matrix() * p - Point::origin();
+        let (x, y) = self.projection.image_point(d)?;
+        Some((
+            (self.half_width - x) / self.pixel_size,
+            (self.half_height - y) / self.pixel_size,
+        ))
     }
 
     /// Summer time rendering haha :weebdoge:.
+    ///
+    /// If the world's settings have a time budget or a pass limit, this renders progressively;
+    /// see [`Camera::render_progressive`].
+    ///
+    /// If the world's settings have ink, outlines are drawn over the finished image.
+    ///
+    /// Pixels are rendered in parallel, but every random choice is seeded by the pixel and pass
+    /// it is made for, or by the point being shaded, and never by the order in which pixels
+    /// happen to be rendered. So the image comes out the same however many threads render it.
+    ///
+    /// Every rendering thread's buffers are warmed up first; see [`arena::prewarm`].
     pub fn render(&self, world: &World) -> Canvas {
-        let mut image = Canvas::new(self.hsize as usize, self.vsize as usize);
+        arena::prewarm(world);
+        let settings = &world.settings;
+        let image = if settings.time_budget().is_some() || settings.pass_limit().is_some() {
+            self.render_progressive(world).0
+        } else {
+            self.render_by(|ray| world.color_at(ray))
+        };
+        match world.settings.ink() {
+            Some(ink) => draw_ink(&image, &self.render_surfaces(world), &ink),
+            None => image,
+        }
+    }
+
+    /// Finds the depth and normal of the first surface seen through the center of each pixel,
+    /// skipping anything hidden from the camera.
+    fn render_surfaces(&self, world: &World) -> Vec<Option<(f64, Vector)>> {
+        (0..self.hsize * self.vsize)
+            .into_par_iter()
+            .map(|idx| {
+                let ray = self.ray_for_pixel(idx % self.hsize, idx / self.hsize);
+                let xs = ray.when_intersect_world(world);
+                xs.hit_for_camera()
+                    .map(|h| (h.t(), h.normal_at(ray.position_at(h.t()))))
+            })
+            .collect()
+    }
+
+    /// Renders a single named pass of the world, with everything else hidden or held out.
+    pub fn render_pass(&self, world: &World, pass: &str, mode: PassMode) -> Canvas {
+        self.render(&world.pass(pass, mode))
+    }
+
+    /// Renders a single term of the shading on its own; see [`ShadingPass`].
+    pub fn render_shading_pass(&self, world: &World, pass: ShadingPass) -> Canvas {
+        self.render_by(|ray| world.shading_pass_at(ray, pass))
+    }
+
+    /// Renders every surface seen through the center of each pixel, with its depth and alpha.
+    pub fn render_deep(&self, world: &World) -> DeepImage {
+        let pixels = (0..self.hsize * self.vsize)
+            .into_par_iter()
+            .map(|idx| world.deep_samples(self.ray_for_pixel(idx % self.hsize, idx / self.hsize)))
+            .collect();
+        DeepImage::new(self.hsize, self.vsize, pixels)
+    }
+
+    /// Renders how far the surface seen through the center of each pixel moves across the image
+    /// by the next frame, where the next frame is the next world seen by the next camera. Either
+    /// may be the same as this frame's, for a still camera or a still scene.
+    ///
+    /// Objects are matched between the worlds by their IDs, and by the IDs of the groups and
+    /// instances they are in, so the next world should be made by moving this one's objects
+    /// around, e.g. with `with_transform`, rather than by building new ones. Objects which aren't
+    /// in the next world are taken to stand still, and objects hidden from the camera are skipped.
+    pub fn render_motion_vectors(
+        &self,
+        world: &World,
+        next_camera: &Camera,
+        next_world: &World,
+    ) -> MotionVectors {
+        let vectors = (0..self.hsize * self.vsize)
+            .into_par_iter()
+            .map(|idx| {
+                let ray = self.ray_for_pixel(idx % self.hsize, idx / self.hsize);
+                let hit = ray.when_intersect_world(world).hit_for_camera()?.clone();
+                let obj = hit.object();
+                let p = ray.position_at(hit.t());
+                let local = obj.world_to_object(p);
+                let next_t = next_world
+                    .transform_along(hit.parent_ids(), obj.id())
+                    .unwrap_or_else(|| obj.transform());
+                let (x0, y0) = self.project(p)?;
+                let (x1, y1) = next_camera.project(next_t.matrix() * local)?;
+                Some((x1 - x0, y1 - y0))
+            })
+            .collect();
+        MotionVectors::new(self.hsize, self.vsize, vectors)
+    }
+
+    /// Renders an image for compositing, along with its alpha as a grayscale image. Colors are
+    /// premultiplied, so the image goes over a background plate as `color + plate * (1 - alpha)`.
+    pub fn render_with_alpha(&self, world: &World) -> (Canvas, Canvas) {
+        let samples: Vec<(Color, f64)> = (0..self.hsize * self.vsize)
+            .into_par_iter()
+            .map(|idx| {
+                world.color_and_alpha_at(self.ray_for_pixel(idx % self.hsize, idx / self.hsize))
+            })
+            .collect();
+        let mut image = Canvas::new(self.hsize, self.vsize);
+        let mut alpha = Canvas::new(self.hsize, self.vsize);
+        for (idx, (c, a)) in samples.into_iter().enumerate() {
+            image.pixels_mut()[idx] = c;
+            alpha.pixels_mut()[idx] = Color::new(a, a, a);
+        }
+        (image, alpha)
+    }
+
+    /// Renders which object is visible where, sampling every pixel on an n by n grid so that
+    /// edges get fractional coverage. Objects hidden from the camera don't show up.
+    pub fn render_object_matte(&self, world: &World, n: usize) -> ObjectMatte {
+        let n = n.max(1);
+        let samples = (0..self.hsize * self.vsize)
+            .into_par_iter()
+            .map(|idx| {
+                let (x, y) = (idx % self.hsize, idx / self.hsize);
+                (0..n * n)
+                    .map(|i| {
+                        let dx = (i % n) as f64 + 0.5;
+                        let dy = (i / n) as f64 + 0.5;
+                        let ray = self.ray_for_subpixel(x, y, dx / n as f64, dy / n as f64);
+                        ray.when_intersect_world(world)
+                            .hit_for_camera()
+                            .map(|h| h.object().id())
+                    })
+                    .collect()
+            })
+            .collect();
+        ObjectMatte::from_samples(self.hsize, self.vsize, samples)
+    }
+
+    /// Renders in passes, each adding one more sample to every pixel, until the world's time
+    /// budget runs out or its pass limit is reached. The first pass samples pixel centers, so with
+    /// neither this is the same as a plain render; later passes jitter the samples within each
+    /// pixel. A pass is only started if the last one suggests it will finish within the budget.
+    pub fn render_progressive(&self, world: &World) -> (Canvas, RenderStats) {
+        let (acc, stats) = self.render_progressive_samples(world);
+        (acc.resolve(), stats)
+    }
+
+    /// Like [`Camera::render_progressive`], but gives back the samples themselves instead of
+    /// their average, e.g. to see how noisy each pixel still is with
+    /// [`Accumulator::resolve_variance`].
+    pub fn render_progressive_samples(&self, world: &World) -> (Accumulator, RenderStats) {
+        self.progressive(world, |_| {})
+    }
+
+    /// Like [`Camera::render_progressive`], but calls `on_pass` with the image so far after every
+    /// pass, e.g. to show it in a preview window. The image is exposed according to the world's
+    /// [`preview exposure`](crate::settings::RenderSettings::with_preview_exposure), while the image returned at the
+    /// end is left as it is.
+    pub fn render_progressive_with(
+        &self,
+        world: &World,
+        mut on_pass: impl FnMut(&Canvas),
+    ) -> (Canvas, RenderStats) {
+        let (acc, stats) = self.progressive(world, |acc| {
+            on_pass(&preview(world, &acc.resolve()));
+        });
+        (acc.resolve(), stats)
+    }
+
+    fn progressive(
+        &self,
+        world: &World,
+        mut on_pass: impl FnMut(&Accumulator),
+    ) -> (Accumulator, RenderStats) {
+        let start = Instant::now();
+        let budget = world.settings.time_budget();
+        let limit = world.settings.pass_limit();
+
+        let mut acc = Accumulator::new(self.hsize, self.vsize);
+        acc.add_pass(|x, y, _| world.color_at(self.ray_for_pixel(x, y)));
+        on_pass(&acc);
+        let mut passes = 1;
+        let mut last_pass = start.elapsed();
+        // With a pass limit but no time budget, every pass up to the limit is rendered.
+        while limit.is_none_or(|n| passes < n)
+            && budget.map_or(limit.is_some(), |b| start.elapsed() + last_pass <= b)
+        {
+            let pass_start = Instant::now();
+            self.jittered_pass(world, &mut acc, passes as u64);
+            on_pass(&acc);
+            passes += 1;
+            last_pass = pass_start.elapsed();
+        }
+
+        let stats = RenderStats {
+            samples_per_pixel: passes,
+            elapsed: start.elapsed(),
+        };
+        (acc, stats)
+    }
+
+    /// Adds one sample to every pixel, jittered within the pixel. Each pixel's jitter depends only
+    /// on the pass and the pixel, so the same pass always gives the same samples.
+    fn jittered_pass(&self, world: &World, acc: &mut Accumulator, pass: u64) {
+        acc.add_pass(|x, y, _| {
+            let mut rng = Rng::new(pass << 32 ^ (y * self.hsize + x) as u64);
+            world.color_at(self.ray_for_subpixel(x, y, rng.next_f64(), rng.next_f64()))
+        });
+    }
+
+    /// Renders how much light arrives at each visible surface, ignoring what the surfaces look
+    /// like, as shades of grey. See [`World::incident_light`].
+    pub fn render_incident_light(&self, world: &World) -> Canvas {
+        self.render_by(|ray| {
+            let v = world.incident_light(ray);
+            Color::new(v, v, v)
+        })
+    }
+
+    /// Renders one tile at a time, in the order given by the world's settings, calling `on_tile`
+    /// with the image so far after each tile is done. The pixels within a tile are rendered in
+    /// parallel. The image given to `on_tile` is exposed according to the world's
+    /// [`preview exposure`](crate::settings::RenderSettings::with_preview_exposure), if it has one.
+    pub fn render_tiled(&self, world: &World, mut on_tile: impl FnMut(Tile, &Canvas)) -> Canvas {
+        let mut image = Canvas::new(self.hsize, self.vsize);
+        let s = world.settings;
+        for tile in tiles(self.hsize, self.vsize, s.tile_size(), s.tile_order()) {
+            let colors: Vec<Color> = (0..tile.width * tile.height)
+                .into_par_iter()
+                .map(|i| {
+                    let (x, y) = (tile.x + i % tile.width, tile.y + i / tile.width);
+                    world.color_at(self.ray_for_pixel(x, y))
+                })
+                .collect();
+            for (i, c) in colors.into_iter().enumerate() {
+                image.write_to(tile.x + i % tile.width, tile.y + i / tile.width, c);
+            }
+            on_tile(tile, &preview(world, &image));
+        }
+        image
+    }
+
+    /// Renders with custom shading, while still using the camera's rays and rendering in
+    /// parallel. The function is given each pixel's coordinates and the ray through its center,
+    /// along with the world, and gives the pixel's color. It may shade however it likes, e.g.
+    /// showing normals for debugging, or flat toon shading, and can fall back on
+    /// [`World::color_at`] for anything it doesn't handle.
+    pub fn render_with(
+        &self,
+        world: &World,
+        f: impl Fn(usize, usize, Ray, &World) -> Color + Sync,
+    ) -> Canvas {
+        self.render_pixels(|x, y, ray| f(x, y, ray, world))
+    }
+
+    /// Colors every pixel with whatever the function gives for the ray through it.
+    fn render_by(&self, f: impl Fn(Ray) -> Color + Sync) -> Canvas {
+        self.render_pixels(|_, _, ray| f(ray))
+    }
+
+    /// Colors every pixel with whatever the function gives for its coordinates and the ray
+    /// through it.
+    fn render_pixels(&self, f: impl Fn(usize, usize, Ray) -> Color + Sync) -> Canvas {
+        let mut image = Canvas::new(self.hsize, self.vsize);
         image
             .pixels_mut()
             .par_iter_mut() // in parallel!
@@ -91,21 +477,53 @@ impl Camera {
                 let x = idx % self.hsize;
                 let y = idx / self.hsize;
                 let ray = self.ray_for_pixel(x, y);
-                *px = world.color_of_ray(ray, MAX_BOUNCE);
+                *px = f(x, y, ray);
             });
         image
     }
 }
 
+/// An image as it should be shown while it is still being rendered, according to the world's
+/// preview exposure.
+fn preview<'a>(world: &World, image: &'a Canvas) -> Cow<'a, Canvas> {
+    match world.settings.preview_exposure() {
+        Some(mode) => Cow::Owned(preview_expose(image, mode)),
+        None => Cow::Borrowed(image),
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::Camera;
+    use super::{Camera, Projection};
+    use crate::accumulator::Accumulator;
     use crate::assert_f64_eq;
     use crate::color::Color;
+    use crate::light::{Material, PointLight};
+    use crate::patterns::{Cached, Gradient};
+    use crate::post::{luminance_percentile, AutoExposure, Ink};
+    use crate::prefab::{cornell_box, cornell_camera};
+    use crate::settings::RenderSettings;
+    use crate::shapes::{Instance, Sphere};
+    use crate::tiles::TileOrder;
     use crate::transform::{view_transform, Tr};
     use crate::tuple::{Point, Vector};
     use crate::world::World;
-    use std::f64::consts::{FRAC_PI_2, FRAC_PI_4};
+    use std::f64::consts::{FRAC_1_SQRT_2, FRAC_PI_2, FRAC_PI_4, PI};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn summarizing_cameras() {
+        let c = Camera::new(160, 120, PI / 3.0).with_transform(view_transform(
+            Point::new(0.0, 1.5, -5.0),
+            Point::new(0.0, 1.0, 0.0),
+            Vector::new(0.0, 1.0, 0.0),
+        ));
+        assert_eq!(
+            c.to_string(),
+            "160x120 perspective camera at (0, 1.5, -5), 60° field of view"
+        );
+    }
 
     #[test]
     fn constructing_a_camera() {
@@ -152,12 +570,8 @@ mod tests {
 
     #[test]
     fn construct_ray_through_transformed_camera() {
-        let c = Camera::new(201, 101, FRAC_PI_2).with_transform(
-            Tr::default()
-                .translate(0.0, -2.0, 5.0)
-                .rotate_y(FRAC_PI_4)
-                .into(),
-        );
+        let c = Camera::new(201, 101, FRAC_PI_2)
+            .with_transform(Tr::default().translate(0.0, -2.0, 5.0).rotate_y(FRAC_PI_4));
         let r = c.ray_for_pixel(100, 50);
 
         assert_eq!(r.origin(), Point::new(0.0, 2.0, -5.0));
@@ -167,6 +581,20 @@ mod tests {
         );
     }
 
+    #[test]
+    fn singular_camera_transforms_are_rejected() {
+        let c = || Camera::new(11, 11, FRAC_PI_2);
+        let down = view_transform(
+            Point::new(0.0, 5.0, 0.0),
+            Point::origin(),
+            Vector::new(0.0, 1.0, 0.0),
+        );
+        assert!(c().try_with_transform(down).is_err());
+        assert!(c()
+            .try_with_transform(Tr::default().scale(1.0, 0.0, 1.0))
+            .is_err());
+    }
+
     #[test]
     fn rendering_a_world_with_camera() {
         let w = World::default();
@@ -178,4 +606,406 @@ mod tests {
         let got = c.render(&w);
         assert_eq!(got.pixel_at(5, 5), Color::new(0.38066, 0.47583, 0.2855));
     }
+
+    fn small_camera() -> Camera {
+        Camera::new(11, 11, FRAC_PI_2).with_transform(view_transform(
+            Point::new(0.0, 0.0, -5.0),
+            Point::new(0.0, 0.0, 0.0),
+            Vector::new(0.0, 1.0, 0.0),
+        ))
+    }
+
+    #[test]
+    fn custom_per_pixel_shading() {
+        let c = small_camera();
+        let w = World::default();
+        let got = c.render_with(&w, |x, y, ray, world| {
+            if x == 0 && y == 0 {
+                return Color::magenta();
+            }
+            match ray.when_intersect_world(world).hit() {
+                Some(_) => Color::white(),
+                None => world.color_at(ray),
+            }
+        });
+        assert_eq!(got.pixel_at(0, 0), Color::magenta());
+        assert_eq!(got.pixel_at(5, 5), Color::white());
+        assert_eq!(got.pixel_at(10, 0), Color::black());
+    }
+
+    #[test]
+    fn ink_outlines_silhouettes() {
+        let c = Camera::new(41, 41, FRAC_PI_4).with_transform(view_transform(
+            Point::new(0.0, 0.0, -5.0),
+            Point::new(0.0, 0.0, 0.0),
+            Vector::new(0.0, 1.0, 0.0),
+        ));
+        let w = World::default();
+        let plain = c.render(&w);
+        let inked = World::default()
+            .with_settings(RenderSettings::new().with_ink(Ink::new().with_color(Color::magenta())));
+        let got = c.render(&inked);
+        // The middle of the spheres and the empty corners are left alone, but somewhere between
+        // them is an outline.
+        assert_eq!(got.pixel_at(20, 20), plain.pixel_at(20, 20));
+        assert_eq!(got.pixel_at(0, 0), Color::black());
+        assert!((0..41).any(|x| got.pixel_at(x, 20) == Color::magenta()));
+    }
+
+    /// The default world, behind a ball which the camera can't see.
+    fn veiled() -> World {
+        let veil = Sphere::default()
+            .with_material(
+                Material::default()
+                    .with_casts_shadow(false)
+                    .with_visible_to_camera(false),
+            )
+            .with_transform(Tr::new().scale(0.5, 0.5, 0.5).translate(0.0, 0.0, -3.0))
+            .as_object();
+        let mut w = World::default();
+        w.add_objects(vec![veil]);
+        w
+    }
+
+    #[test]
+    fn ink_skips_objects_hidden_from_the_camera() {
+        let c = Camera::new(41, 41, FRAC_PI_4).with_transform(view_transform(
+            Point::new(0.0, 0.0, -5.0),
+            Point::new(0.0, 0.0, 0.0),
+            Vector::new(0.0, 1.0, 0.0),
+        ));
+        let ink = RenderSettings::new().with_ink(Ink::new().with_color(Color::magenta()));
+        let got = c.render(&veiled().with_settings(ink));
+        assert_eq!(
+            got.pixels(),
+            c.render(&World::default().with_settings(ink)).pixels()
+        );
+    }
+
+    #[test]
+    fn object_mattes_skip_objects_hidden_from_the_camera() {
+        let w = veiled();
+        let matte = small_camera().render_object_matte(&w, 2);
+        assert!(!matte.ids().contains(&w.objects[2].id()));
+        assert_eq!(matte.coverage_at(5, 5)[0].id, w.objects[0].id());
+    }
+
+    #[test]
+    fn progressive_render_without_budget_is_one_pass() {
+        let c = small_camera();
+        let w = World::default();
+        let (image, stats) = c.render_progressive(&w);
+        assert_eq!(stats.samples_per_pixel, 1);
+        assert_eq!(image.pixel_at(5, 5), c.render(&w).pixel_at(5, 5));
+    }
+
+    #[test]
+    fn progressive_samples_show_variance() {
+        let c = small_camera();
+        let settings = RenderSettings::new().with_pass_limit(16);
+        let w = World::default().with_settings(settings);
+        let (acc, stats) = c.render_progressive_samples(&w);
+        assert_eq!(stats.samples_per_pixel, 16);
+        assert_eq!(acc.count(5, 5) as usize, stats.samples_per_pixel);
+        // Jittering inside a pixel that only sees the sphere changes little, but pixels on its
+        // edge flip between the sphere and the background.
+        let variance = acc.resolve_variance();
+        let noisiest = variance.pixels().iter().map(|c| c.r()).fold(0.0, f64::max);
+        assert!(noisiest > variance.pixel_at(0, 0).r());
+    }
+
+    #[test]
+    fn progressive_render_stops_at_its_pass_limit() {
+        let c = small_camera();
+        let settings = RenderSettings::new().with_pass_limit(16);
+        let w = World::default().with_settings(settings);
+        let (image, stats) = c.render_progressive(&w);
+        assert_eq!(stats.samples_per_pixel, 16);
+        // Averaging samples across the middle pixel lands close to its center sample.
+        let got = image.pixel_at(5, 5);
+        let want = Color::new(0.38066, 0.47583, 0.2855);
+        assert!((got.r() - want.r()).abs() < 0.05 && (got.g() - want.g()).abs() < 0.05);
+        assert_eq!(c.render(&w).pixels(), image.pixels());
+
+        // A budget which could fit many more passes still stops at the limit.
+        let w =
+            World::default().with_settings(settings.with_time_budget(Duration::from_secs(3600)));
+        assert_eq!(c.render_progressive(&w).1.samples_per_pixel, 16);
+    }
+
+    #[test]
+    fn only_previews_are_exposed() {
+        let c = small_camera();
+        let settings = RenderSettings::new()
+            .with_tiles(4, TileOrder::Spiral)
+            .with_preview_exposure(AutoExposure::Percentile(1.0));
+        let mut w = World::default().with_settings(settings);
+        w.light = Some(PointLight::new(
+            Point::new(-10.0, 10.0, -10.0),
+            Color::new(0.01, 0.01, 0.01),
+        ));
+        let want = c.render(&w);
+
+        // However dark the scene, the brightest pixel of every preview is at full brightness.
+        let mut brightest = vec![];
+        let got = c.render_tiled(&w, |_, image| {
+            brightest.push(luminance_percentile(image, 1.0));
+        });
+        assert_eq!(brightest.len(), 9);
+        assert!(brightest.iter().all(|l| (l - 1.0).abs() < 1e-9));
+        assert_eq!(got.pixels(), want.pixels());
+
+        let mut passes = 0;
+        let (got, _) = c.render_progressive_with(&w, |image| {
+            passes += 1;
+            assert!((luminance_percentile(image, 1.0) - 1.0).abs() < 1e-9);
+        });
+        assert_eq!(passes, 1);
+        assert_eq!(got.pixels(), want.pixels());
+    }
+
+    #[test]
+    fn tiled_render_matches_plain_render() {
+        let c = small_camera();
+        let settings = RenderSettings::new().with_tiles(4, TileOrder::Spiral);
+        let w = World::default().with_settings(settings);
+
+        let mut seen = vec![];
+        let got = c.render_tiled(&w, |tile, _| seen.push(tile));
+        // An 11x11 image splits into 3x3 tiles, the first in the middle.
+        assert_eq!(seen.len(), 9);
+        assert_eq!((seen[0].x, seen[0].y), (4, 4));
+
+        let want = c.render(&w);
+        for (x, y) in [(0, 0), (5, 5), (10, 3), (7, 10)] {
+            assert_eq!(got.pixel_at(x, y), want.pixel_at(x, y));
+        }
+    }
+
+    #[test]
+    fn stereographic_rays() {
+        let c = Camera::new(201, 101, 1.5 * PI).with_projection(Projection::Stereographic);
+        // The center still looks straight ahead.
+        let r = c.ray_for_pixel(100, 50);
+        assert_eq!(r.direction(), Vector::new(0.0, 0.0, -1.0));
+        // The left edge is 135 degrees off to the side, so it looks partly behind the camera.
+        let r = c.ray_for_subpixel(0, 50, 0.0, 0.5);
+        let want = Vector::new(FRAC_1_SQRT_2, 0.0, FRAC_1_SQRT_2);
+        assert_eq!(r.direction(), want);
+    }
+
+    #[test]
+    fn projection_from_yaml() {
+        let c: Camera = serde_yaml::from_str(
+            "{ width: 10, height: 10, field_of_view: 5.0, projection: stereographic, \
+               from: [0, 5, 0], to: [0, 0, 0], up: [0, 0, 1] }",
+        )
+        .unwrap();
+        assert_eq!(c.projection, Projection::Stereographic);
+    }
+
+    #[test]
+    fn object_matte_has_soft_edges() {
+        let c = small_camera();
+        let w = World::default();
+        let m = c.render_object_matte(&w, 4);
+        let outer = w.objects[0].id();
+        // Only the outer sphere can be seen; the inner one is inside it.
+        assert_eq!(m.ids(), vec![outer]);
+        assert_eq!(m.coverage_at(5, 5)[0].weight, 1.0);
+        assert!(m.coverage_at(0, 0).is_empty());
+        let edges = (0..11)
+            .flat_map(|y| (0..11).map(move |x| (x, y)))
+            .filter(|&(x, y)| m.coverage_at(x, y).first().is_some_and(|c| c.weight < 1.0))
+            .count();
+        assert!(edges > 0);
+    }
+
+    #[test]
+    fn deep_render_of_opaque_world_flattens_to_plain_render() {
+        let c = small_camera();
+        let w = World::default();
+        let deep = c.render_deep(&w);
+        assert_eq!(deep.samples_at(5, 5).len(), 1);
+        assert!(deep.samples_at(0, 0).is_empty());
+        let (flat, plain) = (deep.flatten(), c.render(&w));
+        assert_eq!(flat.pixel_at(5, 5), plain.pixel_at(5, 5));
+    }
+
+    #[test]
+    fn deep_render_sees_through_glass() {
+        let c = small_camera();
+        let w = World::default().map_objects(|o| {
+            crate::shapes::Sphere::default()
+                .with_transform(o.transform())
+                .with_material(o.material().with_transparency(0.5))
+                .as_object()
+        });
+        let samples = c.render_deep(&w).samples_at(5, 5).to_vec();
+        // In and out of both spheres.
+        assert_eq!(samples.len(), 4);
+        assert!(samples.windows(2).all(|s| s[0].depth < s[1].depth));
+        assert!(samples.iter().all(|s| s.alpha == 0.5));
+    }
+
+    #[test]
+    fn rendering_with_alpha() {
+        let c = small_camera();
+        let w = World::default();
+        let (image, alpha) = c.render_with_alpha(&w);
+        assert_eq!(image.pixel_at(5, 5), c.render(&w).pixel_at(5, 5));
+        assert_eq!(alpha.pixel_at(5, 5), Color::white());
+        assert_eq!(alpha.pixel_at(0, 0), Color::black());
+    }
+
+    #[test]
+    fn renders_do_not_depend_on_thread_count() {
+        // Glossy and refractive surfaces, so that every kind of random choice gets made, and a
+        // cached pattern, whose cache fills up in whatever order the pixels are rendered.
+        let glossy = Material::default().with_reflective(0.5).with_roughness(0.2);
+        let glass = Material::default()
+            .with_transparency(0.9)
+            .with_refractive_index(1.5);
+        let gradient = Gradient::new(Color::new(1.0, 0.0, 0.0), Color::new(0.0, 0.0, 1.0));
+        let world = || {
+            let cached = Cached::new(gradient, 64).with_cell_size(0.25);
+            cornell_box(vec![
+                Sphere::default()
+                    .with_transform(Tr::new().scale(0.4, 0.4, 0.4).translate(-0.4, 0.4, 0.2))
+                    .with_material(glossy.clone())
+                    .as_object(),
+                Sphere::default()
+                    .with_transform(Tr::new().scale(0.3, 0.3, 0.3).translate(0.4, 0.3, -0.3))
+                    .with_material(glass.clone())
+                    .as_object(),
+                Sphere::default()
+                    .with_transform(Tr::new().scale(0.5, 0.5, 0.5).translate(0.0, 1.2, 0.4))
+                    .with_material(Material::default().with_pattern(Arc::new(cached)))
+                    .as_object(),
+            ])
+        };
+        let c = cornell_camera(24, 16);
+        let render = |threads: usize| {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap();
+            let w = world();
+            pool.install(|| {
+                let mut acc = Accumulator::new(c.hsize, c.vsize);
+                for pass in 1..4 {
+                    c.jittered_pass(&w, &mut acc, pass);
+                }
+                (
+                    c.render(&w).pixels().to_vec(),
+                    acc.resolve().pixels().to_vec(),
+                )
+            })
+        };
+        assert_eq!(render(1), render(4));
+    }
+
+    #[test]
+    fn projecting_points_onto_the_image() {
+        let from = Point::new(1.0, 2.0, -5.0);
+        let t = view_transform(from, Point::origin(), Vector::new(0.0, 1.0, 0.0));
+        for projection in [Projection::Perspective, Projection::Stereographic] {
+            let c = Camera::new(40, 30, 2.0)
+                .with_projection(projection)
+                .with_transform(t);
+            let r = c.ray_for_subpixel(7, 22, 0.25, 0.5);
+            let (x, y) = c.project(r.position_at(3.0)).unwrap();
+            assert_f64_eq!(x, 7.25, 1e-9);
+            assert_f64_eq!(y, 22.5, 1e-9);
+        }
+        // Nothing behind a perspective camera shows up.
+        let c = Camera::new(40, 30, 2.0).with_transform(t);
+        assert!(c.project(Point::new(2.0, 4.0, -10.0)).is_none());
+    }
+
+    #[test]
+    fn motion_vectors_follow_moving_objects() {
+        let c = Camera::new(11, 11, FRAC_PI_2).with_transform(view_transform(
+            Point::new(0.0, 0.0, -5.0),
+            Point::origin(),
+            Vector::new(0.0, 1.0, 0.0),
+        ));
+        let ball = Sphere::default();
+        let w = World::new().with_objects(vec![ball.clone().as_object()]);
+        let next = World::new().with_objects(vec![ball
+            .with_transform(Tr::new().translate(0.5, 0.0, 0.0))
+            .as_object()]);
+
+        let mv = c.render_motion_vectors(&w, &c, &next);
+        // The front of the ball is 4 units away, where a pixel is 8/11 units wide.
+        let (dx, dy) = mv.vector_at(5, 5).unwrap();
+        assert_f64_eq!(dx, 0.6875, 1e-9);
+        assert_f64_eq!(dy, 0.0, 1e-9);
+        assert_eq!(mv.vector_at(0, 0), None);
+
+        // Moving the camera the other way instead looks just the same.
+        let panned = Camera::new(11, 11, FRAC_PI_2).with_transform(view_transform(
+            Point::new(-0.5, 0.0, -5.0),
+            Point::new(-0.5, 0.0, 0.0),
+            Vector::new(0.0, 1.0, 0.0),
+        ));
+        let (dx, _) = c
+            .render_motion_vectors(&w, &panned, &w)
+            .vector_at(5, 5)
+            .unwrap();
+        assert_f64_eq!(dx, 0.6875, 1e-9);
+    }
+
+    #[test]
+    fn motion_vectors_skip_objects_hidden_from_the_camera() {
+        let c = small_camera();
+        let veil = Sphere::default()
+            .with_material(Material::default().with_visible_to_camera(false))
+            .with_transform(Tr::new().scale(0.5, 0.5, 0.5).translate(0.0, 0.0, -3.0));
+        let with_veil = |veil: Sphere| {
+            let mut w = World::default();
+            w.add_objects(vec![veil.as_object()]);
+            w
+        };
+        let w = with_veil(veil.clone());
+        let next = with_veil(
+            veil.with_transform(Tr::new().scale(0.5, 0.5, 0.5).translate(1.0, 0.0, -3.0)),
+        );
+        // Only the veil moved, and the camera looks straight through it at the still spheres.
+        let (dx, dy) = c
+            .render_motion_vectors(&w, &c, &next)
+            .vector_at(5, 5)
+            .unwrap();
+        assert_f64_eq!(dx, 0.0, 1e-9);
+        assert_f64_eq!(dy, 0.0, 1e-9);
+    }
+
+    #[test]
+    fn motion_vectors_follow_moving_copies_of_a_shape() {
+        let c = Camera::new(11, 11, FRAC_PI_2).with_transform(view_transform(
+            Point::new(0.0, 0.0, -5.0),
+            Point::origin(),
+            Vector::new(0.0, 1.0, 0.0),
+        ));
+        let ball = Sphere::default().as_object();
+        let place =
+            |x: f64| Instance::new(ball.clone()).with_transform(Tr::new().translate(x, 0.0, 0.0));
+        let (still, moving) = (place(-20.0), place(0.0));
+        let w =
+            World::new().with_objects(vec![still.clone().as_object(), moving.clone().as_object()]);
+        let next = World::new().with_objects(vec![
+            still.as_object(),
+            moving
+                .with_transform(Tr::new().translate(0.5, 0.0, 0.0))
+                .as_object(),
+        ]);
+
+        // Only the copy in view moved, even though both copies are the same ball.
+        let (dx, dy) = c
+            .render_motion_vectors(&w, &c, &next)
+            .vector_at(5, 5)
+            .unwrap();
+        assert_f64_eq!(dx, 0.6875, 1e-9);
+        assert_f64_eq!(dy, 0.0, 1e-9);
+    }
 }
@@ -1,6 +1,7 @@
 use crate::color::Color;
 
 /// A 2D canvas. The (0, 0) coordinate is at the top left.
+#[derive(Debug, Clone)]
 pub struct Canvas {
     width: usize,
     height: usize,
@@ -31,6 +32,20 @@ impl Canvas {
         self.pixels[idx]
     }
 
+    /// Get the pixels of this canvas, row by row.
+    pub fn pixels(&self) -> &[Color] {
+        &self.pixels
+    }
+
+    /// Creates a new canvas of the same size, with every pixel passed through some function.
+    pub fn map(&self, f: impl Fn(Color) -> Color) -> Canvas {
+        Canvas {
+            width: self.width,
+            height: self.height,
+            pixels: self.pixels.iter().map(|c| f(*c)).collect(),
+        }
+    }
+
     /// Get a mutable reference to the pixels of this canvas.
     pub fn pixels_mut(&mut self) -> &mut Vec<Color> {
         &mut self.pixels
@@ -38,19 +53,22 @@ impl Canvas {
 
     /// Exports the current canvas as a PPM format string.
     pub fn to_ppm(&self) -> String {
+        self.to_ppm_dithered(Dither::None)
+    }
+
+    /// Exports the current canvas as a PPM format string, dithering each pixel as it is
+    /// quantized to 8 bits. This hides the banding on smooth gradients.
+    pub fn to_ppm_dithered(&self, dither: Dither) -> String {
         let mut hdr = format!("P3\n{} {}\n255\n", self.width, self.height);
         let pxs = self
             .pixels
             .chunks(self.width)
-            .flat_map(|row| {
+            .enumerate()
+            .flat_map(|(y, row)| {
                 let nums: Vec<String> = row
-                    .into_iter()
-                    .flat_map(|color| {
-                        let s = color.to_string();
-                        let nums: Vec<String> =
-                            s.split_whitespace().map(|x| x.to_owned()).collect();
-                        nums
-                    })
+                    .iter()
+                    .enumerate()
+                    .flat_map(|(x, color)| dither.quantize(*color, x, y).map(|n| n.to_string()))
                     .collect();
                 let mut res: Vec<String> = vec![];
                 let mut tmp_str = String::new();
@@ -82,14 +100,119 @@ impl Canvas {
         hdr
     }
 
+    /// Counts the pixels by luminance, split evenly into some number of bins over [0, 1].
+    /// Anything brighter than 1 goes into the last bin, and anything darker than 0 into the first.
+    pub fn histogram(&self, bins: usize) -> Vec<usize> {
+        let mut res = vec![0; bins];
+        if bins == 0 {
+            return res;
+        }
+        for c in &self.pixels {
+            let l = c.luminance().clamp(0.0, 1.0);
+            let idx = ((l * bins as f64) as usize).min(bins - 1);
+            res[idx] += 1;
+        }
+        res
+    }
+
+    /// Summarizes the luminance of this canvas.
+    pub fn stats(&self) -> ImageStats {
+        let n = self.pixels.len();
+        if n == 0 {
+            return ImageStats::default();
+        }
+        let (mut min, mut max, mut sum, mut clipped) = (f64::INFINITY, f64::NEG_INFINITY, 0.0, 0);
+        for c in &self.pixels {
+            let l = c.luminance();
+            min = min.min(l);
+            max = max.max(l);
+            sum += l;
+            if c.r() > 1.0 || c.g() > 1.0 || c.b() > 1.0 {
+                clipped += 1;
+            }
+        }
+        ImageStats {
+            min,
+            max,
+            mean: sum / n as f64,
+            clipped: 100.0 * clipped as f64 / n as f64,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
     pub fn height(&self) -> usize {
         self.height
     }
 }
 
+/// Luminance statistics for a canvas.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ImageStats {
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    /// The percentage of pixels with some channel brighter than the display can show.
+    pub clipped: f64,
+}
+
+/// How to spread out the error when quantizing a color down to 8 bits per channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Dither {
+    /// No dithering, every channel is simply rounded up.
+    #[default]
+    None,
+    /// An 8x8 Bayer matrix, which gives a regular cross-hatched pattern.
+    Ordered,
+    /// Interleaved gradient noise, a cheap stand-in for a blue noise texture. The noise has few
+    /// low frequencies so it reads as fine grain rather than blotches.
+    BlueNoise,
+}
+
+/// The classic 8x8 Bayer index matrix.
+const BAYER_8: [[u8; 8]; 8] = [
+    [0, 32, 8, 40, 2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44, 4, 36, 14, 46, 6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [3, 35, 11, 43, 1, 33, 9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47, 7, 39, 13, 45, 5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+impl Dither {
+    /// The threshold in [0, 1) which gets added to a pixel before it is truncated.
+    fn threshold(&self, x: usize, y: usize) -> f64 {
+        match self {
+            Dither::None => 0.0,
+            Dither::Ordered => (BAYER_8[y % 8][x % 8] as f64 + 0.5) / 64.0,
+            Dither::BlueNoise => {
+                let t = 0.06711056 * x as f64 + 0.00583715 * y as f64;
+                (52.9829189 * t.fract()).fract()
+            }
+        }
+    }
+
+    /// Quantizes a color into 8 bit channels, for the pixel at (x, y).
+    pub fn quantize(&self, c: Color, x: usize, y: usize) -> [u8; 3] {
+        let t = self.threshold(x, y);
+        [c.r(), c.g(), c.b()].map(|v| {
+            let v = (v * 255.0).clamp(0.0, 255.0);
+            match self {
+                Dither::None => v.ceil() as u8,
+                _ => (v + t).floor().min(255.0) as u8,
+            }
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::Canvas;
+    use super::{Canvas, Dither, ImageStats};
+    use crate::assert_f64_eq;
     use crate::color::Color;
 
     #[test]
@@ -165,4 +288,66 @@ mod tests {
         let mut ppm = c.to_ppm();
         assert_eq!(ppm.pop().unwrap(), '\n');
     }
+
+    #[test]
+    fn dithering_breaks_up_bands() {
+        // A flat color halfway between two 8 bit levels.
+        let grey = Color::new(100.5 / 255.0, 100.5 / 255.0, 100.5 / 255.0);
+        let mut c = Canvas::new(8, 8);
+        for px in c.pixels.iter_mut() {
+            *px = grey;
+        }
+        for dither in [Dither::Ordered, Dither::BlueNoise] {
+            let ppm = c.to_ppm_dithered(dither);
+            let nums: Vec<u32> = ppm
+                .split_whitespace()
+                .skip(4)
+                .map(|n| n.parse().unwrap())
+                .collect();
+            assert_eq!(nums.len(), 8 * 8 * 3);
+            assert!(nums.iter().all(|&n| n == 100 || n == 101));
+            // On average, the dithered pixels should land on the original color.
+            let mean = nums.iter().sum::<u32>() as f64 / nums.len() as f64;
+            assert!((mean - 100.5).abs() < 0.1, "{dither:?} has mean {mean}");
+        }
+    }
+
+    #[test]
+    fn dithering_keeps_extremes() {
+        for dither in [Dither::None, Dither::Ordered, Dither::BlueNoise] {
+            for (x, y) in [(0, 0), (3, 5), (7, 7)] {
+                assert_eq!(dither.quantize(Color::black(), x, y), [0, 0, 0]);
+                assert_eq!(dither.quantize(Color::white(), x, y), [255, 255, 255]);
+            }
+        }
+    }
+
+    #[test]
+    fn luminance_histogram() {
+        let mut c = Canvas::new(4, 1);
+        c.write_to(0, 0, Color::black());
+        c.write_to(1, 0, Color::new(0.3, 0.3, 0.3));
+        c.write_to(2, 0, Color::white());
+        c.write_to(3, 0, Color::new(5.0, 5.0, 5.0));
+        assert_eq!(c.histogram(4), vec![1, 1, 0, 2]);
+        assert_eq!(c.histogram(1), vec![4]);
+        assert!(c.histogram(0).is_empty());
+    }
+
+    #[test]
+    fn luminance_stats() {
+        let mut c = Canvas::new(4, 1);
+        c.write_to(0, 0, Color::black());
+        c.write_to(1, 0, Color::new(0.5, 0.5, 0.5));
+        c.write_to(2, 0, Color::white());
+        c.write_to(3, 0, Color::new(2.5, 2.5, 2.5));
+
+        let got = c.stats();
+        assert_f64_eq!(got.min, 0.0);
+        assert_f64_eq!(got.max, 2.5);
+        assert_f64_eq!(got.mean, 1.0);
+        assert_f64_eq!(got.clipped, 25.0);
+
+        assert_eq!(Canvas::new(0, 0).stats(), ImageStats::default());
+    }
 }
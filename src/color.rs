@@ -21,6 +21,25 @@ impl Color {
         self.0 .2
     }
 
+    /// The relative luminance, using the Rec. 709 weights.
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.r() + 0.7152 * self.g() + 0.0722 * self.b()
+    }
+
+    /// Clamps every channel into [0, 1], which is what a display can actually show.
+    pub fn clamped(&self) -> Color {
+        Color::new(
+            self.r().clamp(0.0, 1.0),
+            self.g().clamp(0.0, 1.0),
+            self.b().clamp(0.0, 1.0),
+        )
+    }
+
+    /// Linearly interpolates between this color and another.
+    pub fn lerp(&self, other: Color, t: f64) -> Color {
+        *self * (1.0 - t) + other * t
+    }
+
     fn hadamard_with(&self, c: Color) -> Color {
         let Tuple(r, g, b, ..) = self.0;
         let Tuple(x, y, z, ..) = c.0;
@@ -44,6 +63,8 @@ macro_rules! add_color {
 add_color! {
     white: 256, 256, 256,
     black: 0, 0, 0,
+    magenta: 256, 0, 256,
+    orange: 256, 128, 0,
 
     // Smoky haze
     sh_ash_gray: 178, 196, 181,
@@ -0,0 +1,198 @@
+use crate::canvas::Canvas;
+use crate::color::Color;
+use crate::patterns::Pattern;
+use crate::transform::Tr;
+use crate::tuple::{Point, Vector};
+use std::sync::Arc;
+
+/// What a decal paints onto surfaces.
+#[derive(Debug, Clone)]
+pub enum DecalSource {
+    /// An image stretched over the projector's face, with its top left corner at (-1, 1).
+    Image(Arc<Canvas>),
+    /// A pattern, evaluated in the projector's space.
+    Pattern(Arc<dyn Pattern>),
+}
+
+/// Images are compared by identity rather than pixel by pixel, like textures.
+impl PartialEq for DecalSource {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Image(a), Self::Image(b)) => Arc::ptr_eq(a, b),
+            (Self::Pattern(a), Self::Pattern(b)) => **a == **b,
+            _ => false,
+        }
+    }
+}
+
+/// How a decal's color combines with the color of the surface it lands on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DecalBlend {
+    /// Paints over the surface, like a label or a poster.
+    Replace,
+    /// Darkens the surface by the decal's color, like a dirt stamp.
+    Multiply,
+}
+
+/// An image or pattern projected onto whatever surfaces lie in front of a projector, so that
+/// surfaces can be decorated without any UV mapping.
+///
+/// In its own space, a projector is the box from (-1, -1, -1) to (1, 1, 1), and shines along
+/// the positive z axis. Its transformation places that box in the world.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decal {
+    source: DecalSource,
+    blend: DecalBlend,
+    /// How strongly the decal shows, from 0 to 1.
+    opacity: f64,
+    transform: Tr,
+    inv_transform: Tr,
+}
+
+impl Decal {
+    pub fn new(source: DecalSource) -> Self {
+        Self {
+            source,
+            blend: DecalBlend::Replace,
+            opacity: 1.0,
+            transform: Tr::default(),
+            inv_transform: Tr::default(),
+        }
+    }
+
+    pub fn with_transform(mut self, t: Tr) -> Self {
+        self.transform = t;
+        self.inv_transform = t.inverse();
+        self
+    }
+
+    pub fn with_blend(mut self, blend: DecalBlend) -> Self {
+        self.blend = blend;
+        self
+    }
+
+    pub fn with_opacity(mut self, opacity: f64) -> Self {
+        self.opacity = opacity.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn transform(&self) -> Tr {
+        self.transform
+    }
+
+    /// Finds the color the decal paints at some point in world space, if the point lies in the
+    /// projector and its surface faces the projector.
+    pub fn color_at(&self, p: Point, normalv: Vector) -> Option<Color> {
+        let facing = self.transform.matrix() * Vector::new(0.0, 0.0, 1.0);
+        if normalv.dot(facing) >= 0.0 {
+            return None;
+        }
+        let local = self.inv_transform.matrix() * p;
+        if [local.x(), local.y(), local.z()]
+            .iter()
+            .any(|c| c.abs() > 1.0)
+        {
+            return None;
+        }
+        Some(match &self.source {
+            DecalSource::Pattern(pat) => pat.color_at(local),
+            DecalSource::Image(img) => {
+                let (w, h) = (img.width(), img.height());
+                let u = (local.x() + 1.0) / 2.0;
+                let v = (1.0 - local.y()) / 2.0;
+                let x = ((u * w as f64) as usize).min(w - 1);
+                let y = ((v * h as f64) as usize).min(h - 1);
+                img.pixel_at(x, y)
+            }
+        })
+    }
+
+    /// Applies this decal to the color of a surface at some point.
+    pub fn apply(&self, base: Color, p: Point, normalv: Vector) -> Color {
+        match self.color_at(p, normalv) {
+            None => base,
+            Some(c) => match self.blend {
+                DecalBlend::Replace => base.lerp(c, self.opacity),
+                DecalBlend::Multiply => base * Color::white().lerp(c, self.opacity),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Decal, DecalBlend, DecalSource};
+    use crate::canvas::Canvas;
+    use crate::color::Color;
+    use crate::patterns::Stripe;
+    use crate::transform::Tr;
+    use crate::{p, v};
+    use std::sync::Arc;
+
+    /// A 2x2 image, red on the left and blue on the right.
+    fn label() -> Decal {
+        let mut img = Canvas::new(2, 2);
+        for y in 0..2 {
+            img.write_to(0, y, Color::new(1.0, 0.0, 0.0));
+            img.write_to(1, y, Color::new(0.0, 0.0, 1.0));
+        }
+        Decal::new(DecalSource::Image(Arc::new(img)))
+    }
+
+    #[test]
+    fn image_decal_lands_inside_projector() {
+        let d = label();
+        let towards = v!(0, 0, -1);
+        assert_eq!(
+            d.color_at(p!(-0.5, 0.5, 0), towards),
+            Some(Color::new(1.0, 0.0, 0.0))
+        );
+        assert_eq!(
+            d.color_at(p!(0.5, -0.5, 0.9), towards),
+            Some(Color::new(0.0, 0.0, 1.0))
+        );
+        assert_eq!(d.color_at(p!(1.5, 0, 0), towards), None);
+        assert_eq!(d.color_at(p!(0, 0, -1.5), towards), None);
+    }
+
+    #[test]
+    fn decal_skips_surfaces_facing_away() {
+        let d = label();
+        assert_eq!(d.color_at(p!(0.5, 0, 0), v!(0, 0, 1)), None);
+        assert_eq!(d.color_at(p!(0.5, 0, 0), v!(1, 0, 0)), None);
+    }
+
+    #[test]
+    fn transformed_projector() {
+        // Points down at the floor, covering a 4x4 patch.
+        let d = label().with_transform(
+            Tr::new()
+                .scale(2.0, 2.0, 2.0)
+                .rotate_x(std::f64::consts::FRAC_PI_2),
+        );
+        let up = v!(0, 1, 0);
+        assert!(d.color_at(p!(1, 0, 1), up).is_some());
+        assert_eq!(d.color_at(p!(3, 0, 0), up), None);
+    }
+
+    #[test]
+    fn blending_decals() {
+        let base = Color::new(0.5, 0.5, 0.5);
+        let towards = v!(0, 0, -1);
+        let d = label().with_opacity(0.5);
+        assert_eq!(
+            d.apply(base, p!(-0.5, 0, 0), towards),
+            Color::new(0.75, 0.25, 0.25)
+        );
+        let dirt = Decal::new(DecalSource::Pattern(Arc::new(Stripe::new(
+            Color::new(0.5, 0.5, 0.5),
+            Color::white(),
+        ))))
+        .with_blend(DecalBlend::Multiply);
+        assert_eq!(
+            dirt.apply(base, p!(0.5, 0, 0), towards),
+            Color::new(0.25, 0.25, 0.25)
+        );
+        assert_eq!(dirt.apply(base, p!(2, 0, 0), towards), base);
+    }
+}
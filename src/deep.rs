@@ -0,0 +1,221 @@
+use crate::canvas::Canvas;
+use crate::color::Color;
+use std::fmt::Display;
+
+/// One surface seen through a pixel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DeepSample {
+    /// Distance along the camera ray.
+    pub depth: f64,
+    pub color: Color,
+    /// How much of whatever is behind this surface it blocks, from 0 to 1.
+    pub alpha: f64,
+}
+
+/// An image which keeps every surface each pixel sees, rather than just the final color. Deep
+/// images can be merged by depth, which flat images can't do for transparent things.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeepImage {
+    width: usize,
+    height: usize,
+    /// The samples of every pixel, row by row, nearest first.
+    pixels: Vec<Vec<DeepSample>>,
+}
+
+/// The first four bytes of a deep image file.
+const MAGIC: &[u8; 4] = b"DEEP";
+/// Bumped whenever the layout of a deep image file changes.
+const FORMAT_VERSION: u32 = 1;
+
+impl DeepImage {
+    pub fn new(width: usize, height: usize, pixels: Vec<Vec<DeepSample>>) -> Self {
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn samples_at(&self, x: usize, y: usize) -> &[DeepSample] {
+        &self.pixels[y * self.width + x]
+    }
+
+    /// Composites the samples of every pixel front to back, over black.
+    pub fn flatten(&self) -> Canvas {
+        let mut res = Canvas::new(self.width, self.height);
+        for (px, samples) in res.pixels_mut().iter_mut().zip(&self.pixels) {
+            let mut seen = 1.0;
+            for s in samples {
+                *px = *px + s.color * (s.alpha * seen);
+                seen *= 1.0 - s.alpha;
+            }
+        }
+        res
+    }
+
+    /// Encodes this image in a simple binary format. Everything is little endian:
+    ///
+    /// - the magic bytes `DEEP`, then the format version, width and height as u32s;
+    /// - for every pixel, row by row, the number of samples as a u32;
+    /// - followed by that many samples, each as depth, red, green, blue, and alpha f32s.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut res = MAGIC.to_vec();
+        for n in [FORMAT_VERSION, self.width as u32, self.height as u32] {
+            res.extend(n.to_le_bytes());
+        }
+        for samples in &self.pixels {
+            res.extend((samples.len() as u32).to_le_bytes());
+            for s in samples {
+                let c = s.color;
+                for x in [s.depth, c.r(), c.g(), c.b(), s.alpha] {
+                    res.extend((x as f32).to_le_bytes());
+                }
+            }
+        }
+        res
+    }
+
+    /// Decodes an image written by [`DeepImage::to_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ErrDeepImage> {
+        let mut r = Reader(bytes);
+        if r.take(4)? != MAGIC {
+            return Err(ErrDeepImage::NotDeep);
+        }
+        let version = r.u32()?;
+        if version != FORMAT_VERSION {
+            return Err(ErrDeepImage::UnsupportedVersion(version));
+        }
+        let (width, height) = (r.u32()? as usize, r.u32()? as usize);
+        let count = width.checked_mul(height).ok_or(ErrDeepImage::Truncated)?;
+        // Every pixel takes at least four bytes, for its sample count, so there's no point making
+        // room for more pixels than the bytes left could hold.
+        let mut pixels = Vec::with_capacity(count.min(r.0.len() / 4));
+        for _ in 0..count {
+            let n = r.u32()?;
+            let samples = (0..n)
+                .map(|_| {
+                    let [depth, cr, cg, cb, alpha] = [(); 5].map(|_| r.f32().map(f64::from));
+                    Ok(DeepSample {
+                        depth: depth?,
+                        color: Color::new(cr?, cg?, cb?),
+                        alpha: alpha?,
+                    })
+                })
+                .collect::<Result<_, ErrDeepImage>>()?;
+            pixels.push(samples);
+        }
+        Ok(Self::new(width, height, pixels))
+    }
+}
+
+/// A cursor over some bytes.
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn take(&mut self, n: usize) -> Result<&[u8], ErrDeepImage> {
+        if self.0.len() < n {
+            return Err(ErrDeepImage::Truncated);
+        }
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn u32(&mut self) -> Result<u32, ErrDeepImage> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn f32(&mut self) -> Result<f32, ErrDeepImage> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+}
+
+/// Represents some problem reading a deep image.
+#[derive(Debug, PartialEq)]
+pub enum ErrDeepImage {
+    /// The bytes don't start with the deep image magic.
+    NotDeep,
+    UnsupportedVersion(u32),
+    /// The bytes end before the image does.
+    Truncated,
+}
+
+impl Display for ErrDeepImage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use ErrDeepImage::*;
+        match self {
+            NotDeep => write!(f, "Not a deep image"),
+            UnsupportedVersion(v) => write!(f, "Unsupported deep image version; version={v}"),
+            Truncated => write!(f, "Deep image is truncated"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DeepImage, DeepSample, ErrDeepImage};
+    use crate::color::Color;
+
+    fn sample(depth: f64, c: Color, alpha: f64) -> DeepSample {
+        DeepSample {
+            depth,
+            color: c,
+            alpha,
+        }
+    }
+
+    fn two_pixels() -> DeepImage {
+        DeepImage::new(
+            2,
+            1,
+            vec![
+                vec![
+                    sample(1.0, Color::new(1.0, 0.0, 0.0), 0.5),
+                    sample(3.0, Color::new(0.0, 0.0, 1.0), 1.0),
+                ],
+                vec![],
+            ],
+        )
+    }
+
+    #[test]
+    fn flattening_composites_front_to_back() {
+        let got = two_pixels().flatten();
+        assert_eq!(got.pixel_at(0, 0), Color::new(0.5, 0.0, 0.5));
+        assert_eq!(got.pixel_at(1, 0), Color::black());
+    }
+
+    #[test]
+    fn round_trip_through_bytes() {
+        let image = two_pixels();
+        let bytes = image.to_bytes();
+        assert_eq!(&bytes[..4], b"DEEP");
+        assert_eq!(DeepImage::from_bytes(&bytes), Ok(image));
+    }
+
+    #[test]
+    fn reading_bad_bytes() {
+        assert_eq!(DeepImage::from_bytes(b"P3\n"), Err(ErrDeepImage::Truncated));
+        assert_eq!(DeepImage::from_bytes(b"PPM!"), Err(ErrDeepImage::NotDeep));
+        let mut bytes = two_pixels().to_bytes();
+        bytes.truncate(bytes.len() - 3);
+        assert_eq!(DeepImage::from_bytes(&bytes), Err(ErrDeepImage::Truncated));
+        bytes[4] = 9;
+        assert_eq!(
+            DeepImage::from_bytes(&bytes),
+            Err(ErrDeepImage::UnsupportedVersion(9))
+        );
+        // A huge image in a handful of bytes is cut short, rather than making room for it first.
+        let mut bytes = two_pixels().to_bytes();
+        bytes[8..16].copy_from_slice(&[0xff; 8]);
+        assert_eq!(DeepImage::from_bytes(&bytes), Err(ErrDeepImage::Truncated));
+    }
+}
@@ -0,0 +1,363 @@
+use crate::canvas::Canvas;
+use std::fmt::Display;
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::{fs, io, thread};
+
+/// Represents some problem with the frame options given on the command line.
+#[derive(Debug, PartialEq)]
+pub enum ErrFrameArgs {
+    /// When a frame range is not of the form `first..last`, or ends before it starts.
+    InvalidRange(String),
+    /// When the number of jobs is not a positive whole number.
+    InvalidJobs(String),
+    /// When a flag is not followed by its value. Holds the flag.
+    MissingValue(String),
+}
+
+impl Display for ErrFrameArgs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use ErrFrameArgs::*;
+        match self {
+            InvalidRange(s) => write!(f, "Invalid frame range, expected first..last; got={s}"),
+            InvalidJobs(s) => write!(f, "Invalid number of jobs; got={s}"),
+            MissingValue(flag) => write!(f, "Missing value for flag; flag={flag}"),
+        }
+    }
+}
+
+/// A range of frame numbers, including both ends. Written like `1..240`, or just `7` for a single
+/// frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameRange {
+    first: usize,
+    last: usize,
+}
+
+impl FrameRange {
+    /// Creates a range of frames. The ends may be given in any order.
+    pub fn new(a: usize, b: usize) -> Self {
+        Self {
+            first: a.min(b),
+            last: a.max(b),
+        }
+    }
+
+    pub fn first(&self) -> usize {
+        self.first
+    }
+
+    pub fn last(&self) -> usize {
+        self.last
+    }
+
+    pub fn frames(&self) -> RangeInclusive<usize> {
+        self.first..=self.last
+    }
+}
+
+impl FromStr for FrameRange {
+    type Err = ErrFrameArgs;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ErrFrameArgs::InvalidRange(s.to_string());
+        let frame = |n: &str| n.trim().parse::<usize>().map_err(|_| invalid());
+        let Some((a, b)) = s.split_once("..") else {
+            let n = frame(s)?;
+            return Ok(Self::new(n, n));
+        };
+        let (first, last) = (frame(a)?, frame(b.strip_prefix('=').unwrap_or(b))?);
+        if last < first {
+            return Err(invalid());
+        }
+        Ok(Self::new(first, last))
+    }
+}
+
+/// How to render an animation, as given on the command line with `--frames 1..240 --jobs 4`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameArgs {
+    pub frames: FrameRange,
+    /// How many frames are rendered at once.
+    pub jobs: usize,
+}
+
+impl Default for FrameArgs {
+    fn default() -> Self {
+        Self {
+            frames: FrameRange::new(1, 1),
+            jobs: 1,
+        }
+    }
+}
+
+impl FrameArgs {
+    /// Picks the frame options out of some command line arguments, skipping over any others.
+    /// Without `--frames` only frame one is rendered, and without `--jobs` one frame is rendered
+    /// at a time.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, ErrFrameArgs> {
+        let mut res = Self::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if arg != "--frames" && arg != "--jobs" {
+                continue;
+            }
+            let value = args
+                .next()
+                .ok_or_else(|| ErrFrameArgs::MissingValue(arg.clone()))?;
+            if arg == "--frames" {
+                res.frames = value.parse()?;
+            } else {
+                res.jobs = match value.parse() {
+                    Ok(n) if n > 0 => n,
+                    _ => return Err(ErrFrameArgs::InvalidJobs(value)),
+                };
+            }
+        }
+        Ok(res)
+    }
+}
+
+/// Names the file for one frame of an animation, e.g. frame 7 of `out.ppm` is `out_0007.ppm`.
+pub fn frame_filepath(path: &str, frame: usize) -> String {
+    let p = Path::new(path);
+    let stem = p.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+    let name = match p.extension().and_then(|s| s.to_str()) {
+        Some(ext) => format!("{stem}_{frame:04}.{ext}"),
+        None => format!("{stem}_{frame:04}"),
+    };
+    match p.parent() {
+        Some(par) => par.join(name).to_string_lossy().into_owned(),
+        None => name,
+    }
+}
+
+/// Finds the frames in a range which don't have a file yet, so that an interrupted render can
+/// pick up where it left off.
+pub fn missing_frames<T>(frames: FrameRange, path: &str, exists_fn: T) -> Vec<usize>
+where
+    T: Fn(&str) -> bool,
+{
+    frames
+        .frames()
+        .filter(|&f| !exists_fn(&frame_filepath(path, f)))
+        .collect()
+}
+
+/// Renders some frames of an animation, `jobs` at a time, and writes each as a PPM file named by
+/// [`frame_filepath`]. Each render may be parallel itself, like [`Camera::render`], in which case
+/// the frames share the threads. Returns the paths written, in the order the frames were given.
+///
+/// If a frame can't be written, no more frames are started, and the first error is returned once
+/// the frames already underway are done.
+///
+/// [`Camera::render`]: crate::camera::Camera::render
+pub fn render_frames(
+    frames: &[usize],
+    jobs: usize,
+    path: &str,
+    render: impl Fn(usize) -> Canvas + Sync,
+) -> io::Result<Vec<String>> {
+    let next = AtomicUsize::new(0);
+    let written = Mutex::new(vec![]);
+    let failed: Mutex<Option<io::Error>> = Mutex::new(None);
+    thread::scope(|s| {
+        for _ in 0..jobs.clamp(1, frames.len().max(1)) {
+            s.spawn(|| loop {
+                if failed.lock().unwrap().is_some() {
+                    break;
+                }
+                let i = next.fetch_add(1, Ordering::SeqCst);
+                let Some(&frame) = frames.get(i) else {
+                    break;
+                };
+                let p = frame_filepath(path, frame);
+                match fs::write(&p, render(frame).to_ppm()) {
+                    Ok(()) => written.lock().unwrap().push((i, p)),
+                    Err(e) => {
+                        failed.lock().unwrap().get_or_insert(e);
+                    }
+                }
+            });
+        }
+    });
+    if let Some(e) = failed.into_inner().unwrap() {
+        return Err(e);
+    }
+    let mut written = written.into_inner().unwrap();
+    written.sort();
+    Ok(written.into_iter().map(|(_, p)| p).collect())
+}
+
+/// Blends two frames of the same size, from all of `a` when `t` is zero to all of `b` when `t` is
+/// one. This is a plain cross fade, so anything moving between the frames shows up twice, faded,
+/// rather than in between; good enough for a quick preview, not for a final render.
+pub fn blend_frames(a: &Canvas, b: &Canvas, t: f64) -> Canvas {
+    assert!(
+        a.width() == b.width() && a.height() == b.height(),
+        "frames to blend should be the same size"
+    );
+    let mut res = a.clone();
+    for (px, other) in res.pixels_mut().iter_mut().zip(b.pixels()) {
+        *px = px.lerp(*other, t);
+    }
+    res
+}
+
+/// Fills in every frame of a range from a few rendered keyframes, each given with its frame
+/// number. Frames between two keyframes blend them by how close each one is, while frames before
+/// the first keyframe or after the last just repeat it. Gives nothing if there are no keyframes.
+pub fn interpolate_frames(keys: &[(usize, Canvas)], frames: FrameRange) -> Vec<Canvas> {
+    let mut keys: Vec<&(usize, Canvas)> = keys.iter().collect();
+    keys.sort_by_key(|(frame, _)| *frame);
+    let (Some(first), Some(last)) = (keys.first(), keys.last()) else {
+        return vec![];
+    };
+    frames
+        .frames()
+        .map(|f| {
+            let next = keys.partition_point(|(frame, _)| *frame < f);
+            if next == 0 {
+                return first.1.clone();
+            }
+            if next == keys.len() {
+                return last.1.clone();
+            }
+            let ((a, before), (b, after)) = (keys[next - 1], keys[next]);
+            if *b == f {
+                return after.clone();
+            }
+            blend_frames(before, after, (f - a) as f64 / (b - a) as f64)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        blend_frames, frame_filepath, interpolate_frames, missing_frames, render_frames,
+        ErrFrameArgs, FrameArgs, FrameRange,
+    };
+    use crate::canvas::Canvas;
+    use crate::color::Color;
+    use crate::{assert_f64_eq, EPSILON};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn parsing_frame_ranges() {
+        assert_eq!("1..240".parse(), Ok(FrameRange::new(1, 240)));
+        assert_eq!("1..=240".parse(), Ok(FrameRange::new(1, 240)));
+        assert_eq!("7".parse(), Ok(FrameRange::new(7, 7)));
+        assert_eq!(
+            "9..2".parse::<FrameRange>(),
+            Err(ErrFrameArgs::InvalidRange("9..2".to_string()))
+        );
+        assert!("1..x".parse::<FrameRange>().is_err());
+        assert_eq!(
+            FrameRange::new(3, 5).frames().collect::<Vec<_>>(),
+            [3, 4, 5]
+        );
+    }
+
+    #[test]
+    fn parsing_frame_args() {
+        let got = FrameArgs::parse(args("scene.yml --frames 1..240 -o out.ppm --jobs 4")).unwrap();
+        assert_eq!(got.frames, FrameRange::new(1, 240));
+        assert_eq!(got.jobs, 4);
+        assert_eq!(
+            FrameArgs::parse(args("scene.yml")),
+            Ok(FrameArgs::default())
+        );
+        assert_eq!(
+            FrameArgs::parse(args("--jobs 0")),
+            Err(ErrFrameArgs::InvalidJobs("0".to_string()))
+        );
+        assert_eq!(
+            FrameArgs::parse(args("--frames")),
+            Err(ErrFrameArgs::MissingValue("--frames".to_string()))
+        );
+    }
+
+    #[test]
+    fn naming_and_resuming_frames() {
+        assert_eq!(frame_filepath("out.ppm", 7), "out_0007.ppm");
+        assert_eq!(
+            frame_filepath("renders/out.ppm", 12345),
+            "renders/out_12345.ppm"
+        );
+        assert_eq!(frame_filepath("out", 1), "out_0001");
+
+        let exists = |p: &str| matches!(p, "out_0001.ppm" | "out_0002.ppm" | "out_0004.ppm");
+        assert_eq!(
+            missing_frames(FrameRange::new(1, 5), "out.ppm", exists),
+            [3, 5]
+        );
+    }
+
+    #[test]
+    fn rendering_frames_a_few_at_a_time() {
+        let dir = std::env::temp_dir().join("toytracer-frames");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("anim.ppm").to_string_lossy().into_owned();
+
+        let (running, most) = (AtomicUsize::new(0), AtomicUsize::new(0));
+        let written = render_frames(&[4, 2, 9, 1, 3], 2, &path, |frame| {
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            most.fetch_max(now, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(20));
+            running.fetch_sub(1, Ordering::SeqCst);
+            let mut c = Canvas::new(1, 1);
+            c.write_to(0, 0, Color::new(frame as f64 / 10.0, 0.0, 0.0));
+            c
+        })
+        .unwrap();
+        assert_eq!(most.load(Ordering::SeqCst), 2);
+        assert_eq!(written.len(), 5);
+        assert_eq!(written[0], frame_filepath(&path, 4));
+        let ppm = std::fs::read_to_string(&written[2]).unwrap();
+        assert_eq!(ppm, {
+            let mut c = Canvas::new(1, 1);
+            c.write_to(0, 0, Color::new(0.9, 0.0, 0.0));
+            c.to_ppm()
+        });
+
+        let bad = dir.join("missing").join("anim.ppm");
+        let got = render_frames(&[1, 2], 2, &bad.to_string_lossy(), |_| Canvas::new(1, 1));
+        assert!(got.is_err());
+    }
+
+    #[test]
+    fn filling_in_frames_between_keyframes() {
+        let gray = |v: f64| {
+            let mut c = Canvas::new(2, 1);
+            c.write_to(0, 0, Color::new(v, v, v));
+            c.write_to(1, 0, Color::new(v, 0.0, 0.0));
+            c
+        };
+        let half = blend_frames(&gray(0.0), &gray(1.0), 0.5);
+        assert_eq!(half.pixel_at(0, 0), Color::new(0.5, 0.5, 0.5));
+        assert_eq!(half.pixel_at(1, 0), Color::new(0.5, 0.0, 0.0));
+
+        // Keyframes at 3 and 7, given out of order.
+        let keys = vec![(7, gray(1.0)), (3, gray(0.2))];
+        let got: Vec<f64> = interpolate_frames(&keys, FrameRange::new(1, 8))
+            .iter()
+            .map(|c| c.pixel_at(0, 0).r())
+            .collect();
+        let want = [0.2, 0.2, 0.2, 0.4, 0.6, 0.8, 1.0, 1.0];
+        assert_eq!(got.len(), 8);
+        for (g, w) in got.into_iter().zip(want) {
+            assert_f64_eq!(g, w, EPSILON);
+        }
+        assert!(interpolate_frames(&[], FrameRange::new(1, 8)).is_empty());
+    }
+}
@@ -0,0 +1,135 @@
+use crate::bounds::Bounds;
+use crate::color::Color;
+use crate::light::{Material, PointLight};
+use crate::patterns::{Checkers, Stripe};
+use crate::rng::Rng;
+use crate::shapes::{Object, Plane, Sphere};
+use crate::transform::Tr;
+use crate::tuple::Point;
+use crate::world::World;
+use std::sync::Arc;
+
+/// The smallest and largest radius of a randomly generated sphere.
+const RADII: (f64, f64) = (0.1, 0.4);
+/// How many times to try placing a sphere before giving up on it.
+const MAX_PLACEMENT_TRIES: usize = 32;
+
+/// Generates a world of spheres scattered over a floor, a la the cover of "Ray Tracing in One
+/// Weekend". The floor sits at the bottom of the bounds, and every sphere rests on it without
+/// overlapping any other. The same seed always gives the same world.
+///
+/// Fewer than `n` spheres may be placed if the bounds are too crowded to fit them all.
+pub fn random_spheres(seed: u64, n: usize, bounds: Bounds) -> World {
+    let mut rng = Rng::new(seed);
+    let (min, max) = (bounds.min(), bounds.max());
+    let floor_y = min.y();
+
+    let mut placed: Vec<(Point, f64)> = Vec::with_capacity(n);
+    for _ in 0..n {
+        for _ in 0..MAX_PLACEMENT_TRIES {
+            let r = rng.range(RADII.0, RADII.1).min(bounds.size().y() / 2.0);
+            let center = Point::new(
+                rng.range(min.x() + r, (max.x() - r).max(min.x() + r)),
+                floor_y + r,
+                rng.range(min.z() + r, (max.z() - r).max(min.z() + r)),
+            );
+            let overlaps = placed
+                .iter()
+                .any(|(c, cr)| (*c - center).magnitude() < r + cr);
+            if !overlaps {
+                placed.push((center, r));
+                break;
+            }
+        }
+    }
+
+    let floor = Plane::default()
+        .with_transform(Tr::new().translate(0.0, floor_y, 0.0))
+        .with_material(
+            Material::default()
+                .with_pattern(Arc::new(Checkers::new(
+                    Color::new(0.35, 0.35, 0.35),
+                    Color::new(0.65, 0.65, 0.65),
+                )))
+                .with_specular(0.0),
+        )
+        .as_object();
+
+    let mut objects: Vec<Object> = vec![floor];
+    for (c, r) in placed {
+        let sphere = Sphere::default()
+            .with_transform(Tr::new().scale(r, r, r).translate(c.x(), c.y(), c.z()))
+            .with_material(random_material(&mut rng));
+        objects.push(sphere.as_object());
+    }
+
+    let light = PointLight::new(
+        Point::new(min.x(), max.y() + bounds.size().y().max(1.0) * 4.0, min.z()),
+        Color::white(),
+    );
+    World::new().with_light(light).with_objects(objects)
+}
+
+/// Picks a material: mostly matte, with some metal, glass, and striped spheres thrown in.
+fn random_material(rng: &mut Rng) -> Material {
+    let color = Color::new(rng.next_f64(), rng.next_f64(), rng.next_f64());
+    match rng.next_f64() {
+        x if x < 0.6 => Material::default().with_color(color).with_specular(0.2),
+        x if x < 0.8 => Material::default()
+            .with_color(color * 0.3)
+            .with_reflective(rng.range(0.5, 0.9))
+            .with_shininess(300.0),
+        x if x < 0.9 => Material::default()
+            .with_color(Color::black())
+            .with_ambient(0.0)
+            .with_diffuse(0.1)
+            .with_reflective(0.9)
+            .with_transparency(0.9)
+            .with_refractive_index(1.5)
+            .with_shininess(300.0),
+        _ => {
+            let stripes = Stripe::new(color, Color::white()).with_transform(
+                Tr::new()
+                    .scale(0.2, 0.2, 0.2)
+                    .rotate_z(rng.range(0.0, std::f64::consts::PI)),
+            );
+            Material::default().with_pattern(Arc::new(stripes))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::random_spheres;
+    use crate::bounds::Bounds;
+    use crate::p;
+
+    #[test]
+    fn same_seed_same_world() {
+        let bounds = Bounds::new(p!(-5, 0, -5), p!(5, 2, 5));
+        let a = random_spheres(1, 20, bounds);
+        let b = random_spheres(1, 20, bounds);
+        let c = random_spheres(2, 20, bounds);
+        assert_eq!(a.objects, b.objects);
+        assert_ne!(a.objects, c.objects);
+    }
+
+    #[test]
+    fn spheres_rest_inside_the_bounds() {
+        let bounds = Bounds::new(p!(-5, 1, -5), p!(5, 3, 5));
+        let w = random_spheres(3, 30, bounds);
+        // A floor, plus every sphere.
+        assert_eq!(w.objects.len(), 31);
+        for obj in &w.objects[1..] {
+            let center = obj.transform().matrix() * p!(0, 0, 0);
+            assert!(bounds.contains(center));
+        }
+    }
+
+    #[test]
+    fn crowded_bounds_fit_fewer_spheres() {
+        let bounds = Bounds::new(p!(0, 0, 0), p!(1, 1, 1));
+        let w = random_spheres(4, 100, bounds);
+        assert!(w.objects.len() < 101);
+    }
+}
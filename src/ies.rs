@@ -0,0 +1,297 @@
+use crate::tuple::Vector;
+use std::fmt::Display;
+use std::path::Path;
+use std::str::FromStr;
+
+/// The angular distribution of light from a real fixture, read from an IES (LM-63) photometric
+/// file. Only type C photometry is supported, which is what practically every architectural
+/// fixture uses.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IesProfile {
+    /// Vertical angles in degrees, where 0 points straight down and 180 straight up.
+    vertical: Vec<f64>,
+    /// Horizontal angles in degrees, going around the fixture.
+    horizontal: Vec<f64>,
+    /// Candela values, one row of vertical samples for every horizontal angle.
+    candela: Vec<Vec<f64>>,
+    max_candela: f64,
+}
+
+/// Represents some problem reading an IES file.
+#[derive(Debug, PartialEq)]
+pub enum ErrParseIes {
+    /// When the file cannot be read at all.
+    Io(String),
+    /// When there is no `TILT=` line ending the header.
+    MissingTilt,
+    /// When the tilt data lives in another file, which we don't follow.
+    UnsupportedTilt(String),
+    UnsupportedPhotometricType(u32),
+    InvalidNumber(String),
+    /// When the file ends before all the data it promises.
+    Truncated,
+    /// When the angles are not in increasing order, or there are none.
+    InvalidAngles,
+}
+
+impl Display for ErrParseIes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use ErrParseIes::*;
+        match self {
+            Io(msg) => write!(f, "Could not read IES file; {msg}"),
+            MissingTilt => write!(f, "IES file has no TILT line"),
+            UnsupportedTilt(t) => write!(f, "Unsupported IES tilt; tilt={t}"),
+            UnsupportedPhotometricType(t) => {
+                write!(f, "Unsupported IES photometric type; type={t}")
+            }
+            InvalidNumber(n) => write!(f, "Invalid number in IES file; n={n}"),
+            Truncated => write!(f, "IES file is truncated"),
+            InvalidAngles => write!(f, "IES angles must be increasing"),
+        }
+    }
+}
+
+impl FromStr for IesProfile {
+    type Err = ErrParseIes;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.lines();
+        // Everything up to the TILT line is free-form keywords which we don't need.
+        let tilt = lines
+            .by_ref()
+            .find_map(|l| l.trim().strip_prefix("TILT="))
+            .ok_or(ErrParseIes::MissingTilt)?
+            .trim();
+        // The rest of the file is just numbers, separated by whitespace or commas.
+        let rest: Vec<&str> = lines
+            .flat_map(|l| l.split(|c: char| c.is_whitespace() || c == ','))
+            .filter(|t| !t.is_empty())
+            .collect();
+        let mut nums = rest.iter().map(|t| {
+            t.parse::<f64>()
+                .map_err(|_| ErrParseIes::InvalidNumber(t.to_string()))
+        });
+        let mut next = || nums.next().unwrap_or(Err(ErrParseIes::Truncated));
+
+        match tilt {
+            "NONE" => {}
+            "INCLUDE" => {
+                // Lamp to luminaire geometry, then pairs of tilt angles and factors. Tilt only
+                // matters for lamps which are not mounted upright, so we skip it.
+                next()?;
+                let n = next()? as usize;
+                // Huge counts saturate, and no file could hold that many numbers anyway.
+                let pairs = n.checked_mul(2).ok_or(ErrParseIes::Truncated)?;
+                for _ in 0..pairs {
+                    next()?;
+                }
+            }
+            t => return Err(ErrParseIes::UnsupportedTilt(t.to_string())),
+        }
+
+        let _lamps = next()?;
+        let _lumens = next()?;
+        let multiplier = next()?;
+        let n_vertical = next()? as usize;
+        let n_horizontal = next()? as usize;
+        let photometric_type = next()? as u32;
+        // Units and the luminous opening's width, length and height.
+        for _ in 0..4 {
+            next()?;
+        }
+        let ballast = next()?;
+        // The ballast-lamp factor and input watts.
+        next()?;
+        next()?;
+        if photometric_type != 1 {
+            return Err(ErrParseIes::UnsupportedPhotometricType(photometric_type));
+        }
+
+        let vertical = (0..n_vertical)
+            .map(|_| next())
+            .collect::<Result<Vec<_>, _>>()?;
+        let horizontal = (0..n_horizontal)
+            .map(|_| next())
+            .collect::<Result<Vec<_>, _>>()?;
+        let increasing = |xs: &[f64]| !xs.is_empty() && xs.windows(2).all(|w| w[0] < w[1]);
+        if !increasing(&vertical) || !increasing(&horizontal) {
+            return Err(ErrParseIes::InvalidAngles);
+        }
+        let candela = (0..n_horizontal)
+            .map(|_| {
+                (0..n_vertical)
+                    .map(|_| next().map(|c| c * multiplier * ballast))
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let max_candela = candela.iter().flatten().copied().fold(0.0, f64::max);
+
+        Ok(Self {
+            vertical,
+            horizontal,
+            candela,
+            max_candela,
+        })
+    }
+}
+
+impl IesProfile {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ErrParseIes> {
+        std::fs::read_to_string(path)
+            .map_err(|e| ErrParseIes::Io(e.to_string()))?
+            .parse()
+    }
+
+    pub fn max_candela(&self) -> f64 {
+        self.max_candela
+    }
+
+    /// Finds the candela value at some vertical and horizontal angle, in degrees, interpolating
+    /// between the measured angles. Directions outside the measured vertical range get no light.
+    pub fn candela(&self, vertical: f64, horizontal: f64) -> f64 {
+        let (first, last) = (self.vertical[0], self.vertical[self.vertical.len() - 1]);
+        if vertical < first || vertical > last {
+            return 0.0;
+        }
+        let h = self.fold_horizontal(horizontal);
+        let (h0, h1, ht) = bracket(&self.horizontal, h);
+        let (v0, v1, vt) = bracket(&self.vertical, vertical);
+        let at = |h: usize| {
+            let row = &self.candela[h];
+            row[v0] + (row[v1] - row[v0]) * vt
+        };
+        at(h0) + (at(h1) - at(h0)) * ht
+    }
+
+    /// Maps any horizontal angle into the range the file actually has data for. Files only store
+    /// as much of the distribution as its symmetry needs.
+    fn fold_horizontal(&self, h: f64) -> f64 {
+        let h = h.rem_euclid(360.0);
+        let last = self.horizontal[self.horizontal.len() - 1];
+        if last <= 90.0 {
+            // Symmetric in every quadrant.
+            let h = if h > 180.0 { 360.0 - h } else { h };
+            if h > 90.0 {
+                180.0 - h
+            } else {
+                h
+            }
+        } else if last <= 180.0 && h > 180.0 {
+            // Symmetric about the 0-180 plane.
+            360.0 - h
+        } else {
+            h
+        }
+    }
+
+    /// Finds how bright light leaving in some direction is, relative to the brightest direction.
+    /// The direction is relative to the fixture, which points down the negative y axis, with
+    /// horizontal angle 0 along the positive x axis and 90 along the positive z axis.
+    pub fn relative_intensity(&self, direction: Vector) -> f64 {
+        if self.max_candela == 0.0 {
+            return 0.0;
+        }
+        let d = direction.normalize();
+        let vertical = (-d.y()).clamp(-1.0, 1.0).acos().to_degrees();
+        let horizontal = d.z().atan2(d.x()).to_degrees();
+        self.candela(vertical, horizontal) / self.max_candela
+    }
+}
+
+/// Finds the two entries of a sorted list around some value, and how far between them it is.
+fn bracket(xs: &[f64], x: f64) -> (usize, usize, f64) {
+    let i = xs.partition_point(|&a| a <= x);
+    if i == 0 {
+        return (0, 0, 0.0);
+    }
+    if i == xs.len() {
+        return (i - 1, i - 1, 0.0);
+    }
+    let (a, b) = (xs[i - 1], xs[i]);
+    (i - 1, i, (x - a) / (b - a))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ErrParseIes, IesProfile};
+    use crate::{assert_f64_eq, v};
+
+    /// A downlight which is symmetric all around, and dims towards its sides.
+    const DOWNLIGHT: &str = "IESNA:LM-63-2002
+[TEST] toy downlight
+[MANUFAC] nobody
+TILT=NONE
+1 1000 1 3 1 1 1 0.1 0.1 0
+1 1 50
+0 45 90
+0
+100 50 0
+";
+
+    #[test]
+    fn parsing_a_profile() {
+        let p: IesProfile = DOWNLIGHT.parse().unwrap();
+        assert_eq!(p.max_candela(), 100.0);
+        assert_eq!(p.candela(0.0, 0.0), 100.0);
+        assert_eq!(p.candela(22.5, 123.0), 75.0);
+        assert_eq!(p.candela(90.0, 0.0), 0.0);
+        assert_eq!(p.candela(120.0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn relative_intensity_by_direction() {
+        let p: IesProfile = DOWNLIGHT.parse().unwrap();
+        assert_f64_eq!(p.relative_intensity(v!(0, -1, 0)), 1.0);
+        assert_f64_eq!(p.relative_intensity(v!(1, -1, 0)), 0.5);
+        assert_f64_eq!(p.relative_intensity(v!(0, -1, -1)), 0.5);
+        assert_f64_eq!(p.relative_intensity(v!(0, 1, 0)), 0.0);
+    }
+
+    #[test]
+    fn horizontal_symmetry() {
+        // Quadrant symmetric: only 0 to 90 degrees are stored.
+        let p: IesProfile = "TILT=NONE
+1 1000 2 2 2 1 1 0 0 0
+1 1 10
+0 90
+0 90
+10 0
+20 0"
+            .parse()
+            .unwrap();
+        assert_eq!(p.max_candela(), 40.0);
+        assert_eq!(p.candela(0.0, 45.0), 30.0);
+        assert_eq!(p.candela(0.0, 135.0), 30.0);
+        assert_eq!(p.candela(0.0, 180.0), 20.0);
+        assert_eq!(p.candela(0.0, 270.0), 40.0);
+        assert_eq!(p.candela(0.0, -90.0), 40.0);
+    }
+
+    #[test]
+    fn reading_bad_profiles() {
+        assert_eq!(
+            "IESNA91\n1 2 3".parse::<IesProfile>(),
+            Err(ErrParseIes::MissingTilt)
+        );
+        assert_eq!(
+            "TILT=lamp.tlt\n".parse::<IesProfile>(),
+            Err(ErrParseIes::UnsupportedTilt("lamp.tlt".to_string()))
+        );
+        assert_eq!(
+            "TILT=NONE\n1 1000 1 3 1 1".parse::<IesProfile>(),
+            Err(ErrParseIes::Truncated)
+        );
+        assert_eq!(
+            "TILT=NONE\n1 1000 1 1 1 2 1 0 0 0 1 1 10 0 0 5".parse::<IesProfile>(),
+            Err(ErrParseIes::UnsupportedPhotometricType(2))
+        );
+        assert_eq!(
+            "TILT=NONE\n1 1000 x".parse::<IesProfile>(),
+            Err(ErrParseIes::InvalidNumber("x".to_string()))
+        );
+        assert_eq!(
+            "TILT=INCLUDE\n1 1e300 0 0".parse::<IesProfile>(),
+            Err(ErrParseIes::Truncated)
+        );
+    }
+}
@@ -1,30 +1,56 @@
+#![allow(incomplete_features)]
 #![feature(generic_const_exprs)]
 use std::path::Path;
 use std::sync::atomic::{AtomicUsize, Ordering};
 
+pub mod accumulator;
+pub mod arena;
+pub mod bake;
+pub mod bezier;
+pub mod bounds;
+pub mod cache;
 pub mod camera;
 pub mod canvas;
 pub mod color;
+pub mod decal;
+pub mod deep;
+pub mod frames;
+pub mod generate;
+pub mod ies;
 pub mod light;
 pub mod matrix;
+pub mod matte;
+pub mod mesh;
+pub mod motion;
+pub mod obj;
 pub mod patterns;
+pub mod post;
+pub mod prefab;
 pub mod ray;
+pub mod rng;
+pub mod roots;
+pub mod session;
+pub mod settings;
 pub mod shapes;
+pub mod sheet;
+pub mod stl;
+pub mod tiles;
 pub mod transform;
 pub mod tuple;
+pub mod units;
 pub mod world;
 pub mod yaml;
 
 /// A much more lenient epsilon for convenience.
-const EPSILON: f64 = 1e-5; // TODO make this configurable
+const EPSILON: f64 = 1e-5; // the default bias, see RenderSettings::with_bias
 /// The maximum number of times a ray is allowed to be reflected or refracted. Once the limit is
 /// reached, it is assumed to end at a white pixel.
-const MAX_BOUNCE: u16 = 5;
+pub const MAX_BOUNCE: u16 = 5;
 
 #[macro_export]
 macro_rules! assert_f64_eq {
     ($a:expr, $b:expr) => {
-        assert!(($a - $b).abs() < 100.0 * std::f64::EPSILON);
+        assert!(($a - $b).abs() < 100.0 * f64::EPSILON);
     };
 
     ($a:expr, $b:expr, $eps:expr) => {
@@ -53,7 +79,7 @@ where
         None => "".to_string(),
         Some(p) => p.to_str().unwrap().to_string(),
     };
-    if par.len() > 0 {
+    if !par.is_empty() {
         par.push('/');
     }
     let stem = path.file_stem().unwrap().to_str().unwrap();
@@ -70,19 +96,13 @@ where
 
 #[test]
 fn test_pad_filepath() {
-    let exists_fn = |filename: &str| match filename {
-        "foo.ppm" | "foo (1).ppm" | "foo (2).ppm" => true,
-        _ => false,
-    };
+    let exists_fn = |filename: &str| matches!(filename, "foo.ppm" | "foo (1).ppm" | "foo (2).ppm");
 
     let got = pad_filepath("foo.ppm", exists_fn);
     let want = "foo (3).ppm";
     assert_eq!(got, want);
 
-    let exists_fn = |filename: &str| match filename {
-        "foo/bar.ppm" | "foo/bar (1).ppm" => true,
-        _ => false,
-    };
+    let exists_fn = |filename: &str| matches!(filename, "foo/bar.ppm" | "foo/bar (1).ppm");
 
     let got = pad_filepath("foo/bar.ppm", exists_fn);
     let want = "foo/bar (2).ppm";
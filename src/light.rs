@@ -1,26 +1,149 @@
+use crate::arena;
 use crate::color::Color;
+use crate::ies::IesProfile;
 use crate::patterns::{Graphic, Pattern};
-use crate::ray::{hit, IntersectionVals, Ray};
-use crate::shapes::Shape;
+use crate::ray::{IntersectionVals, Ray};
+use crate::rng::{point_seed, Rng};
+use crate::settings::RenderSettings;
+use crate::shapes::{Shape, Sphere};
+use crate::transform::Tr;
 use crate::tuple::{Point, Vector};
 use crate::world::World;
 use crate::yaml;
+use crate::EPSILON;
 use serde::Deserialize;
 use std::sync::Arc;
 
-#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[derive(Deserialize, Debug, Clone, PartialEq)]
 #[serde(from = "crate::yaml::PointLightRepr")]
 pub struct PointLight {
     position: Point,
     intensity: Color,
+    /// How the light's brightness varies by direction. Without one, the light shines evenly in
+    /// every direction.
+    profile: Option<Arc<IesProfile>>,
+    /// The cone a spotlight shines in. Without one, the light shines in every direction.
+    cone: Option<Cone>,
+}
+
+/// A point light which only shines within a cone, like a stage light or a flashlight. Inside
+/// the inner angle the light is at full strength, and it fades out smoothly towards the outer
+/// angle, past which there is no light at all. Angles are measured from the direction the light
+/// points in, in radians.
+///
+/// Spotlights are turned into point lights to be placed in a world, e.g.
+/// `world.with_light(spot.into())`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpotLight {
+    position: Point,
+    direction: Vector,
+    intensity: Color,
+    inner_angle: f64,
+    outer_angle: f64,
+}
+
+impl SpotLight {
+    /// Creates a spotlight with a cone which is at full strength up to 15° from its direction,
+    /// and fades out by 25°.
+    pub fn new(position: Point, direction: Vector, intensity: Color) -> Self {
+        Self {
+            position,
+            direction: direction.normalize(),
+            intensity,
+            inner_angle: 15f64.to_radians(),
+            outer_angle: 25f64.to_radians(),
+        }
+    }
+
+    /// Sets the angles the light starts fading at and fades out by. The outer angle is raised to
+    /// the inner one if need be, which gives a hard edge.
+    pub fn with_cone(mut self, inner_angle: f64, outer_angle: f64) -> Self {
+        self.inner_angle = inner_angle;
+        self.outer_angle = outer_angle.max(inner_angle);
+        self
+    }
+
+    pub fn position(&self) -> Point {
+        self.position
+    }
+
+    pub fn direction(&self) -> Vector {
+        self.direction
+    }
+
+    pub fn intensity(&self) -> Color {
+        self.intensity
+    }
+
+    pub fn inner_angle(&self) -> f64 {
+        self.inner_angle
+    }
+
+    pub fn outer_angle(&self) -> f64 {
+        self.outer_angle
+    }
+}
+
+impl From<SpotLight> for PointLight {
+    fn from(s: SpotLight) -> Self {
+        let mut light = PointLight::new(s.position, s.intensity);
+        light.cone = Some(Cone {
+            direction: s.direction,
+            cos_inner: s.inner_angle.cos(),
+            cos_outer: s.outer_angle.cos(),
+        });
+        light
+    }
+}
+
+/// The cone a spotlight shines in, kept as cosines so that they can be compared with dot
+/// products directly.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Cone {
+    direction: Vector,
+    cos_inner: f64,
+    cos_outer: f64,
+}
+
+impl Cone {
+    /// How much of the light goes in some direction, from 0 outside the cone to 1 inside its
+    /// inner angle, easing smoothly in between.
+    fn falloff(&self, direction: Vector) -> f64 {
+        let cos = direction.normalize().dot(self.direction);
+        if self.cos_inner <= self.cos_outer {
+            return if cos >= self.cos_outer { 1.0 } else { 0.0 };
+        }
+        let t = ((cos - self.cos_outer) / (self.cos_inner - self.cos_outer)).clamp(0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
+    }
 }
 
 impl From<yaml::PointLightRepr> for PointLight {
     fn from(r: yaml::PointLightRepr) -> Self {
-        Self {
-            position: r.at.into(),
-            intensity: r.color.into(),
+        Self::new(r.at.into(), r.color.into())
+    }
+}
+
+/// A one line summary, like `light 255 255 255 at (-10, 10, -10)`.
+impl std::fmt::Display for PointLight {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let p = self.position;
+        write!(
+            f,
+            "light {} at ({}, {}, {})",
+            self.intensity,
+            p.x(),
+            p.y(),
+            p.z()
+        )?;
+        if self.profile.is_some() {
+            write!(f, " with a profile")?;
         }
+        if let Some(cone) = self.cone {
+            let d = cone.direction;
+            write!(f, " pointing ({}, {}, {})", d.x(), d.y(), d.z())?;
+        }
+        Ok(())
     }
 }
 
@@ -29,6 +152,64 @@ impl PointLight {
         Self {
             position,
             intensity,
+            profile: None,
+            cone: None,
+        }
+    }
+
+    pub fn position(&self) -> Point {
+        self.position
+    }
+
+    pub fn intensity(&self) -> Color {
+        self.intensity
+    }
+
+    /// A small glowing sphere at this light, since the light itself is a point which nothing can
+    /// see. The bulb is only its glow, and casts no shadows, so putting it in a scene changes
+    /// nothing except what shows up in reflections and to the camera.
+    pub fn bulb(&self, radius: f64) -> Sphere {
+        let p = self.position;
+        Sphere::default()
+            .with_transform(Tr::new().scale_prop(radius).translate(p.x(), p.y(), p.z()))
+            .with_material(
+                Material::default()
+                    .with_color(Color::black())
+                    .with_ambient(0.0)
+                    .with_diffuse(0.0)
+                    .with_specular(0.0)
+                    .with_casts_shadow(false)
+                    .with_emissive(self.intensity),
+            )
+    }
+
+    /// Shapes this light like a real fixture. The light's intensity becomes the intensity of the
+    /// fixture's brightest direction, with the fixture pointing straight down.
+    pub fn with_profile(mut self, profile: Arc<IesProfile>) -> Self {
+        self.profile = Some(profile);
+        self
+    }
+
+    pub fn profile(&self) -> Option<&IesProfile> {
+        self.profile.as_deref()
+    }
+
+    /// Whether this light only shines within a cone; see [`SpotLight`].
+    pub fn is_spot(&self) -> bool {
+        self.cone.is_some()
+    }
+
+    /// The intensity of the light reaching some point, taking the light's profile and cone into
+    /// account.
+    pub fn intensity_towards(&self, p: Point) -> Color {
+        let d = p - self.position;
+        let intensity = match &self.profile {
+            None => self.intensity,
+            Some(profile) => self.intensity * profile.relative_intensity(d),
+        };
+        match &self.cone {
+            None => intensity,
+            Some(cone) => intensity * cone.falloff(d),
         }
     }
 }
@@ -43,8 +224,36 @@ pub struct Material {
     reflective: f64,
     transparency: f64,
     refractive_index: f64,
+    /// Whether objects with this material block light from reaching whatever is behind them.
+    casts_shadow: bool,
+    /// Whether rays from the camera see objects with this material. Hidden objects still show up
+    /// in reflections and through glass, and still cast shadows if they are set to, so a glowing
+    /// bulb can light up the mirrors around it without sitting in the middle of the shot.
+    visible_to_camera: bool,
+    /// How blurry reflections off this material are. Zero gives a perfect mirror.
+    roughness: f64,
+    /// Overrides the number of samples taken when shading this material, so that hero objects can
+    /// get more than the rest of the scene and background props fewer.
+    samples: Option<u32>,
+    /// Overrides how far shading points are nudged off surfaces with this material, for shapes
+    /// too large, too thin or too flat for the bias in [`RenderSettings`] to suit them.
+    bias: Option<f64>,
     /// The pattern on the material. This overrides the color, if it is not None.
     pattern: Graphic,
+    /// A layer over this material, like the lacquer over car paint. Coats may themselves be
+    /// coated.
+    coat: Option<Box<Material>>,
+    /// When set, diffuse light is quantized into this many flat bands and highlights get a hard
+    /// edge, for a cartoonish look.
+    toon_bands: Option<u32>,
+    /// Cuts holes in the surface wherever this pattern is darker than middle grey, like the gaps
+    /// between the leaves on a textured card. Rays of every kind pass straight through the holes.
+    cutout: Graphic,
+    /// Turns the normals of objects with this material inside out, so that a sphere can enclose
+    /// a room or a sky with its surface facing inwards.
+    flip_normals: bool,
+    /// Light given off by the surface itself, regardless of what falls on it.
+    emissive: Color,
 }
 
 impl Default for Material {
@@ -58,7 +267,17 @@ impl Default for Material {
             reflective: 0.0,
             transparency: 0.0,
             refractive_index: 1.0,
+            casts_shadow: true,
+            visible_to_camera: true,
+            roughness: 0.0,
+            samples: None,
+            bias: None,
             pattern: None,
+            coat: None,
+            toon_bands: None,
+            cutout: None,
+            flip_normals: false,
+            emissive: Color::black(),
         }
     }
 }
@@ -113,6 +332,140 @@ impl Material {
         self
     }
 
+    pub fn with_casts_shadow(mut self, casts_shadow: bool) -> Self {
+        self.casts_shadow = casts_shadow;
+        self
+    }
+
+    pub fn with_visible_to_camera(mut self, visible: bool) -> Self {
+        self.visible_to_camera = visible;
+        self
+    }
+
+    /// Sets how blurry reflections are. Negative values are treated as zero.
+    pub fn with_roughness(mut self, roughness: f64) -> Self {
+        self.roughness = roughness.max(0.0);
+        self
+    }
+
+    /// Sets the number of samples this material takes, instead of the defaults in
+    /// [`RenderSettings`]. At least one sample is always taken.
+    pub fn with_samples(mut self, samples: u32) -> Self {
+        self.samples = Some(samples.max(1));
+        self
+    }
+
+    /// Sets how far shading points are nudged off the surface, in world units, instead of the
+    /// bias in [`RenderSettings`]. This is used as it is, without being scaled by the object's
+    /// transformation. Larger values get rid of acne, while smaller ones close the gaps where
+    /// objects touch.
+    pub fn with_bias(mut self, bias: f64) -> Self {
+        self.bias = Some(bias);
+        self
+    }
+
+    pub fn bias(&self) -> Option<f64> {
+        self.bias
+    }
+
+    pub fn roughness(&self) -> f64 {
+        self.roughness
+    }
+
+    pub fn samples(&self) -> Option<u32> {
+        self.samples
+    }
+
+    /// The number of glossy reflection samples to actually take for this material, once its
+    /// override and the global quality are taken into account.
+    pub fn effective_samples(&self, settings: &RenderSettings) -> u32 {
+        settings.scale_samples(self.samples.unwrap_or(settings.glossy_samples()))
+    }
+
+    /// Layers another material over this one. Light which makes it through the coat, according
+    /// to the coat's transparency and how much it reflects at its refractive index, lights this
+    /// material; the rest lights the coat.
+    pub fn with_coat(mut self, coat: Material) -> Self {
+        self.coat = Some(Box::new(coat));
+        self
+    }
+
+    pub fn coat(&self) -> Option<&Material> {
+        self.coat.as_deref()
+    }
+
+    /// Shades this material in flat bands, like a cel animation, instead of smoothly. At least
+    /// one band is always used. See also [`RenderSettings::with_toon_bands`].
+    pub fn with_toon_bands(mut self, bands: u32) -> Self {
+        self.toon_bands = Some(bands.max(1));
+        self
+    }
+
+    pub fn toon_bands(&self) -> Option<u32> {
+        self.toon_bands
+    }
+
+    /// Cuts holes in this material wherever the pattern's luminance is below one half.
+    pub fn with_cutout(mut self, p: Arc<dyn Pattern>) -> Self {
+        self.cutout = Some(p);
+        self
+    }
+
+    pub fn cutout(&self) -> Option<&Arc<dyn Pattern>> {
+        self.cutout.as_ref()
+    }
+
+    /// Makes the normals of objects with this material point inwards instead of outwards. Rays
+    /// from inside such an object then see its outside, and rays from outside see its inside.
+    pub fn with_flip_normals(mut self, flip: bool) -> Self {
+        self.flip_normals = flip;
+        self
+    }
+
+    pub fn flip_normals(&self) -> bool {
+        self.flip_normals
+    }
+
+    /// Makes objects with this material glow with some color, so that they can be seen in the
+    /// dark and show up in reflections like a light would. The glow does not light up anything
+    /// else directly, but whatever reflects or refracts it picks it up.
+    pub fn with_emissive(mut self, c: Color) -> Self {
+        self.emissive = c;
+        self
+    }
+
+    pub fn emissive(&self) -> Color {
+        self.emissive
+    }
+
+    /// Whether objects with this material give off light of their own.
+    pub fn is_emissive(&self) -> bool {
+        self.emissive != Color::black()
+    }
+
+    /// Whether some point on an object falls in a hole of this material's cutout.
+    pub fn is_cut_out(&self, obj: &dyn Shape, p: Point) -> bool {
+        self.cutout
+            .as_ref()
+            .is_some_and(|pat| pat.color_on_object(obj, p).luminance() < 0.5)
+    }
+
+    /// The color of this material at some point on an object, taken from its pattern if it has
+    /// one.
+    pub fn color_on_object(&self, obj: &dyn Shape, p: Point) -> Color {
+        match &self.pattern {
+            None => self.color,
+            Some(pat) => pat.color_on_object(obj, p),
+        }
+    }
+
+    /// Replaces both the color and any pattern of this material with a single color.
+    pub fn with_flat_color(mut self, c: Color) -> Self {
+        self.color = c;
+        self.pattern = None;
+        self
+    }
+
     pub fn refractive_index(&self) -> f64 {
         self.refractive_index
     }
@@ -136,28 +489,157 @@ impl Material {
     pub fn reflective(&self) -> f64 {
         self.reflective
     }
+
+    pub fn casts_shadow(&self) -> bool {
+        self.casts_shadow
+    }
+
+    pub fn visible_to_camera(&self) -> bool {
+        self.visible_to_camera
+    }
+
+    /// The most light this material can give off, as a fraction of the light falling on it. This
+    /// is the sum of its ambient, diffuse, specular, reflective and transparency weights.
+    pub fn energy(&self) -> f64 {
+        self.ambient + self.diffuse + self.specular + self.reflective + self.transparency
+    }
+
+    /// Whether this material gives off no more light than falls on it. Materials which give off
+    /// more are not physically possible, and look like they glow.
+    pub fn conserves_energy(&self) -> bool {
+        self.energy() <= 1.0 + EPSILON
+    }
+}
+
+/// A one line summary of the weights which matter most, like `color 255 255 255, ambient 0.1,
+/// diffuse 0.9, specular 0.9`. Reflection, transparency and emission are only mentioned when
+/// there is some, and patterns only when there is one.
+impl std::fmt::Display for Material {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "color {}, ambient {}, diffuse {}, specular {}",
+            self.color, self.ambient, self.diffuse, self.specular
+        )?;
+        if self.pattern.is_some() {
+            write!(f, ", patterned")?;
+        }
+        if self.reflective > 0.0 {
+            write!(f, ", reflective {}", self.reflective)?;
+        }
+        if self.transparency > 0.0 {
+            write!(
+                f,
+                ", transparency {} (index {})",
+                self.transparency, self.refractive_index
+            )?;
+        }
+        if self.is_emissive() {
+            write!(f, ", emissive {}", self.emissive)?;
+        }
+        Ok(())
+    }
+}
+
+/// Everything [`lighting`] needs to know to shade a point.
+#[derive(Debug, Clone, Copy)]
+pub struct ShadingInput<'a> {
+    pub material: &'a Material,
+    /// The object being shaded, which patterns are mapped onto.
+    pub object: &'a dyn Shape,
+    pub light: &'a PointLight,
+    /// The point being shaded, in world space. This should be the `over_point`, nudged slightly
+    /// away from the surface.
+    pub point: Point,
+    /// Points from the surface towards the eye.
+    pub eyev: Vector,
+    pub normalv: Vector,
+    /// Whether the light is blocked from reaching the point.
+    pub in_shadow: bool,
+}
+
+impl<'a> ShadingInput<'a> {
+    /// Gathers the input for shading a point which is not in shadow.
+    pub fn new(
+        material: &'a Material,
+        object: &'a dyn Shape,
+        light: &'a PointLight,
+        point: Point,
+        eyev: Vector,
+        normalv: Vector,
+    ) -> Self {
+        Self {
+            material,
+            object,
+            light,
+            point,
+            eyev,
+            normalv,
+            in_shadow: false,
+        }
+    }
+
+    /// Gathers the input for shading some precomputed intersection, with the object's own
+    /// material.
+    pub fn from_comps(
+        comps: &'a IntersectionVals,
+        material: &'a Material,
+        light: &'a PointLight,
+    ) -> Self {
+        Self::new(
+            material,
+            &*comps.object,
+            light,
+            comps.over_point,
+            comps.eyev,
+            comps.normalv,
+        )
+    }
+
+    pub fn with_in_shadow(mut self, in_shadow: bool) -> Self {
+        self.in_shadow = in_shadow;
+        self
+    }
+
+    pub fn with_material(mut self, material: &'a Material) -> Self {
+        self.material = material;
+        self
+    }
 }
 
-/// Computes the appropriate color at some point. This is the main function responsible for
-/// figuring out the appropriate color for some pixel.
+/// Computes how much of a light some material reflects towards the eye. This is the main
+/// function responsible for figuring out the appropriate color for some pixel, and the entry
+/// point for anything which wants to shade with this crate's materials: an integrator only has to
+/// decide what the light, eye and shadows are, and this takes care of the rest.
 ///
-/// Note that the point passed to this function should be the `over_point`, nudged slightly away
-/// from the surface.
-pub fn lighting(
-    m: Material,
-    obj: &dyn Shape,
-    light: PointLight,
-    p: Point,
-    eyev: Vector,
-    normalv: Vector,
-    in_shadow: bool,
-) -> Color {
+/// This covers direct light only. Reflections and refractions are up to the caller, as
+/// [`reflected_color`] and [`refracted_color`] do for the [`World`].
+pub fn lighting(input: ShadingInput) -> Color {
+    let ShadingInput {
+        material: m,
+        object,
+        light,
+        point: p,
+        eyev,
+        normalv,
+        in_shadow,
+    } = input;
+    // A coated material splits the light between the coat and whatever is under it, so that the
+    // two layers together never give back more than either would alone.
+    if let Some(coat) = &m.coat {
+        let through = coat_transmittance(coat, eyev.dot(normalv));
+        let top = lighting(input.with_material(coat));
+        let base = Material {
+            coat: None,
+            ..m.clone()
+        };
+        let under = lighting(input.with_material(&base));
+        return top * (1.0 - through) + under * through;
+    }
     // Check if the material has a pattern. If there is a pattern, we'll derive the color from the
     // pattern instead of the material's default color.
-    let effective_color = match m.pattern {
-        None => m.color * light.intensity,
-        Some(pat) => pat.color_on_object(obj, p) * light.intensity,
-    };
+    let intensity = light.intensity_towards(p);
+    let effective_color = m.color_on_object(object, p) * intensity;
     let ambient = effective_color * m.ambient;
     // If the point is in shadow, then only the ambient contributes to its color.
     if in_shadow {
@@ -168,6 +650,10 @@ pub fn lighting(
     let (diffuse, specular) = if light_dot_normal < 0.0 {
         (Color::black(), Color::black())
     } else {
+        let light_dot_normal = match m.toon_bands {
+            Some(n) => toon_step(light_dot_normal, n),
+            None => light_dot_normal,
+        };
         let diffuse = effective_color * m.diffuse * light_dot_normal;
         let reflectv = (-lightv).reflect(normalv);
         let reflect_dot_eye = reflectv.dot(eyev);
@@ -175,16 +661,40 @@ pub fn lighting(
             Color::black()
         } else {
             let factor = reflect_dot_eye.powf(m.shininess);
-            light.intensity * m.specular * factor
+            // Toon highlights are either there or not, cut off where the smooth one is at half
+            // strength.
+            let factor = match m.toon_bands {
+                Some(_) if factor >= 0.5 => 1.0,
+                Some(_) => 0.0,
+                None => factor,
+            };
+            intensity * m.specular * factor
         };
         (diffuse, specular)
     };
     ambient + diffuse + specular
 }
 
+/// Rounds the cosine between the light and the normal up to the next of `bands` evenly spaced
+/// levels, so that anything facing the light at all gets at least the first band.
+fn toon_step(light_dot_normal: f64, bands: u32) -> f64 {
+    let n = bands as f64;
+    ((light_dot_normal * n).ceil() / n).min(1.0)
+}
+
+/// Finds the fraction of light which passes through a coat to the layer below it, given the
+/// cosine of the angle between the eye and the normal. The coat lets through as much as its
+/// transparency allows, less what it reflects by the Schlick approximation.
+fn coat_transmittance(coat: &Material, cos: f64) -> f64 {
+    let n = coat.refractive_index;
+    let r0 = ((1.0 - n) / (1.0 + n)).powi(2);
+    let reflectance = r0 + (1.0 - r0) * (1.0 - cos.clamp(0.0, 1.0)).powi(5);
+    coat.transparency.clamp(0.0, 1.0) * (1.0 - reflectance)
+}
+
 /// Determines if some point in the world is in a shadow.
 pub fn is_shadowed(w: &World, p: Point) -> bool {
-    match w.light {
+    match &w.light {
         None => true,
         Some(l) => {
             let v = l.position - p;
@@ -193,8 +703,7 @@ pub fn is_shadowed(w: &World, p: Point) -> bool {
 
             let r = Ray::new(p, direction);
             let intersections = r.when_intersect_world(w);
-            let h = hit(&intersections);
-            match h {
+            match intersections.shadow_hit_where(r, |x| !w.passes_through(x, r)) {
                 None => false,
                 Some(i) => i.t() < distance,
             }
@@ -206,21 +715,67 @@ pub fn is_shadowed(w: &World, p: Point) -> bool {
 pub fn reflected_color(w: &World, comps: &IntersectionVals, limit: u16) -> Color {
     // If we've already reached the recursion limit, just assume that it is gonna reflect forever,
     // and return white.
-    if limit <= 0 {
+    if limit == 0 {
         return Color::white();
     }
-    if comps.object.material().reflective == 0.0 {
+    let m = comps.object.material();
+    if m.reflective == 0.0 {
         return Color::black();
     }
-    // Reflect the ray, and find out what color the reflected ray's intersection ends up producing.
-    let reflect_ray = Ray::new(comps.over_point, comps.reflectv);
-    let color = w.color_of_ray(reflect_ray, limit - 1);
-    color * comps.object.material().reflective
+    if m.roughness == 0.0 {
+        // Reflect the ray, and find out what color the reflected ray's intersection ends up
+        // producing.
+        let reflect_ray = Ray::new(comps.over_point, comps.reflectv);
+        let color = w.color_of_ray(reflect_ray, limit - 1);
+        return color * m.reflective;
+    }
+
+    // A rough surface scatters its reflection, so we average rays jittered around the mirror
+    // direction. The jitter is seeded by the point, so that renders are repeatable. Only the
+    // first bounce spreads out into many rays; deeper ones follow a single jittered ray, or else
+    // the cost would grow with the number of samples to the power of the bounce depth.
+    let n = if limit >= w.settings.max_bounce() {
+        m.effective_samples(&w.settings)
+    } else {
+        1
+    };
+    let p = comps.over_point;
+    let mut rng = Rng::new(point_seed(p));
+    let r = comps.reflectv.normalize();
+    let helper = if r.x().abs() < 0.9 {
+        Vector::new(1.0, 0.0, 0.0)
+    } else {
+        Vector::new(0.0, 1.0, 0.0)
+    };
+    let (u, v) = (
+        r.cross(helper).normalize(),
+        r.cross(r.cross(helper)).normalize(),
+    );
+    // The directions are gathered in a buffer from this thread's pool, so that spreading out a
+    // reflection doesn't allocate.
+    let mut dirs: Vec<Vector> = arena::take();
+    dirs.extend((0..n).map(|_| {
+        // A uniformly random point on a disk, scaled by the roughness.
+        let radius = m.roughness * rng.next_f64().sqrt();
+        let angle = rng.range(0.0, 2.0 * std::f64::consts::PI);
+        let d = (r + u * (radius * angle.cos()) + v * (radius * angle.sin())).normalize();
+        // Don't send reflections into the surface.
+        if d.dot(comps.normalv) > 0.0 {
+            d
+        } else {
+            r
+        }
+    }));
+    let total = dirs.iter().fold(Color::black(), |acc, &d| {
+        acc + w.color_of_ray(Ray::new(p, d), limit - 1)
+    });
+    arena::give(dirs);
+    total * (m.reflective / n as f64)
 }
 
 /// Computes the refracted component of the color at some intersection.
 pub fn refracted_color(w: &World, comps: &IntersectionVals, limit: u16) -> Color {
-    if limit <= 0 || comps.object.material().transparency == 0.0 {
+    if limit == 0 || comps.object.material().transparency == 0.0 {
         return Color::black();
     }
 
@@ -229,8 +784,10 @@ pub fn refracted_color(w: &World, comps: &IntersectionVals, limit: u16) -> Color
     let n_ratio = comps.n1 / comps.n2;
     let cos_i = comps.eyev.dot(comps.normalv);
     let sin2_t = n_ratio * n_ratio * (1.0 - cos_i * cos_i);
+    // Under total internal reflection no light makes it through the surface. All of it is
+    // reflected instead, which the reflected color accounts for.
     if sin2_t > 1.0 {
-        return Color::white();
+        return Color::black();
     }
 
     let cos_t = (1.0 - sin2_t).sqrt();
@@ -242,10 +799,15 @@ pub fn refracted_color(w: &World, comps: &IntersectionVals, limit: u16) -> Color
 
 #[cfg(test)]
 mod tests {
-    use super::{is_shadowed, lighting, reflected_color, refracted_color, Material, PointLight};
+    use super::{
+        is_shadowed, lighting, reflected_color, refracted_color, Material, PointLight,
+        ShadingInput, SpotLight,
+    };
     use crate::color::Color;
+    use crate::ies::IesProfile;
     use crate::patterns::{Pattern, Stripe};
     use crate::ray::{Intersection, Ray};
+    use crate::settings::RenderSettings;
     use crate::shapes::{Plane, Sphere};
     use crate::transform::Tr;
     use crate::tuple::{Point, Vector};
@@ -264,6 +826,90 @@ mod tests {
         assert_eq!(got.position, position);
     }
 
+    #[test]
+    fn point_light_with_a_profile() {
+        // Full brightness straight down, nothing sideways.
+        let profile: IesProfile = "TILT=NONE
+1 1000 1 2 1 1 1 0 0 0
+1 1 10
+0 90
+0
+80 0"
+            .parse()
+            .unwrap();
+        let light = PointLight::new(p!(0, 10, 0), Color::new(0.5, 1.0, 1.0))
+            .with_profile(Arc::new(profile));
+        assert_eq!(light.profile().map(|p| p.max_candela()), Some(80.0));
+        assert_eq!(
+            light.intensity_towards(p!(0, 0, 0)),
+            Color::new(0.5, 1.0, 1.0)
+        );
+        assert_eq!(
+            light.intensity_towards(p!(10, 0, 0)),
+            Color::new(0.25, 0.5, 0.5)
+        );
+        assert_eq!(light.intensity_towards(p!(10, 10, 0)), Color::black());
+
+        let m = Material::default().with_ambient(0.0).with_specular(0.0);
+        let eyev = v!(0, 1, 0);
+        let shade = |p| {
+            lighting(ShadingInput::new(
+                &m,
+                &Sphere::default(),
+                &light,
+                p,
+                eyev,
+                eyev,
+            ))
+        };
+        assert_eq!(shade(p!(0, 0, 0)), Color::new(0.45, 0.9, 0.9));
+        assert_eq!(
+            shade(p!(10, 0, 0)),
+            Color::new(0.25, 0.5, 0.5) * (0.9 * SQRT_2 / 2.0)
+        );
+    }
+
+    #[test]
+    fn spotlights_fade_out_at_the_edge_of_their_cone() {
+        let spot = SpotLight::new(p!(0, 10, 0), v!(0, -2, 0), Color::white())
+            .with_cone(30f64.to_radians(), 60f64.to_radians());
+        assert_eq!(spot.direction(), v!(0, -1, 0));
+        let light: PointLight = spot.into();
+        assert!(light.is_spot());
+        // Straight down, and just inside the inner angle.
+        assert_eq!(light.intensity_towards(p!(0, 0, 0)), Color::white());
+        assert_eq!(light.intensity_towards(p!(5, 0, 0)), Color::white());
+        // Halfway between the angles' cosines, where the falloff is exactly one half.
+        let cos = (30f64.to_radians().cos() + 60f64.to_radians().cos()) / 2.0;
+        let half = 10.0 * cos.acos().tan();
+        let c = light.intensity_towards(p!(half, 0, 0));
+        assert!((c.r() - 0.5).abs() < 1e-9);
+        // Outside the cone, and behind the light.
+        assert_eq!(light.intensity_towards(p!(20, 0, 0)), Color::black());
+        assert_eq!(light.intensity_towards(p!(0, 20, 0)), Color::black());
+
+        let m = Material::default();
+        let lit = |p| {
+            lighting(ShadingInput::new(
+                &m,
+                &Sphere::default(),
+                &light,
+                p,
+                v!(0, 1, 0),
+                v!(0, 1, 0),
+            ))
+        };
+        assert_eq!(lit(p!(20, 0, 0)), Color::black());
+        assert!(lit(p!(0, 0, 0)).r() > 0.9);
+
+        // Equal angles give a hard edge.
+        let hard: PointLight = SpotLight::new(p!(0, 10, 0), v!(0, -1, 0), Color::white())
+            .with_cone(0.5, 0.1)
+            .into();
+        assert_eq!(hard.intensity_towards(p!(5, 0, 0)), Color::white());
+        assert_eq!(hard.intensity_towards(p!(6, 0, 0)), Color::black());
+    }
+
     #[test]
     fn default_material() {
         let m = Material::default();
@@ -281,7 +927,7 @@ mod tests {
         $(
             #[test]
             fn $name() {
-                let got = lighting(Material::default(), &Sphere::default(), $light, Point::origin(), $eyev, $normalv, $in_shadow);
+                let got = lighting(ShadingInput::new(&Material::default(), &Sphere::default(), &$light, Point::origin(), $eyev, $normalv).with_in_shadow($in_shadow));
                 assert_eq!(got, $want);
             }
         )*
@@ -365,6 +1011,106 @@ mod tests {
         false,
     }
 
+    #[test]
+    fn lighting_in_toon_bands() {
+        let eyev = v!(0, 0, -1);
+        let light = PointLight::new(p!(0, 0, -10), Color::white());
+        let m = Material::default()
+            .with_ambient(0.0)
+            .with_diffuse(1.0)
+            .with_specular(0.0)
+            .with_toon_bands(4);
+        let shade = |normalv: Vector| {
+            lighting(ShadingInput::new(
+                &m,
+                &Sphere::default(),
+                &light,
+                Point::origin(),
+                eyev,
+                normalv,
+            ))
+        };
+        // Cosines of roughly 0.98, 0.6, and 0.1 round up to the next quarter.
+        assert_eq!(shade(v!(0.2, 0, -0.98).normalize()), Color::white());
+        let got = shade(v!(0.8, 0, -0.6));
+        assert_eq!(got, Color::new(0.75, 0.75, 0.75));
+        let got = shade(v!(0.995, 0, -0.1).normalize());
+        assert_eq!(got, Color::new(0.25, 0.25, 0.25));
+
+        // Highlights are all or nothing.
+        let m = Material::default().with_toon_bands(2);
+        let got = lighting(ShadingInput::new(
+            &m,
+            &Sphere::default(),
+            &light,
+            Point::origin(),
+            eyev,
+            eyev,
+        ));
+        assert_eq!(got, Color::new(1.9, 1.9, 1.9));
+    }
+
+    #[test]
+    fn lighting_a_coated_material() {
+        let eyev = v!(0.0, 0.0, -1.0);
+        let normalv = v!(0.0, 0.0, -1.0);
+        let light = PointLight::new(p!(0.0, 0.0, -10.0), Color::white());
+        let shade = |m: Material| {
+            lighting(ShadingInput::new(
+                &m,
+                &Sphere::default(),
+                &light,
+                Point::origin(),
+                eyev,
+                normalv,
+            ))
+        };
+        let base = Material::default().with_color(Color::new(0.8, 0.1, 0.1));
+        let lacquer = Material::default()
+            .with_ambient(0.0)
+            .with_diffuse(0.0)
+            .with_specular(1.0)
+            .with_transparency(1.0)
+            .with_refractive_index(1.5);
+
+        // Head on, a glassy coat reflects 4% of the light.
+        let got = shade(base.clone().with_coat(lacquer.clone()));
+        let want = shade(lacquer.clone()) * 0.04 + shade(base.clone()) * 0.96;
+        assert_eq!(got, want);
+
+        // A fully opaque coat hides the base entirely.
+        let opaque = lacquer.with_transparency(0.0);
+        assert_eq!(shade(base.with_coat(opaque.clone())), shade(opaque));
+    }
+
+    #[test]
+    fn coats_can_be_nested() {
+        let clear = Material::default()
+            .with_transparency(1.0)
+            .with_refractive_index(1.0);
+        let inner = Material::default().with_color(Color::new(0.2, 0.4, 0.6));
+        let m = Material::default().with_coat(inner.clone().with_coat(clear));
+        assert_eq!(
+            m.coat().and_then(|c| c.coat()).map(|c| c.transparency()),
+            Some(1.0)
+        );
+
+        // A perfectly clear coat with no refraction is invisible.
+        let light = PointLight::new(p!(0.0, 0.0, -10.0), Color::white());
+        let eyev = v!(0.0, 0.0, -1.0);
+        let shade = |m: Material| {
+            lighting(ShadingInput::new(
+                &m,
+                &Sphere::default(),
+                &light,
+                Point::origin(),
+                eyev,
+                eyev,
+            ))
+        };
+        assert_eq!(shade(m.coat().unwrap().clone()), shade(inner));
+    }
+
     #[test]
     fn lighting_with_a_pattern_applied() {
         let m = Material::default()
@@ -376,26 +1122,24 @@ mod tests {
         let normalv = v!(0.0, 0.0, -1.0);
         let light = PointLight::new(p!(0.0, 0.0, -10.0), Color::white());
 
-        let c1 = lighting(
-            m.clone(),
+        let c1 = lighting(ShadingInput::new(
+            &m,
             &Sphere::default(),
-            light,
+            &light,
             p!(0.9, 0.0, 0.0),
             eyev,
             normalv,
-            false,
-        );
+        ));
         assert_eq!(c1, Color::white());
 
-        let c2 = lighting(
-            m,
+        let c2 = lighting(ShadingInput::new(
+            &m,
             &Sphere::default(),
-            light,
+            &light,
             p!(1.1, 0.0, 0.0),
             eyev,
             normalv,
-            false,
-        );
+        ));
         assert_eq!(c2, Color::black());
     }
 
@@ -405,6 +1149,22 @@ mod tests {
         assert_eq!(m.reflective, 0.0);
     }
 
+    #[test]
+    fn materials_which_give_off_too_much_light() {
+        let m = Material::default();
+        assert_eq!(m.energy(), 1.9);
+        assert!(!m.conserves_energy());
+
+        let m = m
+            .with_ambient(0.0)
+            .with_diffuse(0.3)
+            .with_specular(0.1)
+            .with_reflective(0.1)
+            .with_transparency(0.5);
+        assert!(m.conserves_energy());
+        assert!(!m.with_reflective(0.2).conserves_energy());
+    }
+
     #[test]
     fn precompute_reflection_vector() {
         let shape = Plane::default().as_object();
@@ -450,6 +1210,58 @@ mod tests {
         assert_eq!(color, Color::new(0.19033, 0.23792, 0.14275));
     }
 
+    #[test]
+    fn glossy_reflection_samples() {
+        let m = Material::default();
+        let settings = RenderSettings::new().with_glossy_samples(16);
+        assert_eq!(m.samples(), None);
+        assert_eq!(m.effective_samples(&settings), 16);
+
+        let hero = m.clone().with_samples(64);
+        let prop = m.with_samples(0);
+        assert_eq!(hero.effective_samples(&settings), 64);
+        assert_eq!(prop.effective_samples(&settings), 1);
+
+        let preview = settings.with_quality(0.25);
+        assert_eq!(hero.effective_samples(&preview), 16);
+        assert_eq!(prop.effective_samples(&preview), 1);
+    }
+
+    #[test]
+    fn reflected_color_for_rough_material() {
+        let reflect_off_at = |m: Material, limit: u16| {
+            let mut w = World::default();
+            let shape = Plane::default()
+                .with_material(m.with_reflective(0.5))
+                .with_transform(Tr::default().translate(0.0, -1.0, 0.0))
+                .as_object();
+            w.add_objects(vec![shape.clone()]);
+            let r = Ray::new(p!(0, 0, -3), v!(0, -SQRT_2 / 2.0, SQRT_2 / 2.0));
+            let comps = Intersection::new(SQRT_2, shape).prepare_computations(r, None);
+            reflected_color(&w, &comps, limit)
+        };
+        let reflect_off = |m: Material| reflect_off_at(m, MAX_BOUNCE);
+        let mirror = Color::new(0.19033, 0.23792, 0.14275);
+
+        // Barely rough is as good as a mirror, however many samples are taken.
+        let got = reflect_off(Material::default().with_roughness(1e-9).with_samples(3));
+        assert_eq!(got, mirror);
+
+        // Rough reflections are blurred, but the same every time.
+        let rough = Material::default().with_roughness(0.5).with_samples(32);
+        let got = reflect_off(rough.clone());
+        assert_ne!(got, mirror);
+        assert_eq!(got, reflect_off(rough.clone()));
+        assert_ne!(got, reflect_off(rough.clone().with_samples(1)));
+
+        // Deeper bounces only send one ray, however many samples the material asks for.
+        let deeper = reflect_off_at(rough.clone(), MAX_BOUNCE - 1);
+        assert_eq!(
+            deeper,
+            reflect_off_at(rough.with_samples(1), MAX_BOUNCE - 1)
+        );
+    }
+
     #[test]
     fn shade_hit_with_reflective_material() {
         let mut w = World::default();
@@ -593,7 +1405,7 @@ mod tests {
         ];
         let comps = xs[1].prepare_computations(r, Some(&xs));
         let c = refracted_color(&w, &comps, MAX_BOUNCE);
-        assert_eq!(c, Color::white());
+        assert_eq!(c, Color::black());
     }
 
     #[derive(Debug, PartialEq)]
@@ -619,7 +1431,7 @@ mod tests {
         }
 
         fn eqx(&self, other: &dyn std::any::Any) -> bool {
-            other.downcast_ref::<Self>().map_or(false, |a| a == self)
+            other.downcast_ref::<Self>().is_some_and(|a| a == self)
         }
 
         fn transform(&self) -> Tr {
@@ -672,7 +1484,11 @@ mod tests {
             )
             .as_object();
         let ball = Sphere::default()
-            .with_material(Material::default().with_color(Color::new(1.0, 0.0, 0.0)))
+            .with_material(
+                Material::default()
+                    .with_color(Color::new(1.0, 0.0, 0.0))
+                    .with_ambient(0.5),
+            )
             .with_transform(Tr::new().translate(0.0, -3.5, -0.5))
             .as_object();
         w.add_objects(vec![floor.clone(), ball.clone()]);
@@ -1,188 +1,325 @@
 use crate::tuple::Tuple;
 use crate::EPSILON;
+use std::array;
+use std::fmt;
 use std::ops;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Clone, Copy)]
 pub struct Matrix<const M: usize, const N: usize>([[f64; N]; M]);
 
-impl<const M: usize, const N: usize> PartialEq for Matrix<M, N> {
-    fn eq(&self, other: &Self) -> bool {
-        for i in 0..M {
-            for j in 0..N {
-                if (self.0[i][j] - other.0[i][j]).abs() > EPSILON {
-                    return false;
-                }
+/// Prints the matrix on a single line, e.g. `Matrix<2, 2>[[1.0, 0.0], [0.0, 1.0]]`. Use the
+/// alternate form (`{:#?}`) to get the same aligned layout as `Display`.
+impl<const M: usize, const N: usize> fmt::Debug for Matrix<M, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            return writeln!(f, "Matrix<{M}, {N}>\n{self}");
+        }
+        write!(f, "Matrix<{M}, {N}>{:?}", self.0)
+    }
+}
+
+/// Prints the matrix one row per line, with the columns aligned. Entries are printed with 5
+/// decimal places unless a precision is given, e.g. `{:.2}`.
+impl<const M: usize, const N: usize> fmt::Display for Matrix<M, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let prec = f.precision().unwrap_or(5);
+        let cells: Vec<Vec<String>> = self
+            .0
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|x| {
+                        // Don't print things like -0.00000.
+                        let x = if x.abs() < 0.5 * 10_f64.powi(-(prec as i32)) {
+                            0.0
+                        } else {
+                            *x
+                        };
+                        format!("{x:.prec$}")
+                    })
+                    .collect()
+            })
+            .collect();
+        let widths: Vec<usize> = (0..N)
+            .map(|j| cells.iter().map(|row| row[j].len()).max().unwrap_or(0))
+            .collect();
+        for (i, row) in cells.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
             }
+            write!(f, "[")?;
+            for (j, cell) in row.iter().enumerate() {
+                let sep = if j == 0 { "" } else { "  " };
+                write!(f, "{sep}{cell:>width$}", width = widths[j])?;
+            }
+            write!(f, "]")?;
         }
-        true
+        Ok(())
     }
 }
 
-#[derive(Debug)]
+impl<const M: usize, const N: usize> PartialEq for Matrix<M, N> {
+    fn eq(&self, other: &Self) -> bool {
+        self.approx_eq(other, EPSILON)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Error {
     Uninvertible,
 }
 
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Uninvertible => write!(f, "matrix is not invertible"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
 impl<const M: usize, const N: usize> Matrix<M, N> {
+    /// Creates a matrix from its rows.
     pub fn new(xss: [[f64; N]; M]) -> Self {
         Self(xss)
     }
 
+    /// Gets the entry at row i and column j.
     pub fn get(&self, i: usize, j: usize) -> f64 {
         self.0[i][j]
     }
 
+    /// Sets the entry at row i and column j.
     pub fn set(&mut self, i: usize, j: usize, val: f64) {
         self.0[i][j] = val
     }
 
-    /// Matrix multiplication.
-    fn mult<const K: usize>(&self, other: &Matrix<N, K>) -> Matrix<M, K> {
-        let mut xss = [[0.0; K]; M];
-        for i in 0..M {
-            for j in 0..K {
-                let mut v = 0.0;
-                for k in 0..N {
-                    v += self.0[i][k] * other.0[k][j];
-                }
-                xss[i][j] = v;
-            }
-        }
-        Matrix(xss)
+    /// Gets the i-th row.
+    pub fn row(&self, i: usize) -> [f64; N] {
+        self.0[i]
     }
-}
 
-impl Matrix<2, 2> {
-    fn det(&self) -> f64 {
-        let [[a, b], [c, d]] = self.0;
-        a * d - b * c
+    /// Gets the j-th column.
+    pub fn col(&self, j: usize) -> [f64; M] {
+        self.0.map(|row| row[j])
     }
 
-    #[allow(dead_code)]
-    fn is_invertible(&self) -> bool {
-        self.det() != 0.0
+    /// Gets every row of this matrix.
+    pub fn rows(&self) -> [[f64; N]; M] {
+        self.0
     }
-}
 
-impl Matrix<3, 3> {
-    fn det(&self) -> f64 {
-        // just expand along the first row
-        let mut res = 0.0;
-        for j in 0..3 {
-            let x = self.0[0][j];
-            res += x * self.cofactor(0, j);
-        }
-        res
+    /// The Frobenius norm, i.e. the square root of the sum of every entry squared.
+    pub fn norm(&self) -> f64 {
+        self.0.iter().flatten().map(|x| x * x).sum::<f64>().sqrt()
     }
 
-    #[allow(dead_code)]
-    fn minor(&self, i: usize, j: usize) -> f64 {
-        self.submatrix(i, j).det()
+    /// Checks if every entry of this matrix is within some tolerance of the other's. The
+    /// `PartialEq` implementation uses the crate's default tolerance.
+    pub fn approx_eq(&self, other: &Self, eps: f64) -> bool {
+        self.0
+            .iter()
+            .flatten()
+            .zip(other.0.iter().flatten())
+            .all(|(a, b)| (a - b).abs() <= eps)
     }
 
-    fn cofactor(&self, i: usize, j: usize) -> f64 {
-        let x = self.submatrix(i, j).det();
-        if (i + j) % 2 == 0 {
-            x
-        } else {
-            -x
-        }
+    /// Matrix multiplication, without consuming either operand. The `*` operator does the same.
+    pub fn mult<const K: usize>(&self, other: &Matrix<N, K>) -> Matrix<M, K> {
+        Matrix(array::from_fn(|i| {
+            array::from_fn(|j| (0..N).map(|k| self.0[i][k] * other.0[k][j]).sum())
+        }))
+    }
+}
+
+impl Matrix<4, 4> {
+    /// Multiplies a tuple, taken as a column vector. The `*` operator does the same.
+    pub fn mult_tuple(&self, t: Tuple) -> Tuple {
+        let Tuple(a, b, c, d) = t;
+        let [r0, r1, r2, r3] = self.0.map(|[w, x, y, z]| w * a + x * b + y * c + z * d);
+        Tuple(r0, r1, r2, r3)
+    }
+
+    /// The 2x2 determinants of the top two rows and the bottom two rows, which is all that is
+    /// needed to find the determinant and inverse of a 4x4 matrix in closed form.
+    fn subdets(&self) -> ([f64; 6], [f64; 6]) {
+        let [[a00, a01, a02, a03], [a10, a11, a12, a13], [a20, a21, a22, a23], [a30, a31, a32, a33]] =
+            self.0;
+        let s = [
+            a00 * a11 - a10 * a01,
+            a00 * a12 - a10 * a02,
+            a00 * a13 - a10 * a03,
+            a01 * a12 - a11 * a02,
+            a01 * a13 - a11 * a03,
+            a02 * a13 - a12 * a03,
+        ];
+        let c = [
+            a20 * a31 - a30 * a21,
+            a20 * a32 - a30 * a22,
+            a20 * a33 - a30 * a23,
+            a21 * a32 - a31 * a22,
+            a21 * a33 - a31 * a23,
+            a22 * a33 - a32 * a23,
+        ];
+        (s, c)
     }
 
-    fn is_invertible(&self) -> bool {
-        self.det() != 0.0
+    fn det4(&self) -> f64 {
+        let (s, c) = self.subdets();
+        s[0] * c[5] - s[1] * c[4] + s[2] * c[3] + s[3] * c[2] - s[4] * c[1] + s[5] * c[0]
     }
 
-    pub fn inverse(&self) -> Result<Self, Error> {
-        if !self.is_invertible() {
+    /// Inverts a 4x4 matrix in closed form. This is the fast path, since every transformation is
+    /// a 4x4 matrix.
+    fn inverse4(&self) -> Result<Self, Error> {
+        let (s, c) = self.subdets();
+        let det = s[0] * c[5] - s[1] * c[4] + s[2] * c[3] + s[3] * c[2] - s[4] * c[1] + s[5] * c[0];
+        // A NaN determinant, e.g. from a NaN entry, would only give back a NaN inverse.
+        if det == 0.0 || !det.is_finite() {
             return Err(Error::Uninvertible);
         }
-        let mut xss = [[0.0; 3]; 3];
-        let det = self.det();
-        for i in 0..3 {
-            for j in 0..3 {
-                xss[j][i] = self.cofactor(i, j) / det;
-            }
-        }
-        Ok(Self(xss))
+        let [[a00, a01, a02, a03], [a10, a11, a12, a13], [a20, a21, a22, a23], [a30, a31, a32, a33]] =
+            self.0;
+        let adj = [
+            [
+                a11 * c[5] - a12 * c[4] + a13 * c[3],
+                -a01 * c[5] + a02 * c[4] - a03 * c[3],
+                a31 * s[5] - a32 * s[4] + a33 * s[3],
+                -a21 * s[5] + a22 * s[4] - a23 * s[3],
+            ],
+            [
+                -a10 * c[5] + a12 * c[2] - a13 * c[1],
+                a00 * c[5] - a02 * c[2] + a03 * c[1],
+                -a30 * s[5] + a32 * s[2] - a33 * s[1],
+                a20 * s[5] - a22 * s[2] + a23 * s[1],
+            ],
+            [
+                a10 * c[4] - a11 * c[2] + a13 * c[0],
+                -a00 * c[4] + a01 * c[2] - a03 * c[0],
+                a30 * s[4] - a31 * s[2] + a33 * s[0],
+                -a20 * s[4] + a21 * s[2] - a23 * s[0],
+            ],
+            [
+                -a10 * c[3] + a11 * c[1] - a12 * c[0],
+                a00 * c[3] - a01 * c[1] + a02 * c[0],
+                -a30 * s[3] + a31 * s[1] - a32 * s[0],
+                a20 * s[3] - a21 * s[1] + a22 * s[0],
+            ],
+        ];
+        Ok(Self(adj.map(|row| row.map(|x| x / det))))
     }
 }
 
-impl Matrix<4, 4> {
-    fn mult_tuple(&self, t: Tuple) -> Tuple {
-        let Tuple(a, b, c, d) = t;
-        let mut tmp = [0.0; 4];
-        for i in 0..4 {
-            let [w, x, y, z] = self.0[i];
-            tmp[i] = w * a + x * b + y * c + z * d;
-        }
-        Tuple(tmp[0], tmp[1], tmp[2], tmp[3])
-    }
-
-    fn det(&self) -> f64 {
-        // just expand along the first row
-        let mut res = 0.0;
-        for j in 0..4 {
-            let x = self.0[0][j];
-            res += x * self.cofactor(0, j);
-        }
-        res
+impl<const K: usize> Matrix<K, K> {
+    /// The sum of the entries along the diagonal.
+    pub fn trace(&self) -> f64 {
+        (0..K).map(|i| self.0[i][i]).sum()
     }
 
-    #[allow(dead_code)]
-    fn minor(&self, i: usize, j: usize) -> f64 {
-        self.submatrix(i, j).det()
+    /// The identity matrix.
+    pub fn ident() -> Self {
+        Matrix(array::from_fn(|i| {
+            array::from_fn(|j| if i == j { 1.0 } else { 0.0 })
+        }))
     }
 
-    fn cofactor(&self, i: usize, j: usize) -> f64 {
-        let x = self.submatrix(i, j).det();
-        if (i + j) % 2 == 0 {
-            x
-        } else {
-            -x
+    /// Flips this matrix over its diagonal.
+    pub fn transpose(&self) -> Self {
+        Matrix(array::from_fn(|i| array::from_fn(|j| self.0[j][i])))
+    }
+
+    /// Computes the determinant. Matrices up to 4x4 are done in closed form, so that the result
+    /// is exact for integer entries; anything larger goes through an LU decomposition.
+    pub fn det(&self) -> f64 {
+        let a = |i: usize, j: usize| self.0[i][j];
+        match K {
+            0 => 1.0,
+            1 => a(0, 0),
+            2 => a(0, 0) * a(1, 1) - a(0, 1) * a(1, 0),
+            3 => {
+                a(0, 0) * (a(1, 1) * a(2, 2) - a(1, 2) * a(2, 1))
+                    - a(0, 1) * (a(1, 0) * a(2, 2) - a(1, 2) * a(2, 0))
+                    + a(0, 2) * (a(1, 0) * a(2, 1) - a(1, 1) * a(2, 0))
+            }
+            4 => Matrix::<4, 4>(array::from_fn(|i| array::from_fn(|j| a(i, j)))).det4(),
+            _ => match self.lu() {
+                None => 0.0,
+                Some((lu, _, sign)) => (0..K).map(|i| lu[i][i]).product::<f64>() * sign,
+            },
         }
     }
 
-    fn is_invertible(&self) -> bool {
-        self.det() != 0.0
+    /// Checks if this matrix has an inverse, i.e. if its determinant is non-zero and finite.
+    pub fn is_invertible(&self) -> bool {
+        let det = self.det();
+        det != 0.0 && det.is_finite()
     }
 
+    /// Inverts this matrix. 4x4 matrices take a closed form fast path; every other size is
+    /// inverted through an LU decomposition.
     pub fn inverse(&self) -> Result<Self, Error> {
-        if !self.is_invertible() {
-            return Err(Error::Uninvertible);
-        }
-        let mut xss = [[0.0; 4]; 4];
-        let det = self.det();
-        for i in 0..4 {
-            for j in 0..4 {
-                xss[j][i] = self.cofactor(i, j) / det;
-            }
+        if K == 4 {
+            let m = Matrix::<4, 4>(array::from_fn(|i| array::from_fn(|j| self.0[i][j])));
+            let inv = m.inverse4()?;
+            return Ok(Matrix(array::from_fn(|i| array::from_fn(|j| inv.0[i][j]))));
         }
-        Ok(Self(xss))
-    }
-}
 
-impl<const K: usize> Matrix<K, K> {
-    pub fn ident() -> Self {
+        let (lu, perm, _) = self.lu().ok_or(Error::Uninvertible)?;
+        // Solve LU x = e_p for every column of the (permuted) identity.
         let mut xss = [[0.0; K]; K];
-        for i in 0..K {
-            for j in 0..K {
-                xss[i][j] = if i == j { 1.0 } else { 0.0 };
+        for col in 0..K {
+            let mut x: [f64; K] = array::from_fn(|i| if perm[i] == col { 1.0 } else { 0.0 });
+            // Forward substitution, L has an implicit unit diagonal.
+            for i in 0..K {
+                for k in 0..i {
+                    x[i] -= lu[i][k] * x[k];
+                }
+            }
+            // Back substitution.
+            for i in (0..K).rev() {
+                for k in i + 1..K {
+                    x[i] -= lu[i][k] * x[k];
+                }
+                x[i] /= lu[i][i];
+            }
+            for (row, v) in xss.iter_mut().zip(x) {
+                row[col] = v;
             }
         }
-        Matrix(xss)
-    }
-
-    pub fn transpose(&self) -> Self {
-        let mut xss = self.0.clone();
-        for i in 0..K {
-            for j in i..K {
-                (xss[j][i], xss[i][j]) = (xss[i][j], xss[j][i]);
+        Ok(Matrix(xss))
+    }
+
+    /// LU decomposition with partial pivoting. Returns L and U packed into one matrix, the row
+    /// permutation, and the sign of that permutation; or None if the matrix is singular.
+    #[allow(clippy::type_complexity)]
+    fn lu(&self) -> Option<([[f64; K]; K], [usize; K], f64)> {
+        let mut lu = self.0;
+        let mut perm: [usize; K] = array::from_fn(|i| i);
+        let mut sign = 1.0;
+        // Pivots this small relative to the entries are treated as zero.
+        let tol = f64::EPSILON * self.norm() * K as f64;
+        for k in 0..K {
+            let p = (k..K).max_by(|&a, &b| lu[a][k].abs().total_cmp(&lu[b][k].abs()))?;
+            if lu[p][k].abs() <= tol {
+                return None;
+            }
+            if p != k {
+                lu.swap(p, k);
+                perm.swap(p, k);
+                sign = -sign;
+            }
+            for i in k + 1..K {
+                lu[i][k] /= lu[k][k];
+                for j in k + 1..K {
+                    lu[i][j] -= lu[i][k] * lu[k][j];
+                }
             }
         }
-        Matrix(xss)
+        Some((lu, perm, sign))
     }
 
+    /// Removes row i and column j.
     pub fn submatrix(&self, i: usize, j: usize) -> Matrix<{ K - 1 }, { K - 1 }>
     where
         [(); K - 1]:,
@@ -205,6 +342,27 @@ impl<const K: usize> Matrix<K, K> {
         }
         Matrix::new(xss)
     }
+
+    /// The determinant of the submatrix at (i, j).
+    pub fn minor(&self, i: usize, j: usize) -> f64
+    where
+        [(); K - 1]:,
+    {
+        self.submatrix(i, j).det()
+    }
+
+    /// The minor at (i, j), negated if i + j is odd.
+    pub fn cofactor(&self, i: usize, j: usize) -> f64
+    where
+        [(); K - 1]:,
+    {
+        let x = self.minor(i, j);
+        if (i + j).is_multiple_of(2) {
+            x
+        } else {
+            -x
+        }
+    }
 }
 
 impl<const M: usize, const N: usize, const K: usize> ops::Mul<Matrix<N, K>> for Matrix<M, N> {
@@ -215,6 +373,12 @@ impl<const M: usize, const N: usize, const K: usize> ops::Mul<Matrix<N, K>> for
     }
 }
 
+impl<const M: usize, const N: usize> From<[[f64; N]; M]> for Matrix<M, N> {
+    fn from(xss: [[f64; N]; M]) -> Self {
+        Self(xss)
+    }
+}
+
 impl ops::Mul<Tuple> for Matrix<4, 4> {
     type Output = Tuple;
 
@@ -269,7 +433,7 @@ mod tests {
             [0.0, 4.0, 3.0, 2.0],
         ];
 
-        let a = Matrix::new(xss.clone());
+        let a = Matrix::new(xss);
         let b = Matrix::new(xss);
         assert_eq!(a, b);
 
@@ -327,7 +491,7 @@ mod tests {
             [4.0, 8.0, 16.0, 32.0],
         ];
         let a = Matrix::new(xss);
-        assert_eq!(a.clone() * Matrix::<4, 4>::ident(), a);
+        assert_eq!(a * Matrix::<4, 4>::ident(), a);
     }
 
     #[test]
@@ -532,4 +696,112 @@ mod tests {
         let c = a * b;
         assert_eq!(c * b.inverse().unwrap(), a);
     }
+
+    #[test]
+    fn display_aligns_columns() {
+        let a = Matrix::new([[1.0, -22.5], [-0.0000001, 3.0]]);
+        assert_eq!(a.to_string(), "[1.00000  -22.50000]\n[0.00000    3.00000]");
+        assert_eq!(format!("{a:.1}"), "[1.0  -22.5]\n[0.0    3.0]");
+    }
+
+    #[test]
+    fn debug_is_compact() {
+        let a = Matrix::new([[1.0, 2.0], [3.0, 4.0]]);
+        assert_eq!(format!("{a:?}"), "Matrix<2, 2>[[1.0, 2.0], [3.0, 4.0]]");
+        assert_eq!(
+            format!("{a:#?}"),
+            "Matrix<2, 2>\n[1.00000  2.00000]\n[3.00000  4.00000]\n"
+        );
+    }
+
+    #[test]
+    fn rows_and_columns() {
+        let a = Matrix::new([[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]]);
+        assert_eq!(a.row(1), [4.0, 5.0, 6.0]);
+        assert_eq!(a.col(2), [3.0, 6.0]);
+        assert_eq!(a.rows(), [[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]]);
+    }
+
+    #[test]
+    fn trace_and_norm() {
+        let a = Matrix::new([[1.0, 2.0], [3.0, 4.0]]);
+        assert_eq!(a.trace(), 5.0);
+        assert_f64_eq!(a.norm(), 30.0_f64.sqrt());
+        assert_eq!(Matrix::<4, 4>::ident().trace(), 4.0);
+    }
+
+    #[test]
+    fn approx_equality_with_tolerance() {
+        let a = Matrix::new([[1.0, 2.0], [3.0, 4.0]]);
+        let b = Matrix::new([[1.01, 2.0], [3.0, 3.99]]);
+        assert!(a.approx_eq(&b, 0.02));
+        assert!(!a.approx_eq(&b, 0.001));
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn determinant_and_inverse_5x5() {
+        let xss = [
+            [2.0, 0.0, 1.0, 3.0, -1.0],
+            [1.0, 4.0, 0.0, -2.0, 2.0],
+            [0.0, 1.0, 5.0, 1.0, 0.0],
+            [3.0, -1.0, 2.0, 6.0, 1.0],
+            [1.0, 2.0, 0.0, 1.0, 3.0],
+        ];
+        let a = Matrix::new(xss);
+        // Expanding along the first row agrees with the LU decomposition.
+        let want: f64 = (0..5).map(|j| a.get(0, j) * a.cofactor(0, j)).sum();
+        assert_f64_eq!(a.det(), want, 1e-9);
+
+        let inv = a.inverse().unwrap();
+        assert_eq!(a * inv, Matrix::<5, 5>::ident());
+        assert_eq!(inv * a, Matrix::<5, 5>::ident());
+    }
+
+    #[test]
+    fn inverse_of_3x3_and_2x2() {
+        let a = Matrix::new([[1.0, 2.0, 6.0], [-5.0, 8.0, -4.0], [2.0, 6.0, 4.0]]);
+        assert_eq!(a * a.inverse().unwrap(), Matrix::<3, 3>::ident());
+
+        let a = Matrix::new([[4.0, 7.0], [2.0, 6.0]]);
+        let want = Matrix::new([[0.6, -0.7], [-0.2, 0.4]]);
+        assert_eq!(a.inverse().unwrap(), want);
+    }
+
+    #[test]
+    fn singular_matrices_are_not_inverted() {
+        let a = Matrix::new([
+            [1.0, 2.0, 3.0, 4.0, 5.0],
+            [2.0, 4.0, 6.0, 8.0, 10.0],
+            [0.0, 1.0, 0.0, 1.0, 0.0],
+            [1.0, 0.0, 1.0, 0.0, 1.0],
+            [3.0, 3.0, 3.0, 3.0, 3.0],
+        ]);
+        assert_eq!(a.det(), 0.0);
+        assert!(a.inverse().is_err());
+
+        let a = Matrix::new([[1.0, 2.0, 3.0], [2.0, 4.0, 6.0], [0.0, 1.0, 1.0]]);
+        assert!(a.inverse().is_err());
+
+        let mut a = Matrix::<4, 4>::ident();
+        a.0[1][1] = f64::NAN;
+        assert!(!a.is_invertible());
+        assert!(a.inverse().is_err());
+    }
+
+    #[test]
+    fn named_methods_agree_with_operators() {
+        let a = Matrix::from([[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]]);
+        let b = Matrix::from([[1.0, 0.0], [0.0, 1.0], [2.0, -1.0]]);
+        assert_eq!(a.mult(&b), a * b);
+
+        let m = Matrix::<4, 4>::ident();
+        let t = Tuple(1.0, 2.0, 3.0, 1.0);
+        assert_eq!(m.mult_tuple(t), m * t);
+
+        assert_eq!(
+            super::Error::Uninvertible.to_string(),
+            "matrix is not invertible"
+        );
+    }
 }
@@ -0,0 +1,141 @@
+use crate::canvas::Canvas;
+use crate::color::Color;
+use crate::rng::Rng;
+
+/// How much of one pixel some object covers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Coverage {
+    pub id: usize,
+    /// The fraction of the pixel covered, from 0 to 1.
+    pub weight: f64,
+}
+
+/// Per-pixel object coverage, in the spirit of Cryptomatte: every pixel records which objects
+/// are visible in it and how much of it each one covers, so that any object can be pulled out
+/// with anti-aliased edges afterwards.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectMatte {
+    width: usize,
+    height: usize,
+    /// The coverage of every pixel, row by row, heaviest first. Background isn't recorded, so
+    /// the weights of a pixel only add up to 1 if objects fill it completely.
+    pixels: Vec<Vec<Coverage>>,
+}
+
+impl ObjectMatte {
+    /// Builds a matte from the IDs of whatever each sample hit, given as one list per pixel.
+    pub fn from_samples(width: usize, height: usize, samples: Vec<Vec<Option<usize>>>) -> Self {
+        let pixels = samples
+            .into_iter()
+            .map(|hits| {
+                let n = hits.len().max(1) as f64;
+                let mut res: Vec<Coverage> = vec![];
+                for id in hits.into_iter().flatten() {
+                    match res.iter_mut().find(|c| c.id == id) {
+                        Some(c) => c.weight += 1.0 / n,
+                        None => res.push(Coverage {
+                            id,
+                            weight: 1.0 / n,
+                        }),
+                    }
+                }
+                res.sort_by(|a, b| b.weight.total_cmp(&a.weight).then(a.id.cmp(&b.id)));
+                res
+            })
+            .collect();
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The objects covering some pixel, heaviest first.
+    pub fn coverage_at(&self, x: usize, y: usize) -> &[Coverage] {
+        &self.pixels[y * self.width + x]
+    }
+
+    /// Every object which shows up anywhere in the matte, in increasing order of ID.
+    pub fn ids(&self) -> Vec<usize> {
+        let mut ids: Vec<usize> = self.pixels.iter().flatten().map(|c| c.id).collect();
+        ids.sort_unstable();
+        ids.dedup();
+        ids
+    }
+
+    /// Extracts the matte for one object, as white where it covers the pixel fully and black
+    /// where it does not show up at all.
+    pub fn matte(&self, id: usize) -> Canvas {
+        let mut res = Canvas::new(self.width, self.height);
+        for (px, cov) in res.pixels_mut().iter_mut().zip(&self.pixels) {
+            let w = cov.iter().find(|c| c.id == id).map_or(0.0, |c| c.weight);
+            *px = Color::new(w, w, w);
+        }
+        res
+    }
+
+    /// Gives every object a made up color and blends them by coverage, to check the matte at a
+    /// glance.
+    pub fn preview(&self) -> Canvas {
+        let mut res = Canvas::new(self.width, self.height);
+        for (px, cov) in res.pixels_mut().iter_mut().zip(&self.pixels) {
+            *px = cov
+                .iter()
+                .fold(Color::black(), |acc, c| acc + id_color(c.id) * c.weight);
+        }
+        res
+    }
+}
+
+/// A color which stays the same for some ID across renders.
+fn id_color(id: usize) -> Color {
+    let mut rng = Rng::new(id as u64);
+    Color::new(
+        rng.range(0.2, 1.0),
+        rng.range(0.2, 1.0),
+        rng.range(0.2, 1.0),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Coverage, ObjectMatte};
+    use crate::color::Color;
+
+    #[test]
+    fn coverage_from_samples() {
+        let m = ObjectMatte::from_samples(
+            2,
+            1,
+            vec![
+                vec![Some(3), Some(7), Some(7), None],
+                vec![Some(3), Some(3), Some(3), Some(3)],
+            ],
+        );
+        assert_eq!(
+            m.coverage_at(0, 0),
+            [
+                Coverage { id: 7, weight: 0.5 },
+                Coverage {
+                    id: 3,
+                    weight: 0.25
+                }
+            ]
+        );
+        assert_eq!(m.coverage_at(1, 0), [Coverage { id: 3, weight: 1.0 }]);
+        assert_eq!(m.ids(), vec![3, 7]);
+
+        let matte = m.matte(3);
+        assert_eq!(matte.pixel_at(0, 0), Color::new(0.25, 0.25, 0.25));
+        assert_eq!(matte.pixel_at(1, 0), Color::white());
+        assert_eq!(m.matte(42).pixel_at(1, 0), Color::black());
+    }
+}
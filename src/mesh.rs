@@ -0,0 +1,394 @@
+use crate::light::Material;
+use crate::shapes::{uv_tangents, Group, Object, Triangle};
+use crate::tuple::{Point, Vector};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// A triangle mesh, as vertices and the faces between them. Meshes are for building and
+/// cleaning up imported models; to render one, turn it into a [`Group`] of triangles.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Mesh {
+    vertices: Vec<Point>,
+    /// Each face is the indices of its three vertices.
+    faces: Vec<[usize; 3]>,
+    /// The normal at each corner of each face, if they have been computed.
+    normals: Option<Vec<[Vector; 3]>>,
+    /// The texture coordinates at each corner of each face, if the model came with them.
+    uvs: Option<Vec<[(f64, f64); 3]>>,
+    /// The tangent and bitangent at each corner of each face, if they have been computed.
+    tangents: Option<Vec<[(Vector, Vector); 3]>>,
+}
+
+/// How the faces of a mesh were arranged into a bounding volume hierarchy, so that it can be built
+/// again without working out where to split. See [`Mesh::to_bvh`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum BvhLayout {
+    /// The index of a face.
+    Face(usize),
+    /// A group holding everything in it.
+    Group(Vec<BvhLayout>),
+}
+
+/// Positions closer than this are welded into a single vertex.
+const WELD_DISTANCE: f64 = 1e-6;
+
+impl Mesh {
+    pub fn new(vertices: Vec<Point>, faces: Vec<[usize; 3]>) -> Self {
+        Self {
+            vertices,
+            faces,
+            normals: None,
+            uvs: None,
+            tangents: None,
+        }
+    }
+
+    /// Builds a mesh out of loose triangles, as they come out of formats like STL. Corners at the
+    /// same position become the same vertex, so that the faces know they are neighbours.
+    pub fn from_triangles(triangles: &[[Point; 3]]) -> Self {
+        let mut vertices = vec![];
+        let mut index: HashMap<[i64; 3], usize> = HashMap::new();
+        let faces = triangles
+            .iter()
+            .map(|tri| {
+                tri.map(|p| {
+                    let key = [p.x(), p.y(), p.z()].map(|c| (c / WELD_DISTANCE).round() as i64);
+                    *index.entry(key).or_insert_with(|| {
+                        vertices.push(p);
+                        vertices.len() - 1
+                    })
+                })
+            })
+            .collect();
+        Self::new(vertices, faces)
+    }
+
+    pub fn vertices(&self) -> &[Point] {
+        &self.vertices
+    }
+
+    pub fn faces(&self) -> &[[usize; 3]] {
+        &self.faces
+    }
+
+    pub fn normals(&self) -> Option<&[[Vector; 3]]> {
+        self.normals.as_deref()
+    }
+
+    pub fn uvs(&self) -> Option<&[[(f64, f64); 3]]> {
+        self.uvs.as_deref()
+    }
+
+    pub fn tangents(&self) -> Option<&[[(Vector, Vector); 3]]> {
+        self.tangents.as_deref()
+    }
+
+    /// Sets the texture coordinates at the corners of every face. There must be one set per
+    /// face.
+    pub fn with_uvs(mut self, uvs: Vec<[(f64, f64); 3]>) -> Self {
+        assert_eq!(uvs.len(), self.faces.len(), "mesh needs UVs for every face");
+        self.uvs = Some(uvs);
+        self
+    }
+
+    /// Sets the normal at the corners of every face, for surfaces which know their own normals
+    /// better than the faces can tell. There must be one set per face.
+    pub fn with_normals(mut self, normals: Vec<[Vector; 3]>) -> Self {
+        assert_eq!(
+            normals.len(),
+            self.faces.len(),
+            "mesh needs normals for every face"
+        );
+        self.normals = Some(normals);
+        self
+    }
+
+    /// The unnormalized normal of a face, whose length is twice the face's area. This uses the
+    /// same winding as [`Triangle`].
+    pub(crate) fn face_normal(&self, f: usize) -> Vector {
+        let [a, b, c] = self.faces[f].map(|i| self.vertices[i]);
+        (c - a).cross(b - a)
+    }
+
+    /// Computes a normal for every corner of every face, so that the mesh shades smoothly. Each
+    /// corner's normal averages the faces around its vertex, weighted by area, but only faces
+    /// within `crease_angle` radians of its own face; sharper edges than that stay hard.
+    pub fn with_smooth_normals(mut self, crease_angle: f64) -> Self {
+        let face_normals: Vec<Vector> =
+            (0..self.faces.len()).map(|f| self.face_normal(f)).collect();
+        let mut around: Vec<Vec<usize>> = vec![vec![]; self.vertices.len()];
+        for (f, face) in self.faces.iter().enumerate() {
+            for &v in face {
+                around[v].push(f);
+            }
+        }
+
+        let cos_crease = crease_angle.cos();
+        let normals = self
+            .faces
+            .iter()
+            .enumerate()
+            .map(|(f, face)| {
+                let own = face_normals[f].normalize();
+                face.map(|v| {
+                    let sum = around[v]
+                        .iter()
+                        .map(|&g| face_normals[g])
+                        .filter(|n| n.normalize().dot(own) >= cos_crease - 1e-9)
+                        .fold(Vector::new(0.0, 0.0, 0.0), |acc, n| acc + n);
+                    sum.normalize()
+                })
+            })
+            .collect();
+        self.normals = Some(normals);
+        self
+    }
+
+    /// Computes a tangent and bitangent for every corner of every face, pointing the way the
+    /// texture coordinates grow, for tangent space normal maps. Each corner averages the faces
+    /// around its vertex which give it the same texture coordinates, so that the tangents are
+    /// smooth across the mesh but not across UV seams. Meshes without texture coordinates are
+    /// left as they are.
+    pub fn with_tangents(mut self) -> Self {
+        let Some(uvs) = &self.uvs else {
+            return self;
+        };
+        let key = |v: usize, uv: (f64, f64)| (v, uv.0.to_bits(), uv.1.to_bits());
+        let zero = Vector::new(0.0, 0.0, 0.0);
+        let mut sums: HashMap<(usize, u64, u64), (Vector, Vector)> = HashMap::new();
+        for (face, uv) in self.faces.iter().zip(uvs) {
+            let points = face.map(|i| self.vertices[i]);
+            let Some((t, b)) = uv_tangents(points, *uv) else {
+                continue;
+            };
+            for k in 0..3 {
+                let sum = sums.entry(key(face[k], uv[k])).or_insert((zero, zero));
+                *sum = (sum.0 + t, sum.1 + b);
+            }
+        }
+        // Corners of faces whose texture coordinates are squashed flat get no tangents at all.
+        let unit = |v: Vector| {
+            if v.magnitude() > 0.0 {
+                v.normalize()
+            } else {
+                v
+            }
+        };
+        let tangents = self
+            .faces
+            .iter()
+            .zip(uvs)
+            .map(|(face, uv)| {
+                [0, 1, 2].map(|k| {
+                    let (t, b) = sums
+                        .get(&key(face[k], uv[k]))
+                        .copied()
+                        .unwrap_or((zero, zero));
+                    (unit(t), unit(b))
+                })
+            })
+            .collect();
+        self.tangents = Some(tangents);
+        self
+    }
+
+    /// Turns every face into a triangle, with the corner normals, texture coordinates and
+    /// tangents if the mesh has them.
+    pub fn triangles(&self, material: &Material) -> Vec<Triangle> {
+        self.faces
+            .iter()
+            .enumerate()
+            .map(|(f, face)| {
+                let [a, b, c] = face.map(|i| self.vertices[i]);
+                let mut tri = Triangle::new(a, b, c).with_material(material.clone());
+                if let Some(ns) = &self.normals {
+                    tri = tri.with_vertex_normals(ns[f][0], ns[f][1], ns[f][2]);
+                }
+                if let Some(uvs) = &self.uvs {
+                    tri = tri.with_vertex_uvs(uvs[f][0], uvs[f][1], uvs[f][2]);
+                }
+                if let Some(ts) = &self.tangents {
+                    tri = tri.with_vertex_tangents(ts[f]);
+                }
+                tri
+            })
+            .collect()
+    }
+
+    pub fn to_group(&self, material: &Material) -> Group {
+        let children = self
+            .triangles(material)
+            .into_iter()
+            .map(|t| t.as_object())
+            .collect();
+        Group::new(children)
+    }
+
+    /// Turns the mesh into a group divided into a bounding volume hierarchy, like
+    /// [`Group::divide`], along with how its faces were arranged.
+    pub fn to_bvh(&self, material: &Material, threshold: usize) -> (Group, BvhLayout) {
+        fn layout(obj: &Object, faces: &HashMap<*const (), usize>) -> BvhLayout {
+            match obj.as_any().downcast_ref::<Group>() {
+                Some(g) => {
+                    BvhLayout::Group(g.children().iter().map(|c| layout(c, faces)).collect())
+                }
+                None => BvhLayout::Face(faces[&Arc::as_ptr(obj).cast::<()>()]),
+            }
+        }
+
+        let group = self.to_group(material);
+        // Dividing moves the triangles around but never copies them, so they can be told apart by
+        // where they live.
+        let faces = group
+            .children()
+            .iter()
+            .enumerate()
+            .map(|(i, c)| (Arc::as_ptr(c).cast::<()>(), i))
+            .collect();
+        let group = group.divide(threshold);
+        let res = BvhLayout::Group(group.children().iter().map(|c| layout(c, &faces)).collect());
+        (group, res)
+    }
+
+    /// Builds the group described by a layout from [`Mesh::to_bvh`], without dividing it again.
+    /// Gives None if the layout doesn't fit this mesh.
+    pub fn to_group_with_layout(&self, material: &Material, layout: &BvhLayout) -> Option<Group> {
+        fn build(layout: &BvhLayout, triangles: &[Object]) -> Option<Object> {
+            match layout {
+                BvhLayout::Face(i) => triangles.get(*i).cloned(),
+                BvhLayout::Group(xs) => Some(
+                    Group::new(
+                        xs.iter()
+                            .map(|x| build(x, triangles))
+                            .collect::<Option<_>>()?,
+                    )
+                    .as_object(),
+                ),
+            }
+        }
+
+        let BvhLayout::Group(xs) = layout else {
+            return None;
+        };
+        let triangles: Vec<Object> = self
+            .triangles(material)
+            .into_iter()
+            .map(|t| t.as_object())
+            .collect();
+        let children = xs
+            .iter()
+            .map(|x| build(x, &triangles))
+            .collect::<Option<_>>()?;
+        Some(Group::new(children))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BvhLayout, Mesh};
+    use crate::light::Material;
+    use crate::ray::Ray;
+    use crate::shapes::Shape;
+    use crate::transform::Tr;
+    use crate::{p, v};
+    use std::f64::consts::{FRAC_1_SQRT_2, FRAC_PI_2, FRAC_PI_4};
+
+    /// Two faces meeting at a right angle along the z axis, like a roof.
+    fn roof() -> Mesh {
+        Mesh::from_triangles(&[
+            [p!(0, 0, 0), p!(0, 0, 1), p!(-1, -1, 0)],
+            [p!(0, 0, 0), p!(1, -1, 0), p!(0, 0, 1)],
+        ])
+    }
+
+    #[test]
+    fn loose_triangles_are_welded() {
+        let m = roof();
+        assert_eq!(m.vertices().len(), 4);
+        assert_eq!(m.faces(), &[[0, 1, 2], [0, 3, 1]]);
+        assert!(m.normals().is_none());
+    }
+
+    #[test]
+    fn smoothing_within_crease_angle() {
+        let m = roof().with_smooth_normals(FRAC_PI_2 + 0.1);
+        let ns = m.normals().unwrap();
+        // Along the ridge both faces count, so the normal points straight up.
+        for n in [ns[0][0], ns[0][1], ns[1][0], ns[1][2]] {
+            assert_eq!(n, v!(0, 1, 0));
+        }
+        // The eaves belong to one face only.
+        assert_eq!(ns[0][2], v!(-FRAC_1_SQRT_2, FRAC_1_SQRT_2, 0));
+        assert_eq!(ns[1][1], v!(FRAC_1_SQRT_2, FRAC_1_SQRT_2, 0));
+    }
+
+    #[test]
+    fn hard_edges_beyond_crease_angle() {
+        let m = roof().with_smooth_normals(FRAC_PI_4);
+        let ns = m.normals().unwrap();
+        assert_eq!(ns[0], [v!(-FRAC_1_SQRT_2, FRAC_1_SQRT_2, 0); 3]);
+        assert_eq!(ns[1], [v!(FRAC_1_SQRT_2, FRAC_1_SQRT_2, 0); 3]);
+    }
+
+    #[test]
+    fn smoothed_mesh_to_triangles() {
+        let m = roof().with_smooth_normals(FRAC_PI_2 + 0.1);
+        let ts = m.triangles(&Material::default());
+        assert_eq!(ts.len(), 2);
+        // Halfway between the ridge and the eave, the normal is halfway between theirs.
+        let n = ts[0].local_normal_at(p!(-0.5, -0.5, 0.25));
+        assert_eq!(n, v!(-0.38268, 0.92388, 0));
+        assert_eq!(m.to_group(&Material::default()).children().len(), 2);
+    }
+
+    #[test]
+    fn tangents_follow_texture_coordinates() {
+        // A quad in the xz plane, with u running along x and v running along -z.
+        let m = Mesh::new(
+            vec![p!(0, 0, 0), p!(1, 0, 0), p!(1, 0, -1), p!(0, 0, -1)],
+            vec![[0, 1, 2], [0, 2, 3]],
+        )
+        .with_uvs(vec![
+            [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0)],
+            [(0.0, 0.0), (1.0, 1.0), (0.0, 1.0)],
+        ])
+        .with_tangents();
+        let ts = m.tangents().unwrap();
+        assert_eq!(ts[0], [(v!(1, 0, 0), v!(0, 0, -1)); 3]);
+        assert_eq!(ts[1], [(v!(1, 0, 0), v!(0, 0, -1)); 3]);
+
+        // Tangents reach the hit record in world space.
+        let g = m
+            .to_group(&Material::default())
+            .with_transform(Tr::new().rotate_y(FRAC_PI_2));
+        let r = Ray::new(p!(-0.5, 5, -0.5), v!(0, -1, 0));
+        let xs = g.intersect_with(r);
+        let comps = xs[0].prepare_computations(r, Some(&xs));
+        let (t, b) = comps.tangents.unwrap();
+        assert_eq!(t, v!(0, 0, -1));
+        assert_eq!(b, v!(-1, 0, 0));
+
+        // Without UVs there is nothing to derive tangents from.
+        assert!(roof().with_tangents().tangents().is_none());
+    }
+
+    #[test]
+    fn rebuilding_a_bvh_from_its_layout() {
+        let strip: Vec<_> = (0..8)
+            .map(|i| {
+                let x = 2.0 * i as f64;
+                [p!(x, 0, 0), p!(x + 1.0, 0, 0), p!(x, 1, 0)]
+            })
+            .collect();
+        let m = Mesh::from_triangles(&strip);
+        let mat = Material::default();
+        let (g, layout) = m.to_bvh(&mat, 2);
+        assert!(matches!(&layout, BvhLayout::Group(xs) if xs.len() < 8));
+        assert_eq!(m.to_group_with_layout(&mat, &layout), Some(g));
+
+        assert_eq!(m.to_group_with_layout(&mat, &BvhLayout::Face(0)), None);
+        let stray = BvhLayout::Group(vec![BvhLayout::Face(8)]);
+        assert_eq!(m.to_group_with_layout(&mat, &stray), None);
+    }
+}
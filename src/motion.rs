@@ -0,0 +1,72 @@
+use crate::canvas::Canvas;
+use crate::color::Color;
+
+/// How far the surface seen through each pixel moves across the image by the next frame, in
+/// pixels, for motion blur and temporal denoising in other tools. Right and down are positive,
+/// like pixel coordinates. Pixels which see nothing, or whose surface goes behind the camera,
+/// have no vector.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MotionVectors {
+    width: usize,
+    height: usize,
+    /// The vector of every pixel, row by row.
+    vectors: Vec<Option<(f64, f64)>>,
+}
+
+impl MotionVectors {
+    pub fn new(width: usize, height: usize, vectors: Vec<Option<(f64, f64)>>) -> Self {
+        assert_eq!(
+            vectors.len(),
+            width * height,
+            "motion vectors should cover every pixel"
+        );
+        Self {
+            width,
+            height,
+            vectors,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn vector_at(&self, x: usize, y: usize) -> Option<(f64, f64)> {
+        self.vectors[y * self.width + x]
+    }
+
+    /// Encodes the vectors as colors, the way compositing tools usually take them: red for
+    /// motion to the right and green for motion down, each mapped from `-max..max` pixels onto
+    /// `0..1`. Pixels which don't move, or have no vector, come out as (0.5, 0.5, 0).
+    pub fn to_canvas(&self, max: f64) -> Canvas {
+        let max = max.max(f64::MIN_POSITIVE);
+        let encode = |d: f64| (0.5 + d / (2.0 * max)).clamp(0.0, 1.0);
+        let mut res = Canvas::new(self.width, self.height);
+        for (px, v) in res.pixels_mut().iter_mut().zip(&self.vectors) {
+            let (dx, dy) = v.unwrap_or((0.0, 0.0));
+            *px = Color::new(encode(dx), encode(dy), 0.0);
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MotionVectors;
+    use crate::color::Color;
+
+    #[test]
+    fn encoding_motion_as_colors() {
+        let mv = MotionVectors::new(3, 1, vec![Some((2.0, -1.0)), None, Some((-8.0, 0.0))]);
+        assert_eq!(mv.vector_at(0, 0), Some((2.0, -1.0)));
+        let c = mv.to_canvas(4.0);
+        assert_eq!(c.pixel_at(0, 0), Color::new(0.75, 0.375, 0.0));
+        assert_eq!(c.pixel_at(1, 0), Color::new(0.5, 0.5, 0.0));
+        // Anything faster than the maximum is clamped.
+        assert_eq!(c.pixel_at(2, 0), Color::new(0.0, 0.5, 0.0));
+    }
+}
@@ -0,0 +1,197 @@
+use crate::mesh::Mesh;
+use crate::tuple::Point;
+use std::fmt::Display;
+use std::path::Path;
+
+/// Represents some problem reading a Wavefront OBJ file.
+#[derive(Debug, PartialEq)]
+pub enum ErrParseObj {
+    /// When the file cannot be read at all.
+    Io(String),
+    InvalidNumber(String),
+    /// When a face refers to a vertex which does not exist.
+    InvalidIndex(String),
+    /// When a face has fewer than three vertices.
+    DegenerateFace(usize),
+}
+
+impl Display for ErrParseObj {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use ErrParseObj::*;
+        match self {
+            Io(msg) => write!(f, "Could not read OBJ file; {msg}"),
+            InvalidNumber(n) => write!(f, "Invalid number in OBJ file; n={n}"),
+            InvalidIndex(i) => write!(f, "Invalid vertex index in OBJ file; i={i}"),
+            DegenerateFace(line) => write!(f, "OBJ face has too few vertices; line={line}"),
+        }
+    }
+}
+
+/// Parses the geometry of an OBJ file into a mesh. Only vertices, texture coordinates and faces
+/// are read; polygons with more than three sides are split into a fan of triangles, and every
+/// other statement (normals, groups, materials) is ignored. The mesh only gets UVs if every corner
+/// of every face refers to a texture coordinate, and then gets tangents to go with them.
+pub fn parse_obj(s: &str) -> Result<Mesh, ErrParseObj> {
+    let mut vertices = vec![];
+    let mut texcoords = vec![];
+    let mut faces = vec![];
+    let mut uvs = Some(vec![]);
+    for (n, line) in s.lines().enumerate() {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("v") => {
+                vertices.push(Point::new(
+                    number(&mut words)?,
+                    number(&mut words)?,
+                    number(&mut words)?,
+                ));
+            }
+            Some("vt") => texcoords.push((number(&mut words)?, number(&mut words)?)),
+            Some("f") => {
+                let corners = words
+                    .map(|w| {
+                        let v = vertex_index(w, vertices.len())?;
+                        Ok((v, texcoord_index(w, texcoords.len())))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                if corners.len() < 3 {
+                    return Err(ErrParseObj::DegenerateFace(n + 1));
+                }
+                for i in 1..corners.len() - 1 {
+                    let [a, b, c] = [corners[0], corners[i], corners[i + 1]];
+                    faces.push([a.0, b.0, c.0]);
+                    uvs = uvs.and_then(|mut uvs: Vec<_>| {
+                        uvs.push([a.1?, b.1?, c.1?].map(|t| texcoords[t]));
+                        Some(uvs)
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    let mesh = Mesh::new(vertices, faces);
+    Ok(match uvs {
+        Some(uvs) if !uvs.is_empty() => mesh.with_uvs(uvs).with_tangents(),
+        _ => mesh,
+    })
+}
+
+/// Reads and parses an OBJ file. See [`parse_obj`].
+pub fn read_obj(path: impl AsRef<Path>) -> Result<Mesh, ErrParseObj> {
+    parse_obj(&std::fs::read_to_string(path).map_err(|e| ErrParseObj::Io(e.to_string()))?)
+}
+
+fn number<'a>(words: &mut impl Iterator<Item = &'a str>) -> Result<f64, ErrParseObj> {
+    let w = words.next().unwrap_or_default();
+    w.parse()
+        .map_err(|_| ErrParseObj::InvalidNumber(w.to_string()))
+}
+
+/// Finds which vertex a face corner refers to. Corners look like `v`, `v/vt`, `v//vn` or
+/// `v/vt/vn`, where `v` counts from one, or backwards from the latest vertex if negative.
+fn vertex_index(word: &str, count: usize) -> Result<usize, ErrParseObj> {
+    let v = word.split('/').next().unwrap_or_default();
+    let invalid = || ErrParseObj::InvalidIndex(word.to_string());
+    let i: i64 = v.parse().map_err(|_| invalid())?;
+    let index = if i < 0 { count as i64 + i } else { i - 1 };
+    if index < 0 || index >= count as i64 {
+        return Err(invalid());
+    }
+    Ok(index as usize)
+}
+
+/// Finds which texture coordinate a face corner refers to, if it refers to a valid one.
+fn texcoord_index(word: &str, count: usize) -> Option<usize> {
+    let i: i64 = word.split('/').nth(1)?.parse().ok()?;
+    let index = if i < 0 { count as i64 + i } else { i - 1 };
+    (0..count as i64).contains(&index).then_some(index as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_obj, ErrParseObj};
+    use crate::{p, v};
+
+    #[test]
+    fn ignores_unrecognized_lines() {
+        let obj = "There was a young lady named Bright\nwho traveled much faster than light.\n";
+        let m = parse_obj(obj).unwrap();
+        assert!(m.vertices().is_empty());
+        assert!(m.faces().is_empty());
+    }
+
+    #[test]
+    fn vertices_and_faces() {
+        let obj = "
+v -1 1 0
+v -1.0000 0.5000 0.0000
+v 1 0 0
+v 1 1 0
+
+f 1 2 3
+f 1 3 4
+";
+        let m = parse_obj(obj).unwrap();
+        assert_eq!(m.vertices()[1], p!(-1, 0.5, 0));
+        assert_eq!(m.faces(), &[[0, 1, 2], [0, 2, 3]]);
+    }
+
+    #[test]
+    fn polygons_become_triangle_fans() {
+        let obj = "
+v -1 1 0
+v -1 0 0
+v 1 0 0
+v 1 1 0
+v 0 2 0
+vn 0 0 1
+
+f 1/1/1 2//1 3 -2 -1
+";
+        let m = parse_obj(obj).unwrap();
+        assert_eq!(m.faces(), &[[0, 1, 2], [0, 2, 3], [0, 3, 4]]);
+        assert!(m.uvs().is_none());
+    }
+
+    #[test]
+    fn texture_coordinates() {
+        let obj = "
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+
+f 1/1 2/2/1 3/3 4/-1
+";
+        let m = parse_obj(obj).unwrap();
+        assert_eq!(
+            m.uvs().unwrap(),
+            &[
+                [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0)],
+                [(0.0, 0.0), (1.0, 1.0), (0.0, 1.0)]
+            ]
+        );
+        let along = (v!(1, 0, 0), v!(0, 1, 0));
+        assert_eq!(m.tangents().unwrap(), &[[along; 3], [along; 3]]);
+    }
+
+    #[test]
+    fn bad_faces_are_rejected() {
+        assert_eq!(
+            parse_obj("v 0 0 0\nv 1 0 0\nf 1 2 3"),
+            Err(ErrParseObj::InvalidIndex("3".to_string()))
+        );
+        assert_eq!(
+            parse_obj("v 0 0 0\nv 1 0 0\nf 1 2"),
+            Err(ErrParseObj::DegenerateFace(3))
+        );
+        assert_eq!(
+            parse_obj("v 0 zero 0"),
+            Err(ErrParseObj::InvalidNumber("zero".to_string()))
+        );
+    }
+}
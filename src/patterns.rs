@@ -1,10 +1,19 @@
+use crate::canvas::Canvas;
 use crate::color::Color;
-use crate::shapes::Shape;
+use crate::ray::Ray;
+use crate::shapes::{tangent_frame, Shape};
 use crate::transform::Tr;
 use crate::tuple::Point;
+use crate::EPSILON;
+use serde::Deserialize;
 use std::any::Any;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::f64::consts::PI;
 use std::fmt::Debug;
-use std::sync::Arc;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 
 /// Just a trait object for a pattern. But wrapped in some shit so we can send it across threads.
 pub type Graphic = Option<Arc<dyn Pattern>>;
@@ -13,7 +22,7 @@ pub trait Pattern: Send + Sync + Any + Debug {
     /// Given a shape and a point on that shape (in world space), returns the correct color for
     /// that point. This method should not be implemented manually.
     fn color_on_object(&self, s: &dyn Shape, p: Point) -> Color {
-        let object_p = s.inv_transform().matrix() * p; // the point, in object space
+        let object_p = s.world_to_object(p); // the point, in object space
         let pattern_p = self.inv_transform().matrix() * object_p; // the point, in pattern space
         self.color_at(pattern_p)
     }
@@ -73,7 +82,7 @@ impl Pattern for Stripe {
     }
 
     fn eqx(&self, other: &dyn Any) -> bool {
-        other.downcast_ref::<Self>().map_or(false, |a| a == self)
+        other.downcast_ref::<Self>().is_some_and(|a| a == self)
     }
 
     fn transform(&self) -> Tr {
@@ -129,7 +138,7 @@ impl Pattern for Gradient {
     }
 
     fn eqx(&self, other: &dyn Any) -> bool {
-        other.downcast_ref::<Self>().map_or(false, |a| a == self)
+        other.downcast_ref::<Self>().is_some_and(|a| a == self)
     }
 
     fn transform(&self) -> Tr {
@@ -187,7 +196,7 @@ impl Pattern for Ring {
     }
 
     fn eqx(&self, other: &dyn Any) -> bool {
-        other.downcast_ref::<Self>().map_or(false, |a| a == self)
+        other.downcast_ref::<Self>().is_some_and(|a| a == self)
     }
 
     fn transform(&self) -> Tr {
@@ -246,7 +255,712 @@ impl Pattern for Checkers {
     }
 
     fn eqx(&self, other: &dyn Any) -> bool {
-        other.downcast_ref::<Self>().map_or(false, |a| a == self)
+        other.downcast_ref::<Self>().is_some_and(|a| a == self)
+    }
+
+    fn transform(&self) -> Tr {
+        self.transform
+    }
+
+    fn inv_transform(&self) -> Tr {
+        self.inv_transform
+    }
+
+    fn set_transform(&mut self, t: Tr) {
+        self.transform = t;
+        self.inv_transform = t.inverse();
+    }
+}
+
+/// A grid of thin lines on the xz plane, for floors. The lines are filtered with a footprint
+/// which grows with distance from the origin, so instead of shimmering towards the horizon the
+/// grid smoothly turns into its average color, and then fades out into a solid color.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Grid {
+    line: Color,
+    fill: Color,
+    /// Width of the lines, as a fraction of the spacing between them.
+    width: f64,
+    /// Distances over which the grid fades into the far color.
+    fade: (f64, f64),
+    far: Color,
+    /// How wide the filter is per unit of distance from the origin.
+    filter_slope: f64,
+
+    transform: Tr,
+    inv_transform: Tr,
+}
+
+impl Grid {
+    pub fn new(line: Color, fill: Color) -> Self {
+        let width = 0.05;
+        Self {
+            line,
+            fill,
+            width,
+            fade: (20.0, 60.0),
+            far: Self::average(line, fill, width),
+            filter_slope: 0.01,
+            transform: Tr::default(),
+            inv_transform: Tr::default(),
+        }
+    }
+
+    pub fn with_transform(mut self, t: Tr) -> Self {
+        self.transform = t;
+        self.inv_transform = t.inverse();
+        self
+    }
+
+    /// Sets the width of the lines, as a fraction of the spacing between them. Unless a far
+    /// color was set, the grid fades into its new average color.
+    pub fn with_line_width(mut self, width: f64) -> Self {
+        let far_is_average = self.far == Self::average(self.line, self.fill, self.width);
+        self.width = width.clamp(0.0, 1.0);
+        if far_is_average {
+            self.far = Self::average(self.line, self.fill, self.width);
+        }
+        self
+    }
+
+    /// Sets where the grid starts and finishes fading out.
+    pub fn with_fade(mut self, start: f64, end: f64) -> Self {
+        self.fade = (start, end.max(start));
+        self
+    }
+
+    /// Sets the color the grid fades into. By default this is the grid's average color, so that
+    /// the fade is invisible and the floor just goes smooth.
+    pub fn with_far_color(mut self, far: Color) -> Self {
+        self.far = far;
+        self
+    }
+
+    pub fn with_filter_slope(mut self, slope: f64) -> Self {
+        self.filter_slope = slope.max(0.0);
+        self
+    }
+
+    /// The color of the grid when seen from very far away.
+    fn average(line: Color, fill: Color, width: f64) -> Color {
+        let coverage = 1.0 - (1.0 - width) * (1.0 - width);
+        fill.lerp(line, coverage)
+    }
+
+    /// The fraction of `[x - fw / 2, x + fw / 2]` covered by lines, found analytically by
+    /// integrating over the lines.
+    fn coverage(&self, x: f64, fw: f64) -> f64 {
+        let w = self.width;
+        // Lines run from n - w/2 to n + w/2. This counts how much of [0, x] they cover.
+        let covered = |x: f64| {
+            let x = x + w / 2.0;
+            x.floor() * w + (x - x.floor()).min(w)
+        };
+        if fw <= 0.0 {
+            let f = (x + w / 2.0) - (x + w / 2.0).floor();
+            return if f < w { 1.0 } else { 0.0 };
+        }
+        (covered(x + fw / 2.0) - covered(x - fw / 2.0)) / fw
+    }
+}
+
+impl Pattern for Grid {
+    fn color_at(&self, p: Point) -> Color {
+        let d = p.x().hypot(p.z());
+        let fw = d * self.filter_slope;
+        let (cx, cz) = (self.coverage(p.x(), fw), self.coverage(p.z(), fw));
+        let coverage = 1.0 - (1.0 - cx) * (1.0 - cz);
+        let near = self.fill.lerp(self.line, coverage);
+
+        let (start, end) = self.fade;
+        if d <= start {
+            return near;
+        }
+        if d >= end {
+            return self.far;
+        }
+        let t = (d - start) / (end - start);
+        near.lerp(self.far, t * t * (3.0 - 2.0 * t))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn eqx(&self, other: &dyn Any) -> bool {
+        other.downcast_ref::<Self>().is_some_and(|a| a == self)
+    }
+
+    fn transform(&self) -> Tr {
+        self.transform
+    }
+
+    fn inv_transform(&self) -> Tr {
+        self.inv_transform
+    }
+
+    fn set_transform(&mut self, t: Tr) {
+        self.transform = t;
+        self.inv_transform = t.inverse();
+    }
+}
+
+/// An image wrapped onto shapes by their UV coordinates, e.g. a label around a can. The image is
+/// sampled at the nearest pixel, with v running from the bottom of the image to the top.
+///
+/// Without a shape to give UVs, points are mapped straight down onto the xz plane, with the image
+/// repeating every unit.
+#[derive(Debug, Clone)]
+pub struct Texture {
+    image: Arc<Canvas>,
+    uv: UvTransform,
+
+    transform: Tr,
+    inv_transform: Tr,
+}
+
+impl PartialEq for Texture {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.image, &other.image)
+            && self.uv == other.uv
+            && self.transform == other.transform
+    }
+}
+
+impl Texture {
+    pub fn new(image: Arc<Canvas>) -> Self {
+        Self {
+            image,
+            uv: UvTransform::default(),
+            transform: Tr::default(),
+            inv_transform: Tr::default(),
+        }
+    }
+
+    /// Sets how the image is placed on the shape's UV coordinates.
+    pub fn with_uv_transform(mut self, uv: UvTransform) -> Self {
+        self.uv = uv;
+        self
+    }
+
+    pub fn with_transform(mut self, t: Tr) -> Self {
+        self.transform = t;
+        self.inv_transform = t.inverse();
+        self
+    }
+
+    /// The color of the image at some UV coordinate.
+    pub fn color_at_uv(&self, u: f64, v: f64) -> Color {
+        let (u, v) = self.uv.apply(u, v);
+        let (w, h) = (self.image.width(), self.image.height());
+        if w == 0 || h == 0 {
+            return Color::black();
+        }
+        let x = ((u * w as f64) as usize).min(w - 1);
+        let y = (((1.0 - v) * h as f64) as usize).min(h - 1);
+        self.image.pixel_at(x, y)
+    }
+}
+
+impl Pattern for Texture {
+    fn color_at(&self, p: Point) -> Color {
+        self.color_at_uv(p.x(), p.z())
+    }
+
+    /// Samples the image at the UV coordinates of the point on the shape. The pattern's own
+    /// transformation is not used, since the shape decides where its UVs go.
+    fn color_on_object(&self, s: &dyn Shape, p: Point) -> Color {
+        let (u, v) = s.uv_at(p);
+        self.color_at_uv(u, v)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn eqx(&self, other: &dyn Any) -> bool {
+        other.downcast_ref::<Self>().is_some_and(|a| a == self)
+    }
+
+    fn transform(&self) -> Tr {
+        self.transform
+    }
+
+    fn inv_transform(&self) -> Tr {
+        self.inv_transform
+    }
+
+    fn set_transform(&mut self, t: Tr) {
+        self.transform = t;
+        self.inv_transform = t.inverse();
+    }
+}
+
+/// Places a texture on a surface's UV coordinates: turned about the middle of the texture, then
+/// repeated some number of times along each axis, then shifted. UVs which end up past the edge of
+/// the texture wrap around, so a scale of (4, 2) tiles it four times across and twice up.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct UvTransform {
+    offset: (f64, f64),
+    scale: (f64, f64),
+    /// In radians, counterclockwise.
+    rotation: f64,
+}
+
+impl Default for UvTransform {
+    fn default() -> Self {
+        Self {
+            offset: (0.0, 0.0),
+            scale: (1.0, 1.0),
+            rotation: 0.0,
+        }
+    }
+}
+
+impl UvTransform {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_offset(mut self, u: f64, v: f64) -> Self {
+        self.offset = (u, v);
+        self
+    }
+
+    /// Sets how many times the texture repeats along each axis.
+    pub fn with_scale(mut self, u: f64, v: f64) -> Self {
+        self.scale = (u, v);
+        self
+    }
+
+    pub fn with_rotation(mut self, radians: f64) -> Self {
+        self.rotation = radians;
+        self
+    }
+
+    pub fn offset(&self) -> (f64, f64) {
+        self.offset
+    }
+
+    pub fn scale(&self) -> (f64, f64) {
+        self.scale
+    }
+
+    pub fn rotation(&self) -> f64 {
+        self.rotation
+    }
+
+    /// Maps a UV coordinate on a surface to where it lands on the texture, within [0, 1).
+    pub fn apply(&self, u: f64, v: f64) -> (f64, f64) {
+        let (sin, cos) = self.rotation.sin_cos();
+        let (cu, cv) = (u - 0.5, v - 0.5);
+        let (ru, rv) = (cu * cos - cv * sin + 0.5, cu * sin + cv * cos + 0.5);
+        (
+            (ru * self.scale.0 + self.offset.0).rem_euclid(1.0),
+            (rv * self.scale.1 + self.offset.1).rem_euclid(1.0),
+        )
+    }
+}
+
+/// How many locks a [`Cached`] pattern splits its entries between, so that threads looking up
+/// different points rarely wait on each other.
+const CACHE_SHARDS: usize = 16;
+
+/// Remembers the colors some expensive pattern gives, so that points close enough together to
+/// round to the same spot only work out their color once. Points are rounded to a grid of cells,
+/// and each cell is colored as the pattern colors its center, so what ends up cached doesn't
+/// depend on which point in the cell happened to be looked up first. Each shape gets entries of
+/// its own, since the same point may be colored differently on different shapes. Once full, the
+/// least recently used colors are forgotten first.
+///
+/// Rounding makes the colors a little blocky, so the cells should be smaller than a pixel's
+/// footprint. Caching only pays off when lookups are much cheaper than the pattern itself, like
+/// for image lookups with many layers of noise on top; check the [`CacheStats`] to be sure.
+#[derive(Debug)]
+pub struct Cached<P: Pattern> {
+    pattern: P,
+    /// The size of the cells points are rounded to.
+    cell: f64,
+    shards: Vec<Mutex<Lru>>,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+/// Where a color sits in a cache: the shape it is on, if any, and the cell it is in.
+type CacheKey = (Option<usize>, i64, i64, i64);
+
+/// A least recently used cache of colors. Each entry is stamped with when it was last used, so
+/// that the oldest can be found quickly.
+#[derive(Debug, Default)]
+struct Lru {
+    capacity: usize,
+    entries: HashMap<CacheKey, (Color, u64)>,
+    by_age: BTreeMap<u64, CacheKey>,
+    clock: u64,
+}
+
+impl Lru {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            ..Self::default()
+        }
+    }
+
+    fn get(&mut self, key: &CacheKey) -> Option<Color> {
+        self.clock += 1;
+        let (color, stamp) = self.entries.get_mut(key)?;
+        self.by_age.remove(stamp);
+        *stamp = self.clock;
+        self.by_age.insert(self.clock, *key);
+        Some(*color)
+    }
+
+    fn insert(&mut self, key: CacheKey, color: Color) {
+        self.clock += 1;
+        if let Some((_, stamp)) = self.entries.insert(key, (color, self.clock)) {
+            self.by_age.remove(&stamp);
+        }
+        self.by_age.insert(self.clock, key);
+        while self.entries.len() > self.capacity {
+            let Some((_, oldest)) = self.by_age.pop_first() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.by_age.clear();
+    }
+}
+
+/// How well a [`Cached`] pattern is doing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CacheStats {
+    pub hits: usize,
+    pub misses: usize,
+}
+
+impl CacheStats {
+    /// The fraction of lookups which found a color already worked out, or zero before any
+    /// lookups.
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            return 0.0;
+        }
+        self.hits as f64 / total as f64
+    }
+}
+
+impl<P: Pattern> Cached<P> {
+    /// Caches some pattern, keeping at most `capacity` colors. Points are rounded to cells of
+    /// 1e-4 units unless told otherwise.
+    pub fn new(pattern: P, capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        let shards = CACHE_SHARDS.min(capacity);
+        Self {
+            pattern,
+            cell: 1e-4,
+            shards: (0..shards)
+                .map(|_| Mutex::new(Lru::new(capacity.div_ceil(shards))))
+                .collect(),
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+        }
+    }
+
+    /// Sets the size of the cells points are rounded to. Cached colors are forgotten.
+    pub fn with_cell_size(mut self, cell: f64) -> Self {
+        self.cell = cell.max(f64::MIN_POSITIVE);
+        self.clear();
+        self
+    }
+
+    pub fn pattern(&self) -> &P {
+        &self.pattern
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Forgets every cached color, and resets the statistics.
+    pub fn clear(&mut self) {
+        for shard in &mut self.shards {
+            shard.get_mut().unwrap_or_else(|e| e.into_inner()).clear();
+        }
+        self.hits = AtomicUsize::new(0);
+        self.misses = AtomicUsize::new(0);
+    }
+
+    /// Finds the color for the cell some point is in, working it out at the cell's center only if
+    /// it is not cached yet.
+    fn lookup(&self, shape: Option<usize>, p: Point, color: impl FnOnce(Point) -> Color) -> Color {
+        let q = |c: f64| (c / self.cell).round() as i64;
+        let key = (shape, q(p.x()), q(p.y()), q(p.z()));
+        let mut h = DefaultHasher::new();
+        key.hash(&mut h);
+        let shard = &self.shards[h.finish() as usize % self.shards.len()];
+        // A thread which panicked mid-update can at worst leave a stale entry behind.
+        if let Some(c) = shard.lock().unwrap_or_else(|e| e.into_inner()).get(&key) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return c;
+        }
+        // Work out the color without holding the lock, since that is the slow part.
+        let (_, x, y, z) = key;
+        let center = Point::new(
+            x as f64 * self.cell,
+            y as f64 * self.cell,
+            z as f64 * self.cell,
+        );
+        let c = color(center);
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        shard
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(key, c);
+        c
+    }
+}
+
+impl<P: Pattern> Pattern for Cached<P> {
+    fn color_at(&self, p: Point) -> Color {
+        self.lookup(None, p, |p| self.pattern.color_at(p))
+    }
+
+    fn color_on_object(&self, s: &dyn Shape, p: Point) -> Color {
+        self.lookup(Some(s.id()), p, |p| self.pattern.color_on_object(s, p))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    /// Cached patterns are equal if the patterns they cache are, whatever is in their caches.
+    fn eqx(&self, other: &dyn Any) -> bool {
+        other
+            .downcast_ref::<Self>()
+            .is_some_and(|a| a.cell == self.cell && a.pattern.eqx(self.pattern.as_any()))
+    }
+
+    fn transform(&self) -> Tr {
+        self.pattern.transform()
+    }
+
+    fn inv_transform(&self) -> Tr {
+        self.pattern.inv_transform()
+    }
+
+    fn set_transform(&mut self, t: Tr) {
+        self.pattern.set_transform(t);
+        self.clear();
+    }
+}
+
+/// Estimates how curved a surface is around some point on it, given in world space, from how much
+/// its normal turns over `radius`. This is roughly one over the radius of the curve: positive on
+/// bumps and edges, negative in creases and hollows, and zero on flat ground.
+pub fn curvature_at(s: &dyn Shape, p: Point, radius: f64) -> f64 {
+    if radius <= 0.0 {
+        return 0.0;
+    }
+    let turn: f64 = s
+        .normals_around(p, radius)
+        .iter()
+        .map(|(d, n)| n.dot(*d))
+        .sum();
+    turn / (4.0 * radius * radius)
+}
+
+/// Estimates how much of the sky some point on a surface, given in world space, cannot see because
+/// the shape itself is in the way, looking no further than `radius`. Gives 0 out in the open and
+/// approaches 1 at the bottom of deep crevices. Only the shape itself is checked, so a shape in a
+/// group is not darkened by its neighbours. The directions are spread evenly over the hemisphere
+/// and weighted towards the normal, so the same point always gives the same answer.
+pub fn occlusion_at(s: &dyn Shape, p: Point, radius: f64, samples: usize) -> f64 {
+    if samples == 0 || radius <= 0.0 {
+        return 0.0;
+    }
+    let n = s.normal_at(p);
+    let (u, v) = tangent_frame(n);
+    let origin = p + n * EPSILON;
+    let golden_angle = PI * (3.0 - 5f64.sqrt());
+    let blocked = (0..samples)
+        .filter(|&i| {
+            let r = ((i as f64 + 0.5) / samples as f64).sqrt();
+            let phi = golden_angle * i as f64;
+            let d = u * (r * phi.cos()) + v * (r * phi.sin()) + n * (1.0 - r * r).sqrt();
+            s.intersect_with(Ray::new(origin, d))
+                .iter()
+                .any(|x| x.t() > EPSILON && x.t() < radius)
+        })
+        .count();
+    blocked as f64 / samples as f64
+}
+
+/// Wears a surface where its shape calls for it. Edges and bumps, which would get knocked about,
+/// fade to the edge color, while creases and hollows, where grime collects, fade to the cavity
+/// color. How curved the surface is comes from [`curvature_at`], so this works on any shape, but
+/// it needs a shape to look at; on its own it is just the base color.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Wear {
+    base: Color,
+    edge: Color,
+    cavity: Color,
+    /// How far around each point to look.
+    radius: f64,
+    /// How far the normal has to turn across the radius, in radians, for full wear.
+    full: f64,
+
+    transform: Tr,
+    inv_transform: Tr,
+}
+
+impl Wear {
+    pub fn new(base: Color, edge: Color) -> Self {
+        Self {
+            base,
+            edge,
+            cavity: base,
+            radius: 0.05,
+            full: 0.25,
+            transform: Tr::default(),
+            inv_transform: Tr::default(),
+        }
+    }
+
+    pub fn with_transform(mut self, t: Tr) -> Self {
+        self.transform = t;
+        self.inv_transform = t.inverse();
+        self
+    }
+
+    /// Sets the color creases and hollows fade to. By default they are left alone.
+    pub fn with_cavity(mut self, cavity: Color) -> Self {
+        self.cavity = cavity;
+        self
+    }
+
+    /// Sets how far around each point to look. Wider radii wear a wider band along each edge.
+    pub fn with_radius(mut self, radius: f64) -> Self {
+        self.radius = radius.max(0.0);
+        self
+    }
+
+    /// Sets how far the normal has to turn across the radius, in radians, for the surface to be
+    /// fully worn. The default of 0.25 fully wears a right-angled edge.
+    pub fn with_full_wear(mut self, turn: f64) -> Self {
+        self.full = turn.max(EPSILON);
+        self
+    }
+}
+
+impl Pattern for Wear {
+    fn color_at(&self, _: Point) -> Color {
+        self.base
+    }
+
+    fn color_on_object(&self, s: &dyn Shape, p: Point) -> Color {
+        let amount = (curvature_at(s, p, self.radius) * self.radius / self.full).clamp(-1.0, 1.0);
+        if amount >= 0.0 {
+            self.base.lerp(self.edge, amount)
+        } else {
+            self.base.lerp(self.cavity, -amount)
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn eqx(&self, other: &dyn Any) -> bool {
+        other.downcast_ref::<Self>().is_some_and(|a| a == self)
+    }
+
+    fn transform(&self) -> Tr {
+        self.transform
+    }
+
+    fn inv_transform(&self) -> Tr {
+        self.inv_transform
+    }
+
+    fn set_transform(&mut self, t: Tr) {
+        self.transform = t;
+        self.inv_transform = t.inverse();
+    }
+}
+
+/// Darkens the parts of a surface which the shape hides from the sky, like the inside of a ring
+/// or the folds of a blob, using [`occlusion_at`]. Points out in the open get the open color,
+/// and the more hidden a point is, the closer it gets to the occluded color.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cavity {
+    open: Color,
+    occluded: Color,
+    /// How far to look for the shape blocking the sky.
+    radius: f64,
+    samples: usize,
+
+    transform: Tr,
+    inv_transform: Tr,
+}
+
+impl Cavity {
+    pub fn new(open: Color, occluded: Color) -> Self {
+        Self {
+            open,
+            occluded,
+            radius: 0.5,
+            samples: 16,
+            transform: Tr::default(),
+            inv_transform: Tr::default(),
+        }
+    }
+
+    pub fn with_transform(mut self, t: Tr) -> Self {
+        self.transform = t;
+        self.inv_transform = t.inverse();
+        self
+    }
+
+    /// Sets how far to look for the shape blocking the sky. Parts of the shape further away than
+    /// this don't darken the point.
+    pub fn with_radius(mut self, radius: f64) -> Self {
+        self.radius = radius.max(0.0);
+        self
+    }
+
+    /// Sets how many directions to look in. More directions give smoother shading.
+    pub fn with_samples(mut self, samples: usize) -> Self {
+        self.samples = samples.max(1);
+        self
+    }
+}
+
+impl Pattern for Cavity {
+    fn color_at(&self, _: Point) -> Color {
+        self.open
+    }
+
+    fn color_on_object(&self, s: &dyn Shape, p: Point) -> Color {
+        let occlusion = occlusion_at(s, p, self.radius, self.samples);
+        self.open.lerp(self.occluded, occlusion)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn eqx(&self, other: &dyn Any) -> bool {
+        other.downcast_ref::<Self>().is_some_and(|a| a == self)
     }
 
     fn transform(&self) -> Tr {
@@ -265,12 +979,20 @@ impl Pattern for Checkers {
 
 #[cfg(test)]
 mod tests {
-    use super::{Checkers, Gradient, Pattern, Ring, Stripe};
+    use super::{
+        curvature_at, occlusion_at, CacheStats, Cached, Cavity, Checkers, Gradient, Grid, Pattern,
+        Ring, Stripe, Texture, UvTransform, Wear,
+    };
+    use crate::canvas::Canvas;
     use crate::color::Color;
-    use crate::p;
-    use crate::shapes::Sphere;
+    use crate::light::Material;
+    use crate::shapes::{Plane, Quad, RoundedBox, Sphere, Torus};
     use crate::transform::Tr;
     use crate::tuple::Point;
+    use crate::{assert_f64_eq, p};
+    use rayon::prelude::*;
+    use std::f64::consts::FRAC_PI_2;
+    use std::sync::Arc;
 
     #[test]
     fn creating_a_stripe_pattern() {
@@ -341,6 +1063,31 @@ mod tests {
         assert_eq!(c, Color::white());
     }
 
+    #[test]
+    fn transforming_uvs() {
+        let check = |t: UvTransform, uv: (f64, f64), want: (f64, f64)| {
+            let got = t.apply(uv.0, uv.1);
+            assert_f64_eq!(got.0, want.0, 1e-9);
+            assert_f64_eq!(got.1, want.1, 1e-9);
+        };
+        check(UvTransform::new(), (0.25, 0.75), (0.25, 0.75));
+        // Tiled twice across, so the right half repeats the left.
+        let tiled = UvTransform::new().with_scale(2.0, 1.0);
+        check(tiled, (0.75, 0.5), (0.5, 0.5));
+        let shifted = UvTransform::new().with_offset(0.5, -0.25);
+        check(shifted, (0.75, 0.0), (0.25, 0.75));
+        // A quarter turn swings the right edge up to the top.
+        let turned = UvTransform::new().with_rotation(FRAC_PI_2);
+        check(turned, (0.75, 0.5), (0.5, 0.75));
+        check(turned, (0.5, 0.5), (0.5, 0.5));
+
+        let t: UvTransform = serde_yaml::from_str("scale: [4, 2]\nrotation: 0.5").unwrap();
+        assert_eq!(
+            t,
+            UvTransform::new().with_scale(4.0, 2.0).with_rotation(0.5)
+        );
+    }
+
     #[derive(Debug)]
     struct TestPattern {
         transform: Tr,
@@ -476,4 +1223,156 @@ mod tests {
         assert_eq!(pattern.color_at(p!(0.0, 0.0, 0.99)), Color::white());
         assert_eq!(pattern.color_at(p!(0.0, 0.0, 1.01)), Color::black());
     }
+
+    #[test]
+    fn grid_has_lines_on_integers() {
+        let pattern = Grid::new(Color::white(), Color::black()).with_filter_slope(0.0);
+        assert_eq!(pattern.color_at(p!(0.0, 0.0, 0.5)), Color::white());
+        assert_eq!(pattern.color_at(p!(2.01, 0.0, 0.5)), Color::white());
+        assert_eq!(pattern.color_at(p!(0.5, 0.0, -3.02)), Color::white());
+        assert_eq!(pattern.color_at(p!(0.5, 0.0, 0.5)), Color::black());
+        assert_eq!(pattern.color_at(p!(1.9, 0.0, 0.1)), Color::black());
+    }
+
+    #[test]
+    fn grid_blurs_into_average_with_distance() {
+        let pattern = Grid::new(Color::white(), Color::black())
+            .with_line_width(0.1)
+            .with_fade(1000.0, 2000.0);
+        let average = Color::new(0.19, 0.19, 0.19);
+        // Far enough that the filter spans many lines, the grid is its average everywhere.
+        for x in [500.0, 500.3, 500.5, 500.95] {
+            let got = pattern.color_at(p!(x, 0.0, 0.0));
+            assert_f64_eq!(got.r(), average.r(), 1e-3);
+        }
+        assert_eq!(pattern.color_at(p!(5000.0, 0.0, 0.0)), average);
+    }
+
+    #[test]
+    fn grid_fades_into_far_color() {
+        let pattern = Grid::new(Color::white(), Color::black())
+            .with_fade(10.0, 20.0)
+            .with_far_color(Color::new(0.5, 0.0, 0.0));
+        assert_eq!(
+            pattern.color_at(p!(0.0, 0.0, 20.0)),
+            Color::new(0.5, 0.0, 0.0)
+        );
+        assert_eq!(
+            pattern.color_at(p!(0.5, 0.0, 30.5)),
+            Color::new(0.5, 0.0, 0.0)
+        );
+        // Halfway through the fade, on a line.
+        let mid = pattern.color_at(p!(15.0, 0.0, 0.5));
+        assert!(mid.r() > mid.g() && mid.g() > 0.0);
+    }
+
+    #[test]
+    fn textures_follow_the_shape_uvs() {
+        let mut image = Canvas::new(2, 2);
+        image.write_to(0, 0, Color::magenta());
+        image.write_to(1, 0, Color::orange());
+        image.write_to(0, 1, Color::black());
+        image.write_to(1, 1, Color::white());
+        let tex = Texture::new(Arc::new(image));
+        let quad = Quad::default().with_transform(Tr::new().translate(0.0, 3.0, 0.0));
+        let m = Material::default().with_pattern(Arc::new(tex.clone()));
+        // The top of the image is towards +z, where v is largest.
+        assert_eq!(m.color_on_object(&quad, p!(-0.5, 3, 0.5)), Color::magenta());
+        assert_eq!(m.color_on_object(&quad, p!(0.5, 3, 0.5)), Color::orange());
+        assert_eq!(m.color_on_object(&quad, p!(-0.5, 3, -0.5)), Color::black());
+        assert_eq!(m.color_on_object(&quad, p!(0.5, 3, -0.5)), Color::white());
+
+        let shifted = tex.with_uv_transform(UvTransform::new().with_offset(0.5, 0.0));
+        assert_eq!(
+            shifted.color_on_object(&quad, p!(-0.5, 3, 0.5)),
+            Color::orange()
+        );
+    }
+
+    #[test]
+    fn curvature_of_simple_shapes() {
+        let ball = Sphere::default().with_transform(Tr::new().scale(2.0, 2.0, 2.0));
+        assert_f64_eq!(curvature_at(&ball, p!(0, 2, 0), 0.01), 0.5, 1e-3);
+        assert_f64_eq!(curvature_at(&Plane::default(), p!(3, 0, -1), 0.1), 0.0);
+        // The inside of a fat ring curves away in the hole more than it bulges across the tube.
+        let ring = Torus::new(1.0, 0.75);
+        assert!(curvature_at(&ring, p!(0.25, 0, 0), 0.05) < 0.0);
+        assert!(curvature_at(&ring, p!(1.75, 0, 0), 0.05) > 0.0);
+    }
+
+    #[test]
+    fn wear_marks_edges_and_creases() {
+        let pattern = Wear::new(Color::white(), Color::orange()).with_cavity(Color::black());
+        let cube = RoundedBox::new(0.0);
+        assert_eq!(pattern.color_on_object(&cube, p!(0, 1, 0)), Color::white());
+        assert_eq!(
+            pattern.color_on_object(&cube, p!(0.98, 1, 0)),
+            Color::orange()
+        );
+        let ring = Torus::new(1.0, 0.75);
+        let c = pattern.color_on_object(&ring, p!(0.25, 0, 0));
+        assert!(c.r() < 1.0 && c.r() == c.b());
+        assert_eq!(pattern.color_at(p!(0.98, 1, 0)), Color::white());
+    }
+
+    #[test]
+    fn cavities_are_darker_than_open_surfaces() {
+        let ring = Torus::new(1.0, 0.75);
+        assert_eq!(occlusion_at(&ring, p!(1.75, 0, 0), 3.0, 32), 0.0);
+        let hidden = occlusion_at(&ring, p!(0.25, 0, 0), 3.0, 32);
+        assert!(hidden > 0.5);
+        // Looking only a little way, the far side of the ring is out of reach.
+        assert_eq!(occlusion_at(&ring, p!(0.25, 0, 0), 0.1, 32), 0.0);
+
+        let pattern = Cavity::new(Color::white(), Color::black()).with_radius(3.0);
+        let c = pattern.color_on_object(&ring, p!(0.25, 0, 0));
+        assert!(c.r() < 0.5);
+        assert_eq!(
+            pattern.color_on_object(&ring, p!(1.75, 0, 0)),
+            Color::white()
+        );
+    }
+
+    #[test]
+    fn cached_patterns_remember_colors() {
+        let stripes = Stripe::new(Color::white(), Color::black());
+        let cached = Cached::new(stripes, 100).with_cell_size(0.01);
+        let ball = Sphere::default();
+        assert_eq!(cached.color_at(p!(0.5, 0, 0)), Color::white());
+        assert_eq!(cached.color_at(p!(0.501, 0, 0)), Color::white());
+        assert_eq!(
+            cached.color_on_object(&ball, p!(-0.5, 0, 0)),
+            Color::black()
+        );
+        assert_eq!(cached.stats(), CacheStats { hits: 1, misses: 2 });
+        assert_f64_eq!(cached.stats().hit_rate(), 1.0 / 3.0);
+        // What happens to be cached doesn't matter when comparing.
+        let other = Cached::new(stripes, 5).with_cell_size(0.01);
+        assert!(cached.eqx(other.as_any()));
+
+        // With room for one color, looking up another pushes the first out.
+        let tiny = Cached::new(stripes, 1);
+        for x in [0.5, 1.5, 0.5] {
+            tiny.color_at(p!(x, 0, 0));
+        }
+        assert_eq!(tiny.stats().misses, 3);
+    }
+
+    #[test]
+    fn cached_patterns_are_shared_between_threads() {
+        let image = Arc::new(Canvas::new(4, 4));
+        let cached = Cached::new(Texture::new(image.clone()), 1000);
+        let points: Vec<Point> = (0..400)
+            .map(|i| p!((i % 20) as f64 * 0.05, 0, (i / 20 % 4) as f64 * 0.25))
+            .collect();
+        let got: Vec<Color> = points.par_iter().map(|&p| cached.color_at(p)).collect();
+        let want: Vec<Color> = points
+            .iter()
+            .map(|&p| Texture::new(image.clone()).color_at(p))
+            .collect();
+        assert_eq!(got, want);
+        let stats = cached.stats();
+        assert_eq!(stats.hits + stats.misses, 400);
+        assert!(stats.misses >= 80 && stats.hit_rate() > 0.5);
+    }
 }
@@ -0,0 +1,515 @@
+use crate::canvas::Canvas;
+use crate::color::Color;
+use crate::tuple::Vector;
+use std::f64::consts::FRAC_PI_3;
+use std::path::Path;
+use std::{fs, io};
+
+/// A piecewise linear map from luminance to color.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorRamp {
+    /// Pairs of (luminance, color), sorted by luminance.
+    stops: Vec<(f64, Color)>,
+}
+
+impl Default for ColorRamp {
+    /// The usual false-color ramp: black and blue for crushed shadows, green around middle grey,
+    /// and yellow through red to white as the image approaches and passes full brightness.
+    fn default() -> Self {
+        Self::new(vec![
+            (0.0, Color::black()),
+            (0.02, Color::new(0.0, 0.0, 1.0)),
+            (0.1, Color::new(0.0, 1.0, 1.0)),
+            (0.18, Color::new(0.0, 1.0, 0.0)),
+            (0.5, Color::new(1.0, 1.0, 0.0)),
+            (0.9, Color::new(1.0, 0.0, 0.0)),
+            (1.0, Color::white()),
+        ])
+    }
+}
+
+impl ColorRamp {
+    /// Creates a ramp from (luminance, color) stops, which may be given in any order.
+    pub fn new(mut stops: Vec<(f64, Color)>) -> Self {
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self { stops }
+    }
+
+    /// Finds the color for some luminance. Anything outside the stops takes the color of the
+    /// nearest stop.
+    pub fn sample(&self, l: f64) -> Color {
+        let i = self.stops.partition_point(|(x, _)| *x <= l);
+        match (
+            i.checked_sub(1).map(|i| self.stops[i]),
+            self.stops.get(i).copied(),
+        ) {
+            (None, None) => Color::black(),
+            (Some((_, c)), None) | (None, Some((_, c))) => c,
+            (Some((x0, c0)), Some((x1, c1))) => c0.lerp(c1, (l - x0) / (x1 - x0)),
+        }
+    }
+}
+
+/// Colors a variance image, like the one from
+/// [`Accumulator::resolve_variance`](crate::accumulator::Accumulator::resolve_variance), from
+/// black where there is no noise through blue and red to yellow where there is the most. The
+/// standard deviation is shown rather than the variance, so that moderately noisy pixels still
+/// stand out next to the worst ones.
+pub fn noise_heatmap(variance: &Canvas) -> Canvas {
+    let worst = variance
+        .pixels()
+        .iter()
+        .map(|c| c.luminance().max(0.0).sqrt())
+        .fold(0.0, f64::max);
+    if worst == 0.0 {
+        return Canvas::new(variance.width(), variance.height());
+    }
+    let ramp = ColorRamp::new(vec![
+        (0.0, Color::black()),
+        (0.33, Color::new(0.0, 0.0, 1.0)),
+        (0.67, Color::new(1.0, 0.0, 0.0)),
+        (1.0, Color::new(1.0, 1.0, 0.0)),
+    ]);
+    variance.map(|c| ramp.sample(c.luminance().max(0.0).sqrt() / worst))
+}
+
+/// Replaces every pixel with a color showing how bright it is, to spot where the image is blown
+/// out or underexposed.
+pub fn false_color(image: &Canvas, ramp: &ColorRamp) -> Canvas {
+    image.map(|c| ramp.sample(c.luminance()))
+}
+
+/// The exposures to bracket with when nothing else is specified, in stops.
+pub const DEFAULT_BRACKETS: [f64; 3] = [-2.0, 0.0, 2.0];
+
+/// Scales the brightness of an image by some number of stops. Each stop doubles the light.
+pub fn expose(image: &Canvas, ev: f64) -> Canvas {
+    let k = ev.exp2();
+    image.map(|c| c * k)
+}
+
+/// Produces one copy of an image for each exposure.
+pub fn bracket(image: &Canvas, evs: &[f64]) -> Vec<Canvas> {
+    evs.iter().map(|ev| expose(image, *ev)).collect()
+}
+
+/// Names the file for one exposure in a bracket, e.g. `out.ppm` becomes `out_+2ev.ppm`.
+pub fn bracket_filepath(path: &str, ev: f64) -> String {
+    let p = Path::new(path);
+    let stem = p.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+    let name = match p.extension().and_then(|s| s.to_str()) {
+        Some(ext) if ev == 0.0 => format!("{stem}_0ev.{ext}"),
+        Some(ext) => format!("{stem}_{ev:+}ev.{ext}"),
+        None if ev == 0.0 => format!("{stem}_0ev"),
+        None => format!("{stem}_{ev:+}ev"),
+    };
+    match p.parent() {
+        Some(par) => par.join(name).to_string_lossy().into_owned(),
+        None => name,
+    }
+}
+
+/// Writes every exposure of an image as a separate PPM file next to `path`, and returns the
+/// paths written.
+pub fn write_brackets(image: &Canvas, path: &str, evs: &[f64]) -> io::Result<Vec<String>> {
+    evs.iter()
+        .map(|ev| {
+            let p = bracket_filepath(path, *ev);
+            fs::write(&p, expose(image, *ev).to_ppm())?;
+            Ok(p)
+        })
+        .collect()
+}
+
+/// Draws contour lines over an image, wherever the luminance of `field` crosses a multiple of
+/// `step`. The field would usually be a render of the incident light, so the lines show how
+/// light falls off across the scene.
+pub fn draw_isolines(image: &Canvas, field: &Canvas, step: f64, color: Color) -> Canvas {
+    let (w, h) = (field.width(), field.height());
+    let level = |x: usize, y: usize| (field.pixel_at(x, y).luminance() / step).floor();
+    let mut res = image.clone();
+    if step <= 0.0 {
+        return res;
+    }
+    for y in 0..h {
+        for x in 0..w {
+            let l = level(x, y);
+            let crosses =
+                (x + 1 < w && level(x + 1, y) != l) || (y + 1 < h && level(x, y + 1) != l);
+            if crosses {
+                res.write_to(x, y, color);
+            }
+        }
+    }
+    res
+}
+
+/// How to draw ink outlines over an image, for an illustrated look. Lines go around silhouettes,
+/// and wherever the surface seen folds sharply or jumps in depth.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ink {
+    color: Color,
+    /// Neighbouring pixels whose normals are further apart than this angle, in radians, get a
+    /// line between them.
+    crease_angle: f64,
+    /// Neighbouring pixels whose depths differ by more than this fraction of the nearer one get a
+    /// line between them.
+    depth_ratio: f64,
+}
+
+impl Default for Ink {
+    fn default() -> Self {
+        Self {
+            color: Color::black(),
+            crease_angle: FRAC_PI_3,
+            depth_ratio: 0.1,
+        }
+    }
+}
+
+impl Ink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_color(mut self, c: Color) -> Self {
+        self.color = c;
+        self
+    }
+
+    pub fn with_crease_angle(mut self, angle: f64) -> Self {
+        self.crease_angle = angle;
+        self
+    }
+
+    pub fn with_depth_ratio(mut self, ratio: f64) -> Self {
+        self.depth_ratio = ratio;
+        self
+    }
+
+    pub fn color(&self) -> Color {
+        self.color
+    }
+
+    /// Whether there should be a line between two neighbouring pixels, given the depth and
+    /// normal of whatever surface each one sees.
+    fn separates(&self, a: Option<(f64, Vector)>, b: Option<(f64, Vector)>) -> bool {
+        match (a, b) {
+            (None, None) => false,
+            (Some((da, na)), Some((db, nb))) => {
+                (da - db).abs() > self.depth_ratio * da.min(db)
+                    || na.dot(nb).clamp(-1.0, 1.0).acos() > self.crease_angle
+            }
+            _ => true,
+        }
+    }
+}
+
+/// Draws ink outlines over an image. `surfaces` holds the depth and normal of the surface seen
+/// at each pixel, row by row, or None where nothing was hit.
+pub fn draw_ink(image: &Canvas, surfaces: &[Option<(f64, Vector)>], ink: &Ink) -> Canvas {
+    let (w, h) = (image.width(), image.height());
+    let at = |x: usize, y: usize| surfaces[y * w + x];
+    let mut res = image.clone();
+    for y in 0..h {
+        for x in 0..w {
+            let here = at(x, y);
+            let edge = (x + 1 < w && ink.separates(here, at(x + 1, y)))
+                || (y + 1 < h && ink.separates(here, at(x, y + 1)));
+            if edge {
+                res.write_to(x, y, ink.color);
+            }
+        }
+    }
+    res
+}
+
+/// How to pick an exposure automatically.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AutoExposure {
+    /// Expose so that the median luminance lands on some target, 0.18 being middle grey.
+    Median(f64),
+    /// Expose so that the given percentile of luminance, e.g. 0.99, just reaches full
+    /// brightness. Only the pixels above that percentile will clip.
+    Percentile(f64),
+}
+
+impl Default for AutoExposure {
+    fn default() -> Self {
+        AutoExposure::Median(0.18)
+    }
+}
+
+/// Finds the luminance below which some fraction of the pixels fall.
+pub fn luminance_percentile(image: &Canvas, p: f64) -> f64 {
+    percentile(image.pixels().iter().map(|c| c.luminance()).collect(), p)
+}
+
+/// Finds the value below which some fraction of a list of values fall.
+fn percentile(mut ls: Vec<f64>, p: f64) -> f64 {
+    if ls.is_empty() {
+        return 0.0;
+    }
+    let idx = ((p.clamp(0.0, 1.0) * ls.len() as f64) as usize).min(ls.len() - 1);
+    *ls.select_nth_unstable_by(idx, |a, b| a.total_cmp(b)).1
+}
+
+/// Works out how many stops to expose an image by. An image which is completely black needs no
+/// exposure at all, since no amount of light will help.
+pub fn auto_exposure(image: &Canvas, mode: AutoExposure) -> f64 {
+    exposure_for(image.pixels().iter().map(|c| c.luminance()).collect(), mode)
+}
+
+/// Works out how many stops to expose pixels with some luminances by.
+fn exposure_for(ls: Vec<f64>, mode: AutoExposure) -> f64 {
+    let (l, target) = match mode {
+        AutoExposure::Median(target) => (percentile(ls, 0.5), target),
+        AutoExposure::Percentile(p) => (percentile(ls, p), 1.0),
+    };
+    if l <= 0.0 || target <= 0.0 {
+        return 0.0;
+    }
+    (target / l).log2()
+}
+
+/// Exposes an image automatically. This should happen before the image is tone mapped or
+/// quantized.
+pub fn auto_expose(image: &Canvas, mode: AutoExposure) -> Canvas {
+    expose(image, auto_exposure(image, mode))
+}
+
+/// Exposes an image automatically for a quick look at it, so that dark renders still in progress
+/// can be inspected. Pixels which are still black, like the parts of a tiled render which aren't
+/// done yet, don't count towards the exposure. This is only meant for previews; the finished
+/// image should be exported as it is, or exposed with [`auto_expose`].
+pub fn preview_expose(image: &Canvas, mode: AutoExposure) -> Canvas {
+    let ls = image
+        .pixels()
+        .iter()
+        .map(|c| c.luminance())
+        .filter(|l| *l > 0.0)
+        .collect();
+    expose(image, exposure_for(ls, mode))
+}
+
+/// Merges differently exposed images into one, favouring whichever exposure shows each pixel
+/// closest to middle grey. This is a per-pixel take on exposure fusion, without the pyramid
+/// blending, so it can look a little flat but never blows out.
+pub fn fuse_exposures(images: &[Canvas]) -> Canvas {
+    let Some(first) = images.first() else {
+        return Canvas::new(0, 0);
+    };
+    let mut res = Canvas::new(first.width(), first.height());
+    for (idx, px) in res.pixels_mut().iter_mut().enumerate() {
+        let (sum, total) = images
+            .iter()
+            .map(|image| image.pixels()[idx].clamped())
+            .fold((Color::black(), 0.0), |(sum, total), c| {
+                // Well exposedness, a gaussian centered on 0.5 as in Mertens et al.
+                let w = (-(c.luminance() - 0.5).powi(2) / 0.08).exp() + 1e-12;
+                (sum + c * w, total + w)
+            });
+        *px = sum * (1.0 / total);
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        auto_expose, auto_exposure, bracket, bracket_filepath, draw_ink, draw_isolines, expose,
+        false_color, fuse_exposures, luminance_percentile, noise_heatmap, preview_expose,
+        AutoExposure, ColorRamp, Ink,
+    };
+    use crate::assert_f64_eq;
+    use crate::canvas::Canvas;
+    use crate::color::Color;
+    use crate::v;
+
+    #[test]
+    fn sampling_a_ramp() {
+        let ramp = ColorRamp::new(vec![
+            (1.0, Color::white()),
+            (0.0, Color::black()),
+            (0.5, Color::new(1.0, 0.0, 0.0)),
+        ]);
+        assert_eq!(ramp.sample(-1.0), Color::black());
+        assert_eq!(ramp.sample(0.25), Color::new(0.5, 0.0, 0.0));
+        assert_eq!(ramp.sample(0.5), Color::new(1.0, 0.0, 0.0));
+        assert_eq!(ramp.sample(0.75), Color::new(1.0, 0.5, 0.5));
+        assert_eq!(ramp.sample(2.0), Color::white());
+        assert_eq!(ColorRamp::new(vec![]).sample(0.5), Color::black());
+    }
+
+    #[test]
+    fn false_color_by_luminance() {
+        let mut image = Canvas::new(3, 1);
+        image.write_to(0, 0, Color::black());
+        image.write_to(1, 0, Color::new(0.18, 0.18, 0.18));
+        image.write_to(2, 0, Color::new(4.0, 4.0, 4.0));
+
+        let got = false_color(&image, &ColorRamp::default());
+        assert_eq!(got.pixel_at(0, 0), Color::black());
+        assert_eq!(got.pixel_at(1, 0), Color::new(0.0, 1.0, 0.0));
+        assert_eq!(got.pixel_at(2, 0), Color::white());
+    }
+
+    #[test]
+    fn exposing_by_stops() {
+        let mut image = Canvas::new(1, 1);
+        image.write_to(0, 0, Color::new(0.25, 0.5, 1.0));
+        assert_eq!(
+            expose(&image, 1.0).pixel_at(0, 0),
+            Color::new(0.5, 1.0, 2.0)
+        );
+        assert_eq!(
+            expose(&image, -2.0).pixel_at(0, 0),
+            Color::new(0.0625, 0.125, 0.25)
+        );
+
+        let got = bracket(&image, &[-1.0, 0.0, 1.0]);
+        assert_eq!(got.len(), 3);
+        assert_eq!(got[1].pixel_at(0, 0), image.pixel_at(0, 0));
+    }
+
+    #[test]
+    fn naming_bracket_files() {
+        assert_eq!(bracket_filepath("out.ppm", -2.0), "out_-2ev.ppm");
+        assert_eq!(bracket_filepath("out.ppm", 0.0), "out_0ev.ppm");
+        assert_eq!(
+            bracket_filepath("renders/out.ppm", 1.5),
+            "renders/out_+1.5ev.ppm"
+        );
+        assert_eq!(bracket_filepath("out", 2.0), "out_+2ev");
+    }
+
+    #[test]
+    fn fusing_exposures_picks_the_best_exposed() {
+        let mut dark = Canvas::new(2, 1);
+        dark.write_to(0, 0, Color::new(0.5, 0.5, 0.5));
+        dark.write_to(1, 0, Color::new(0.01, 0.01, 0.01));
+        let bright = expose(&dark, 4.0);
+
+        let got = fuse_exposures(&[dark, bright]);
+        // The left pixel is well exposed in the dark image, and clipped in the bright one.
+        assert!((got.pixel_at(0, 0).r() - 0.5).abs() < 0.05);
+        // The right pixel is nearly black in the dark image, but visible in the bright one.
+        assert!(got.pixel_at(1, 0).r() > 0.1);
+    }
+
+    /// A 1x5 image with luminances 0.1, 0.2, ..., 0.5.
+    fn grey_ramp() -> Canvas {
+        let mut image = Canvas::new(5, 1);
+        for x in 0..5 {
+            let l = (x + 1) as f64 / 10.0;
+            image.write_to(x, 0, Color::new(l, l, l));
+        }
+        image
+    }
+
+    #[test]
+    fn finding_luminance_percentiles() {
+        let image = grey_ramp();
+        assert_f64_eq!(luminance_percentile(&image, 0.0), 0.1, 1e-9);
+        assert_f64_eq!(luminance_percentile(&image, 0.5), 0.3, 1e-9);
+        assert_f64_eq!(luminance_percentile(&image, 1.0), 0.5, 1e-9);
+        assert_eq!(luminance_percentile(&Canvas::new(0, 0), 0.5), 0.0);
+    }
+
+    #[test]
+    fn auto_exposing_to_the_median() {
+        let image = grey_ramp();
+        let ev = auto_exposure(&image, AutoExposure::Median(0.6));
+        assert_f64_eq!(ev, 1.0, 1e-9);
+
+        let got = auto_expose(&image, AutoExposure::Median(0.6));
+        assert_f64_eq!(luminance_percentile(&got, 0.5), 0.6, 1e-9);
+    }
+
+    #[test]
+    fn auto_exposing_to_a_percentile() {
+        let got = auto_expose(&grey_ramp(), AutoExposure::Percentile(0.7));
+        // The 0.4 pixel now sits right at full brightness, and only the one above it clips.
+        assert_f64_eq!(got.pixel_at(3, 0).r(), 1.0, 1e-9);
+        assert_eq!(got.stats().clipped, 20.0);
+    }
+
+    #[test]
+    fn auto_exposing_black_does_nothing() {
+        assert_eq!(
+            auto_exposure(&Canvas::new(2, 2), AutoExposure::default()),
+            0.0
+        );
+    }
+
+    #[test]
+    fn previews_ignore_unrendered_pixels() {
+        // Partly rendered, with the rest still black.
+        let mut image = Canvas::new(12, 1);
+        for x in 0..5 {
+            image.write_to(x, 0, grey_ramp().pixel_at(x, 0));
+        }
+        assert_eq!(auto_exposure(&image, AutoExposure::Median(0.6)), 0.0);
+        let got = preview_expose(&image, AutoExposure::Median(0.6));
+        assert_f64_eq!(got.pixel_at(2, 0).r(), 0.6, 1e-9);
+        assert_eq!(got.pixel_at(7, 0), Color::black());
+    }
+
+    #[test]
+    fn isolines_where_levels_change() {
+        let mut field = Canvas::new(8, 2);
+        for y in 0..2 {
+            for x in 0..8 {
+                let l = x as f64 / 8.0;
+                field.write_to(x, y, Color::new(l, l, l));
+            }
+        }
+        let got = draw_isolines(&Canvas::new(8, 2), &field, 0.25, Color::white());
+        // Levels step up between columns 1-2, 3-4, and 5-6.
+        let row: Vec<bool> = (0..8)
+            .map(|x| got.pixel_at(x, 0) == Color::white())
+            .collect();
+        assert_eq!(row, [false, true, false, true, false, true, false, false]);
+    }
+
+    #[test]
+    fn ink_around_silhouettes_and_creases() {
+        // A row of pixels: background, a flat face, a fold, another face further back.
+        let up = v!(0, 0, -1);
+        let side = v!(1, 0, 0);
+        let surfaces = [
+            None,
+            Some((5.0, up)),
+            Some((5.1, up)),
+            Some((5.2, side)),
+            Some((5.3, side)),
+            Some((9.0, side)),
+        ];
+        let got = draw_ink(
+            &Canvas::new(6, 1),
+            &surfaces,
+            &Ink::new().with_color(Color::white()),
+        );
+        let row: Vec<bool> = (0..6)
+            .map(|x| got.pixel_at(x, 0) == Color::white())
+            .collect();
+        assert_eq!(row, [true, false, true, false, true, false]);
+    }
+
+    #[test]
+    fn heatmap_of_noise() {
+        let mut variance = Canvas::new(3, 1);
+        variance.write_to(1, 0, Color::new(0.25, 0.25, 0.25));
+        variance.write_to(2, 0, Color::new(1.0, 1.0, 1.0));
+        let got = noise_heatmap(&variance);
+        assert_eq!(got.pixel_at(0, 0), Color::black());
+        assert_eq!(got.pixel_at(2, 0), Color::new(1.0, 1.0, 0.0));
+        // Half the worst standard deviation lands between blue and red.
+        let mid = got.pixel_at(1, 0);
+        assert!(mid.r() > 0.0 && mid.b() > 0.0 && mid.g() == 0.0);
+        assert_eq!(
+            noise_heatmap(&Canvas::new(2, 2)).pixel_at(1, 1),
+            Color::black()
+        );
+    }
+}
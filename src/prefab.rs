@@ -0,0 +1,138 @@
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::light::{Material, PointLight};
+use crate::shapes::{Object, Plane, Quad};
+use crate::transform::{view_transform, Tr};
+use crate::tuple::{Point, Vector};
+use crate::world::World;
+use std::f64::consts::{FRAC_PI_2, FRAC_PI_3, PI};
+
+/// The reflectance of the white walls in the original Cornell box.
+fn cornell_white() -> Color {
+    Color::new(0.73, 0.73, 0.73)
+}
+
+fn cornell_red() -> Color {
+    Color::new(0.65, 0.05, 0.05)
+}
+
+fn cornell_green() -> Color {
+    Color::new(0.12, 0.45, 0.15)
+}
+
+fn wall(color: Color, t: Tr) -> Object {
+    Plane::default()
+        .with_transform(t)
+        .with_material(Material::default().with_color(color).with_specular(0.0))
+        .as_object()
+}
+
+/// The glowing panel in the middle of the ceiling, sized like the one in the original box.
+fn ceiling_light() -> Object {
+    Quad::new(0.47, 0.38)
+        .with_name("light")
+        .with_transform(Tr::new().translate(0.0, 1.999, 0.0))
+        .with_material(
+            Material::default()
+                .with_color(Color::black())
+                .with_ambient(0.0)
+                .with_diffuse(0.0)
+                .with_specular(0.0)
+                .with_casts_shadow(false)
+                .with_emissive(Color::white()),
+        )
+        .as_object()
+}
+
+/// Builds the classic Cornell box: a white floor, ceiling, and back wall, with a red wall on the
+/// left and a green wall on the right, and a glowing panel named `light` in the ceiling. The box
+/// spans [-1, 1] on x and z, and [0, 2] on y, with the front left open. Whatever is in `contents`
+/// gets placed inside.
+///
+/// Known gap: nothing is lit by the panel itself, since there are no area lights or indirect
+/// light yet. The box is lit by a point light just beneath the panel instead, so its shadows are
+/// hard and it won't match reference renders of the original.
+pub fn cornell_box(contents: Vec<Object>) -> World {
+    let mut objects = vec![
+        // floor
+        wall(cornell_white(), Tr::new()),
+        // ceiling
+        wall(
+            cornell_white(),
+            Tr::new().rotate_x(PI).translate(0.0, 2.0, 0.0),
+        ),
+        // back
+        wall(
+            cornell_white(),
+            Tr::new().rotate_x(-FRAC_PI_2).translate(0.0, 0.0, 1.0),
+        ),
+        // left
+        wall(
+            cornell_red(),
+            Tr::new().rotate_z(-FRAC_PI_2).translate(-1.0, 0.0, 0.0),
+        ),
+        // right
+        wall(
+            cornell_green(),
+            Tr::new().rotate_z(FRAC_PI_2).translate(1.0, 0.0, 0.0),
+        ),
+        ceiling_light(),
+    ];
+    objects.extend(contents);
+
+    let light = PointLight::new(Point::new(0.0, 1.98, 0.0), Color::white());
+    World::new().with_light(light).with_objects(objects)
+}
+
+/// A camera looking straight into the open side of [`cornell_box`], framing the whole box.
+pub fn cornell_camera(hsize: usize, vsize: usize) -> Camera {
+    Camera::new(hsize, vsize, FRAC_PI_3).with_transform(view_transform(
+        Point::new(0.0, 1.0, -2.8),
+        Point::new(0.0, 1.0, 0.0),
+        Vector::new(0.0, 1.0, 0.0),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cornell_box, cornell_camera};
+    use crate::ray::Ray;
+    use crate::shapes::Sphere;
+    use crate::transform::Tr;
+    use crate::tuple::{Point, Vector};
+
+    #[test]
+    fn box_holds_its_contents() {
+        let ball = Sphere::default()
+            .with_transform(Tr::new().scale(0.3, 0.3, 0.3).translate(0.0, 0.3, 0.0))
+            .as_object();
+        let w = cornell_box(vec![ball.clone()]);
+        assert_eq!(w.objects.len(), 7);
+        assert_eq!(*w.objects[6], *ball);
+    }
+
+    #[test]
+    fn ceiling_panel_glows() {
+        let w = cornell_box(vec![]);
+        assert!(w.object_named("light").is_some());
+        let up = Ray::new(Point::new(0.0, 1.0, 0.0), Vector::new(0.0, 1.0, 0.0));
+        let c = w.color_at(up);
+        assert!(c.r() >= 1.0 && c.g() >= 1.0 && c.b() >= 1.0);
+        // Just past the panel is the plain ceiling.
+        let beside = Ray::new(Point::new(0.0, 1.0, 0.0), Vector::new(0.5, 1.0, 0.0));
+        assert!(w.color_at(beside).r() < 1.0);
+    }
+
+    #[test]
+    fn red_on_the_left_green_on_the_right() {
+        let w = cornell_box(vec![]);
+        let image = cornell_camera(21, 11).render(&w);
+        let left = image.pixel_at(0, 5);
+        let right = image.pixel_at(20, 5);
+        assert!(left.r() > left.g() && left.r() > left.b());
+        assert!(right.g() > right.r() && right.g() > right.b());
+
+        let center = image.pixel_at(10, 5);
+        assert!((center.r() - center.g()).abs() < 1e-9);
+    }
+}
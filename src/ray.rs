@@ -1,9 +1,58 @@
+use crate::arena;
 use crate::light::Material;
-use crate::shapes::Object;
+use crate::settings::March;
+use crate::shapes::{InGroup, Object, Parents};
 use crate::transform::Tr;
 use crate::tuple::{Point, Vector};
 use crate::world::World;
 use crate::EPSILON;
+use std::cell::Cell;
+use std::sync::Arc;
+
+thread_local! {
+    /// The number of intersection tests done for the camera ray currently being traced on this
+    /// thread, including every ray it spawns.
+    static WORK: Cell<usize> = const { Cell::new(0) };
+    /// How the world last intersected on this thread wants rays marched through shapes.
+    static MARCH: Cell<March> = Cell::new(March::default());
+}
+
+/// How rays should be marched through shapes, according to the settings of the world most
+/// recently intersected on this thread. Shapes intersected outside any world get the defaults.
+pub(crate) fn march_settings() -> March {
+    MARCH.with(|m| m.get())
+}
+
+/// Gets ready to intersect a ray with every object in a world: counts the tests about to be
+/// done, and picks up the world's march settings. Gives false if the current ray has already
+/// used up the world's work limit.
+pub(crate) fn enter_world(w: &World) -> bool {
+    if let Some(limit) = w.settings.work_limit() {
+        if add_work(w.objects.len()) > limit {
+            return false;
+        }
+    }
+    MARCH.with(|m| m.set(w.settings.march()));
+    true
+}
+
+/// Starts counting the intersection tests for a new camera ray.
+pub(crate) fn reset_work() {
+    WORK.with(|w| w.set(0));
+}
+
+/// Records some number of intersection tests, returning the total so far.
+pub(crate) fn add_work(tests: usize) -> usize {
+    WORK.with(|w| {
+        w.set(w.get().saturating_add(tests));
+        w.get()
+    })
+}
+
+/// The number of intersection tests done since the last reset.
+pub(crate) fn work_done() -> usize {
+    WORK.with(|w| w.get())
+}
 
 #[derive(Clone, Copy, Debug)]
 pub struct Ray {
@@ -21,17 +70,25 @@ impl Ray {
         self.origin + t * self.direction
     }
 
-    /// Finds all the places where this ray intersects with stuff in a given world. The list of
+    /// Finds all the places where this ray intersects with stuff in a given world. The
     /// intersections returned will be sorted by increasing distance form the ray's origin.
-    pub fn when_intersect_world(&self, w: &World) -> Vec<Intersection> {
-        let mut res = Vec::new();
+    ///
+    /// Hits which land in holes cut out of a material are left out, so every kind of ray passes
+    /// through them.
+    ///
+    /// If the world has a work limit and the current ray has already used it up, this finds no
+    /// intersections at all, so that a degenerate scene can't keep the ray busy forever.
+    pub fn when_intersect_world(&self, w: &World) -> Intersections {
+        if !enter_world(w) {
+            return Intersections::default();
+        }
+        let mut xs = arena::take();
         for obj in &w.objects {
-            res.append(&mut obj.intersect_with(*self));
+            let mut found = obj.intersect_with(*self);
+            xs.extend(found.drain(..).filter(|x| !x.is_cut_out(*self)));
+            arena::give(found);
         }
-
-        // Sort every intersection by it's t value.
-        res.sort_by(|a, b| a.t.total_cmp(&b.t));
-        res
+        xs.into()
     }
 
     pub fn with_transform(&self, t: Tr) -> Self {
@@ -55,43 +112,106 @@ impl Ray {
 pub struct Intersection {
     t: f64,
     object: Object,
+    /// Whatever the object was hit inside of, such as groups and instances.
+    parents: Option<Arc<Parents>>,
 }
 
 impl Intersection {
     pub fn new(t: f64, object: Object) -> Self {
-        Self { t, object }
+        Self {
+            t,
+            object,
+            parents: None,
+        }
     }
 
     pub fn t(&self) -> f64 {
         self.t
     }
 
+    /// The IDs of the groups, instances and the like which the object was hit inside of, innermost
+    /// first.
+    pub(crate) fn parent_ids(&self) -> &[usize] {
+        self.parents.as_deref().map_or(&[], Parents::ids)
+    }
+
+    /// The parents of the object which was hit, for groups and the like to add themselves to.
+    pub(crate) fn parents_mut(&mut self) -> &mut Parents {
+        Arc::make_mut(self.parents.get_or_insert_with(Default::default))
+    }
+
     /// Get the material of the object associated with this intersection.
     pub fn material(&self) -> Material {
-        self.object.material()
+        match self.parents.as_deref().and_then(|p| p.material()) {
+            Some(m) => (**m).clone(),
+            None => self.object.material(),
+        }
     }
 
+    /// The object which was hit. Objects hit inside groups, or anything else made of shapes, come
+    /// out converting between world and object space through everything they are in.
     pub fn object(&self) -> Object {
-        self.object.clone()
+        match &self.parents {
+            None => self.object.clone(),
+            Some(p) => Arc::new(InGroup::new(self.object.clone(), p.clone())),
+        }
+    }
+
+    /// The normal of the object which was hit at some point, turned inside out if its material
+    /// flips normals.
+    pub fn normal_at(&self, p: Point) -> Vector {
+        let n = self.object().normal_at(p);
+        if self.material().flip_normals() {
+            -n
+        } else {
+            n
+        }
+    }
+
+    /// Whether this hit lands in a hole cut by its material, so that the ray should carry on
+    /// through it. `r` is the ray which made the hit.
+    pub fn is_cut_out(&self, r: Ray) -> bool {
+        let m = self.material();
+        m.cutout().is_some() && m.is_cut_out(&*self.object(), r.position_at(self.t))
     }
 
     pub fn prepare_computations(
         &self,
         r: Ray,
         intersections: Option<&[Intersection]>,
+    ) -> IntersectionVals {
+        self.prepare_computations_with_bias(r, intersections, EPSILON)
+    }
+
+    /// Like [`Intersection::prepare_computations`], but with the over and under points nudged off
+    /// the surface by `bias` instead of the default epsilon.
+    pub fn prepare_computations_with_bias(
+        &self,
+        r: Ray,
+        intersections: Option<&[Intersection]>,
+        bias: f64,
     ) -> IntersectionVals {
         let point = r.position_at(self.t);
         let eyev = -r.direction;
-        let normalv = self.object.normal_at(point);
+        let normalv = self.normal_at(point);
         let inside = eyev.dot(normalv) < 0.0;
         let normalv = if inside { -normalv } else { normalv };
-        let over_point = point + normalv * EPSILON;
-        let under_point = point - normalv * EPSILON;
+        let over_point = point + normalv * bias;
+        let under_point = point - normalv * bias;
         let reflectv = r.direction().reflect(normalv);
+        let object = self.object();
+        let tangents = object.tangents_at(point).and_then(|(t, b)| {
+            let t = t - normalv * normalv.dot(t);
+            let b = b - normalv * normalv.dot(b);
+            (t.magnitude() > 0.0).then(|| {
+                let t = t.normalize();
+                (t, (b - t * t.dot(b)).normalize())
+            })
+        });
 
         let mut res = IntersectionVals {
             t: self.t,
-            object: self.object.clone(),
+            object,
             point,
             eyev,
             normalv,
@@ -101,48 +221,191 @@ impl Intersection {
             reflectv,
             n1: 1.0,
             n2: 1.0,
+            tangents,
         };
 
-        match intersections {
-            None => res,
-            Some(xs) => {
-                // Now compute n1 and n2.
-                let mut containers: Vec<Object> = vec![];
-
-                for i in xs {
-                    if i == self {
-                        // The previous object holds n1.
-                        res.n1 = containers
-                            .last()
-                            .map_or(1.0, |x| x.material().refractive_index())
-                    }
-                    match containers.iter().position(|x| *x == i.clone().object) {
-                        None => {
-                            containers.push(i.clone().object);
-                        }
-                        Some(idx) => {
-                            containers.remove(idx);
-                        }
-                    }
-                    if i == self {
-                        res.n2 = containers
-                            .last()
-                            .map_or(1.0, |x| x.material().refractive_index());
-                        break;
-                    }
-                }
-                res
-            }
+        let Some(xs) = intersections else {
+            return res;
+        };
+        if let Some(idx) = xs.iter().position(|i| i == self) {
+            res.n1 = medium_at(xs, over_point, idx);
+            res.n2 = medium_at(xs, under_point, idx + 1);
         }
+        res
     }
 }
 
+/// Finds the refractive index of whatever medium some point along a ray is in, where `xs` are
+/// the ray's intersections and the first `crossed` of them are behind the point.
+///
+/// Solid shapes are asked whether they hold the point, while surfaces without an inside count as
+/// holding it after an odd number of crossings. Where media are nested, the one crossed most
+/// recently wins, or else the one the ray leaves soonest.
+fn medium_at(xs: &[Intersection], p: Point, crossed: usize) -> f64 {
+    let mut seen: Vec<Object> = arena::take();
+    let mut res = 1.0;
+    for x in xs[..crossed].iter().rev().chain(&xs[crossed..]) {
+        if seen.iter().any(|o| **o == *x.object) {
+            continue;
+        }
+        seen.push(x.object.clone());
+        let inside = if x.object.is_solid() {
+            x.object().contains(p)
+        } else {
+            xs[..crossed]
+                .iter()
+                .filter(|y| *y.object == *x.object)
+                .count()
+                % 2
+                == 1
+        };
+        if inside {
+            res = x.material().refractive_index();
+            break;
+        }
+    }
+    arena::give(seen);
+    res
+}
+
 impl PartialEq for Intersection {
     fn eq(&self, other: &Self) -> bool {
         self.t == other.t && *self.object == *other.object
     }
 }
 
+/// A list of intersections, always kept sorted by increasing t value.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Intersections(Vec<Intersection>);
+
+impl Intersections {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an intersection, keeping the list sorted. Intersections with equal t values stay in
+    /// the order they were inserted.
+    pub fn insert(&mut self, i: Intersection) {
+        let idx = self.0.partition_point(|x| x.t <= i.t);
+        self.0.insert(idx, i);
+    }
+
+    /// The intersection with the lowest non-negative t value.
+    pub fn hit(&self) -> Option<&Intersection> {
+        self.ahead().next()
+    }
+
+    /// Like [`Intersections::hit`], but skips over objects whose material is hidden from the
+    /// camera.
+    pub fn hit_for_camera(&self) -> Option<&Intersection> {
+        self.ahead().find(|x| x.material().visible_to_camera())
+    }
+
+    /// Like [`Intersections::hit`], but skips over objects whose material does not cast shadows.
+    pub fn hit_for_shadow(&self) -> Option<&Intersection> {
+        self.ahead().find(|x| x.material().casts_shadow())
+    }
+
+    /// Finds what blocks a shadow ray. Like [`Intersections::hit_for_shadow`], but also skips the
+    /// back faces of closed meshes: a ray can't leave a closed mesh without having entered it
+    /// first, so those hits are either redundant or the ray leaving the very surface it started
+    /// on, which is where shadow acne comes from.
+    pub fn shadow_hit(&self, r: Ray) -> Option<&Intersection> {
+        self.shadow_hit_where(r, |_| true)
+    }
+
+    /// Like [`Intersections::shadow_hit`], but only counts the intersections which pass some
+    /// test.
+    pub fn shadow_hit_where(
+        &self,
+        r: Ray,
+        keep: impl Fn(&Intersection) -> bool,
+    ) -> Option<&Intersection> {
+        self.ahead()
+            .filter(|x| x.material().casts_shadow() && keep(x))
+            .find(|x| {
+                !x.object.is_closed_mesh()
+                    || x.normal_at(r.position_at(x.t)).dot(r.direction()) < 0.0
+            })
+    }
+
+    /// Iterates over every intersection, in order of increasing t.
+    pub fn iter(&self) -> std::slice::Iter<'_, Intersection> {
+        self.0.iter()
+    }
+
+    /// Iterates over the intersections which are not behind the ray's origin.
+    pub fn ahead(&self) -> impl Iterator<Item = &Intersection> {
+        self.0.iter().skip_while(|x| x.t < 0.0)
+    }
+
+    /// Iterates over the intersections with t in the range [0, t_max).
+    pub fn within(&self, t_max: f64) -> impl Iterator<Item = &Intersection> {
+        self.ahead().take_while(move |x| x.t < t_max)
+    }
+
+    pub fn as_slice(&self) -> &[Intersection] {
+        &self.0
+    }
+
+    pub fn into_vec(mut self) -> Vec<Intersection> {
+        std::mem::take(&mut self.0)
+    }
+}
+
+/// The list goes back to this thread's pool, so the next ray can reuse it.
+impl Drop for Intersections {
+    fn drop(&mut self) {
+        arena::give(std::mem::take(&mut self.0));
+    }
+}
+
+impl std::ops::Deref for Intersections {
+    type Target = [Intersection];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl From<Vec<Intersection>> for Intersections {
+    fn from(mut xs: Vec<Intersection>) -> Self {
+        xs.sort_by(|a, b| a.t.total_cmp(&b.t));
+        Self(xs)
+    }
+}
+
+impl FromIterator<Intersection> for Intersections {
+    fn from_iter<I: IntoIterator<Item = Intersection>>(iter: I) -> Self {
+        iter.into_iter().collect::<Vec<_>>().into()
+    }
+}
+
+impl Extend<Intersection> for Intersections {
+    fn extend<I: IntoIterator<Item = Intersection>>(&mut self, iter: I) {
+        self.0.extend(iter);
+        self.0.sort_by(|a, b| a.t.total_cmp(&b.t));
+    }
+}
+
+impl IntoIterator for Intersections {
+    type Item = Intersection;
+    type IntoIter = std::vec::IntoIter<Intersection>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.into_vec().into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Intersections {
+    type Item = &'a Intersection;
+    type IntoIter = std::slice::Iter<'a, Intersection>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
 /// Given a list of intersections, finds the intersection with the lowest non-negative t value.
 pub fn hit(xs: &[Intersection]) -> Option<Intersection> {
     let mut res: Option<Intersection> = None;
@@ -159,6 +422,14 @@ pub fn hit(xs: &[Intersection]) -> Option<Intersection> {
     res
 }
 
+/// Old name for [`Intersection`].
+#[deprecated(note = "renamed to `Intersection`")]
+pub type Itrsectn = Intersection;
+
+/// Old name for [`IntersectionVals`].
+#[deprecated(note = "renamed to `IntersectionVals`")]
+pub type ItrsectnVs = IntersectionVals;
+
 /// A utility struct with some values related to a point of intersection.
 #[derive(Debug)]
 pub struct IntersectionVals {
@@ -180,6 +451,9 @@ pub struct IntersectionVals {
     pub n1: f64,
     /// Refractive index of the material being entered.
     pub n2: f64,
+    /// The tangent and bitangent at the point, at right angles to the normal and each other, if
+    /// the surface has them; see [`Shape::tangents_at`](crate::shapes::Shape::tangents_at).
+    pub tangents: Option<(Vector, Vector)>,
 }
 
 /// Computes the reflectance, which is the fraction of light reflected from a surface.
@@ -202,9 +476,9 @@ pub fn schlick(comps: IntersectionVals) -> f64 {
 
 #[cfg(test)]
 mod tests {
-    use super::{hit, schlick, Intersection, Ray};
+    use super::{hit, schlick, Intersection, Intersections, Ray};
     use crate::light::Material;
-    use crate::shapes::Sphere;
+    use crate::shapes::{Sphere, Triangle};
     use crate::transform::Tr;
     use crate::tuple::{Point, Vector};
     use crate::{assert_f64_eq, p, v, EPSILON};
@@ -348,7 +622,7 @@ mod tests {
         let shape = Arc::new(Sphere::default());
         let i = Intersection::new(4.0, shape);
         let comps = i.prepare_computations(r, None);
-        assert_eq!(comps.inside, false);
+        assert!(!comps.inside);
     }
 
     #[test]
@@ -359,7 +633,27 @@ mod tests {
         let comps = i.prepare_computations(r, None);
         assert_eq!(comps.point, Point::new(0.0, 0.0, 1.0));
         assert_eq!(comps.eyev, Vector::new(0.0, 0.0, -1.0));
-        assert_eq!(comps.inside, true);
+        assert!(comps.inside);
+        assert_eq!(comps.normalv, Vector::new(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn flipped_normals_face_inwards() {
+        let dome =
+            Arc::new(Sphere::default().with_material(Material::default().with_flip_normals(true)));
+        let r = Ray::new(Point::new(0.0, 0.0, 0.0), Vector::new(0.0, 0.0, 1.0));
+        let i = Intersection::new(1.0, dome.clone());
+        assert_eq!(
+            i.normal_at(Point::new(0.0, 0.0, 1.0)),
+            Vector::new(0.0, 0.0, -1.0)
+        );
+        let comps = i.prepare_computations(r, None);
+        assert!(!comps.inside);
+        assert_eq!(comps.normalv, Vector::new(0.0, 0.0, -1.0));
+
+        let r = Ray::new(Point::new(0.0, 0.0, -5.0), Vector::new(0.0, 0.0, 1.0));
+        let comps = Intersection::new(4.0, dome).prepare_computations(r, None);
+        assert!(comps.inside);
         assert_eq!(comps.normalv, Vector::new(0.0, 0.0, -1.0));
     }
 
@@ -384,6 +678,20 @@ mod tests {
         )
     }
 
+    #[test]
+    fn points_are_offset_by_the_bias() {
+        let r = Ray::new(p!(0, 0, -5), v!(0, 0, 1));
+        let i = Intersection::new(4.0, Sphere::default().as_object());
+
+        let comps = i.prepare_computations(r, None);
+        assert_f64_eq!(comps.over_point.z(), -1.0 - EPSILON);
+        assert_f64_eq!(comps.under_point.z(), -1.0 + EPSILON);
+
+        let comps = i.prepare_computations_with_bias(r, None, 0.25);
+        assert_f64_eq!(comps.over_point.z(), -1.25);
+        assert_f64_eq!(comps.under_point.z(), -0.75);
+    }
+
     #[test]
     fn under_point_is_offset_below_surface() {
         let r = Ray::new(p!(0, 0, 0), v!(0, 0, 1));
@@ -432,4 +740,87 @@ mod tests {
         let reflectance = schlick(comps);
         assert_f64_eq!(reflectance, 0.48873, EPSILON);
     }
+
+    #[test]
+    fn media_are_found_without_every_crossing() {
+        // The ray starts inside the glass, so it never sees itself going in.
+        let shape = glass_sphere().as_object();
+        let r = Ray::new(p!(0, 0, 0), v!(0, 0, 1));
+        let xs = vec![Intersection::new(1.0, shape.clone())];
+        let comps = xs[0].prepare_computations(r, Some(&xs));
+        assert_eq!((comps.n1, comps.n2), (1.5, 1.0));
+
+        // A bubble of water in the glass, whose surface the ray only meets on the way out.
+        let water = Sphere::default()
+            .with_transform(Tr::new().scale(0.5, 0.5, 0.5))
+            .with_material(Material::default().with_refractive_index(1.33))
+            .as_object();
+        let xs = vec![
+            Intersection::new(0.5, water.clone()),
+            Intersection::new(1.0, shape),
+        ];
+        let comps = xs[0].prepare_computations(r, Some(&xs));
+        assert_eq!((comps.n1, comps.n2), (1.33, 1.5));
+    }
+
+    #[test]
+    fn intersections_stay_sorted() {
+        let s = Sphere::default().as_object();
+        let mut xs = Intersections::new();
+        for t in [5.0, -3.0, 7.0, 2.0] {
+            xs.insert(Intersection::new(t, s.clone()));
+        }
+        let got: Vec<f64> = xs.iter().map(|x| x.t()).collect();
+        assert_eq!(got, vec![-3.0, 2.0, 5.0, 7.0]);
+        assert_eq!(xs.hit().unwrap().t(), 2.0);
+
+        let got: Vec<f64> = xs.within(6.0).map(|x| x.t()).collect();
+        assert_eq!(got, vec![2.0, 5.0]);
+
+        let xs: Intersections = vec![Intersection::new(-1.0, s.clone())].into();
+        assert!(xs.hit().is_none());
+    }
+
+    #[test]
+    fn shadow_hit_skips_objects_without_shadows() {
+        let ghost = Sphere::default()
+            .with_material(Material::default().with_casts_shadow(false))
+            .as_object();
+        let s = Sphere::default().as_object();
+        let xs: Intersections = vec![
+            Intersection::new(1.0, ghost.clone()),
+            Intersection::new(3.0, s.clone()),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(*xs.hit().unwrap(), Intersection::new(1.0, ghost));
+        assert_eq!(*xs.hit_for_shadow().unwrap(), Intersection::new(3.0, s));
+    }
+
+    #[test]
+    fn shadow_hit_skips_back_faces_of_closed_meshes() {
+        let tri = || Triangle::new(p!(0, 1, 0), p!(-1, 0, 0), p!(1, 0, 0));
+        let open = tri().as_object();
+        let closed = tri().with_closed_mesh(true).as_object();
+        for (r, culled) in [
+            (Ray::new(p!(0, 0.5, -2), v!(0, 0, 1)), false),
+            (Ray::new(p!(0, 0.5, 2), v!(0, 0, -1)), true),
+        ] {
+            let xs: Intersections = open.intersect_with(r).into();
+            assert!(xs.shadow_hit(r).is_some());
+            let xs: Intersections = closed.intersect_with(r).into();
+            assert_eq!(xs.shadow_hit(r).is_none(), culled);
+        }
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn old_names_still_resolve() {
+        let s = Sphere::default().as_object();
+        let i: super::Itrsectn = Intersection::new(1.0, s);
+        let r = Ray::new(p!(0, 0, -2), v!(0, 0, 1));
+        let comps: super::ItrsectnVs = i.prepare_computations(r, None);
+        assert_eq!(comps.point, p!(0, 0, -1));
+    }
 }
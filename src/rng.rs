@@ -0,0 +1,78 @@
+use crate::tuple::Point;
+
+/// A small, seedable pseudo random number generator (SplitMix64). It is nowhere near good enough
+/// for cryptography, but it is fast, and the same seed always gives the same sequence, which is
+/// what we want for reproducible scenes and renders.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A uniformly distributed number in [0, 1).
+    pub fn next_f64(&mut self) -> f64 {
+        // Use the top 53 bits, which is all the precision an f64 has.
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// A uniformly distributed number in [lo, hi).
+    pub fn range(&mut self, lo: f64, hi: f64) -> f64 {
+        lo + (hi - lo) * self.next_f64()
+    }
+
+    /// A uniformly distributed index in [0, n). Returns 0 if n is 0.
+    pub fn below(&mut self, n: usize) -> usize {
+        if n == 0 {
+            return 0;
+        }
+        (self.next_f64() * n as f64) as usize
+    }
+}
+
+/// A seed which depends only on some point, for random choices made while shading it, so that
+/// shading the same point always makes the same choices.
+pub(crate) fn point_seed(p: Point) -> u64 {
+    p.x().to_bits() ^ p.y().to_bits().rotate_left(21) ^ p.z().to_bits().rotate_left(42)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Rng;
+
+    #[test]
+    fn same_seed_same_sequence() {
+        let mut a = Rng::new(42);
+        let mut b = Rng::new(42);
+        let mut c = Rng::new(43);
+        let xs: Vec<u64> = (0..8).map(|_| a.next_u64()).collect();
+        let ys: Vec<u64> = (0..8).map(|_| b.next_u64()).collect();
+        let zs: Vec<u64> = (0..8).map(|_| c.next_u64()).collect();
+        assert_eq!(xs, ys);
+        assert_ne!(xs, zs);
+    }
+
+    #[test]
+    fn numbers_stay_in_range() {
+        let mut rng = Rng::new(7);
+        for _ in 0..1000 {
+            let x = rng.next_f64();
+            assert!((0.0..1.0).contains(&x));
+            let y = rng.range(-2.0, 3.0);
+            assert!((-2.0..3.0).contains(&y));
+            assert!(rng.below(5) < 5);
+        }
+        assert_eq!(rng.below(0), 0);
+    }
+}
@@ -0,0 +1,168 @@
+use std::f64::consts::PI;
+
+/// Coefficients smaller than this are treated as zero, so that a polynomial which is really of a
+/// lower degree gets solved as one.
+const NEGLIGIBLE: f64 = 1e-12;
+
+/// Finds the real roots of `a x^2 + b x + c`, in increasing order. A double root is returned
+/// once.
+pub fn solve_quadratic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    if a.abs() < NEGLIGIBLE {
+        if b.abs() < NEGLIGIBLE {
+            return vec![];
+        }
+        return vec![-c / b];
+    }
+    let disc = b * b - 4.0 * a * c;
+    if disc < 0.0 {
+        return vec![];
+    }
+    if disc == 0.0 {
+        return vec![-b / (2.0 * a)];
+    }
+    // This avoids subtracting two nearly equal numbers, which the textbook formula does when b is
+    // large.
+    let q = -0.5 * (b + b.signum() * disc.sqrt());
+    let (x0, x1) = if q == 0.0 {
+        let x = (-c / a).sqrt();
+        (-x, x)
+    } else {
+        (q / a, c / q)
+    };
+    vec![x0.min(x1), x0.max(x1)]
+}
+
+/// Finds the real roots of `a x^3 + b x^2 + c x + d`, in increasing order.
+pub fn solve_cubic(a: f64, b: f64, c: f64, d: f64) -> Vec<f64> {
+    if a.abs() < NEGLIGIBLE {
+        return solve_quadratic(b, c, d);
+    }
+    let (a, b, c) = (b / a, c / a, d / a);
+    let q = (a * a - 3.0 * b) / 9.0;
+    let r = (2.0 * a * a * a - 9.0 * a * b + 27.0 * c) / 54.0;
+    let shift = a / 3.0;
+    let mut roots = if r * r < q * q * q {
+        // Three real roots.
+        let theta = (r / (q * q * q).sqrt()).clamp(-1.0, 1.0).acos();
+        let k = -2.0 * q.sqrt();
+        vec![
+            k * (theta / 3.0).cos() - shift,
+            k * ((theta + 2.0 * PI) / 3.0).cos() - shift,
+            k * ((theta - 2.0 * PI) / 3.0).cos() - shift,
+        ]
+    } else {
+        let s = -r.signum() * (r.abs() + (r * r - q * q * q).sqrt()).cbrt();
+        let t = if s == 0.0 { 0.0 } else { q / s };
+        vec![s + t - shift]
+    };
+    roots.sort_by(f64::total_cmp);
+    roots
+}
+
+/// Finds the real roots of `a x^4 + b x^3 + c x^2 + d x + e`, in increasing order. Roots are
+/// found with Ferrari's method, then polished with a few steps of Newton's method, since the
+/// closed form loses a fair bit of precision.
+pub fn solve_quartic(a: f64, b: f64, c: f64, d: f64, e: f64) -> Vec<f64> {
+    if a.abs() < NEGLIGIBLE {
+        return solve_cubic(b, c, d, e);
+    }
+    let (a3, a2, a1, a0) = (b / a, c / a, d / a, e / a);
+
+    // Substituting x = y - a3 / 4 gets rid of the cubic term: y^4 + p y^2 + q y + r.
+    let shift = a3 / 4.0;
+    let p = a2 - 3.0 * a3 * a3 / 8.0;
+    let q = a1 - a3 * a2 / 2.0 + a3 * a3 * a3 / 8.0;
+    let r = a0 - a3 * a1 / 4.0 + a3 * a3 * a2 / 16.0 - 3.0 * a3.powi(4) / 256.0;
+
+    let mut ys = vec![];
+    if q.abs() < NEGLIGIBLE {
+        // A quadratic in y^2.
+        for z in solve_quadratic(1.0, p, r) {
+            if z >= 0.0 {
+                ys.extend([-z.sqrt(), z.sqrt()]);
+            }
+        }
+    } else {
+        // Any positive root of the resolvent cubic splits the quartic into two quadratics. One
+        // always exists, since the cubic is negative at zero.
+        let m = solve_cubic(1.0, p, p * p / 4.0 - r, -q * q / 8.0)
+            .into_iter()
+            .fold(f64::NAN, f64::max);
+        if m.is_nan() || m <= 0.0 {
+            return vec![];
+        }
+        let s = (2.0 * m).sqrt();
+        ys.extend(solve_quadratic(1.0, -s, p / 2.0 + m + q / (2.0 * s)));
+        ys.extend(solve_quadratic(1.0, s, p / 2.0 + m - q / (2.0 * s)));
+    }
+
+    let f = |x: f64| (((a3 + x) * x + a2) * x + a1) * x + a0;
+    let df = |x: f64| ((4.0 * x + 3.0 * a3) * x + 2.0 * a2) * x + a1;
+    let mut roots: Vec<f64> = ys
+        .into_iter()
+        .map(|y| {
+            let mut x = y - shift;
+            for _ in 0..3 {
+                let slope = df(x);
+                if slope.abs() < NEGLIGIBLE {
+                    break;
+                }
+                x -= f(x) / slope;
+            }
+            x
+        })
+        .collect();
+    roots.sort_by(f64::total_cmp);
+    roots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{solve_cubic, solve_quadratic, solve_quartic};
+    use crate::assert_f64_eq;
+
+    fn assert_roots(got: Vec<f64>, want: &[f64]) {
+        assert_eq!(got.len(), want.len(), "got {got:?}, want {want:?}");
+        for (g, w) in got.iter().zip(want) {
+            assert_f64_eq!(*g, *w, 1e-9);
+        }
+    }
+
+    #[test]
+    fn quadratics() {
+        assert_roots(solve_quadratic(1.0, -3.0, 2.0), &[1.0, 2.0]);
+        assert_roots(solve_quadratic(1.0, 2.0, 1.0), &[-1.0]);
+        assert_roots(solve_quadratic(1.0, 0.0, 1.0), &[]);
+        assert_roots(solve_quadratic(0.0, 2.0, -4.0), &[2.0]);
+        assert_roots(solve_quadratic(1.0, 0.0, -4.0), &[-2.0, 2.0]);
+        // Would lose all precision on the small root with the textbook formula.
+        assert_roots(solve_quadratic(1.0, -1e8, 1.0), &[1e-8, 1e8]);
+    }
+
+    #[test]
+    fn cubics() {
+        // (x - 1)(x - 2)(x - 3)
+        assert_roots(solve_cubic(1.0, -6.0, 11.0, -6.0), &[1.0, 2.0, 3.0]);
+        // (x - 2)(x^2 + 1)
+        assert_roots(solve_cubic(2.0, -4.0, 2.0, -4.0), &[2.0]);
+        assert_roots(solve_cubic(0.0, 1.0, -3.0, 2.0), &[1.0, 2.0]);
+    }
+
+    #[test]
+    fn quartics() {
+        // (x - 1)(x - 2)(x - 3)(x - 4)
+        assert_roots(
+            solve_quartic(1.0, -10.0, 35.0, -50.0, 24.0),
+            &[1.0, 2.0, 3.0, 4.0],
+        );
+        // (x^2 - 1)(x^2 - 4), which has no odd terms.
+        assert_roots(
+            solve_quartic(1.0, 0.0, -5.0, 0.0, 4.0),
+            &[-2.0, -1.0, 1.0, 2.0],
+        );
+        // (x + 0.5)(x - 3)(x^2 + 1)
+        assert_roots(solve_quartic(2.0, -5.0, -1.0, -5.0, -3.0), &[-0.5, 3.0]);
+        assert_roots(solve_quartic(1.0, 0.0, 0.0, 0.0, 1.0), &[]);
+        assert_roots(solve_quartic(0.0, 1.0, -6.0, 11.0, -6.0), &[1.0, 2.0, 3.0]);
+    }
+}
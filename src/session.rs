@@ -0,0 +1,347 @@
+use crate::camera::Camera;
+use crate::canvas::Canvas;
+use crate::light::Material;
+use crate::shapes::{Group, Instance, Object};
+use crate::transform::{self, Tr};
+use crate::world::World;
+use crate::yaml::{parse_scene, ErrParseYaml, Scene};
+use std::fmt::Display;
+
+/// Represents some problem with editing a scene in a session.
+#[derive(Debug, PartialEq)]
+pub enum ErrSession {
+    /// When no object in the session has the given ID.
+    UnknownObject(usize),
+    Transform(transform::Error),
+}
+
+impl Display for ErrSession {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use ErrSession::*;
+        match self {
+            UnknownObject(id) => write!(f, "No such object in session; id={id}"),
+            Transform(e) => write!(f, "Invalid transformation; {e:?}"),
+        }
+    }
+}
+
+impl From<transform::Error> for ErrSession {
+    fn from(e: transform::Error) -> Self {
+        Self::Transform(e)
+    }
+}
+
+/// One of the objects a session was given, along with whatever edits have been made to it.
+struct Entry {
+    /// The object as it was given. Edits never change it, only place it again.
+    object: Object,
+    /// Applied on top of the object's own transformation.
+    transform: Option<Tr>,
+    /// Replaces the object's own material.
+    material: Option<Material>,
+    /// What actually goes into the bounding volume hierarchy.
+    placed: Object,
+}
+
+impl Entry {
+    fn new(object: Object) -> Self {
+        Self {
+            placed: object.clone(),
+            object,
+            transform: None,
+            material: None,
+        }
+    }
+
+    /// Places the object again after an edit. Unedited objects are used as they are.
+    fn place(&mut self) -> Result<(), transform::Error> {
+        if self.transform.is_none() && self.material.is_none() {
+            self.placed = self.object.clone();
+            return Ok(());
+        }
+        let mut placed = Instance::new(self.object.clone())
+            .try_with_transform(self.transform.unwrap_or_default())?;
+        if let Some(m) = &self.material {
+            placed = placed.with_material(m.clone());
+        }
+        self.placed = placed.as_object();
+        Ok(())
+    }
+}
+
+/// A scene kept around to be edited and rendered again and again, as in an interactive editor.
+///
+/// The session owns the world and a bounding volume hierarchy over its objects. Objects are
+/// edited by their IDs, which never change however many times they are edited. An edit only
+/// rebuilds the boxes of the hierarchy which lead to the edited object; everything else is shared
+/// with the hierarchy from before the edit. An object moved far enough may leave its boxes
+/// loose, which [`SceneSession::rebuild`] tightens up again.
+pub struct SceneSession {
+    camera: Camera,
+    /// The world being rendered, whose only object is the root of the hierarchy.
+    world: World,
+    entries: Vec<Entry>,
+    bvh_threshold: usize,
+}
+
+impl SceneSession {
+    /// Starts a session on a scene, with at most 8 objects in each box of the hierarchy.
+    pub fn new(scene: Scene) -> Self {
+        let Scene { camera, mut world } = scene;
+        let entries = std::mem::take(&mut world.objects)
+            .into_iter()
+            .map(Entry::new)
+            .collect();
+        let mut res = Self {
+            camera,
+            world,
+            entries,
+            bvh_threshold: 8,
+        };
+        res.rebuild();
+        res
+    }
+
+    /// Starts a session on a scene parsed from a YAML string.
+    pub fn from_yaml(s: &str) -> Result<Self, ErrParseYaml> {
+        Ok(Self::new(parse_scene(s)?))
+    }
+
+    /// Sets the most objects in one box of the hierarchy; see [`Group::divide`].
+    pub fn with_bvh_threshold(mut self, threshold: usize) -> Self {
+        self.bvh_threshold = threshold;
+        self.rebuild();
+        self
+    }
+
+    pub fn camera(&self) -> &Camera {
+        &self.camera
+    }
+
+    pub fn set_camera(&mut self, camera: Camera) {
+        self.camera = camera;
+    }
+
+    /// The world as it is rendered, with every edit so far.
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+
+    /// The IDs of the objects in the session, in the order they were given.
+    pub fn object_ids(&self) -> Vec<usize> {
+        self.entries.iter().map(|e| e.object.id()).collect()
+    }
+
+    /// The ID of the first object in the session with some name, for editing it.
+    pub fn object_named(&self, name: &str) -> Option<usize> {
+        self.entries
+            .iter()
+            .find(|e| e.object.name() == Some(name))
+            .map(|e| e.object.id())
+    }
+
+    /// Replaces the material of some object.
+    pub fn set_material(&mut self, id: usize, m: Material) -> Result<(), ErrSession> {
+        self.edit(id, |e| e.material = Some(m))
+    }
+
+    /// Moves some object by a transformation, applied on top of its own. This replaces any
+    /// earlier move, rather than adding to it.
+    pub fn move_object(&mut self, id: usize, t: Tr) -> Result<(), ErrSession> {
+        self.edit(id, |e| e.transform = Some(t))
+    }
+
+    /// Undoes every edit made to some object.
+    pub fn reset_object(&mut self, id: usize) -> Result<(), ErrSession> {
+        self.edit(id, |e| {
+            e.transform = None;
+            e.material = None;
+        })
+    }
+
+    fn edit(&mut self, id: usize, f: impl FnOnce(&mut Entry)) -> Result<(), ErrSession> {
+        let entry = self
+            .entries
+            .iter_mut()
+            .find(|e| e.object.id() == id)
+            .ok_or(ErrSession::UnknownObject(id))?;
+        let (old_transform, old_material) = (entry.transform, entry.material.clone());
+        let old = entry.placed.clone();
+        f(entry);
+        if let Err(e) = entry.place() {
+            (entry.transform, entry.material) = (old_transform, old_material);
+            return Err(e.into());
+        }
+        match replace_node(&self.world.objects[0], &old, &entry.placed) {
+            Some(root) => self.world.objects[0] = root,
+            // Only possible if the hierarchy was somehow built without the object.
+            None => self.rebuild(),
+        }
+        Ok(())
+    }
+
+    /// Builds the whole hierarchy again from scratch. Groups among the objects get hierarchies of
+    /// their own, which are kept however they are edited.
+    pub fn rebuild(&mut self) {
+        for e in &mut self.entries {
+            if let Some(g) = e.object.as_any().downcast_ref::<Group>() {
+                e.object = g.clone().divide(self.bvh_threshold).as_object();
+                e.place()
+                    .expect("transformation should have been checked when it was set");
+            }
+        }
+        let placed = self.entries.iter().map(|e| e.placed.clone()).collect();
+        self.world.objects = vec![Group::new(placed).divide(self.bvh_threshold).as_object()];
+    }
+
+    /// Renders the scene as it is now.
+    pub fn render(&self) -> Canvas {
+        self.camera.render(&self.world)
+    }
+}
+
+/// Finds `old` somewhere under `node` and swaps in `new`, rebuilding only the groups on the way
+/// down to it. Gives None if `old` is not there. Objects are matched by ID, since dividing a
+/// group copies it.
+fn replace_node(node: &Object, old: &Object, new: &Object) -> Option<Object> {
+    if node.id() == old.id() {
+        return Some(new.clone());
+    }
+    let g = node.as_any().downcast_ref::<Group>()?;
+    let (i, child) = g
+        .children()
+        .iter()
+        .enumerate()
+        .find_map(|(i, c)| replace_node(c, old, new).map(|c| (i, c)))?;
+    Some(g.with_child_replaced(i, child).as_object())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ErrSession, SceneSession};
+    use crate::camera::Camera;
+    use crate::color::Color;
+    use crate::light::{Material, PointLight};
+    use crate::ray::Ray;
+    use crate::shapes::{Group, Object, Sphere};
+    use crate::transform::{self, view_transform, Tr};
+    use crate::world::World;
+    use crate::yaml::Scene;
+    use crate::{p, v};
+    use std::f64::consts::FRAC_PI_3;
+    use std::sync::Arc;
+
+    /// A row of unit spheres along the x axis, three apart.
+    fn row(n: usize) -> (Vec<usize>, SceneSession) {
+        let balls: Vec<Object> = (0..n)
+            .map(|i| {
+                Sphere::default()
+                    .with_transform(Tr::new().translate(i as f64 * 3.0, 0.0, 0.0))
+                    .as_object()
+            })
+            .collect();
+        let ids = balls.iter().map(|b| b.id()).collect();
+        let scene = Scene {
+            camera: Camera::new(5, 5, FRAC_PI_3),
+            world: World::new()
+                .with_light(PointLight::new(p!(-10, 10, -10), Color::white()))
+                .with_objects(balls),
+        };
+        (ids, SceneSession::new(scene).with_bvh_threshold(2))
+    }
+
+    /// Every node in a hierarchy.
+    fn nodes(o: &Object) -> Vec<Object> {
+        let mut res = vec![o.clone()];
+        if let Some(g) = o.as_any().downcast_ref::<Group>() {
+            res.extend(g.children().iter().flat_map(nodes));
+        }
+        res
+    }
+
+    fn hit(s: &SceneSession, r: Ray) -> Option<(f64, usize, Material)> {
+        let xs = r.when_intersect_world(s.world());
+        xs.hit().map(|h| (h.t(), h.object().id(), h.material()))
+    }
+
+    #[test]
+    fn edits_only_rebuild_their_part_of_the_hierarchy() {
+        let (ids, mut s) = row(32);
+        let before = nodes(&s.world().objects[0]);
+        s.move_object(ids[5], Tr::new().translate(0.0, 1.0, 0.0))
+            .unwrap();
+        let after = nodes(&s.world().objects[0]);
+        assert_eq!(before.len(), after.len());
+        let fresh = after
+            .iter()
+            .filter(|n| !before.iter().any(|b| Arc::ptr_eq(b, n)))
+            .count();
+        // The object itself, plus one group per level of the hierarchy.
+        assert!(fresh > 1);
+        assert!(fresh <= 8, "rebuilt {fresh} of {} nodes", after.len());
+    }
+
+    #[test]
+    fn moving_and_recoloring_objects() {
+        let (ids, mut s) = row(10);
+        let down = Ray::new(p!(15, 10, 0), v!(0, -1, 0));
+        assert_eq!(hit(&s, down).map(|h| (h.0, h.1)), Some((9.0, ids[5])));
+
+        let red = Material::default().with_color(Color::new(1.0, 0.0, 0.0));
+        s.move_object(ids[5], Tr::new().translate(0.0, 2.0, 0.0))
+            .unwrap();
+        s.set_material(ids[5], red.clone()).unwrap();
+        let (t, id, m) = hit(&s, down).unwrap();
+        assert_eq!((t, id, m), (7.0, ids[5], red.clone()));
+
+        // Moves replace each other, rather than adding up.
+        s.move_object(ids[5], Tr::new().translate(0.0, -2.0, 0.0))
+            .unwrap();
+        assert_eq!(hit(&s, down).unwrap().0, 11.0);
+
+        s.reset_object(ids[5]).unwrap();
+        assert_eq!(hit(&s, down).unwrap(), (9.0, ids[5], Material::default()));
+        assert_eq!(s.object_ids(), ids);
+    }
+
+    #[test]
+    fn editing_objects_by_name() {
+        let lamp = Sphere::default().with_name("lamp").as_object();
+        let scene = Scene {
+            camera: Camera::new(5, 5, FRAC_PI_3),
+            world: World::new().with_objects(vec![Sphere::default().as_object(), lamp.clone()]),
+        };
+        let mut s = SceneSession::new(scene);
+        let id = s.object_named("lamp").unwrap();
+        assert_eq!(id, lamp.id());
+        s.move_object(id, Tr::new().translate(0.0, 5.0, 0.0))
+            .unwrap();
+        // The moved lamp keeps its name, and the session still finds the original.
+        assert_eq!(s.world().objects_named("lamp").len(), 1);
+        assert_eq!(s.object_named("lamp"), Some(id));
+        assert_eq!(s.object_named("table"), None);
+    }
+
+    #[test]
+    fn bad_edits_change_nothing() {
+        let (ids, mut s) = row(3);
+        assert_eq!(
+            s.set_material(12345678, Material::default()),
+            Err(ErrSession::UnknownObject(12345678))
+        );
+        let flat = Tr::new().scale(1.0, 0.0, 1.0);
+        assert_eq!(
+            s.move_object(ids[1], flat),
+            Err(ErrSession::Transform(transform::Error::Uninvertible))
+        );
+        let down = Ray::new(p!(3, 10, 0), v!(0, -1, 0));
+        assert_eq!(hit(&s, down).unwrap().0, 9.0);
+
+        s.set_camera(Camera::new(5, 5, FRAC_PI_3).with_transform(view_transform(
+            p!(3, 0, -5),
+            p!(3, 0, 0),
+            v!(0, 1, 0),
+        )));
+        assert_ne!(s.render().pixel_at(2, 2), Color::black());
+    }
+}
@@ -0,0 +1,372 @@
+use crate::post::{AutoExposure, Ink};
+use crate::tiles::TileOrder;
+use crate::transform::Tr;
+use crate::{EPSILON, MAX_BOUNCE};
+use std::time::Duration;
+
+/// What to do about materials whose weights add up to more light than falls on them, i.e. whose
+/// ambient, diffuse, specular, reflective and transparency add up to more than one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EnergyAudit {
+    /// Shade every material as it is.
+    #[default]
+    Off,
+    /// Panic on shading any such material, in debug builds. Release builds shade it as it is.
+    Check,
+    /// Scale down the light given off by any such material, so that its weights add up to one.
+    Normalize,
+}
+
+/// How the world finds the first thing a ray hits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HitSearch {
+    /// Gathers every intersection along the ray, sorts them, and picks the first one ahead.
+    Sorted,
+    /// Keeps only the nearest intersection so far while going through the objects, and skips
+    /// objects whose bounds start past it. Transparent hits still gather the full list, since
+    /// their refractive indices depend on everything the ray is inside.
+    #[default]
+    Nearest,
+}
+
+/// How rays get through transparent surfaces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Transparency {
+    /// Bends rays through every transparent surface, which looks right for glass and water but
+    /// costs a bounce per surface, so that stacks of more surfaces than the bounce limit come out
+    /// wrong.
+    #[default]
+    Refract,
+    /// Lets rays straight through each transparent surface as often as it is transparent, and
+    /// otherwise shades it as if it were opaque, deciding by a coin toss seeded by the point hit.
+    /// Every ray then stops at a single surface, however many are stacked up, so dozens of
+    /// foliage cards or sprites cost no more than one. A single sample is grainy, but the grain
+    /// averages out over the passes of a progressive render. Rays are not bent, and shadows fall
+    /// through transparent surfaces the same way.
+    Stochastic,
+}
+
+/// Controls how rays are marched through shapes which have no exact intersection, like
+/// [`Sdf`](crate::shapes::Sdf), or whose exact intersection was turned off.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct March {
+    /// The most steps a ray may take through one shape before giving up.
+    pub max_steps: usize,
+    /// How close a ray must get to the surface to count as hitting it.
+    pub precision: f64,
+}
+
+impl Default for March {
+    fn default() -> Self {
+        Self {
+            max_steps: 256,
+            // Well under the bias, so that shading points end up on the right side of the
+            // surface.
+            precision: EPSILON * 0.1,
+        }
+    }
+}
+
+/// Knobs which control how a world gets rendered, as opposed to what is in it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderSettings {
+    /// The maximum number of times a ray is allowed to be reflected or refracted.
+    max_bounce: u16,
+    /// Multiplies the glossy samples of every material, so that previews can be rendered with
+    /// fewer samples than the final image without touching the scene.
+    quality: f64,
+    /// How far shading points get nudged off a surface, so that rays leaving them don't hit the
+    /// surface they started from.
+    bias: f64,
+    /// Whether the bias grows and shrinks with the scale of each object, so that tiny and huge
+    /// objects are nudged off their surfaces by a distance which suits them.
+    scale_bias: bool,
+    /// When set, pixels with shadow acne are painted magenta instead of being shaded. Acne is
+    /// flagged when a shadow ray hits its own object again within this many multiples of
+    /// [`EPSILON`](crate::EPSILON).
+    acne_diagnostics: Option<f64>,
+    /// When set, the camera keeps refining the image with more samples per pixel until this much
+    /// time has passed.
+    time_budget: Option<Duration>,
+    /// When set, progressive renders stop after this many passes, even with time to spare.
+    pass_limit: Option<usize>,
+    /// The side length, in pixels, of the tiles used by tiled rendering.
+    tile_size: usize,
+    /// The order in which tiles are rendered.
+    tile_order: TileOrder,
+    /// The most intersection tests a camera ray, and everything it bounces into, may do. Rays
+    /// which go over are shaded with a warning color.
+    work_limit: Option<usize>,
+    /// How many rays glossy surfaces hit by camera rays send out to gather their blurry
+    /// reflections, unless their material asks for something else. Glossy surfaces seen in
+    /// reflections or through glass send out one.
+    glossy_samples: u32,
+    /// When set, every material without toon bands of its own is shaded in this many bands.
+    toon_bands: Option<u32>,
+    /// When set, the camera draws ink outlines over its renders.
+    ink: Option<Ink>,
+    /// Whether to check that materials conserve energy.
+    energy_audit: EnergyAudit,
+    /// How rays are marched through shapes.
+    march: March,
+    /// When set, the images handed out while a render is still in progress are exposed
+    /// automatically, so that dark scenes can still be made out. The finished image is never
+    /// touched.
+    preview_exposure: Option<AutoExposure>,
+    /// How camera rays and their bounces find what they hit.
+    hit_search: HitSearch,
+    /// How rays get through transparent surfaces.
+    transparency: Transparency,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            max_bounce: MAX_BOUNCE,
+            quality: 1.0,
+            bias: EPSILON,
+            scale_bias: true,
+            acne_diagnostics: None,
+            time_budget: None,
+            pass_limit: None,
+            tile_size: 32,
+            tile_order: TileOrder::default(),
+            work_limit: None,
+            glossy_samples: 8,
+            toon_bands: None,
+            ink: None,
+            energy_audit: EnergyAudit::Off,
+            march: March::default(),
+            preview_exposure: None,
+            hit_search: HitSearch::default(),
+            transparency: Transparency::default(),
+        }
+    }
+}
+
+impl RenderSettings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_max_bounce(mut self, max_bounce: u16) -> Self {
+        self.max_bounce = max_bounce;
+        self
+    }
+
+    /// Sets the quality multiplier. Negative values are treated as zero.
+    pub fn with_quality(mut self, quality: f64) -> Self {
+        self.quality = quality.max(0.0);
+        self
+    }
+
+    pub fn with_bias(mut self, bias: f64) -> Self {
+        self.bias = bias;
+        self
+    }
+
+    /// Sets whether the bias is scaled by the size of each object, as placed by its
+    /// transformation. This is on by default; turn it off to nudge everything by exactly the
+    /// same distance.
+    pub fn with_scaled_bias(mut self, scale_bias: bool) -> Self {
+        self.scale_bias = scale_bias;
+        self
+    }
+
+    /// Turns on the shadow acne diagnostics, with a threshold of `k` times the default epsilon.
+    pub fn with_acne_diagnostics(mut self, k: f64) -> Self {
+        self.acne_diagnostics = Some(k);
+        self
+    }
+
+    /// Renders progressively for roughly this long, returning the best image reached. The first
+    /// pass always completes, however long it takes.
+    pub fn with_time_budget(mut self, budget: Duration) -> Self {
+        self.time_budget = Some(budget);
+        self
+    }
+
+    /// Renders progressively with at most this many samples per pixel. Without a time budget,
+    /// every pass is rendered, so the image comes out the same however fast the machine is.
+    pub fn with_pass_limit(mut self, passes: usize) -> Self {
+        self.pass_limit = Some(passes.max(1));
+        self
+    }
+
+    pub fn with_tiles(mut self, size: usize, order: TileOrder) -> Self {
+        self.tile_size = size.max(1);
+        self.tile_order = order;
+        self
+    }
+
+    /// Limits the number of intersection tests per camera ray, to catch degenerate scenes which
+    /// would otherwise take forever to render.
+    pub fn with_work_limit(mut self, tests: usize) -> Self {
+        self.work_limit = Some(tests);
+        self
+    }
+
+    /// Sets the number of glossy reflection samples. At least one sample is always taken.
+    pub fn with_glossy_samples(mut self, samples: u32) -> Self {
+        self.glossy_samples = samples.max(1);
+        self
+    }
+
+    /// Shades the whole render in flat bands, as if every material had
+    /// [`Material::with_toon_bands`](crate::light::Material::with_toon_bands). Materials with
+    /// bands of their own keep them.
+    pub fn with_toon_bands(mut self, bands: u32) -> Self {
+        self.toon_bands = Some(bands.max(1));
+        self
+    }
+
+    pub fn with_ink(mut self, ink: Ink) -> Self {
+        self.ink = Some(ink);
+        self
+    }
+
+    pub fn with_energy_audit(mut self, audit: EnergyAudit) -> Self {
+        self.energy_audit = audit;
+        self
+    }
+
+    /// Sets how rays are marched through shapes. A precision of zero or less is replaced by the
+    /// default, since rays would never get close enough to hit anything.
+    pub fn with_march(mut self, mut march: March) -> Self {
+        if march.precision <= 0.0 {
+            march.precision = March::default().precision;
+        }
+        self.march = march;
+        self
+    }
+
+    /// Exposes the images handed out while rendering; see
+    /// [`preview_expose`](crate::post::preview_expose).
+    pub fn with_preview_exposure(mut self, mode: AutoExposure) -> Self {
+        self.preview_exposure = Some(mode);
+        self
+    }
+
+    pub fn with_hit_search(mut self, search: HitSearch) -> Self {
+        self.hit_search = search;
+        self
+    }
+
+    /// Sets how rays get through transparent surfaces. Stochastic transparency always finds hits
+    /// as in [`HitSearch::Nearest`].
+    pub fn with_transparency(mut self, transparency: Transparency) -> Self {
+        self.transparency = transparency;
+        self
+    }
+
+    pub fn max_bounce(&self) -> u16 {
+        self.max_bounce
+    }
+
+    pub fn quality(&self) -> f64 {
+        self.quality
+    }
+
+    pub fn time_budget(&self) -> Option<Duration> {
+        self.time_budget
+    }
+
+    pub fn pass_limit(&self) -> Option<usize> {
+        self.pass_limit
+    }
+
+    pub fn tile_size(&self) -> usize {
+        self.tile_size
+    }
+
+    pub fn tile_order(&self) -> TileOrder {
+        self.tile_order
+    }
+
+    pub fn work_limit(&self) -> Option<usize> {
+        self.work_limit
+    }
+
+    pub fn glossy_samples(&self) -> u32 {
+        self.glossy_samples
+    }
+
+    pub fn toon_bands(&self) -> Option<u32> {
+        self.toon_bands
+    }
+
+    pub fn ink(&self) -> Option<Ink> {
+        self.ink
+    }
+
+    pub fn energy_audit(&self) -> EnergyAudit {
+        self.energy_audit
+    }
+
+    pub fn march(&self) -> March {
+        self.march
+    }
+
+    pub fn preview_exposure(&self) -> Option<AutoExposure> {
+        self.preview_exposure
+    }
+
+    pub fn hit_search(&self) -> HitSearch {
+        self.hit_search
+    }
+
+    pub fn transparency(&self) -> Transparency {
+        self.transparency
+    }
+
+    pub fn bias(&self) -> f64 {
+        self.bias
+    }
+
+    pub fn scales_bias(&self) -> bool {
+        self.scale_bias
+    }
+
+    /// The bias for an object placed by some transformation, which is scaled along with the
+    /// object unless scaling is turned off.
+    pub fn bias_for(&self, t: Tr) -> f64 {
+        if self.scale_bias {
+            self.bias * t.max_scale()
+        } else {
+            self.bias
+        }
+    }
+
+    /// The distance within which a shadow ray hitting its own object counts as acne, if the
+    /// diagnostics are turned on.
+    pub fn acne_threshold(&self) -> Option<f64> {
+        self.acne_diagnostics.map(|k| k * EPSILON)
+    }
+
+    /// Applies the quality multiplier to some number of samples. This never goes below one
+    /// sample.
+    pub fn scale_samples(&self, samples: u32) -> u32 {
+        ((samples as f64 * self.quality).round() as u32).max(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RenderSettings;
+
+    #[test]
+    fn quality_scales_samples() {
+        let s = RenderSettings::default();
+        assert_eq!(s.scale_samples(16), 16);
+
+        let s = RenderSettings::new().with_quality(0.25);
+        assert_eq!(s.scale_samples(16), 4);
+        assert_eq!(s.scale_samples(1), 1);
+
+        let s = RenderSettings::new().with_quality(4.0);
+        assert_eq!(s.scale_samples(16), 64);
+
+        let s = RenderSettings::new().with_quality(-1.0);
+        assert_eq!(s.scale_samples(64), 1);
+    }
+}
@@ -0,0 +1,340 @@
+use crate::bounds::Bounds;
+use crate::light::Material;
+use crate::matrix::Matrix;
+use crate::ray::{march_settings, Intersection, Ray};
+use crate::shapes::{derived_transforms, Object, Shape};
+use crate::transform::{self, Tr};
+use crate::tuple::{Point, Vector};
+use crate::{get_uid, v};
+use std::sync::Arc;
+
+/// One of the centers a blob is built around. Its influence is strongest at the center and fades
+/// smoothly to nothing at the radius. A negative strength carves into the blob instead.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlobComponent {
+    pub center: Point,
+    pub radius: f64,
+    pub strength: f64,
+}
+
+impl BlobComponent {
+    pub fn new(center: Point, radius: f64, strength: f64) -> Self {
+        Self {
+            center,
+            radius,
+            strength,
+        }
+    }
+
+    /// How much this component adds to the field at some point.
+    fn field(&self, p: Point) -> f64 {
+        let d2 = (p - self.center).magnitude().powi(2) / (self.radius * self.radius);
+        if d2 >= 1.0 {
+            return 0.0;
+        }
+        self.strength * (1.0 - d2) * (1.0 - d2)
+    }
+
+    /// The gradient of this component's field at some point.
+    fn gradient(&self, p: Point) -> Vector {
+        let r2 = self.radius * self.radius;
+        let d = p - self.center;
+        let d2 = d.magnitude().powi(2) / r2;
+        if d2 >= 1.0 {
+            return v!(0, 0, 0);
+        }
+        d * (-4.0 * self.strength * (1.0 - d2) / r2)
+    }
+}
+
+/// A blobby shape, or metaballs, which flows smoothly around a set of centers. Each center gives
+/// off a field, and the surface is wherever the fields add up to the threshold, so centers close
+/// to each other melt together.
+///
+/// The field is not a distance, so rays can't be sphere traced through it. Instead they are
+/// stepped through it evenly, and each crossing is narrowed down by bisection to the precision in
+/// the render settings.
+#[derive(Debug, Clone)]
+pub struct Blob {
+    id: usize,
+    name: Option<String>,
+    components: Vec<BlobComponent>,
+    threshold: f64,
+
+    transform: Tr,
+    inv_transform: Tr,
+    /// A matrix used in computing normals.
+    norm_transform: Matrix<3, 3>,
+
+    material: Material,
+}
+
+impl Default for Blob {
+    /// A blob with no components, and so no surface, and a threshold of one half.
+    fn default() -> Self {
+        Self {
+            id: get_uid(),
+            name: None,
+            components: vec![],
+            threshold: 0.5,
+            transform: Tr::default(),
+            inv_transform: Tr::default(),
+            norm_transform: Matrix::<3, 3>::ident(),
+            material: Material::default(),
+        }
+    }
+}
+
+impl PartialEq for Blob {
+    fn eq(&self, other: &Self) -> bool {
+        self.components == other.components
+            && self.threshold == other.threshold
+            && self.transform == other.transform
+            && self.inv_transform == other.inv_transform
+            && self.norm_transform == other.norm_transform
+            && self.material == other.material
+    }
+}
+
+impl Shape for Blob {
+    fn transform(&self) -> Tr {
+        self.transform
+    }
+
+    fn inv_transform(&self) -> Tr {
+        self.inv_transform
+    }
+
+    fn norm_transform(&self) -> Matrix<3, 3> {
+        self.norm_transform
+    }
+
+    fn set_transform(&mut self, t: Tr) {
+        self.try_set_transform(t)
+            .expect("shape transformation should be invertible");
+    }
+
+    fn material(&self) -> Material {
+        self.material.clone()
+    }
+
+    fn set_material(&mut self, m: Material) {
+        self.material = m;
+    }
+
+    fn local_intersect_with(&self, r: Ray) -> Vec<Intersection> {
+        let Some((start, end)) = self.local_bounds().clip(r) else {
+            return vec![];
+        };
+        let settings = march_settings();
+        let speed = r.direction().magnitude();
+        // Steps must be small enough not to skip over the smallest component entirely.
+        let smallest = self
+            .components
+            .iter()
+            .map(|c| c.radius)
+            .fold(f64::INFINITY, f64::min);
+        let steps = (((end - start) * speed) / (smallest / 4.0))
+            .ceil()
+            .clamp(1.0, settings.max_steps as f64) as usize;
+        let dt = (end - start) / steps as f64;
+
+        let inside = |t: f64| self.field(r.position_at(t)) >= self.threshold;
+        let mut xs = vec![];
+        let (mut t0, mut in0) = (start, inside(start));
+        for i in 1..=steps {
+            let t1 = start + dt * i as f64;
+            let in1 = inside(t1);
+            if in0 != in1 {
+                // Bisect until the crossing is pinned down.
+                let (mut lo, mut hi) = (t0, t1);
+                while (hi - lo) * speed > settings.precision {
+                    let mid = (lo + hi) / 2.0;
+                    if inside(mid) == in0 {
+                        lo = mid;
+                    } else {
+                        hi = mid;
+                    }
+                }
+                xs.push(Intersection::new((lo + hi) / 2.0, Arc::new(self.clone())));
+            }
+            (t0, in0) = (t1, in1);
+        }
+        xs
+    }
+
+    fn local_normal_at(&self, p: Point) -> Vector {
+        let g = self
+            .components
+            .iter()
+            .fold(v!(0, 0, 0), |acc, c| acc + c.gradient(p));
+        // The field grows towards the inside, so the normal points the other way.
+        (-g).normalize()
+    }
+
+    /// The box around every component which adds to the field. Components which carve into the
+    /// blob can't make it any bigger.
+    fn local_bounds(&self) -> Bounds {
+        self.components
+            .iter()
+            .filter(|c| c.strength > 0.0)
+            .fold(Bounds::empty(), |acc, c| {
+                let r = v!(c.radius, c.radius, c.radius);
+                acc.union(&Bounds::new(c.center - r, c.center + r))
+            })
+    }
+
+    fn is_solid(&self) -> bool {
+        true
+    }
+
+    fn local_contains(&self, p: Point) -> bool {
+        self.field(p) >= self.threshold
+    }
+
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn eqx(&self, other: &dyn std::any::Any) -> bool {
+        other.downcast_ref::<Self>().is_some_and(|a| a == self)
+    }
+}
+
+impl Blob {
+    /// Creates a blob around some components, with a threshold of one half.
+    pub fn new(components: Vec<BlobComponent>) -> Self {
+        Self {
+            components,
+            ..Self::default()
+        }
+    }
+
+    pub fn components(&self) -> &[BlobComponent] {
+        &self.components
+    }
+
+    pub fn with_component(mut self, c: BlobComponent) -> Self {
+        self.components.push(c);
+        self
+    }
+
+    pub fn threshold(&self) -> f64 {
+        self.threshold
+    }
+
+    /// Sets how strong the field must be for a point to be inside the blob. Lower thresholds give
+    /// fatter blobs which melt together sooner.
+    pub fn with_threshold(mut self, threshold: f64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// The strength of the field at some point in object space.
+    pub fn field(&self, p: Point) -> f64 {
+        self.components.iter().map(|c| c.field(p)).sum()
+    }
+
+    pub fn with_transform(mut self, t: Tr) -> Self {
+        self.set_transform(t);
+        self
+    }
+
+    /// Like `with_transform`, but fails instead of panicking if the transformation is singular.
+    pub fn try_with_transform(mut self, t: Tr) -> Result<Self, transform::Error> {
+        self.try_set_transform(t)?;
+        Ok(self)
+    }
+
+    fn try_set_transform(&mut self, t: Tr) -> Result<(), transform::Error> {
+        (self.inv_transform, self.norm_transform) = derived_transforms(t)?;
+        self.transform = t;
+        Ok(())
+    }
+
+    pub fn with_material(mut self, m: Material) -> Self {
+        self.material = m;
+        self
+    }
+
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn as_object(self) -> Object {
+        Arc::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Blob, BlobComponent};
+    use crate::ray::Ray;
+    use crate::shapes::Shape;
+    use crate::transform::Tr;
+    use crate::{assert_f64_eq, p, v, EPSILON};
+
+    /// How far the surface of a lone unit component is from its center, at a threshold of one
+    /// half.
+    fn lone_radius() -> f64 {
+        (1.0 - 0.5f64.sqrt()).sqrt()
+    }
+
+    #[test]
+    fn a_single_component_is_a_sphere() {
+        let b = Blob::new(vec![BlobComponent::new(p!(0, 0, 0), 1.0, 1.0)]);
+        let xs = b.local_intersect_with(Ray::new(p!(0, 0, -5), v!(0, 0, 1)));
+        assert_eq!(xs.len(), 2);
+        assert_f64_eq!(xs[0].t(), 5.0 - lone_radius(), EPSILON);
+        assert_f64_eq!(xs[1].t(), 5.0 + lone_radius(), EPSILON);
+
+        let n = b.local_normal_at(p!(0, lone_radius(), 0));
+        assert_f64_eq!(n.y(), 1.0, EPSILON);
+        assert!(Blob::default()
+            .local_intersect_with(Ray::new(p!(0, 0, -5), v!(0, 0, 1)))
+            .is_empty());
+    }
+
+    #[test]
+    fn nearby_components_melt_together() {
+        let pair = |gap: f64| {
+            Blob::default()
+                .with_component(BlobComponent::new(p!(-gap / 2.0, 0, 0), 1.0, 1.0))
+                .with_component(BlobComponent::new(p!(gap / 2.0, 0, 0), 1.0, 1.0))
+        };
+        let r = Ray::new(p!(-5, 0, 0), v!(1, 0, 0));
+        assert_eq!(pair(1.2).local_intersect_with(r).len(), 2);
+        assert_eq!(pair(3.0).local_intersect_with(r).len(), 4);
+
+        // Between the two, the surface pinches in, but the normal still points straight out.
+        let b = pair(1.2);
+        let xs = b.local_intersect_with(Ray::new(p!(0, 5, 0), v!(0, -1, 0)));
+        assert_eq!(xs.len(), 2);
+        let n = b.local_normal_at(Ray::new(p!(0, 5, 0), v!(0, -1, 0)).position_at(xs[0].t()));
+        assert_f64_eq!(n.y(), 1.0, EPSILON);
+        assert!(b.field(p!(0, 0, 0)) > b.threshold());
+    }
+
+    #[test]
+    fn carving_and_transforming_blobs() {
+        let b = Blob::new(vec![
+            BlobComponent::new(p!(0, 0, 0), 1.0, 1.0),
+            BlobComponent::new(p!(0, 0, 0), 0.3, -2.0),
+        ])
+        .with_transform(Tr::new().scale(2.0, 2.0, 2.0));
+        // A hollow ball, so a ray through the middle crosses four surfaces.
+        let xs = b.intersect_with(Ray::new(p!(0, 0, -5), v!(0, 0, 1)));
+        assert_eq!(xs.len(), 4);
+        assert_f64_eq!(xs[0].t(), 5.0 - 2.0 * lone_radius(), EPSILON);
+        assert_eq!(b.bounds().max(), p!(2, 2, 2));
+    }
+}
@@ -0,0 +1,407 @@
+use crate::bounds::Bounds;
+use crate::get_uid;
+use crate::light::Material;
+use crate::matrix::Matrix;
+use crate::ray::{Intersection, Ray};
+use crate::shapes::group::InGroup;
+use crate::shapes::{derived_transforms, Instance, Object, Shape};
+use crate::transform::{self, Tr};
+use crate::tuple::{Point, Vector};
+use std::sync::Arc;
+
+/// How a [`Csg`] combines its two shapes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CsgOp {
+    /// Everything in either shape.
+    Union,
+    /// Only what is in both shapes.
+    Intersection,
+    /// What is in the left shape, but not the right.
+    Difference,
+}
+
+impl CsgOp {
+    /// Decides if an intersection survives the operation, given which side was hit, and whether
+    /// the ray is currently inside the left and right shapes.
+    pub fn allows(&self, left_hit: bool, in_left: bool, in_right: bool) -> bool {
+        match self {
+            CsgOp::Union => (left_hit && !in_right) || (!left_hit && !in_left),
+            CsgOp::Intersection => (left_hit && in_right) || (!left_hit && in_left),
+            CsgOp::Difference => (left_hit && !in_right) || (!left_hit && in_left),
+        }
+    }
+}
+
+/// Constructive solid geometry: a shape made by combining two other shapes, like a lens from two
+/// spheres or a sphere with a bite taken out of it. Either side may itself be a group or another
+/// CSG shape.
+#[derive(Debug, Clone)]
+pub struct Csg {
+    id: usize,
+    name: Option<String>,
+    op: CsgOp,
+    left: Object,
+    right: Object,
+    /// The box around both sides, in object space.
+    bounds: Bounds,
+
+    transform: Tr,
+    inv_transform: Tr,
+    /// A matrix used in computing normals.
+    norm_transform: Matrix<3, 3>,
+
+    /// Rays only ever hit the two sides, which have materials of their own. This is only here to
+    /// satisfy the [`Shape`] trait.
+    material: Material,
+}
+
+impl PartialEq for Csg {
+    fn eq(&self, other: &Self) -> bool {
+        self.op == other.op
+            && *self.left == *other.left
+            && *self.right == *other.right
+            && self.transform == other.transform
+    }
+}
+
+impl Shape for Csg {
+    fn transform(&self) -> Tr {
+        self.transform
+    }
+
+    fn inv_transform(&self) -> Tr {
+        self.inv_transform
+    }
+
+    fn norm_transform(&self) -> Matrix<3, 3> {
+        self.norm_transform
+    }
+
+    fn set_transform(&mut self, t: Tr) {
+        self.try_set_transform(t)
+            .expect("shape transformation should be invertible");
+    }
+
+    fn material(&self) -> Material {
+        self.material.clone()
+    }
+
+    fn set_material(&mut self, m: Material) {
+        self.material = m;
+    }
+
+    fn local_intersect_with(&self, r: Ray) -> Vec<Intersection> {
+        if !self.bounds.intersects(r) {
+            return vec![];
+        }
+        let mut xs: Vec<(Intersection, bool)> = self
+            .left
+            .intersect_with(r)
+            .into_iter()
+            .map(|x| (x, true))
+            .chain(self.right.intersect_with(r).into_iter().map(|x| (x, false)))
+            .collect();
+        xs.sort_by(|a, b| a.0.t().total_cmp(&b.0.t()));
+        self.filter_intersections(xs)
+            .into_iter()
+            .map(|x| InGroup::wrap(x, self))
+            .collect()
+    }
+
+    fn local_normal_at(&self, _: Point) -> Vector {
+        unreachable!("rays never hit a CSG shape, only its sides")
+    }
+
+    fn local_bounds(&self) -> Bounds {
+        self.bounds
+    }
+
+    fn is_solid(&self) -> bool {
+        self.left.is_solid() && self.right.is_solid()
+    }
+
+    fn local_contains(&self, p: Point) -> bool {
+        let (l, r) = (self.left.contains(p), self.right.contains(p));
+        match self.op {
+            CsgOp::Union => l || r,
+            CsgOp::Intersection => l && r,
+            CsgOp::Difference => l && !r,
+        }
+    }
+
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn eqx(&self, other: &dyn std::any::Any) -> bool {
+        other.downcast_ref::<Self>().is_some_and(|a| a == self)
+    }
+}
+
+impl Csg {
+    pub fn new(op: CsgOp, left: Object, right: Object) -> Self {
+        Self {
+            id: get_uid(),
+            name: None,
+            op,
+            bounds: left.bounds().union(&right.bounds()),
+            left,
+            right,
+            transform: Tr::default(),
+            inv_transform: Tr::default(),
+            norm_transform: Matrix::<3, 3>::ident(),
+            material: Material::default(),
+        }
+    }
+
+    pub fn union(left: Object, right: Object) -> Self {
+        Self::new(CsgOp::Union, left, right)
+    }
+
+    pub fn intersection(left: Object, right: Object) -> Self {
+        Self::new(CsgOp::Intersection, left, right)
+    }
+
+    pub fn difference(left: Object, right: Object) -> Self {
+        Self::new(CsgOp::Difference, left, right)
+    }
+
+    /// Hollows out a solid shape, leaving a shell of some thickness, like the walls of a glass.
+    /// The hollow is a copy of the shape, shrunk about the middle of its box until each side of
+    /// the box has moved in by the thickness. That makes the walls exactly as thick as asked for
+    /// on spheres, cylinders and boxes, and roughly so on other convex shapes. Shapes which go on
+    /// forever along some axis are not shrunk along it.
+    pub fn shell(object: Object, thickness: f64) -> Self {
+        let b = object.bounds();
+        let (size, c) = (b.size(), b.center());
+        let shrink = |s: f64| {
+            if s.is_finite() && s > 0.0 {
+                ((s - 2.0 * thickness) / s).max(f64::EPSILON)
+            } else {
+                1.0
+            }
+        };
+        let hollow = Instance::new(object.clone()).with_transform(Tr::new().scale_about(
+            shrink(size.x()),
+            shrink(size.y()),
+            shrink(size.z()),
+            if c.x().is_finite() { c.x() } else { 0.0 },
+            if c.y().is_finite() { c.y() } else { 0.0 },
+            if c.z().is_finite() { c.z() } else { 0.0 },
+        ));
+        Self::difference(object, hollow.as_object())
+    }
+
+    pub fn op(&self) -> CsgOp {
+        self.op
+    }
+
+    pub fn left(&self) -> &Object {
+        &self.left
+    }
+
+    pub fn right(&self) -> &Object {
+        &self.right
+    }
+
+    /// Keeps only the intersections which lie on the surface of the combined shape. Every
+    /// intersection is tagged with whether it was with the left side, and they must be sorted.
+    fn filter_intersections(&self, xs: Vec<(Intersection, bool)>) -> Vec<Intersection> {
+        let (mut in_left, mut in_right) = (false, false);
+        let mut res = vec![];
+        for (x, left_hit) in xs {
+            if self.op.allows(left_hit, in_left, in_right) {
+                res.push(x);
+            }
+            if left_hit {
+                in_left = !in_left;
+            } else {
+                in_right = !in_right;
+            }
+        }
+        res
+    }
+
+    pub fn with_transform(mut self, t: Tr) -> Self {
+        self.set_transform(t);
+        self
+    }
+
+    /// Like `with_transform`, but fails instead of panicking if the transformation is singular.
+    pub fn try_with_transform(mut self, t: Tr) -> Result<Self, transform::Error> {
+        self.try_set_transform(t)?;
+        Ok(self)
+    }
+
+    fn try_set_transform(&mut self, t: Tr) -> Result<(), transform::Error> {
+        (self.inv_transform, self.norm_transform) = derived_transforms(t)?;
+        self.transform = t;
+        Ok(())
+    }
+
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn as_object(self) -> Object {
+        Arc::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Csg, CsgOp};
+    use crate::ray::{Intersection, Ray};
+    use crate::shapes::{Cylinder, Shape, Sphere};
+    use crate::transform::Tr;
+    use crate::{assert_f64_eq, p, v, EPSILON};
+
+    #[test]
+    fn evaluating_rules_for_operations() {
+        use CsgOp::*;
+        #[rustfmt::skip]
+        let tests = [
+            (Union, true, true, true, false),
+            (Union, true, true, false, true),
+            (Union, true, false, true, false),
+            (Union, true, false, false, true),
+            (Union, false, true, true, false),
+            (Union, false, true, false, false),
+            (Union, false, false, true, true),
+            (Union, false, false, false, true),
+            (Intersection, true, true, true, true),
+            (Intersection, true, true, false, false),
+            (Intersection, true, false, true, true),
+            (Intersection, true, false, false, false),
+            (Intersection, false, true, true, true),
+            (Intersection, false, true, false, true),
+            (Intersection, false, false, true, false),
+            (Intersection, false, false, false, false),
+            (Difference, true, true, true, false),
+            (Difference, true, true, false, true),
+            (Difference, true, false, true, false),
+            (Difference, true, false, false, true),
+            (Difference, false, true, true, true),
+            (Difference, false, true, false, true),
+            (Difference, false, false, true, false),
+            (Difference, false, false, false, false),
+        ];
+        for (op, left_hit, in_left, in_right, want) in tests {
+            assert_eq!(
+                op.allows(left_hit, in_left, in_right),
+                want,
+                "{op:?} {left_hit} {in_left} {in_right}"
+            );
+        }
+    }
+
+    #[test]
+    fn filtering_list_of_intersections() {
+        let s1 = Sphere::default().as_object();
+        let s2 = Sphere::default().as_object();
+        let tests = [
+            (CsgOp::Union, 0, 3),
+            (CsgOp::Intersection, 1, 2),
+            (CsgOp::Difference, 0, 1),
+        ];
+        for (op, x0, x1) in tests {
+            let c = Csg::new(op, s1.clone(), s2.clone());
+            let xs: Vec<_> = [(1.0, true), (2.0, false), (3.0, true), (4.0, false)]
+                .into_iter()
+                .map(|(t, left)| {
+                    let obj = if left { s1.clone() } else { s2.clone() };
+                    (Intersection::new(t, obj), left)
+                })
+                .collect();
+            let want = [xs[x0].0.t(), xs[x1].0.t()];
+            let got: Vec<f64> = c.filter_intersections(xs).iter().map(|x| x.t()).collect();
+            assert_eq!(got, want);
+        }
+    }
+
+    #[test]
+    fn ray_misses_csg() {
+        let c = Csg::union(Sphere::default().as_object(), Sphere::default().as_object());
+        let r = Ray::new(p!(0, 2, -5), v!(0, 0, 1));
+        assert!(c.local_intersect_with(r).is_empty());
+    }
+
+    #[test]
+    fn ray_hits_csg() {
+        let s1 = Sphere::default().as_object();
+        let s2 = Sphere::default()
+            .with_transform(Tr::new().translate(0.0, 0.0, 0.5))
+            .as_object();
+        let c = Csg::union(s1.clone(), s2.clone());
+        let r = Ray::new(p!(0, 0, -5), v!(0, 0, 1));
+        let xs = c.local_intersect_with(r);
+        assert_eq!(xs.len(), 2);
+        assert_eq!(xs[0].t(), 4.0);
+        assert_eq!(*xs[0].object(), *s1);
+        assert_eq!(xs[1].t(), 6.5);
+        assert_eq!(*xs[1].object(), *s2);
+    }
+
+    #[test]
+    fn carving_a_hole() {
+        // A unit sphere with a smaller sphere taken out of its middle is hollow.
+        let small = Sphere::default()
+            .with_transform(Tr::new().scale_prop(0.5))
+            .as_object();
+        let c = Csg::difference(Sphere::default().as_object(), small)
+            .with_transform(Tr::new().translate(0.0, 0.0, 1.0));
+        let r = Ray::new(p!(0, 0, -5), v!(0, 0, 1));
+        let got: Vec<f64> = c.intersect_with(r).iter().map(|x| x.t()).collect();
+        assert_eq!(got, vec![5.0, 5.5, 6.5, 7.0]);
+        // The walls of the hole are the smaller sphere, seen through the transformation.
+        let xs = c.intersect_with(r);
+        assert_eq!(xs[1].object().normal_at(p!(0, 0, 0.5)), v!(0, 0, -1));
+
+        // Inside the hole is not inside the shape.
+        assert!(c.contains(p!(0, 0, 0.2)));
+        assert!(!c.contains(p!(0, 0, 1)));
+        assert!(!c.contains(p!(0, 0, -0.2)));
+    }
+
+    #[test]
+    fn hollowing_out_shapes() {
+        let ball = Csg::shell(Sphere::default().as_object(), 0.1);
+        let r = Ray::new(p!(0, 0, -5), v!(0, 0, 1));
+        let got: Vec<f64> = ball.intersect_with(r).iter().map(|x| x.t()).collect();
+        assert_eq!(got.len(), 4);
+        for (got, want) in got.iter().zip([4.0, 4.1, 5.9, 6.0]) {
+            assert_f64_eq!(got, want, EPSILON);
+        }
+        assert!(ball.contains(p!(0, 0.95, 0)));
+        assert!(!ball.contains(p!(0, 0, 0)));
+
+        // A closed can, with walls, a floor and a lid all as thick as each other.
+        let can = Cylinder::default()
+            .with_minimum(0.0)
+            .with_maximum(2.0)
+            .with_closed(true)
+            .as_object();
+        let can = Csg::shell(can, 0.1);
+        let down = Ray::new(p!(0, 5, 0), v!(0, -1, 0));
+        let got: Vec<f64> = can.intersect_with(down).iter().map(|x| x.t()).collect();
+        assert_eq!(got.len(), 4);
+        for (got, want) in got.iter().zip([3.0, 3.1, 4.9, 5.0]) {
+            assert_f64_eq!(got, want, EPSILON);
+        }
+        let across = Ray::new(p!(-5, 1, 0), v!(1, 0, 0));
+        let got: Vec<f64> = can.intersect_with(across).iter().map(|x| x.t()).collect();
+        assert_eq!(got.len(), 4);
+        for (got, want) in got.iter().zip([4.0, 4.1, 5.9, 6.0]) {
+            assert_f64_eq!(got, want, EPSILON);
+        }
+    }
+}
@@ -0,0 +1,424 @@
+use crate::bounds::Bounds;
+use crate::light::Material;
+use crate::matrix::Matrix;
+use crate::ray::{Intersection, Ray};
+use crate::shapes::{derived_transforms, Object, Shape};
+use crate::transform::{self, Tr};
+use crate::tuple::{Point, Vector};
+use crate::v;
+use crate::{get_uid, EPSILON};
+use std::f64::consts::TAU;
+use std::sync::Arc;
+
+/// A cylinder of radius one around the y axis. By default it goes on forever in both directions,
+/// but it can be truncated, and the truncated ends can be capped.
+#[derive(Debug, Clone)]
+pub struct Cylinder {
+    id: usize,
+    name: Option<String>,
+    /// The lowest y value of the cylinder, exclusive.
+    minimum: f64,
+    /// The highest y value of the cylinder, exclusive.
+    maximum: f64,
+    /// Whether the ends of a truncated cylinder are capped.
+    closed: bool,
+
+    transform: Tr,
+    inv_transform: Tr,
+    /// A matrix used in computing normals.
+    norm_transform: Matrix<3, 3>,
+
+    material: Material,
+}
+
+impl Default for Cylinder {
+    fn default() -> Self {
+        Self {
+            id: get_uid(),
+            name: None,
+            minimum: f64::NEG_INFINITY,
+            maximum: f64::INFINITY,
+            closed: false,
+            transform: Tr::default(),
+            inv_transform: Tr::default(),
+            norm_transform: Matrix::<3, 3>::ident(),
+            material: Material::default(),
+        }
+    }
+}
+
+impl PartialEq for Cylinder {
+    fn eq(&self, other: &Self) -> bool {
+        self.minimum == other.minimum
+            && self.maximum == other.maximum
+            && self.closed == other.closed
+            && self.transform == other.transform
+            && self.inv_transform == other.inv_transform
+            && self.norm_transform == other.norm_transform
+            && self.material == other.material
+    }
+}
+
+impl Shape for Cylinder {
+    fn transform(&self) -> Tr {
+        self.transform
+    }
+
+    fn inv_transform(&self) -> Tr {
+        self.inv_transform
+    }
+
+    fn norm_transform(&self) -> Matrix<3, 3> {
+        self.norm_transform
+    }
+
+    fn set_transform(&mut self, t: Tr) {
+        self.try_set_transform(t)
+            .expect("shape transformation should be invertible");
+    }
+
+    fn material(&self) -> Material {
+        self.material.clone()
+    }
+
+    fn set_material(&mut self, m: Material) {
+        self.material = m;
+    }
+
+    fn local_intersect_with(&self, r: Ray) -> Vec<Intersection> {
+        let (o, d) = (r.origin(), r.direction());
+        let mut xs = vec![];
+
+        let a = d.x() * d.x() + d.z() * d.z();
+        // A ray parallel to the y axis can only hit the caps.
+        if a.abs() >= EPSILON {
+            let b = 2.0 * o.x() * d.x() + 2.0 * o.z() * d.z();
+            let c = o.x() * o.x() + o.z() * o.z() - 1.0;
+            let disc = b * b - 4.0 * a * c;
+            if disc < 0.0 {
+                return vec![];
+            }
+            let t0 = (-b - disc.sqrt()) / (2.0 * a);
+            let t1 = (-b + disc.sqrt()) / (2.0 * a);
+            for t in [t0.min(t1), t0.max(t1)] {
+                let y = o.y() + t * d.y();
+                if self.minimum < y && y < self.maximum {
+                    xs.push(Intersection::new(t, Arc::new(self.clone())));
+                }
+            }
+        }
+
+        self.intersect_caps(r, &mut xs);
+        xs
+    }
+
+    fn local_normal_at(&self, p: Point) -> Vector {
+        let dist = p.x() * p.x() + p.z() * p.z();
+        if dist < 1.0 && p.y() >= self.maximum - EPSILON {
+            v!(0.0, 1.0, 0.0)
+        } else if dist < 1.0 && p.y() <= self.minimum + EPSILON {
+            v!(0.0, -1.0, 0.0)
+        } else {
+            v!(p.x(), 0.0, p.z())
+        }
+    }
+
+    fn local_bounds(&self) -> Bounds {
+        Bounds::new(
+            Point::new(-1.0, self.minimum, -1.0),
+            Point::new(1.0, self.maximum, 1.0),
+        )
+    }
+
+    fn is_solid(&self) -> bool {
+        self.closed
+    }
+
+    fn local_contains(&self, p: Point) -> bool {
+        self.minimum < p.y() && p.y() < self.maximum && p.x().powi(2) + p.z().powi(2) < 1.0
+    }
+
+    /// Wraps the texture around the wall once, starting and ending at -z, like a label around a
+    /// can. A truncated wall gets the texture once from bottom to top, while an endless one
+    /// repeats it every unit.
+    ///
+    /// The caps of a closed cylinder each get the whole texture, with its circle touching the
+    /// edges of the cap. Seen from outside, the top of the texture is towards -z on the top cap,
+    /// and towards +z on the bottom cap.
+    fn local_uv_at(&self, p: Point) -> (f64, f64) {
+        let on_cap = self.closed && p.x() * p.x() + p.z() * p.z() < 1.0;
+        if on_cap && p.y() >= self.maximum - EPSILON {
+            return ((p.x() + 1.0) / 2.0, (1.0 - p.z()) / 2.0);
+        }
+        if on_cap && p.y() <= self.minimum + EPSILON {
+            return ((p.x() + 1.0) / 2.0, (1.0 + p.z()) / 2.0);
+        }
+        let theta = p.x().atan2(p.z());
+        let u = (1.0 - (theta / TAU + 0.5)).rem_euclid(1.0);
+        if self.minimum.is_finite() && self.maximum.is_finite() {
+            let v = (p.y() - self.minimum) / (self.maximum - self.minimum);
+            return (u, v.clamp(0.0, 1.0));
+        }
+        (u, p.y().rem_euclid(1.0))
+    }
+
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn eqx(&self, other: &dyn std::any::Any) -> bool {
+        other.downcast_ref::<Self>().is_some_and(|a| a == self)
+    }
+}
+
+impl Cylinder {
+    pub fn minimum(&self) -> f64 {
+        self.minimum
+    }
+
+    pub fn maximum(&self) -> f64 {
+        self.maximum
+    }
+
+    pub fn closed(&self) -> bool {
+        self.closed
+    }
+
+    pub fn with_minimum(mut self, minimum: f64) -> Self {
+        self.minimum = minimum;
+        self
+    }
+
+    pub fn with_maximum(mut self, maximum: f64) -> Self {
+        self.maximum = maximum;
+        self
+    }
+
+    pub fn with_closed(mut self, closed: bool) -> Self {
+        self.closed = closed;
+        self
+    }
+
+    pub fn with_transform(mut self, t: Tr) -> Self {
+        self.set_transform(t);
+        self
+    }
+
+    /// Like `with_transform`, but fails instead of panicking if the transformation is singular.
+    pub fn try_with_transform(mut self, t: Tr) -> Result<Self, transform::Error> {
+        self.try_set_transform(t)?;
+        Ok(self)
+    }
+
+    fn try_set_transform(&mut self, t: Tr) -> Result<(), transform::Error> {
+        (self.inv_transform, self.norm_transform) = derived_transforms(t)?;
+        self.transform = t;
+        Ok(())
+    }
+
+    pub fn with_material(mut self, m: Material) -> Self {
+        self.material = m;
+        self
+    }
+
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn as_object(self) -> Object {
+        Arc::new(self)
+    }
+
+    /// Adds the intersections a ray has with the end caps, if the cylinder is closed.
+    fn intersect_caps(&self, r: Ray, xs: &mut Vec<Intersection>) {
+        let (o, d) = (r.origin(), r.direction());
+        if !self.closed || d.y().abs() < EPSILON {
+            return;
+        }
+        for y in [self.minimum, self.maximum] {
+            let t = (y - o.y()) / d.y();
+            let (x, z) = (o.x() + t * d.x(), o.z() + t * d.z());
+            // Is the point within the radius of the cylinder?
+            if x * x + z * z <= 1.0 {
+                xs.push(Intersection::new(t, Arc::new(self.clone())));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Cylinder;
+    use crate::ray::Ray;
+    use crate::shapes::Shape;
+    use crate::{assert_f64_eq, p, v, EPSILON};
+    use std::f64::consts::FRAC_1_SQRT_2;
+
+    #[test]
+    fn ray_misses_cylinder() {
+        let cyl = Cylinder::default();
+        let tests = vec![
+            (p!(1, 0, 0), v!(0, 1, 0)),
+            (p!(0, 0, 0), v!(0, 1, 0)),
+            (p!(0, 0, -5), v!(1, 1, 1)),
+        ];
+        for (origin, direction) in tests {
+            let r = Ray::new(origin, direction.normalize());
+            assert_eq!(cyl.local_intersect_with(r).len(), 0);
+        }
+    }
+
+    #[test]
+    fn ray_strikes_cylinder() {
+        let cyl = Cylinder::default();
+        let tests = vec![
+            (p!(1, 0, -5), v!(0, 0, 1), 5.0, 5.0),
+            (p!(0, 0, -5), v!(0, 0, 1), 4.0, 6.0),
+            (p!(0.5, 0, -5), v!(0.1, 1, 1), 6.80798, 7.08872),
+        ];
+        for (origin, direction, t0, t1) in tests {
+            let r = Ray::new(origin, direction.normalize());
+            let xs = cyl.local_intersect_with(r);
+            assert_eq!(xs.len(), 2);
+            assert_f64_eq!(xs[0].t(), t0, EPSILON);
+            assert_f64_eq!(xs[1].t(), t1, EPSILON);
+        }
+    }
+
+    #[test]
+    fn normal_on_cylinder() {
+        let cyl = Cylinder::default();
+        let tests = vec![
+            (p!(1, 0, 0), v!(1, 0, 0)),
+            (p!(0, 5, -1), v!(0, 0, -1)),
+            (p!(0, -2, 1), v!(0, 0, 1)),
+            (p!(-1, 1, 0), v!(-1, 0, 0)),
+        ];
+        for (point, want) in tests {
+            assert_eq!(cyl.local_normal_at(point), want);
+        }
+    }
+
+    #[test]
+    fn default_cylinder_is_infinite_and_open() {
+        let cyl = Cylinder::default();
+        assert_eq!(cyl.minimum(), f64::NEG_INFINITY);
+        assert_eq!(cyl.maximum(), f64::INFINITY);
+        assert!(!cyl.closed());
+    }
+
+    #[test]
+    fn intersecting_constrained_cylinder() {
+        let cyl = Cylinder::default().with_minimum(1.0).with_maximum(2.0);
+        let tests = vec![
+            (p!(0, 1.5, 0), v!(0.1, 1, 0), 0),
+            (p!(0, 3, -5), v!(0, 0, 1), 0),
+            (p!(0, 0, -5), v!(0, 0, 1), 0),
+            (p!(0, 2, -5), v!(0, 0, 1), 0),
+            (p!(0, 1, -5), v!(0, 0, 1), 0),
+            (p!(0, 1.5, -2), v!(0, 0, 1), 2),
+        ];
+        for (origin, direction, count) in tests {
+            let r = Ray::new(origin, direction.normalize());
+            assert_eq!(cyl.local_intersect_with(r).len(), count);
+        }
+    }
+
+    #[test]
+    fn intersecting_caps_of_closed_cylinder() {
+        let cyl = Cylinder::default()
+            .with_minimum(1.0)
+            .with_maximum(2.0)
+            .with_closed(true);
+        let tests = vec![
+            (p!(0, 3, 0), v!(0, -1, 0), 2),
+            (p!(0, 3, -2), v!(0, -1, 2), 2),
+            (p!(0, 4, -2), v!(0, -1, 1), 2),
+            (p!(0, 0, -2), v!(0, 1, 2), 2),
+            (p!(0, -1, -2), v!(0, 1, 1), 2),
+        ];
+        for (origin, direction, count) in tests {
+            let r = Ray::new(origin, direction.normalize());
+            assert_eq!(cyl.local_intersect_with(r).len(), count);
+        }
+    }
+
+    #[test]
+    fn normal_on_end_caps() {
+        let cyl = Cylinder::default()
+            .with_minimum(1.0)
+            .with_maximum(2.0)
+            .with_closed(true);
+        let tests = vec![
+            (p!(0, 1, 0), v!(0, -1, 0)),
+            (p!(0.5, 1, 0), v!(0, -1, 0)),
+            (p!(0, 1, 0.5), v!(0, -1, 0)),
+            (p!(0, 2, 0), v!(0, 1, 0)),
+            (p!(0.5, 2, 0), v!(0, 1, 0)),
+            (p!(0, 2, 0.5), v!(0, 1, 0)),
+        ];
+        for (point, want) in tests {
+            assert_eq!(cyl.local_normal_at(point), want);
+        }
+    }
+
+    #[test]
+    fn only_closed_cylinders_contain_points() {
+        let cyl = Cylinder::default().with_minimum(1.0).with_maximum(2.0);
+        assert!(!cyl.is_solid());
+        assert!(!cyl.contains(p!(0, 1.5, 0)));
+
+        let cyl = cyl.with_closed(true);
+        assert!(cyl.contains(p!(0.5, 1.5, -0.5)));
+        assert!(!cyl.contains(p!(0, 2.5, 0)));
+        assert!(!cyl.contains(p!(1, 1.5, 0.5)));
+    }
+
+    #[test]
+    fn cylindrical_uv_mapping() {
+        let h = FRAC_1_SQRT_2;
+        let tests = vec![
+            (p!(0, 0, -1), (0.0, 0.0)),
+            (p!(0, 0.5, -1), (0.0, 0.5)),
+            (p!(0, 1, -1), (0.0, 0.0)),
+            (p!(h, 0.5, -h), (0.125, 0.5)),
+            (p!(1, 0.5, 0), (0.25, 0.5)),
+            (p!(h, 0.5, h), (0.375, 0.5)),
+            (p!(0, -0.25, 1), (0.5, 0.75)),
+            (p!(-h, 0.5, h), (0.625, 0.5)),
+            (p!(-1, 1.25, 0), (0.75, 0.25)),
+            (p!(-h, 0.5, -h), (0.875, 0.5)),
+        ];
+        let cyl = Cylinder::default();
+        for (p, (u, v)) in tests {
+            let got = cyl.local_uv_at(p);
+            assert_f64_eq!(got.0, u, EPSILON);
+            assert_f64_eq!(got.1, v, EPSILON);
+        }
+    }
+
+    #[test]
+    fn labels_and_lids_on_a_can() {
+        let can = Cylinder::default()
+            .with_minimum(1.0)
+            .with_maximum(3.0)
+            .with_closed(true);
+        // The wall gets the texture once, from bottom to top.
+        assert_eq!(can.local_uv_at(p!(1, 1, 0)), (0.25, 0.0));
+        assert_eq!(can.local_uv_at(p!(0, 2.5, 1)), (0.5, 0.75));
+        assert_eq!(can.local_uv_at(p!(-1, 3, 0)), (0.75, 1.0));
+        // Each cap gets all of it.
+        assert_eq!(can.local_uv_at(p!(0, 3, 0)), (0.5, 0.5));
+        assert_eq!(can.local_uv_at(p!(0.5, 3, -0.5)), (0.75, 0.75));
+        assert_eq!(can.local_uv_at(p!(0.5, 1, -0.5)), (0.75, 0.25));
+    }
+}
@@ -0,0 +1,687 @@
+use crate::bounds::Bounds;
+use crate::get_uid;
+use crate::light::Material;
+use crate::matrix::Matrix;
+use crate::ray::{march_settings, Intersection, Ray};
+use crate::shapes::{derived_transforms, Object, Shape};
+use crate::transform::{self, Tr};
+use crate::tuple::{Point, Vector};
+use std::f64::consts::FRAC_PI_2;
+use std::sync::Arc;
+
+/// A bend of space which no matrix can express, for deforming shapes with [`Deformed`]. Each is
+/// built around the y axis.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Deformation {
+    /// Turns each horizontal slice about the y axis, by some angle in radians per unit up, like
+    /// the grooves of a twisted column.
+    Twist(f64),
+    /// Curves the y axis into an arc towards +x, turning by some angle in radians per unit up.
+    /// The origin stays where it is. Shapes bent more than halfway around the circle fold over
+    /// themselves, and come out wrong.
+    Bend(f64),
+    /// Scales each horizontal slice by one plus some rate times its height, so that a positive
+    /// rate widens the shape going up. Slices which would shrink to nothing or turn inside out
+    /// are kept at a sliver instead.
+    Taper(f64),
+}
+
+/// The thinnest a tapered slice gets.
+const MIN_TAPER: f64 = 1e-3;
+
+impl Deformation {
+    /// Where some point ends up after the deformation.
+    pub fn apply(&self, p: Point) -> Point {
+        match *self {
+            Self::Twist(k) => turn(p, k * p.y()),
+            Self::Bend(k) => {
+                if k == 0.0 {
+                    return p;
+                }
+                let (r, angle) = (1.0 / k, k * p.y());
+                let rho = r - p.x();
+                Point::new(r - rho * angle.cos(), rho * angle.sin(), p.z())
+            }
+            Self::Taper(k) => {
+                let s = taper(k, p.y());
+                Point::new(p.x() * s, p.y(), p.z() * s)
+            }
+        }
+    }
+
+    /// Where some point was before the deformation.
+    pub fn undo(&self, p: Point) -> Point {
+        match *self {
+            Self::Twist(k) => turn(p, -k * p.y()),
+            Self::Bend(k) => {
+                if k == 0.0 {
+                    return p;
+                }
+                let (r, s) = (1.0 / k, k.signum());
+                let angle = (s * p.y()).atan2(s * (r - p.x()));
+                let rho = s * (r - p.x()).hypot(p.y());
+                Point::new(r - rho, angle / k, p.z())
+            }
+            Self::Taper(k) => {
+                let s = taper(k, p.y());
+                Point::new(p.x() / s, p.y(), p.z() / s)
+            }
+        }
+    }
+
+    /// A box around everything the deformation makes of some box.
+    fn bounds(&self, b: &Bounds) -> Bounds {
+        let (lo, hi) = (b.min(), b.max());
+        match *self {
+            Self::Twist(k) if k != 0.0 => {
+                let r = [lo.x(), hi.x()]
+                    .iter()
+                    .flat_map(|x| [lo.z(), hi.z()].map(|z| x.hypot(z)))
+                    .fold(0.0, f64::max);
+                Bounds::new(Point::new(-r, lo.y(), -r), Point::new(r, hi.y(), r))
+            }
+            Self::Bend(k) if k != 0.0 => {
+                // Along each arc the far points are at its ends or where it crosses an axis, and
+                // across the arcs they are on the innermost or outermost one.
+                let r = 1.0 / k;
+                let (a0, a1) = (k * lo.y(), k * hi.y());
+                let (a0, a1) = (a0.min(a1), a0.max(a1));
+                let mut angles = vec![a0, a1];
+                let mut quarter = (a0 / FRAC_PI_2).ceil() * FRAC_PI_2;
+                while quarter < a1 {
+                    angles.push(quarter);
+                    quarter += FRAC_PI_2;
+                }
+                let mut res = Bounds::empty();
+                for rho in [r - lo.x(), r - hi.x()] {
+                    for a in &angles {
+                        let (x, y) = (r - rho * a.cos(), rho * a.sin());
+                        res = res.union(&Bounds::new(
+                            Point::new(x, y, lo.z()),
+                            Point::new(x, y, hi.z()),
+                        ));
+                    }
+                }
+                res
+            }
+            Self::Taper(k) if k != 0.0 => {
+                let mut res = Bounds::empty();
+                for y in [lo.y(), hi.y()] {
+                    let s = taper(k, y);
+                    res = res.union(&Bounds::new(
+                        Point::new(lo.x() * s, y, lo.z() * s),
+                        Point::new(hi.x() * s, y, hi.z() * s),
+                    ));
+                }
+                res
+            }
+            _ => *b,
+        }
+    }
+}
+
+/// Turns a point about the y axis by some angle.
+fn turn(p: Point, angle: f64) -> Point {
+    let (sin, cos) = angle.sin_cos();
+    Point::new(p.x() * cos + p.z() * sin, p.y(), -p.x() * sin + p.z() * cos)
+}
+
+/// How much a slice at some height is scaled by a taper.
+fn taper(k: f64, y: f64) -> f64 {
+    (1.0 + k * y).max(MIN_TAPER)
+}
+
+/// A shape bent by deformations which an affine transformation can't express, like twisting,
+/// bending or tapering. The deformations are applied one after the other, to the shape as it is
+/// placed by its own transformation; the deformed shape is then placed by this one's.
+///
+/// There is no exact way to intersect most deformed shapes, so rays are bent the other way
+/// instead: each ray is cut into straight pieces, and each piece is undeformed and intersected
+/// with the shape. Pieces which hit are cut up finer and finer, until the hits are as precise as
+/// the render settings ask for. This works for any shape, but a piece which only grazes the
+/// surface can miss it, so shapes with fine detail need more pieces.
+#[derive(Debug, Clone)]
+pub struct Deformed {
+    id: usize,
+    name: Option<String>,
+    object: Object,
+    deformations: Arc<[Deformation]>,
+    /// How many pieces each ray through the shape is cut into at first.
+    segments: usize,
+    /// Replaces the materials of everything in the shape, if set.
+    material: Option<Arc<Material>>,
+
+    transform: Tr,
+    inv_transform: Tr,
+    /// A matrix used in computing normals.
+    norm_transform: Matrix<3, 3>,
+}
+
+impl PartialEq for Deformed {
+    fn eq(&self, other: &Self) -> bool {
+        *self.object == *other.object
+            && self.deformations == other.deformations
+            && self.segments == other.segments
+            && self.material == other.material
+            && self.transform == other.transform
+            && self.inv_transform == other.inv_transform
+            && self.norm_transform == other.norm_transform
+    }
+}
+
+impl Shape for Deformed {
+    fn transform(&self) -> Tr {
+        self.transform
+    }
+
+    fn inv_transform(&self) -> Tr {
+        self.inv_transform
+    }
+
+    fn norm_transform(&self) -> Matrix<3, 3> {
+        self.norm_transform
+    }
+
+    fn set_transform(&mut self, t: Tr) {
+        self.try_set_transform(t)
+            .expect("shape transformation should be invertible");
+    }
+
+    /// The deformed shape's own material if it has one, or else the shape's.
+    fn material(&self) -> Material {
+        match &self.material {
+            Some(m) => (**m).clone(),
+            None => self.object.material(),
+        }
+    }
+
+    fn set_material(&mut self, m: Material) {
+        self.material = Some(Arc::new(m));
+    }
+
+    /// Shapes which go on forever can't be cut into pieces, and so are never hit.
+    fn local_intersect_with(&self, r: Ray) -> Vec<Intersection> {
+        let Some((start, end)) = self.local_bounds().clip(r) else {
+            return vec![];
+        };
+        if !(start.is_finite() && end.is_finite()) {
+            return vec![];
+        }
+        let mut xs = vec![];
+        self.hits_between(r, start, end, self.segments, &mut xs);
+        xs
+    }
+
+    fn local_normal_at(&self, _: Point) -> Vector {
+        unreachable!("rays never hit a deformed shape, only the shape it deforms")
+    }
+
+    fn local_bounds(&self) -> Bounds {
+        let b = self.object.bounds();
+        if b.is_empty() || b.is_infinite() {
+            return b;
+        }
+        self.deformations.iter().fold(b, |b, d| d.bounds(&b))
+    }
+
+    fn is_solid(&self) -> bool {
+        self.object.is_solid()
+    }
+
+    fn local_contains(&self, p: Point) -> bool {
+        self.object.contains(self.undo(p))
+    }
+
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    /// The deformed shape's own name if it has one, or else the shape's.
+    fn name(&self) -> Option<&str> {
+        self.name.as_deref().or_else(|| self.object.name())
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn eqx(&self, other: &dyn std::any::Any) -> bool {
+        other.downcast_ref::<Self>().is_some_and(|a| a == self)
+    }
+}
+
+impl Deformed {
+    /// Deforms a shape, by nothing yet.
+    pub fn new(object: Object) -> Self {
+        Self {
+            id: get_uid(),
+            name: None,
+            object,
+            deformations: Arc::new([]),
+            segments: 32,
+            material: None,
+            transform: Tr::default(),
+            inv_transform: Tr::default(),
+            norm_transform: Matrix::<3, 3>::ident(),
+        }
+    }
+
+    /// The shape being deformed.
+    pub fn object(&self) -> &Object {
+        &self.object
+    }
+
+    pub fn deformations(&self) -> &[Deformation] {
+        &self.deformations
+    }
+
+    /// Adds a deformation, applied after all the ones before it.
+    pub fn with_deformation(mut self, d: Deformation) -> Self {
+        let mut ds = self.deformations.to_vec();
+        ds.push(d);
+        self.deformations = ds.into();
+        self
+    }
+
+    pub fn segments(&self) -> usize {
+        self.segments
+    }
+
+    /// Sets how many pieces rays are cut into at first. At least one piece is always used.
+    pub fn with_segments(mut self, segments: usize) -> Self {
+        self.segments = segments.max(1);
+        self
+    }
+
+    /// Where some point in this shape's object space was before the deformations.
+    fn undo(&self, p: Point) -> Point {
+        self.deformations.iter().rev().fold(p, |p, d| d.undo(p))
+    }
+
+    /// Finds where a ray crosses the shape between two distances along it, cutting that stretch
+    /// into some number of pieces.
+    fn hits_between(
+        &self,
+        r: Ray,
+        start: f64,
+        end: f64,
+        pieces: usize,
+        xs: &mut Vec<Intersection>,
+    ) {
+        let dt = (end - start) / pieces as f64;
+        let fine = dt * r.direction().magnitude() <= march_settings().precision;
+        for i in 0..pieces {
+            let (t0, t1) = (start + dt * i as f64, start + dt * (i + 1) as f64);
+            let (a, b) = (self.undo(r.position_at(t0)), self.undo(r.position_at(t1)));
+            let piece: Vec<Intersection> = self
+                .object
+                .intersect_with(Ray::new(a, b - a))
+                .into_iter()
+                .filter(|x| (0.0..1.0).contains(&x.t()))
+                .collect();
+            if piece.is_empty() {
+                continue;
+            }
+            if !fine {
+                let before = xs.len();
+                self.hits_between(r, t0, t1, 8, xs);
+                if xs.len() > before {
+                    continue;
+                }
+            }
+            // Either as fine as it gets, or the finer pieces just missed a grazing hit.
+            for x in piece {
+                let t = t0 + x.t() * (t1 - t0);
+                xs.push(DeformedHit::wrap(x, self, r.position_at(t)).into_hit(t));
+            }
+        }
+    }
+
+    pub fn with_transform(mut self, t: Tr) -> Self {
+        self.set_transform(t);
+        self
+    }
+
+    /// Like `with_transform`, but fails instead of panicking if the transformation is singular.
+    pub fn try_with_transform(mut self, t: Tr) -> Result<Self, transform::Error> {
+        self.try_set_transform(t)?;
+        Ok(self)
+    }
+
+    fn try_set_transform(&mut self, t: Tr) -> Result<(), transform::Error> {
+        (self.inv_transform, self.norm_transform) = derived_transforms(t)?;
+        self.transform = t;
+        Ok(())
+    }
+
+    /// Shades everything in the deformed shape with some material, instead of the shape's own.
+    pub fn with_material(mut self, m: Material) -> Self {
+        self.set_material(m);
+        self
+    }
+
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn as_object(self) -> Object {
+        Arc::new(self)
+    }
+}
+
+/// A hit on a deformed shape, which finds normals and the like by undoing the deformations, in
+/// the same way as [`InGroup`](super::group::InGroup) undoes a group's transformation.
+///
+/// Deformations bend normals differently at every point, so the hit remembers how space is
+/// stretched around the point it was made at.
+#[derive(Debug)]
+struct DeformedHit {
+    child: Object,
+    parent: Tr,
+    parent_inv: Tr,
+    parent_norm: Matrix<3, 3>,
+    deformations: Arc<[Deformation]>,
+    material: Option<Arc<Material>>,
+    /// How the undeformed point moves as the deformed point moves along each axis, at the hit.
+    stretch: [Vector; 3],
+}
+
+impl DeformedHit {
+    /// Makes an intersection with the undeformed shape remember how it was deformed. `at` is the
+    /// point which was hit, in the deformed shape's object space.
+    fn wrap(x: Intersection, parent: &Deformed, at: Point) -> Self {
+        const H: f64 = 1e-5;
+        let axes = [
+            Vector::new(1.0, 0.0, 0.0),
+            Vector::new(0.0, 1.0, 0.0),
+            Vector::new(0.0, 0.0, 1.0),
+        ];
+        let stretch = axes.map(|d| (parent.undo(at + d * H) - parent.undo(at - d * H)) * (0.5 / H));
+        Self {
+            child: x.object(),
+            parent: parent.transform,
+            parent_inv: parent.inv_transform,
+            parent_norm: parent.norm_transform,
+            deformations: parent.deformations.clone(),
+            material: parent.material.clone(),
+            stretch,
+        }
+    }
+
+    fn into_hit(self, t: f64) -> Intersection {
+        Intersection::new(t, Arc::new(self))
+    }
+}
+
+impl Shape for DeformedHit {
+    /// The transformations of the shape and the deformed shape around it, leaving out the
+    /// deformations in between, which no matrix can hold.
+    fn transform(&self) -> Tr {
+        self.child.transform().and(self.parent)
+    }
+
+    fn inv_transform(&self) -> Tr {
+        self.parent_inv.and(self.child.inv_transform())
+    }
+
+    fn norm_transform(&self) -> Matrix<3, 3> {
+        self.parent_norm * self.child.norm_transform()
+    }
+
+    /// Moves this hit of the shape, by changing the transformation it gets from the deformed shape
+    /// so that [`Shape::transform`] becomes `t`. The deformations stay the same.
+    fn set_transform(&mut self, t: Tr) {
+        let parent = self.child.inv_transform().and(t);
+        (self.parent_inv, self.parent_norm) =
+            derived_transforms(parent).expect("shape transformation should be invertible");
+        self.parent = parent;
+    }
+
+    fn material(&self) -> Material {
+        match &self.material {
+            Some(m) => (**m).clone(),
+            None => self.child.material(),
+        }
+    }
+
+    /// Shades this hit of the shape with some material. The deformed shape doesn't change.
+    fn set_material(&mut self, m: Material) {
+        self.material = Some(Arc::new(m));
+    }
+
+    fn local_intersect_with(&self, r: Ray) -> Vec<Intersection> {
+        self.child.local_intersect_with(r)
+    }
+
+    fn local_normal_at(&self, p: Point) -> Vector {
+        self.child.local_normal_at(p)
+    }
+
+    fn local_bounds(&self) -> Bounds {
+        self.child.local_bounds()
+    }
+
+    fn world_to_object(&self, p: Point) -> Point {
+        let p = self.parent_inv.matrix() * p;
+        let p = self.deformations.iter().rev().fold(p, |p, d| d.undo(p));
+        self.child.world_to_object(p)
+    }
+
+    /// The undeformed shape's normal is the gradient of its surface, which the chain rule carries
+    /// over to the deformed shape through how space is stretched.
+    fn normal_to_world(&self, n: Vector) -> Vector {
+        let n = self.child.normal_to_world(n);
+        let [dx, dy, dz] = self.stretch;
+        let m = self.parent_norm * Matrix::new([[dx.dot(n)], [dy.dot(n)], [dz.dot(n)]]);
+        Vector::new(m.get(0, 0), m.get(1, 0), m.get(2, 0)).normalize()
+    }
+
+    /// Directions along the surface are carried the other way through the stretch, so they
+    /// bend along with the surface.
+    fn vector_to_world(&self, v: Vector) -> Vector {
+        let v = self.child.vector_to_world(v);
+        let [dx, dy, dz] = self.stretch;
+        let undo = Matrix::new([
+            [dx.x(), dy.x(), dz.x()],
+            [dx.y(), dy.y(), dz.y()],
+            [dx.z(), dy.z(), dz.z()],
+        ]);
+        let v = match undo.inverse() {
+            Ok(apply) => {
+                let m = apply * Matrix::new([[v.x()], [v.y()], [v.z()]]);
+                Vector::new(m.get(0, 0), m.get(1, 0), m.get(2, 0))
+            }
+            Err(_) => v,
+        };
+        self.parent.matrix() * v
+    }
+
+    fn is_closed_mesh(&self) -> bool {
+        self.child.is_closed_mesh()
+    }
+
+    fn local_uv_at(&self, p: Point) -> (f64, f64) {
+        self.child.local_uv_at(p)
+    }
+
+    fn local_tangents_at(&self, p: Point) -> Option<(Vector, Vector)> {
+        self.child.local_tangents_at(p)
+    }
+
+    fn is_solid(&self) -> bool {
+        self.child.is_solid()
+    }
+
+    fn local_contains(&self, p: Point) -> bool {
+        self.child.local_contains(p)
+    }
+
+    fn id(&self) -> usize {
+        self.child.id()
+    }
+
+    fn name(&self) -> Option<&str> {
+        self.child.name()
+    }
+
+    // A deformed shape is still the same shape, as far as anyone comparing them cares.
+    fn as_any(&self) -> &dyn std::any::Any {
+        self.child.as_any()
+    }
+
+    fn eqx(&self, other: &dyn std::any::Any) -> bool {
+        self.child.eqx(other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Deformation, Deformed};
+    use crate::color::Color;
+    use crate::light::Material;
+    use crate::ray::Ray;
+    use crate::shapes::{Cylinder, Plane, Quad, Shape, Sphere};
+    use crate::transform::Tr;
+    use crate::{assert_f64_eq, p, v};
+    use std::f64::consts::{FRAC_PI_2, FRAC_PI_4};
+    use std::sync::Arc;
+
+    #[test]
+    fn deformations_can_be_undone() {
+        let p = p!(0.3, 1.7, -0.4);
+        for d in [
+            Deformation::Twist(0.8),
+            Deformation::Bend(0.5),
+            Deformation::Bend(-0.5),
+            Deformation::Taper(-0.3),
+        ] {
+            let q = d.undo(d.apply(p));
+            assert_f64_eq!((q - p).magnitude(), 0.0, 1e-9);
+        }
+        // Bending the y axis a quarter of the way around a circle.
+        let top = Deformation::Bend(FRAC_PI_2).apply(p!(0, 1, 0));
+        let r = 1.0 / FRAC_PI_2;
+        assert_f64_eq!(top.x(), r, 1e-9);
+        assert_f64_eq!(top.y(), r, 1e-9);
+    }
+
+    #[test]
+    fn changing_a_hit_leaves_the_deformed_shape_alone() {
+        let ball = Sphere::default().as_object();
+        let bent = Deformed::new(ball)
+            .with_deformation(Deformation::Bend(0.5))
+            .with_transform(Tr::new().translate(0.0, 0.0, 2.0));
+        let r = Ray::new(p!(0, 0, -5), v!(0, 0, 1));
+        let mut hit = bent.intersect_with(r)[0].object();
+        assert_eq!(hit.transform(), Tr::new().translate(0.0, 0.0, 2.0));
+
+        let t = Tr::new().translate(0.0, 3.0, 0.0);
+        let red = Material::default().with_color(Color::new(1.0, 0.0, 0.0));
+        let s = Arc::get_mut(&mut hit).expect("hits should not be shared");
+        s.set_transform(t);
+        s.set_material(red.clone());
+        assert_eq!(hit.transform(), t);
+        assert_eq!(hit.material(), red);
+
+        let fresh = bent.intersect_with(r)[0].object();
+        assert_eq!(fresh.transform(), Tr::new().translate(0.0, 0.0, 2.0));
+        assert_eq!(fresh.material(), Material::default());
+    }
+
+    #[test]
+    fn twisting_shapes() {
+        // A round column looks the same however it is twisted.
+        let column = Cylinder::default()
+            .with_minimum(-2.0)
+            .with_maximum(2.0)
+            .with_closed(true)
+            .with_transform(Tr::new().scale(0.5, 1.0, 0.5))
+            .as_object();
+        let twisted = Deformed::new(column).with_deformation(Deformation::Twist(FRAC_PI_4));
+        assert_f64_eq!(twisted.bounds().max().x(), 0.5 * 2f64.sqrt(), 1e-9);
+        let r = Ray::new(p!(-5, 1, 0), v!(1, 0, 0));
+        let xs = twisted.intersect_with(r);
+        assert_eq!(xs.len(), 2);
+        assert_f64_eq!(xs[0].t(), 4.5, 1e-5);
+        let n = xs[0].normal_at(r.position_at(xs[0].t()));
+        assert_f64_eq!(n.x(), -1.0, 1e-4);
+
+        // A ball off to the side is swept around the column.
+        let ball = Sphere::default()
+            .with_transform(Tr::new().scale(0.5, 0.5, 0.5).translate(2.0, 1.0, 0.0))
+            .as_object();
+        let swept = Deformed::new(ball).with_deformation(Deformation::Twist(FRAC_PI_4));
+        let h = 2f64.sqrt();
+        assert!(swept
+            .intersect_with(Ray::new(p!(2, 5, 0), v!(0, -1, 0)))
+            .is_empty());
+        let xs = swept.intersect_with(Ray::new(p!(-5, 1, -h), v!(1, 0, 0)));
+        assert_eq!(xs.len(), 2);
+
+        // Shapes which go on forever can't be deformed.
+        let floor =
+            Deformed::new(Plane::default().as_object()).with_deformation(Deformation::Twist(1.0));
+        assert!(floor
+            .intersect_with(Ray::new(p!(0, 5, 0), v!(0, -1, 0)))
+            .is_empty());
+    }
+
+    #[test]
+    fn twisting_turns_normals() {
+        // An upright square facing along z, a unit in front of the axis.
+        let wall = Quad::default()
+            .with_transform(Tr::new().rotate_x(FRAC_PI_2).translate(0.0, 0.0, -1.0))
+            .as_object();
+        let twisted = Deformed::new(wall).with_deformation(Deformation::Twist(FRAC_PI_2));
+        // Half way up, the wall has turned by an eighth of a turn.
+        let (sin, cos) = FRAC_PI_4.sin_cos();
+        let r = Ray::new(p!(-5.0 * sin, 0.5, -5.0 * cos), v!(sin, 0, cos));
+        let xs = twisted.intersect_with(r);
+        assert_eq!(xs.len(), 1);
+        assert_f64_eq!(xs[0].t(), 4.0, 1e-5);
+        let n = xs[0].normal_at(r.position_at(xs[0].t()));
+        assert_f64_eq!(n.x().abs(), sin, 1e-4);
+        assert_f64_eq!(n.y(), 0.0, 1e-4);
+        assert_f64_eq!(n.z().abs(), cos, 1e-4);
+    }
+
+    #[test]
+    fn tapering_and_bending() {
+        let cone =
+            Deformed::new(Sphere::default().as_object()).with_deformation(Deformation::Taper(0.5));
+        // Half way up, the slice is a quarter wider, and the side leans out.
+        let edge = 0.75f64.sqrt() * 1.25;
+        let r = Ray::new(p!(-5, 0.5, 0), v!(1, 0, 0));
+        let xs = cone.intersect_with(r);
+        assert_eq!(xs.len(), 2);
+        assert_f64_eq!(xs[0].t(), 5.0 - edge, 1e-5);
+        let n = xs[0].normal_at(r.position_at(xs[0].t()));
+        let (nx, ny) = (-0.75f64.sqrt() / 1.25, 0.2);
+        assert_f64_eq!(n.x(), nx / nx.hypot(ny), 1e-4);
+        assert_f64_eq!(n.y(), ny / nx.hypot(ny), 1e-4);
+        assert!(cone.contains(p!(-1.05, 0.4, 0)));
+        assert!(!Sphere::default().contains(p!(-1.05, 0.4, 0)));
+
+        // A thin post bent over to the right, a quarter of the way around by the top.
+        let post = Cylinder::default()
+            .with_minimum(0.0)
+            .with_maximum(2.0)
+            .with_closed(true)
+            .with_transform(Tr::new().scale(0.2, 1.0, 0.2))
+            .as_object();
+        let bend = Deformation::Bend(FRAC_PI_4);
+        let bent = Deformed::new(post).with_deformation(bend);
+        let middle = bend.apply(p!(0, 1, 0));
+        let xs = bent.intersect_with(Ray::new(p!(middle.x(), middle.y(), -5), v!(0, 0, 1)));
+        assert_eq!(xs.len(), 2);
+        assert_f64_eq!(xs[0].t(), 4.8, 1e-5);
+        let r = 1.0 / FRAC_PI_4;
+        assert_f64_eq!(bent.bounds().max().x(), r, 1e-9);
+        assert_f64_eq!(bent.bounds().max().y(), r + 0.2, 1e-9);
+        // Straight up from the base, the post is no longer there.
+        assert!(bent
+            .intersect_with(Ray::new(p!(0, 1.9, -5), v!(0, 0, 1)))
+            .is_empty());
+    }
+}
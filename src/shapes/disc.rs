@@ -0,0 +1,219 @@
+use crate::bounds::Bounds;
+use crate::get_uid;
+use crate::light::Material;
+use crate::matrix::Matrix;
+use crate::ray::{Intersection, Ray};
+use crate::shapes::plane::xz_plane_hit;
+use crate::shapes::{derived_transforms, Object, Shape};
+use crate::transform::{self, Tr};
+use crate::tuple::{Point, Vector};
+use crate::v;
+use std::sync::Arc;
+
+/// A flat disc in the xz plane, centred on the origin. With a non-zero inner radius it becomes a
+/// ring, with a hole in the middle.
+#[derive(Debug, Clone)]
+pub struct Disc {
+    id: usize,
+    name: Option<String>,
+    inner: f64,
+    outer: f64,
+
+    transform: Tr,
+    inv_transform: Tr,
+    /// A matrix used in computing normals.
+    norm_transform: Matrix<3, 3>,
+
+    material: Material,
+}
+
+impl Default for Disc {
+    fn default() -> Self {
+        Self {
+            id: get_uid(),
+            name: None,
+            inner: 0.0,
+            outer: 1.0,
+            transform: Tr::default(),
+            inv_transform: Tr::default(),
+            norm_transform: Matrix::<3, 3>::ident(),
+            material: Material::default(),
+        }
+    }
+}
+
+impl PartialEq for Disc {
+    fn eq(&self, other: &Self) -> bool {
+        self.inner == other.inner
+            && self.outer == other.outer
+            && self.transform == other.transform
+            && self.inv_transform == other.inv_transform
+            && self.norm_transform == other.norm_transform
+            && self.material == other.material
+    }
+}
+
+impl Shape for Disc {
+    fn transform(&self) -> Tr {
+        self.transform
+    }
+
+    fn inv_transform(&self) -> Tr {
+        self.inv_transform
+    }
+
+    fn norm_transform(&self) -> Matrix<3, 3> {
+        self.norm_transform
+    }
+
+    fn set_transform(&mut self, t: Tr) {
+        self.try_set_transform(t)
+            .expect("shape transformation should be invertible");
+    }
+
+    fn material(&self) -> Material {
+        self.material.clone()
+    }
+
+    fn set_material(&mut self, m: Material) {
+        self.material = m;
+    }
+
+    fn local_intersect_with(&self, r: Ray) -> Vec<Intersection> {
+        let Some(t) = xz_plane_hit(r) else {
+            return vec![];
+        };
+        let p = r.position_at(t);
+        let dist = p.x() * p.x() + p.z() * p.z();
+        if dist < self.inner * self.inner || dist > self.outer * self.outer {
+            return vec![];
+        }
+        vec![Intersection::new(t, Arc::new(self.clone()))]
+    }
+
+    fn local_normal_at(&self, _: Point) -> Vector {
+        v!(0.0, 1.0, 0.0)
+    }
+
+    fn local_bounds(&self) -> Bounds {
+        Bounds::new(
+            Point::new(-self.outer, 0.0, -self.outer),
+            Point::new(self.outer, 0.0, self.outer),
+        )
+    }
+
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn eqx(&self, other: &dyn std::any::Any) -> bool {
+        other.downcast_ref::<Self>().is_some_and(|a| a == self)
+    }
+}
+
+impl Disc {
+    pub fn new(inner: f64, outer: f64) -> Self {
+        Self::default()
+            .with_inner_radius(inner)
+            .with_outer_radius(outer)
+    }
+
+    pub fn inner_radius(&self) -> f64 {
+        self.inner
+    }
+
+    pub fn outer_radius(&self) -> f64 {
+        self.outer
+    }
+
+    pub fn with_inner_radius(mut self, inner: f64) -> Self {
+        self.inner = inner;
+        self
+    }
+
+    pub fn with_outer_radius(mut self, outer: f64) -> Self {
+        self.outer = outer;
+        self
+    }
+
+    pub fn with_transform(mut self, t: Tr) -> Self {
+        self.set_transform(t);
+        self
+    }
+
+    /// Like `with_transform`, but fails instead of panicking if the transformation is singular.
+    pub fn try_with_transform(mut self, t: Tr) -> Result<Self, transform::Error> {
+        self.try_set_transform(t)?;
+        Ok(self)
+    }
+
+    fn try_set_transform(&mut self, t: Tr) -> Result<(), transform::Error> {
+        (self.inv_transform, self.norm_transform) = derived_transforms(t)?;
+        self.transform = t;
+        Ok(())
+    }
+
+    pub fn with_material(mut self, m: Material) -> Self {
+        self.material = m;
+        self
+    }
+
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn as_object(self) -> Object {
+        Arc::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Disc;
+    use crate::ray::Ray;
+    use crate::shapes::Shape;
+    use crate::{p, v};
+
+    #[test]
+    fn ray_hits_inside_the_disc() {
+        let disc = Disc::default();
+        let r = Ray::new(p!(0.5, 1, 0.5), v!(0, -1, 0));
+        let xs = disc.local_intersect_with(r);
+        assert_eq!(xs.len(), 1);
+        assert_eq!(xs[0].t(), 1.0);
+    }
+
+    #[test]
+    fn ray_misses_outside_the_disc() {
+        let disc = Disc::default();
+        let r = Ray::new(p!(1.5, 1, 0), v!(0, -1, 0));
+        assert!(disc.local_intersect_with(r).is_empty());
+        let r = Ray::new(p!(0, 1, 0), v!(1, 0, 0));
+        assert!(disc.local_intersect_with(r).is_empty());
+    }
+
+    #[test]
+    fn ray_passes_through_the_hole_of_a_ring() {
+        let ring = Disc::new(0.5, 1.0);
+        let r = Ray::new(p!(0.25, 1, 0), v!(0, -1, 0));
+        assert!(ring.local_intersect_with(r).is_empty());
+        let r = Ray::new(p!(0, -1, 0.75), v!(0, 1, 0));
+        assert_eq!(ring.local_intersect_with(r).len(), 1);
+    }
+
+    #[test]
+    fn normal_of_disc_constant_everywhere() {
+        let disc = Disc::new(0.5, 2.0);
+        assert_eq!(disc.local_normal_at(p!(1, 0, 0)), v!(0, 1, 0));
+        assert_eq!(disc.local_normal_at(p!(0, 0, -1.5)), v!(0, 1, 0));
+    }
+}
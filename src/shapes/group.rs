@@ -0,0 +1,680 @@
+use crate::arena;
+use crate::bounds::Bounds;
+use crate::get_uid;
+use crate::light::Material;
+use crate::matrix::Matrix;
+use crate::ray::{add_work, Intersection, Ray};
+use crate::shapes::{derived_transforms, Object, Shape};
+use crate::transform::{self, Tr};
+use crate::tuple::{Point, Vector};
+use std::sync::Arc;
+
+/// A collection of shapes which are transformed together, so that compound models can be built
+/// once and then placed anywhere. Groups may contain other groups.
+#[derive(Debug, Clone)]
+pub struct Group {
+    id: usize,
+    name: Option<String>,
+    children: Vec<Object>,
+    /// The box around every child, in object space.
+    bounds: Bounds,
+
+    transform: Tr,
+    inv_transform: Tr,
+    /// A matrix used in computing normals.
+    norm_transform: Matrix<3, 3>,
+
+    /// Groups are never shaded themselves, since rays only ever hit their children. This is only
+    /// here to satisfy the [`Shape`] trait.
+    material: Material,
+}
+
+impl Default for Group {
+    fn default() -> Self {
+        Self {
+            id: get_uid(),
+            name: None,
+            children: vec![],
+            bounds: Bounds::empty(),
+            transform: Tr::default(),
+            inv_transform: Tr::default(),
+            norm_transform: Matrix::<3, 3>::ident(),
+            material: Material::default(),
+        }
+    }
+}
+
+impl PartialEq for Group {
+    fn eq(&self, other: &Self) -> bool {
+        self.transform == other.transform
+            && self.children.len() == other.children.len()
+            && self
+                .children
+                .iter()
+                .zip(&other.children)
+                .all(|(a, b)| **a == **b)
+    }
+}
+
+impl Shape for Group {
+    fn transform(&self) -> Tr {
+        self.transform
+    }
+
+    fn inv_transform(&self) -> Tr {
+        self.inv_transform
+    }
+
+    fn norm_transform(&self) -> Matrix<3, 3> {
+        self.norm_transform
+    }
+
+    fn set_transform(&mut self, t: Tr) {
+        self.try_set_transform(t)
+            .expect("shape transformation should be invertible");
+    }
+
+    fn material(&self) -> Material {
+        self.material.clone()
+    }
+
+    fn set_material(&mut self, m: Material) {
+        self.material = m;
+    }
+
+    /// Intersects every child, unless the ray misses the group altogether. The objects in the
+    /// intersections remember that they are in this group, so that their normals and patterns
+    /// come out right in world space.
+    ///
+    /// The list comes from this thread's pool, and the lists of the children go back to it, so
+    /// going down a hierarchy of groups doesn't allocate once the pool is warm.
+    fn local_intersect_with(&self, r: Ray) -> Vec<Intersection> {
+        if !self.bounds.intersects(r) {
+            return vec![];
+        }
+        add_work(self.children.len());
+        // Wrapping through an untransformed group changes nothing, and the groups made by
+        // `divide` are all untransformed.
+        let wrap = self.transform != Tr::default();
+        let mut xs: Vec<Intersection> = arena::take();
+        for child in &self.children {
+            let mut hits = child.intersect_with(r);
+            if wrap {
+                xs.extend(hits.drain(..).map(|x| InGroup::wrap(x, self)));
+            } else {
+                xs.append(&mut hits);
+            }
+            arena::give(hits);
+        }
+        xs.sort_by(|a, b| a.t().total_cmp(&b.t()));
+        xs
+    }
+
+    fn local_normal_at(&self, _: Point) -> Vector {
+        unreachable!("rays never hit a group, only its children")
+    }
+
+    fn local_bounds(&self) -> Bounds {
+        self.bounds
+    }
+
+    /// A group is only solid if everything in it is, and then a point is inside it if it is inside
+    /// any of its children.
+    fn is_solid(&self) -> bool {
+        !self.children.is_empty() && self.children.iter().all(|c| c.is_solid())
+    }
+
+    fn local_contains(&self, p: Point) -> bool {
+        self.children.iter().any(|c| c.contains(p))
+    }
+
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn eqx(&self, other: &dyn std::any::Any) -> bool {
+        other.downcast_ref::<Self>().is_some_and(|a| a == self)
+    }
+}
+
+impl Group {
+    pub fn new(children: Vec<Object>) -> Self {
+        let mut g = Self::default();
+        for child in children {
+            g.add_child(child);
+        }
+        g
+    }
+
+    pub fn children(&self) -> &[Object] {
+        &self.children
+    }
+
+    pub fn add_child(&mut self, child: Object) {
+        self.bounds = self.bounds.union(&child.bounds());
+        self.children.push(child);
+    }
+
+    pub fn with_child(mut self, child: Object) -> Self {
+        self.add_child(child);
+        self
+    }
+
+    /// A copy of this group with one child swapped for another, and its box fitted around the
+    /// children again. The other children are shared with this group, not copied.
+    pub fn with_child_replaced(&self, idx: usize, child: Object) -> Self {
+        let mut res = self.clone();
+        res.children[idx] = child;
+        res.bounds = res
+            .children
+            .iter()
+            .fold(Bounds::empty(), |acc, c| acc.union(&c.bounds()));
+        res
+    }
+
+    /// Builds a bounding volume hierarchy out of this group, by recursively splitting its children
+    /// into smaller groups according to where they are. A ray then only tests the children in the
+    /// boxes it actually passes through, instead of every single one. Groups with fewer than
+    /// `threshold` children are not split any further, and a threshold of zero leaves the group
+    /// as it is.
+    ///
+    /// Child groups are rebuilt rather than changed in place, so any other references to them
+    /// keep seeing the original.
+    pub fn divide(mut self, threshold: usize) -> Self {
+        if threshold == 0 {
+            return self;
+        }
+        if self.children.len() >= threshold {
+            let (left, right) = self.partition_children();
+            for side in [left, right] {
+                if !side.is_empty() {
+                    self.add_child(Group::new(side).as_object());
+                }
+            }
+        }
+        self.children = std::mem::take(&mut self.children)
+            .into_iter()
+            .map(|child| match child.as_any().downcast_ref::<Group>() {
+                Some(g) => g.clone().divide(threshold).as_object(),
+                None => child,
+            })
+            .collect();
+        self
+    }
+
+    /// Takes out the children which fit entirely in one half or the other of the box around the
+    /// finite children. Children which straddle the middle, or go on forever, stay where they are.
+    /// Nothing is taken out if everything would end up on the same side anyway.
+    fn partition_children(&mut self) -> (Vec<Object>, Vec<Object>) {
+        let finite = self
+            .children
+            .iter()
+            .map(|c| c.bounds())
+            .filter(|b| !b.is_infinite())
+            .fold(Bounds::empty(), |acc, b| acc.union(&b));
+        if finite.is_empty() {
+            return (vec![], vec![]);
+        }
+        let (lbox, rbox) = finite.split();
+        let (mut left, mut right, mut rest) = (vec![], vec![], vec![]);
+        for child in std::mem::take(&mut self.children) {
+            let b = child.bounds();
+            if lbox.contains_box(&b) {
+                left.push(child);
+            } else if rbox.contains_box(&b) {
+                right.push(child);
+            } else {
+                rest.push(child);
+            }
+        }
+        if rest.is_empty() && (left.is_empty() || right.is_empty()) {
+            self.children = left.into_iter().chain(right).collect();
+            return (vec![], vec![]);
+        }
+        self.children = rest;
+        (left, right)
+    }
+
+    pub fn with_transform(mut self, t: Tr) -> Self {
+        self.set_transform(t);
+        self
+    }
+
+    /// Like `with_transform`, but fails instead of panicking if the transformation is singular.
+    pub fn try_with_transform(mut self, t: Tr) -> Result<Self, transform::Error> {
+        self.try_set_transform(t)?;
+        Ok(self)
+    }
+
+    fn try_set_transform(&mut self, t: Tr) -> Result<(), transform::Error> {
+        (self.inv_transform, self.norm_transform) = derived_transforms(t)?;
+        self.transform = t;
+        Ok(())
+    }
+
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn as_object(self) -> Object {
+        Arc::new(self)
+    }
+}
+
+/// The transformations and material which a hit picks up from the groups, or any other shapes
+/// made of shapes, that it was found inside. Every level folds its own into the same set, so a hit
+/// deep in a hierarchy still only carries one.
+#[derive(Debug, Clone)]
+pub(crate) struct Parents {
+    transform: Tr,
+    inv_transform: Tr,
+    norm_transform: Matrix<3, 3>,
+    material: Option<Arc<Material>>,
+    /// The IDs of the parents, innermost first.
+    ids: Vec<usize>,
+}
+
+impl Default for Parents {
+    fn default() -> Self {
+        Self {
+            transform: Tr::default(),
+            inv_transform: Tr::default(),
+            norm_transform: Matrix::<3, 3>::ident(),
+            material: None,
+            ids: vec![],
+        }
+    }
+}
+
+impl Parents {
+    /// Adds a parent around everything so far. Its material, if it has one, replaces any from
+    /// further in.
+    fn push(&mut self, parent: &dyn Shape, material: Option<Arc<Material>>) {
+        self.transform = self.transform.and(parent.transform());
+        self.inv_transform = parent.inv_transform().and(self.inv_transform);
+        self.norm_transform = parent.norm_transform() * self.norm_transform;
+        if material.is_some() {
+            self.material = material;
+        }
+        self.ids.push(parent.id());
+    }
+
+    pub(crate) fn material(&self) -> Option<&Arc<Material>> {
+        self.material.as_ref()
+    }
+
+    pub(crate) fn ids(&self) -> &[usize] {
+        &self.ids
+    }
+}
+
+/// A shape which was hit inside a group, or any other shape made of shapes, along with the
+/// transformations of everything it is in. It behaves exactly like the shape itself, except that
+/// it converts between world and object space through its parents, and may have its material
+/// replaced by theirs.
+#[derive(Debug)]
+pub(crate) struct InGroup {
+    child: Object,
+    parents: Arc<Parents>,
+}
+
+impl InGroup {
+    pub(crate) fn new(child: Object, parents: Arc<Parents>) -> Self {
+        Self { child, parents }
+    }
+
+    /// Makes an intersection with some child remember the transformations of its parent.
+    pub(crate) fn wrap(x: Intersection, parent: &dyn Shape) -> Intersection {
+        Self::wrap_with_material(x, parent, None)
+    }
+
+    /// Like [`InGroup::wrap`], but also shades the child with some other material, if one is
+    /// given.
+    pub(crate) fn wrap_with_material(
+        mut x: Intersection,
+        parent: &dyn Shape,
+        material: Option<Arc<Material>>,
+    ) -> Intersection {
+        x.parents_mut().push(parent, material);
+        x
+    }
+}
+
+impl Shape for InGroup {
+    fn transform(&self) -> Tr {
+        self.child.transform().and(self.parents.transform)
+    }
+
+    fn inv_transform(&self) -> Tr {
+        self.parents.inv_transform.and(self.child.inv_transform())
+    }
+
+    fn norm_transform(&self) -> Matrix<3, 3> {
+        self.parents.norm_transform * self.child.norm_transform()
+    }
+
+    /// Moves this hit of the shape, by changing the transformation it gets from its parents so
+    /// that its whole transformation becomes `t`. Neither the shape nor its groups change.
+    fn set_transform(&mut self, t: Tr) {
+        let parent = self.child.inv_transform().and(t);
+        let parents = Arc::make_mut(&mut self.parents);
+        (parents.inv_transform, parents.norm_transform) =
+            derived_transforms(parent).expect("shape transformation should be invertible");
+        parents.transform = parent;
+    }
+
+    fn material(&self) -> Material {
+        match &self.parents.material {
+            Some(m) => (**m).clone(),
+            None => self.child.material(),
+        }
+    }
+
+    /// Shades this hit of the shape with some material. Neither the shape nor its groups change.
+    fn set_material(&mut self, m: Material) {
+        Arc::make_mut(&mut self.parents).material = Some(Arc::new(m));
+    }
+
+    fn intersect_with(&self, r: Ray) -> Vec<Intersection> {
+        self.child
+            .intersect_with(r.with_transform(self.parents.inv_transform))
+    }
+
+    fn local_intersect_with(&self, r: Ray) -> Vec<Intersection> {
+        self.child.local_intersect_with(r)
+    }
+
+    fn local_normal_at(&self, p: Point) -> Vector {
+        self.child.local_normal_at(p)
+    }
+
+    fn local_bounds(&self) -> Bounds {
+        self.child.local_bounds()
+    }
+
+    fn world_to_object(&self, p: Point) -> Point {
+        self.child
+            .world_to_object(self.parents.inv_transform.matrix() * p)
+    }
+
+    fn normal_to_world(&self, n: Vector) -> Vector {
+        let n = self.child.normal_to_world(n);
+        let m = self.parents.norm_transform * Matrix::new([[n.x()], [n.y()], [n.z()]]);
+        Vector::new(m.get(0, 0), m.get(1, 0), m.get(2, 0)).normalize()
+    }
+
+    fn is_closed_mesh(&self) -> bool {
+        self.child.is_closed_mesh()
+    }
+
+    fn vector_to_world(&self, v: Vector) -> Vector {
+        self.parents.transform.matrix() * self.child.vector_to_world(v)
+    }
+
+    fn local_uv_at(&self, p: Point) -> (f64, f64) {
+        self.child.local_uv_at(p)
+    }
+
+    fn local_tangents_at(&self, p: Point) -> Option<(Vector, Vector)> {
+        self.child.local_tangents_at(p)
+    }
+
+    fn is_solid(&self) -> bool {
+        self.child.is_solid()
+    }
+
+    fn local_contains(&self, p: Point) -> bool {
+        self.child.local_contains(p)
+    }
+
+    fn id(&self) -> usize {
+        self.child.id()
+    }
+
+    fn name(&self) -> Option<&str> {
+        self.child.name()
+    }
+
+    // A shape in a group is still the same shape, as far as anyone comparing them cares.
+    fn as_any(&self) -> &dyn std::any::Any {
+        self.child.as_any()
+    }
+
+    fn eqx(&self, other: &dyn std::any::Any) -> bool {
+        self.child.eqx(other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Group;
+    use crate::color::Color;
+    use crate::light::Material;
+    use crate::ray::Ray;
+    use crate::shapes::{Shape, Sphere};
+    use crate::transform::Tr;
+    use crate::{p, v};
+    use std::f64::consts::FRAC_PI_2;
+    use std::sync::Arc;
+
+    #[test]
+    fn creating_a_group() {
+        let g = Group::default();
+        assert_eq!(g.transform(), Tr::default());
+        assert!(g.children().is_empty());
+
+        let s = Sphere::default().as_object();
+        let g = g.with_child(s.clone());
+        assert_eq!(g.children().len(), 1);
+        assert_eq!(*g.children()[0], *s);
+    }
+
+    #[test]
+    fn intersecting_empty_group() {
+        let r = Ray::new(p!(0, 0, 0), v!(0, 0, 1));
+        assert!(Group::default().local_intersect_with(r).is_empty());
+    }
+
+    #[test]
+    fn intersecting_nonempty_group() {
+        let s1 = Sphere::default().as_object();
+        let s2 = Sphere::default()
+            .with_transform(Tr::new().translate(0.0, 0.0, -3.0))
+            .as_object();
+        let s3 = Sphere::default()
+            .with_transform(Tr::new().translate(5.0, 0.0, 0.0))
+            .as_object();
+        let g = Group::new(vec![s1.clone(), s2.clone(), s3]);
+        let r = Ray::new(p!(0, 0, -5), v!(0, 0, 1));
+        let xs = g.local_intersect_with(r);
+        let got: Vec<_> = xs.iter().map(|x| (x.t(), x.object().id())).collect();
+        let want = vec![
+            (1.0, s2.id()),
+            (3.0, s2.id()),
+            (4.0, s1.id()),
+            (6.0, s1.id()),
+        ];
+        assert_eq!(got, want);
+        assert_eq!(*xs[0].object(), *s2);
+    }
+
+    #[test]
+    fn intersecting_transformed_group() {
+        let s = Sphere::default()
+            .with_transform(Tr::new().translate(5.0, 0.0, 0.0))
+            .as_object();
+        let g = Group::new(vec![s]).with_transform(Tr::new().scale(2.0, 2.0, 2.0));
+        let r = Ray::new(p!(10, 0, -10), v!(0, 0, 1));
+        assert_eq!(g.intersect_with(r).len(), 2);
+    }
+
+    /// A sphere inside two nested groups, with the hit wrapped the way a ray would find it.
+    fn nested_sphere(sx: f64, sy: f64, sz: f64) -> crate::shapes::Object {
+        let s = Sphere::default()
+            .with_transform(Tr::new().translate(5.0, 0.0, 0.0))
+            .as_object();
+        let g2 = Group::new(vec![s]).with_transform(Tr::new().scale(sx, sy, sz));
+        let g1 = Group::new(vec![g2.as_object()]).with_transform(Tr::new().rotate_y(FRAC_PI_2));
+        // Straight down through the sphere, which ends up centered on (0, 0, -5 * sx).
+        let r = Ray::new(p!(0, 10, -5.0 * sx), v!(0, -1, 0));
+        g1.intersect_with(r)[0].object()
+    }
+
+    #[test]
+    fn converting_point_from_world_to_object_space() {
+        let s = nested_sphere(2.0, 2.0, 2.0);
+        assert_eq!(s.world_to_object(p!(-2, 0, -10)), p!(0, 0, -1));
+    }
+
+    #[test]
+    fn converting_normal_from_object_to_world_space() {
+        let s = nested_sphere(1.0, 2.0, 3.0);
+        let k = 3.0_f64.sqrt() / 3.0;
+        let got = s.normal_to_world(v!(k, k, k));
+        assert_eq!(got, v!(0.28571, 0.42857, -0.85714));
+    }
+
+    #[test]
+    fn finding_normal_on_child_object() {
+        let s = nested_sphere(1.0, 2.0, 3.0);
+        let got = s.normal_at(p!(1.7321, 1.1547, -5.5774));
+        assert_eq!(got, v!(0.28570, 0.42854, -0.85716));
+    }
+
+    #[test]
+    fn changing_a_hit_leaves_its_groups_alone() {
+        let mut s = nested_sphere(2.0, 2.0, 2.0);
+        let t = Tr::new().translate(0.0, 3.0, 0.0);
+        let red = Material::default().with_color(Color::new(1.0, 0.0, 0.0));
+        let hit = Arc::get_mut(&mut s).expect("hits should not be shared");
+        hit.set_transform(t);
+        hit.set_material(red.clone());
+        assert_eq!(s.transform(), t);
+        assert_eq!(s.world_to_object(p!(1, 3, 0)), p!(1, 0, 0));
+        assert_eq!(s.normal_at(p!(0, 4, 0)), v!(0, 1, 0));
+        assert_eq!(s.material(), red);
+
+        let fresh = nested_sphere(2.0, 2.0, 2.0);
+        assert_eq!(fresh.world_to_object(p!(-2, 0, -10)), p!(0, 0, -1));
+        assert_eq!(fresh.material(), Material::default());
+    }
+
+    fn as_group(o: &crate::shapes::Object) -> &Group {
+        o.as_any()
+            .downcast_ref::<Group>()
+            .expect("should be a group")
+    }
+
+    #[test]
+    fn group_bounds_contain_children() {
+        let s = Sphere::default()
+            .with_transform(Tr::new().scale(2.0, 2.0, 2.0).translate(2.0, 5.0, -3.0))
+            .as_object();
+        let c = crate::shapes::Cylinder::default()
+            .with_minimum(-2.0)
+            .with_maximum(2.0)
+            .with_transform(Tr::new().scale(0.5, 1.0, 0.5).translate(-4.0, -1.0, 4.0))
+            .as_object();
+        let g = Group::new(vec![s, c]);
+        assert_eq!(g.local_bounds().min(), p!(-4.5, -3, -5));
+        assert_eq!(g.local_bounds().max(), p!(4, 7, 4.5));
+    }
+
+    #[test]
+    fn ray_missing_bounds_skips_children() {
+        let s = Sphere::default()
+            .with_transform(Tr::new().translate(5.0, 0.0, 0.0))
+            .as_object();
+        let g = Group::new(vec![s]);
+        crate::ray::reset_work();
+        assert!(g
+            .local_intersect_with(Ray::new(p!(0, 0, -5), v!(0, 1, 0)))
+            .is_empty());
+        assert_eq!(crate::ray::work_done(), 0);
+        assert_eq!(
+            g.local_intersect_with(Ray::new(p!(5, 0, -5), v!(0, 0, 1)))
+                .len(),
+            2
+        );
+        assert_eq!(crate::ray::work_done(), 1);
+    }
+
+    #[test]
+    fn dividing_a_group_partitions_its_children() {
+        let s1 = Sphere::default()
+            .with_transform(Tr::new().translate(-2.0, -2.0, 0.0))
+            .as_object();
+        let s2 = Sphere::default()
+            .with_transform(Tr::new().translate(-2.0, 2.0, 0.0))
+            .as_object();
+        let s3 = Sphere::default()
+            .with_transform(Tr::new().scale(4.0, 4.0, 4.0))
+            .as_object();
+        let g = Group::new(vec![s1.clone(), s2.clone(), s3.clone()]).divide(1);
+        assert_eq!(g.children().len(), 2);
+        assert_eq!(*g.children()[0], *s3);
+        let sub = as_group(&g.children()[1]);
+        assert_eq!(sub.children().len(), 2);
+        assert_eq!(*as_group(&sub.children()[0]).children()[0], *s1);
+        assert_eq!(*as_group(&sub.children()[1]).children()[0], *s2);
+    }
+
+    #[test]
+    fn dividing_a_group_with_too_few_children() {
+        let s1 = Sphere::default()
+            .with_transform(Tr::new().translate(-2.0, 0.0, 0.0))
+            .as_object();
+        let s2 = Sphere::default()
+            .with_transform(Tr::new().translate(2.0, 1.0, 0.0))
+            .as_object();
+        let s3 = Sphere::default()
+            .with_transform(Tr::new().translate(2.0, -1.0, 0.0))
+            .as_object();
+        let inner = Group::new(vec![s1, s2.clone(), s3.clone()]).as_object();
+        let s4 = Sphere::default().as_object();
+        let g = Group::new(vec![inner, s4.clone()]).divide(3);
+        assert_eq!(g.children().len(), 2);
+        assert_eq!(*g.children()[1], *s4);
+        let inner = as_group(&g.children()[0]);
+        assert_eq!(inner.children().len(), 2);
+        let right = as_group(&inner.children()[1]);
+        assert_eq!(right.children().len(), 2);
+        assert_eq!(*right.children()[0], *s2);
+        assert_eq!(*right.children()[1], *s3);
+    }
+
+    #[test]
+    fn dividing_keeps_the_same_hits() {
+        let spheres = (0..100)
+            .map(|i| {
+                let (x, z) = ((i % 10) as f64 * 3.0, (i / 10) as f64 * 3.0);
+                Sphere::default()
+                    .with_transform(Tr::new().translate(x, 0.0, z))
+                    .as_object()
+            })
+            .collect();
+        let flat = Group::new(spheres);
+        let tree = flat.clone().divide(4);
+        for (o, d) in [
+            (p!(-5, 0, 6), v!(1, 0, 0)),
+            (p!(9, 10, 9), v!(0, -1, 0)),
+            (p!(-5, 0, -5), v!(1, 0, 1)),
+            (p!(1.5, 0, -5), v!(0, 0, 1)),
+        ] {
+            let r = Ray::new(o, d.normalize());
+            let ts = |g: &Group| -> Vec<f64> {
+                g.local_intersect_with(r).iter().map(|x| x.t()).collect()
+            };
+            assert_eq!(ts(&flat), ts(&tree));
+        }
+    }
+}
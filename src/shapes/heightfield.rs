@@ -0,0 +1,434 @@
+use crate::bounds::Bounds;
+use crate::canvas::Canvas;
+use crate::light::Material;
+use crate::matrix::Matrix;
+use crate::ray::{Intersection, Ray};
+use crate::shapes::{derived_transforms, Object, Shape};
+use crate::transform::{self, Tr};
+use crate::tuple::{Point, Vector};
+use crate::{get_uid, v, EPSILON};
+use std::fmt::Display;
+use std::sync::Arc;
+
+/// Represents some problem building a heightfield from a grid of heights.
+#[derive(Debug, PartialEq)]
+pub enum ErrHeightfield {
+    /// When the grid has fewer than two rows or columns, so there is not a single cell.
+    TooSmall,
+    /// When some row has a different number of heights from the first. Holds the row's index.
+    Ragged(usize),
+}
+
+impl Display for ErrHeightfield {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use ErrHeightfield::*;
+        match self {
+            TooSmall => write!(f, "Heightfield needs at least two rows and two columns"),
+            Ragged(row) => write!(f, "Heightfield rows have different lengths; row={row}"),
+        }
+    }
+}
+
+/// Terrain given by a grid of heights. The grid is stretched over the unit square from the
+/// origin to (1, 0, 1), with columns running along x and rows along z, and each height is the y
+/// of the surface at that grid point. Every cell is split into two triangles, and rays walk
+/// through the cells under them one at a time, so only the triangles near a ray are ever tested.
+#[derive(Debug, Clone)]
+pub struct Heightfield {
+    id: usize,
+    name: Option<String>,
+    cols: usize,
+    rows: usize,
+    /// The heights, row by row.
+    heights: Vec<f64>,
+    /// The normal at each grid point, for smooth shading across the triangles.
+    normals: Vec<Vector>,
+    bounds: Bounds,
+
+    transform: Tr,
+    inv_transform: Tr,
+    /// A matrix used in computing normals.
+    norm_transform: Matrix<3, 3>,
+
+    material: Material,
+}
+
+impl Default for Heightfield {
+    /// A single flat cell.
+    fn default() -> Self {
+        Self::new(vec![vec![0.0; 2]; 2]).unwrap()
+    }
+}
+
+impl PartialEq for Heightfield {
+    fn eq(&self, other: &Self) -> bool {
+        self.cols == other.cols
+            && self.rows == other.rows
+            && self.heights == other.heights
+            && self.transform == other.transform
+            && self.inv_transform == other.inv_transform
+            && self.norm_transform == other.norm_transform
+            && self.material == other.material
+    }
+}
+
+impl Shape for Heightfield {
+    fn transform(&self) -> Tr {
+        self.transform
+    }
+
+    fn inv_transform(&self) -> Tr {
+        self.inv_transform
+    }
+
+    fn norm_transform(&self) -> Matrix<3, 3> {
+        self.norm_transform
+    }
+
+    fn set_transform(&mut self, t: Tr) {
+        self.try_set_transform(t)
+            .expect("shape transformation should be invertible");
+    }
+
+    fn material(&self) -> Material {
+        self.material.clone()
+    }
+
+    fn set_material(&mut self, m: Material) {
+        self.material = m;
+    }
+
+    /// Walks the ray through the grid cells it passes over, from where it enters the bounds to
+    /// where it leaves them, testing only the triangles in those cells.
+    fn local_intersect_with(&self, r: Ray) -> Vec<Intersection> {
+        let Some((start, end)) = self.bounds.clip(r) else {
+            return vec![];
+        };
+        let (o, d) = (r.origin(), r.direction());
+        let (sx, sz) = self.cell_size();
+        let entry = r.position_at(start);
+        let last_col = self.cols as isize - 2;
+        let last_row = self.rows as isize - 2;
+        let mut col = ((entry.x() / sx).floor() as isize).clamp(0, last_col);
+        let mut row = ((entry.z() / sz).floor() as isize).clamp(0, last_row);
+
+        // The distance along the ray to the next cell boundary on each axis, and between
+        // boundaries, as in Amanatides and Woo's grid traversal.
+        let axis = |cell: isize, size: f64, o: f64, d: f64| {
+            if d > 0.0 {
+                (1, ((cell + 1) as f64 * size - o) / d, size / d)
+            } else if d < 0.0 {
+                (-1, (cell as f64 * size - o) / d, -size / d)
+            } else {
+                (0, f64::INFINITY, f64::INFINITY)
+            }
+        };
+        let (step_col, mut next_col, delta_col) = axis(col, sx, o.x(), d.x());
+        let (step_row, mut next_row, delta_row) = axis(row, sz, o.z(), d.z());
+
+        let mut ts = vec![];
+        loop {
+            for [a, b, c] in self.cell_triangles(col as usize, row as usize) {
+                if let Some(t) = hit_triangle(r, a, b, c) {
+                    ts.push(t);
+                }
+            }
+            // Rays straight up or down never cross into another cell, so the next boundary is
+            // infinitely far away.
+            if next_col.min(next_row) > end {
+                break;
+            }
+            if next_col < next_row {
+                col += step_col;
+                next_col += delta_col;
+            } else {
+                row += step_row;
+                next_row += delta_row;
+            }
+            if !(0..=last_col).contains(&col) || !(0..=last_row).contains(&row) {
+                break;
+            }
+        }
+
+        // Rays through an edge or corner hit every triangle which shares it.
+        ts.sort_by(|a, b| a.total_cmp(b));
+        ts.dedup_by(|a, b| (*a - *b).abs() < EPSILON);
+        ts.into_iter()
+            .map(|t| Intersection::new(t, Arc::new(self.clone())))
+            .collect()
+    }
+
+    /// Blends the normals at the corners of whichever triangle the point is on.
+    fn local_normal_at(&self, p: Point) -> Vector {
+        let (sx, sz) = self.cell_size();
+        let (fx, fz) = (p.x() / sx, p.z() / sz);
+        let col = (fx.floor() as isize).clamp(0, self.cols as isize - 2) as usize;
+        let row = (fz.floor() as isize).clamp(0, self.rows as isize - 2) as usize;
+        let (u, w) = (fx - col as f64, fz - row as f64);
+        let n = |c: usize, r: usize| self.normals[(row + r) * self.cols + col + c];
+        let blended = if u >= w {
+            n(0, 0) * (1.0 - u) + n(1, 0) * (u - w) + n(1, 1) * w
+        } else {
+            n(0, 0) * (1.0 - w) + n(1, 1) * u + n(0, 1) * (w - u)
+        };
+        blended.normalize()
+    }
+
+    fn local_bounds(&self) -> Bounds {
+        self.bounds
+    }
+
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn eqx(&self, other: &dyn std::any::Any) -> bool {
+        other.downcast_ref::<Self>().is_some_and(|a| a == self)
+    }
+}
+
+impl Heightfield {
+    /// Creates a heightfield from rows of heights, the first row lying along z = 0 and the first
+    /// height of each row along x = 0.
+    pub fn new(heights: Vec<Vec<f64>>) -> Result<Self, ErrHeightfield> {
+        let rows = heights.len();
+        let cols = heights.first().map_or(0, |r| r.len());
+        if rows < 2 || cols < 2 {
+            return Err(ErrHeightfield::TooSmall);
+        }
+        if let Some(i) = heights.iter().position(|r| r.len() != cols) {
+            return Err(ErrHeightfield::Ragged(i));
+        }
+        let heights: Vec<f64> = heights.into_iter().flatten().collect();
+        let (lo, hi) = heights
+            .iter()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &h| {
+                (lo.min(h), hi.max(h))
+            });
+        let mut res = Self {
+            id: get_uid(),
+            name: None,
+            cols,
+            rows,
+            heights,
+            normals: vec![],
+            bounds: Bounds::new(Point::new(0.0, lo, 0.0), Point::new(1.0, hi, 1.0)),
+            transform: Tr::default(),
+            inv_transform: Tr::default(),
+            norm_transform: Matrix::<3, 3>::ident(),
+            material: Material::default(),
+        };
+        res.normals = (0..rows)
+            .flat_map(|r| (0..cols).map(move |c| (c, r)))
+            .map(|(c, r)| res.grid_normal(c, r))
+            .collect();
+        Ok(res)
+    }
+
+    /// Creates a heightfield from a grayscale image, with a height of one for white and zero for
+    /// black. Each pixel becomes a grid point, with the top row of the image along z = 0.
+    pub fn from_image(image: &Canvas) -> Result<Self, ErrHeightfield> {
+        let heights = (0..image.height())
+            .map(|y| {
+                (0..image.width())
+                    .map(|x| image.pixel_at(x, y).luminance())
+                    .collect()
+            })
+            .collect();
+        Self::new(heights)
+    }
+
+    /// The number of grid points along x.
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    /// The number of grid points along z.
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// The height at some grid point.
+    pub fn height_at(&self, col: usize, row: usize) -> f64 {
+        self.heights[row * self.cols + col]
+    }
+
+    fn cell_size(&self) -> (f64, f64) {
+        (1.0 / (self.cols - 1) as f64, 1.0 / (self.rows - 1) as f64)
+    }
+
+    fn grid_point(&self, col: usize, row: usize) -> Point {
+        let (sx, sz) = self.cell_size();
+        Point::new(col as f64 * sx, self.height_at(col, row), row as f64 * sz)
+    }
+
+    /// The two triangles of a cell, split along the diagonal from its lowest corner.
+    fn cell_triangles(&self, col: usize, row: usize) -> [[Point; 3]; 2] {
+        let a = self.grid_point(col, row);
+        let b = self.grid_point(col + 1, row);
+        let c = self.grid_point(col, row + 1);
+        let d = self.grid_point(col + 1, row + 1);
+        [[a, b, d], [a, d, c]]
+    }
+
+    /// Estimates the normal at a grid point from the slope to its neighbours.
+    fn grid_normal(&self, col: usize, row: usize) -> Vector {
+        let (sx, sz) = self.cell_size();
+        let (c0, c1) = (col.saturating_sub(1), (col + 1).min(self.cols - 1));
+        let (r0, r1) = (row.saturating_sub(1), (row + 1).min(self.rows - 1));
+        let dx = (self.height_at(c1, row) - self.height_at(c0, row)) / ((c1 - c0) as f64 * sx);
+        let dz = (self.height_at(col, r1) - self.height_at(col, r0)) / ((r1 - r0) as f64 * sz);
+        v!(-dx, 1, -dz).normalize()
+    }
+
+    pub fn with_transform(mut self, t: Tr) -> Self {
+        self.set_transform(t);
+        self
+    }
+
+    /// Like `with_transform`, but fails instead of panicking if the transformation is singular.
+    pub fn try_with_transform(mut self, t: Tr) -> Result<Self, transform::Error> {
+        self.try_set_transform(t)?;
+        Ok(self)
+    }
+
+    fn try_set_transform(&mut self, t: Tr) -> Result<(), transform::Error> {
+        (self.inv_transform, self.norm_transform) = derived_transforms(t)?;
+        self.transform = t;
+        Ok(())
+    }
+
+    pub fn with_material(mut self, m: Material) -> Self {
+        self.material = m;
+        self
+    }
+
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn as_object(self) -> Object {
+        Arc::new(self)
+    }
+}
+
+/// Finds where a ray crosses a triangle, in the same way as [`Triangle`](super::Triangle).
+fn hit_triangle(r: Ray, p1: Point, p2: Point, p3: Point) -> Option<f64> {
+    let (e1, e2) = (p2 - p1, p3 - p1);
+    let dir_cross_e2 = r.direction().cross(e2);
+    let det = e1.dot(dir_cross_e2);
+    if det.abs() < EPSILON {
+        return None;
+    }
+    let f = 1.0 / det;
+    let p1_to_origin = r.origin() - p1;
+    let u = f * p1_to_origin.dot(dir_cross_e2);
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let origin_cross_e1 = p1_to_origin.cross(e1);
+    let v = f * r.direction().dot(origin_cross_e1);
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    Some(f * e2.dot(origin_cross_e1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ErrHeightfield, Heightfield};
+    use crate::canvas::Canvas;
+    use crate::color::Color;
+    use crate::ray::Ray;
+    use crate::rng::Rng;
+    use crate::shapes::{Shape, Triangle};
+    use crate::{assert_f64_eq, p, v, EPSILON};
+
+    #[test]
+    fn building_heightfields() {
+        assert_eq!(
+            Heightfield::new(vec![vec![0.0, 1.0]]),
+            Err(ErrHeightfield::TooSmall)
+        );
+        assert_eq!(
+            Heightfield::new(vec![vec![0.0, 1.0], vec![0.0]]),
+            Err(ErrHeightfield::Ragged(1))
+        );
+        let mut image = Canvas::new(3, 2);
+        image.write_to(2, 1, Color::white());
+        let h = Heightfield::from_image(&image).unwrap();
+        assert_eq!((h.cols(), h.rows()), (3, 2));
+        assert_f64_eq!(h.height_at(2, 1), 1.0, EPSILON);
+        assert_eq!(h.height_at(0, 0), 0.0);
+        assert_eq!(h.local_bounds().max(), p!(1, 1, 1));
+    }
+
+    #[test]
+    fn ray_onto_a_flat_field() {
+        let h = Heightfield::new(vec![vec![0.5; 4]; 4]).unwrap();
+        let xs = h.local_intersect_with(Ray::new(p!(0.3, 2, 0.7), v!(0, -1, 0)));
+        assert_eq!(xs.len(), 1);
+        assert_f64_eq!(xs[0].t(), 1.5, EPSILON);
+        // Rays over grid lines and corners only count once.
+        let xs = h.local_intersect_with(Ray::new(p!(1.0 / 3.0, 2, 1.0 / 3.0), v!(0, -1, 0)));
+        assert_eq!(xs.len(), 1);
+        assert!(h
+            .local_intersect_with(Ray::new(p!(1.5, 2, 0.5), v!(0, -1, 0)))
+            .is_empty());
+    }
+
+    #[test]
+    fn walking_the_grid_matches_every_triangle() {
+        let mut rng = Rng::new(7);
+        let heights: Vec<Vec<f64>> = (0..9)
+            .map(|_| (0..7).map(|_| rng.next_f64()).collect())
+            .collect();
+        let h = Heightfield::new(heights).unwrap();
+        let triangles: Vec<Triangle> = (0..h.rows() - 1)
+            .flat_map(|r| (0..h.cols() - 1).map(move |c| (c, r)))
+            .flat_map(|(c, r)| h.cell_triangles(c, r))
+            .map(|[a, b, c]| Triangle::new(a, b, c))
+            .collect();
+        for _ in 0..200 {
+            let origin = p!(
+                rng.range(-0.5, 1.5),
+                rng.range(1.5, 2.0),
+                rng.range(-0.5, 1.5)
+            );
+            let target = p!(rng.next_f64(), rng.range(-0.5, 0.5), rng.next_f64());
+            let r = Ray::new(origin, (target - origin).normalize());
+            let got: Vec<f64> = h.local_intersect_with(r).iter().map(|x| x.t()).collect();
+            let mut want: Vec<f64> = triangles
+                .iter()
+                .flat_map(|t| t.local_intersect_with(r))
+                .map(|x| x.t())
+                .collect();
+            want.sort_by(|a, b| a.total_cmp(b));
+            want.dedup_by(|a, b| (*a - *b).abs() < EPSILON);
+            assert_eq!(got.len(), want.len(), "{r:?}");
+            for (g, w) in got.iter().zip(&want) {
+                assert_f64_eq!(g, w, EPSILON);
+            }
+        }
+    }
+
+    #[test]
+    fn normals_on_a_slope() {
+        // A ramp rising along x by one unit over the field.
+        let h = Heightfield::new(vec![vec![0.0, 0.5, 1.0]; 3]).unwrap();
+        let n = h.local_normal_at(p!(0.3, 0.3, 0.6));
+        let want = v!(-1, 1, 0).normalize();
+        assert_f64_eq!(n.x(), want.x(), EPSILON);
+        assert_f64_eq!(n.y(), want.y(), EPSILON);
+        assert_f64_eq!(n.z(), want.z(), EPSILON);
+    }
+}
@@ -0,0 +1,242 @@
+use crate::bounds::Bounds;
+use crate::get_uid;
+use crate::light::Material;
+use crate::matrix::Matrix;
+use crate::ray::{Intersection, Ray};
+use crate::shapes::group::InGroup;
+use crate::shapes::{derived_transforms, Object, Shape};
+use crate::transform::{self, Tr};
+use crate::tuple::{Point, Vector};
+use std::sync::Arc;
+
+/// Another placement of some shared shape, with a transformation and optionally a material of
+/// its own. The shape itself is never copied, so a forest of identical trees only keeps one tree
+/// in memory, however many instances of it there are.
+#[derive(Debug, Clone)]
+pub struct Instance {
+    id: usize,
+    name: Option<String>,
+    object: Object,
+    /// Replaces the materials of everything in the shape, if set.
+    material: Option<Arc<Material>>,
+
+    transform: Tr,
+    inv_transform: Tr,
+    /// A matrix used in computing normals.
+    norm_transform: Matrix<3, 3>,
+}
+
+impl PartialEq for Instance {
+    fn eq(&self, other: &Self) -> bool {
+        *self.object == *other.object
+            && self.material == other.material
+            && self.transform == other.transform
+            && self.inv_transform == other.inv_transform
+            && self.norm_transform == other.norm_transform
+    }
+}
+
+impl Shape for Instance {
+    fn transform(&self) -> Tr {
+        self.transform
+    }
+
+    fn inv_transform(&self) -> Tr {
+        self.inv_transform
+    }
+
+    fn norm_transform(&self) -> Matrix<3, 3> {
+        self.norm_transform
+    }
+
+    fn set_transform(&mut self, t: Tr) {
+        self.try_set_transform(t)
+            .expect("shape transformation should be invertible");
+    }
+
+    /// The instance's own material if it has one, or else the shared shape's.
+    fn material(&self) -> Material {
+        match &self.material {
+            Some(m) => (**m).clone(),
+            None => self.object.material(),
+        }
+    }
+
+    fn set_material(&mut self, m: Material) {
+        self.material = Some(Arc::new(m));
+    }
+
+    /// Intersects the shared shape, with the hits remembering this instance's transformation and
+    /// material, in the same way as shapes in a [`Group`](super::Group).
+    fn local_intersect_with(&self, r: Ray) -> Vec<Intersection> {
+        if !self.object.bounds().intersects(r) {
+            return vec![];
+        }
+        self.object
+            .intersect_with(r)
+            .into_iter()
+            .map(|x| InGroup::wrap_with_material(x, self, self.material.clone()))
+            .collect()
+    }
+
+    fn local_normal_at(&self, _: Point) -> Vector {
+        unreachable!("rays never hit an instance, only the shape it places")
+    }
+
+    fn local_bounds(&self) -> Bounds {
+        self.object.bounds()
+    }
+
+    fn local_uv_at(&self, p: Point) -> (f64, f64) {
+        self.object.uv_at(p)
+    }
+
+    fn local_tangents_at(&self, p: Point) -> Option<(Vector, Vector)> {
+        self.object.tangents_at(p)
+    }
+
+    fn is_solid(&self) -> bool {
+        self.object.is_solid()
+    }
+
+    fn local_contains(&self, p: Point) -> bool {
+        self.object.contains(p)
+    }
+
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    /// The instance's own name if it has one, or else the shared shape's.
+    fn name(&self) -> Option<&str> {
+        self.name.as_deref().or_else(|| self.object.name())
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn eqx(&self, other: &dyn std::any::Any) -> bool {
+        other.downcast_ref::<Self>().is_some_and(|a| a == self)
+    }
+}
+
+impl Instance {
+    /// Places a shared shape, without any transformation or material of its own yet.
+    pub fn new(object: Object) -> Self {
+        Self {
+            id: get_uid(),
+            name: None,
+            object,
+            material: None,
+            transform: Tr::default(),
+            inv_transform: Tr::default(),
+            norm_transform: Matrix::<3, 3>::ident(),
+        }
+    }
+
+    /// Places a shape under its own ID, for moving an object without it turning into a different
+    /// object. The shape must not also stay in the same world as the instance.
+    pub(crate) fn in_place_of(object: Object) -> Self {
+        Self {
+            id: object.id(),
+            ..Self::new(object)
+        }
+    }
+
+    /// The shared shape being placed.
+    pub fn object(&self) -> &Object {
+        &self.object
+    }
+
+    pub fn with_transform(mut self, t: Tr) -> Self {
+        self.set_transform(t);
+        self
+    }
+
+    /// Like `with_transform`, but fails instead of panicking if the transformation is singular.
+    pub fn try_with_transform(mut self, t: Tr) -> Result<Self, transform::Error> {
+        self.try_set_transform(t)?;
+        Ok(self)
+    }
+
+    fn try_set_transform(&mut self, t: Tr) -> Result<(), transform::Error> {
+        (self.inv_transform, self.norm_transform) = derived_transforms(t)?;
+        self.transform = t;
+        Ok(())
+    }
+
+    /// Shades everything in this instance with some material, instead of the materials of the
+    /// shared shape.
+    pub fn with_material(mut self, m: Material) -> Self {
+        self.set_material(m);
+        self
+    }
+
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn as_object(self) -> Object {
+        Arc::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Instance;
+    use crate::color::Color;
+    use crate::light::Material;
+    use crate::ray::Ray;
+    use crate::shapes::{Group, Shape, Sphere};
+    use crate::transform::Tr;
+    use crate::{assert_f64_eq, p, v, EPSILON};
+    use std::f64::consts::FRAC_1_SQRT_2;
+    use std::sync::Arc;
+
+    #[test]
+    fn instances_share_their_shape() {
+        let red = Material::default().with_color(Color::new(1.0, 0.0, 0.0));
+        let tree = Group::new(vec![Sphere::default()
+            .with_material(red.clone())
+            .as_object()])
+        .as_object();
+        let forest: Vec<Instance> = (0..500)
+            .map(|i| {
+                Instance::new(tree.clone()).with_transform(Tr::new().translate(i as f64, 0.0, 0.0))
+            })
+            .collect();
+        assert_eq!(Arc::strong_count(&tree), 501);
+        assert!(Arc::ptr_eq(forest[42].object(), &tree));
+
+        let r = Ray::new(p!(42, 0, -5), v!(0, 0, 1));
+        let xs = forest[42].intersect_with(r);
+        assert_eq!(xs.len(), 2);
+        assert_f64_eq!(xs[0].t(), 4.0, EPSILON);
+        assert_eq!(xs[0].material(), red);
+        assert!(forest[40].intersect_with(r).is_empty());
+    }
+
+    #[test]
+    fn instance_transforms_and_materials() {
+        let sphere = Sphere::default().as_object();
+        let blue = Material::default().with_color(Color::new(0.0, 0.0, 1.0));
+        let i = Instance::new(sphere.clone())
+            .with_transform(Tr::new().scale(2.0, 2.0, 2.0).translate(0.0, 0.0, 10.0))
+            .with_material(blue.clone());
+        let r = Ray::new(p!(0, 0, 0), v!(0, 0, 1));
+        let xs = i.intersect_with(r);
+        assert_eq!(xs.len(), 2);
+        assert_f64_eq!(xs[0].t(), 8.0, EPSILON);
+        assert_eq!(xs[0].material(), blue);
+        assert_eq!(sphere.material(), Material::default());
+
+        let n = xs[0]
+            .object()
+            .normal_at(p!(FRAC_1_SQRT_2 * 2.0, 0, 10.0 - FRAC_1_SQRT_2 * 2.0));
+        assert_f64_eq!(n.x(), FRAC_1_SQRT_2, EPSILON);
+        assert_f64_eq!(n.z(), -FRAC_1_SQRT_2, EPSILON);
+        assert_eq!(i.bounds().min(), p!(-2, -2, 8));
+    }
+}
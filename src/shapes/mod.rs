@@ -1,12 +1,43 @@
+mod blob;
+mod csg;
+mod cylinder;
+mod deform;
+mod disc;
+mod group;
+mod heightfield;
+mod instance;
 mod plane;
+mod prism;
+mod quad;
+mod rounded_box;
+mod sdf;
 mod sphere;
+mod torus;
+mod triangle;
+pub use blob::{Blob, BlobComponent};
+pub use csg::{Csg, CsgOp};
+pub use cylinder::Cylinder;
+pub use deform::{Deformation, Deformed};
+pub use disc::Disc;
+pub use group::Group;
+pub(crate) use group::{InGroup, Parents};
+pub use heightfield::{ErrHeightfield, Heightfield};
+pub use instance::Instance;
 pub use plane::Plane;
+pub use prism::{ErrPrism, Prism};
+pub use quad::Quad;
+pub use rounded_box::RoundedBox;
+pub use sdf::{DistanceFn, Sdf};
 pub use sphere::Sphere;
+pub use torus::Torus;
+pub(crate) use triangle::uv_tangents;
+pub use triangle::Triangle;
 
+use crate::bounds::Bounds;
 use crate::light::Material;
 use crate::matrix::Matrix;
 use crate::ray::{Intersection, Ray};
-use crate::transform::Tr;
+use crate::transform::{self, Tr};
 use crate::tuple::{Point, Tuple, Vector};
 use std::any::Any;
 use std::fmt::Debug;
@@ -36,18 +67,109 @@ pub trait Shape: Send + Sync + Debug {
     /// Finds the normal vector at some point on the surface of this shape. Note that this method
     /// should not be implemented manually. Instead, implement only `local_normal_at`.
     fn normal_at(&self, p: Point) -> Vector {
-        let local_point = self.inv_transform().matrix() * p;
+        let local_point = self.world_to_object(p);
         let local_normal = self.local_normal_at(local_point);
-        let Tuple(x, y, z, _) = local_normal.inner();
+        self.normal_to_world(local_normal)
+    }
+    /// Converts a point from world space to this shape's object space, going through every group
+    /// the shape is in.
+    fn world_to_object(&self, p: Point) -> Point {
+        self.inv_transform().matrix() * p
+    }
+    /// Converts a normal from this shape's object space to world space, going through every group
+    /// the shape is in.
+    fn normal_to_world(&self, n: Vector) -> Vector {
+        let Tuple(x, y, z, _) = n.inner();
         let m = self.norm_transform() * Matrix::new([[x], [y], [z]]);
-        let world_normal = Vector::new(m.get(0, 0), m.get(1, 0), m.get(2, 0));
-        world_normal.normalize()
+        Vector::new(m.get(0, 0), m.get(1, 0), m.get(2, 0)).normalize()
+    }
+    /// Converts a direction along the surface, like a tangent, from this shape's object space to
+    /// world space, going through every group the shape is in. Unlike normals, such directions
+    /// stretch along with the shape. The result is not normalized.
+    fn vector_to_world(&self, v: Vector) -> Vector {
+        self.transform().matrix() * v
     }
     /// Finds the normal vector at some point, where the point is given in object space.
     fn local_normal_at(&self, p: Point) -> Vector;
 
+    /// Finds the tangent and bitangent at some point on the surface, given in world space, which
+    /// point the way the surface's U and V coordinates grow, for tangent space normal maps.
+    /// Shapes without tangents of their own give None. Note that this method should not be
+    /// implemented manually. Instead, implement only `local_tangents_at`.
+    fn tangents_at(&self, p: Point) -> Option<(Vector, Vector)> {
+        let (t, b) = self.local_tangents_at(self.world_to_object(p))?;
+        Some((self.vector_to_world(t), self.vector_to_world(b)))
+    }
+    /// Finds the tangent and bitangent at some point on the surface, in object space.
+    fn local_tangents_at(&self, _p: Point) -> Option<(Vector, Vector)> {
+        None
+    }
+
+    /// Samples the normal at four points around some point on the surface, given in world space,
+    /// each `radius` away along the surface. Gives each offset along with the normal found there,
+    /// for patterns which care about the shape of the surface around a point, like how sharp an
+    /// edge is. The samples are taken on the plane touching the surface, so on curved surfaces
+    /// they lie slightly off it, which makes little difference for small radii.
+    fn normals_around(&self, p: Point, radius: f64) -> [(Vector, Vector); 4] {
+        let (u, v) = tangent_frame(self.normal_at(p));
+        [u, -u, v, -v].map(|d| {
+            let d = d * radius;
+            (d, self.normal_at(p + d))
+        })
+    }
+
+    /// The box this shape fits in, in its own object space. Shapes which don't say are assumed to
+    /// go on forever, so they are never skipped.
+    fn local_bounds(&self) -> Bounds {
+        Bounds::infinite()
+    }
+    /// The box this shape fits in, in the space of whatever contains it, i.e. its group or the
+    /// world.
+    fn bounds(&self) -> Bounds {
+        self.local_bounds().transform(self.transform())
+    }
+
+    /// Finds the UV coordinates of some point on the surface of this shape, given in world space,
+    /// for mapping textures onto it. Note that this method should not be implemented manually.
+    /// Instead, implement only `local_uv_at`.
+    fn uv_at(&self, p: Point) -> (f64, f64) {
+        self.local_uv_at(self.world_to_object(p))
+    }
+    /// Finds the UV coordinates of some point on the surface of this shape, in object space. Both
+    /// coordinates are within [0, 1]. Unless a shape has a mapping of its own, the point is
+    /// projected straight down onto the xz plane, and the texture repeats every unit.
+    fn local_uv_at(&self, p: Point) -> (f64, f64) {
+        (p.x().rem_euclid(1.0), p.z().rem_euclid(1.0))
+    }
+
+    /// Checks if some point in world space is inside this shape. Note that this method should not
+    /// be implemented manually. Instead, implement only `is_solid` and `local_contains`.
+    fn contains(&self, p: Point) -> bool {
+        self.is_solid() && self.local_contains(self.world_to_object(p))
+    }
+    /// Checks if some point in object space is inside this shape.
+    fn local_contains(&self, _: Point) -> bool {
+        false
+    }
+    /// Whether this shape encloses some space, and so can tell what is inside it. Surfaces like
+    /// planes and triangles have no inside of their own.
+    fn is_solid(&self) -> bool {
+        false
+    }
+
+    /// Whether this shape is part of a closed mesh, whose back faces can never be seen from
+    /// outside. Shadow rays skip such back faces.
+    fn is_closed_mesh(&self) -> bool {
+        false
+    }
+
     /// Every shape must have a unique ID.
     fn id(&self) -> usize;
+    /// The name this shape was given, if any. Unlike IDs, names are chosen by whoever builds the
+    /// scene, and need not be unique.
+    fn name(&self) -> Option<&str> {
+        None
+    }
 
     /// Converts to the any trait object.
     fn as_any(&self) -> &dyn Any;
@@ -55,6 +177,32 @@ pub trait Shape: Send + Sync + Debug {
     fn eqx(&self, other: &dyn Any) -> bool;
 }
 
+/// Picks two unit vectors at right angles to each other and to some normal, for sampling around a
+/// point on a surface which has no tangents of its own.
+pub(crate) fn tangent_frame(n: Vector) -> (Vector, Vector) {
+    let helper = if n.x().abs() < 0.9 {
+        Vector::new(1.0, 0.0, 0.0)
+    } else {
+        Vector::new(0.0, 1.0, 0.0)
+    };
+    let u = n.cross(helper).normalize();
+    (u, n.cross(u))
+}
+
+/// Computes the inverse and normal transformations which shapes cache alongside their
+/// transformation, failing if the transformation is singular.
+pub(crate) fn derived_transforms(t: Tr) -> Result<(Tr, Matrix<3, 3>), transform::Error> {
+    let inv = t.try_inverse()?;
+    // If the full affine transformation is invertible, then so is its linear part.
+    let norm = t
+        .matrix()
+        .submatrix(3, 3)
+        .inverse()
+        .map_err(|_| transform::Error::Uninvertible)?
+        .transpose();
+    Ok((inv, norm))
+}
+
 impl PartialEq for dyn Shape {
     fn eq(&self, other: &Self) -> bool {
         self.eqx(other.as_any())
@@ -63,14 +211,14 @@ impl PartialEq for dyn Shape {
 
 #[cfg(test)]
 mod tests {
-    use super::Shape;
+    use super::{derived_transforms, Shape};
     use crate::get_uid;
     use crate::light::Material;
     use crate::ray::{Intersection, Ray};
     use crate::transform::Tr;
     use crate::tuple::{Point, Vector};
     use std::any::Any;
-    use std::f64::consts::PI;
+    use std::f64::consts::{FRAC_1_SQRT_2, PI};
     use std::sync::Mutex;
 
     /// Just a struct used to test the Shape trait.
@@ -115,12 +263,7 @@ mod tests {
         }
 
         fn norm_transform(&self) -> crate::matrix::Matrix<3, 3> {
-            self.transform
-                .matrix()
-                .submatrix(3, 3)
-                .inverse()
-                .unwrap()
-                .transpose()
+            derived_transforms(self.transform).unwrap().1
         }
 
         fn set_transform(&mut self, t: Tr) {
@@ -210,8 +353,8 @@ mod tests {
     #[test]
     fn computing_normal_on_translated_shape() {
         let s = TestShape::new().with_transform(Tr::default().translate(0.0, 1.0, 0.0));
-        let got = s.normal_at(Point::new(0.0, 1.70711, -0.70711));
-        let want = Vector::new(0.0, 0.70711, -0.70711);
+        let got = s.normal_at(Point::new(0.0, 1.0 + FRAC_1_SQRT_2, -FRAC_1_SQRT_2));
+        let want = Vector::new(0.0, FRAC_1_SQRT_2, -FRAC_1_SQRT_2);
         assert_eq!(got, want);
     }
 
@@ -1,8 +1,9 @@
+use crate::bounds::Bounds;
 use crate::light::Material;
 use crate::matrix::Matrix;
 use crate::ray::{Intersection, Ray};
-use crate::shapes::{Object, Shape};
-use crate::transform::Tr;
+use crate::shapes::{derived_transforms, Object, Shape};
+use crate::transform::{self, Tr};
 use crate::tuple::{Point, Vector};
 use crate::v;
 use crate::{get_uid, EPSILON};
@@ -11,6 +12,7 @@ use std::sync::Arc;
 #[derive(Debug, Clone)]
 pub struct Plane {
     id: usize,
+    name: Option<String>,
 
     transform: Tr,
     inv_transform: Tr,
@@ -24,6 +26,7 @@ impl Default for Plane {
     fn default() -> Self {
         Self {
             id: get_uid(),
+            name: None,
             transform: Tr::default(),
             inv_transform: Tr::default(),
             norm_transform: Matrix::<3, 3>::ident(),
@@ -55,9 +58,8 @@ impl Shape for Plane {
     }
 
     fn set_transform(&mut self, t: Tr) {
-        self.transform = t;
-        self.inv_transform = t.inverse();
-        self.norm_transform = t.matrix().submatrix(3, 3).inverse().unwrap().transpose();
+        self.try_set_transform(t)
+            .expect("shape transformation should be invertible");
     }
 
     fn material(&self) -> Material {
@@ -69,27 +71,37 @@ impl Shape for Plane {
     }
 
     fn local_intersect_with(&self, r: Ray) -> Vec<Intersection> {
-        if r.direction().y().abs() < EPSILON {
-            return vec![];
+        match xz_plane_hit(r) {
+            Some(t) => vec![Intersection::new(t, Arc::new(self.clone()))],
+            None => vec![],
         }
-        let t = -r.origin().y() / r.direction().y();
-        vec![Intersection::new(t, Arc::new(self.clone()))]
     }
 
     fn local_normal_at(&self, _: Point) -> Vector {
         v!(0.0, 1.0, 0.0)
     }
 
+    fn local_bounds(&self) -> Bounds {
+        Bounds::new(
+            Point::new(f64::NEG_INFINITY, 0.0, f64::NEG_INFINITY),
+            Point::new(f64::INFINITY, 0.0, f64::INFINITY),
+        )
+    }
+
     fn id(&self) -> usize {
         self.id
     }
 
+    fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
     fn as_any(&self) -> &dyn std::any::Any {
         self
     }
 
     fn eqx(&self, other: &dyn std::any::Any) -> bool {
-        other.downcast_ref::<Self>().map_or(false, |a| a == self)
+        other.downcast_ref::<Self>().is_some_and(|a| a == self)
     }
 }
 
@@ -99,16 +111,41 @@ impl Plane {
         self
     }
 
+    /// Like `with_transform`, but fails instead of panicking if the transformation is singular.
+    pub fn try_with_transform(mut self, t: Tr) -> Result<Self, transform::Error> {
+        self.try_set_transform(t)?;
+        Ok(self)
+    }
+
+    fn try_set_transform(&mut self, t: Tr) -> Result<(), transform::Error> {
+        (self.inv_transform, self.norm_transform) = derived_transforms(t)?;
+        self.transform = t;
+        Ok(())
+    }
+
     pub fn with_material(mut self, m: Material) -> Self {
         self.material = m;
         self
     }
 
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
     pub fn as_object(self) -> Object {
         Arc::new(self)
     }
 }
 
+/// Finds where a ray crosses the xz plane, if it does. Rays parallel to the plane never hit it.
+pub(crate) fn xz_plane_hit(r: Ray) -> Option<f64> {
+    if r.direction().y().abs() < EPSILON {
+        return None;
+    }
+    Some(-r.origin().y() / r.direction().y())
+}
+
 #[cfg(test)]
 mod tests {
     use super::Plane;
@@ -163,4 +200,20 @@ mod tests {
         assert_eq!(xs[0].t(), 1.0);
         assert_eq!(*xs[0].object(), *p);
     }
+
+    #[test]
+    fn planar_uv_mapping() {
+        let tests = vec![
+            (p!(0.25, 0, 0.5), (0.25, 0.5)),
+            (p!(0.25, 0, -0.25), (0.25, 0.75)),
+            (p!(0.25, 0.5, -0.25), (0.25, 0.75)),
+            (p!(1.25, 0, 0.5), (0.25, 0.5)),
+            (p!(0.25, 0, -1.75), (0.25, 0.25)),
+            (p!(1, 0, -1), (0.0, 0.0)),
+            (p!(0, 0, 0), (0.0, 0.0)),
+        ];
+        for (p, want) in tests {
+            assert_eq!(Plane::default().local_uv_at(p), want);
+        }
+    }
 }
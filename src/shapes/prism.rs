@@ -0,0 +1,394 @@
+use crate::bounds::Bounds;
+use crate::light::Material;
+use crate::matrix::Matrix;
+use crate::ray::{Intersection, Ray};
+use crate::shapes::{derived_transforms, Object, Shape};
+use crate::transform::{self, Tr};
+use crate::tuple::{Point, Vector};
+use crate::v;
+use crate::{get_uid, EPSILON};
+use std::fmt::Display;
+use std::sync::Arc;
+
+/// Represents some problem with the outline of a prism.
+#[derive(Debug, PartialEq)]
+pub enum ErrPrism {
+    /// When the outline has fewer than three corners. Holds the number it has.
+    TooFewCorners(usize),
+    /// When the outline encloses no area, e.g. because every corner lies on one line.
+    Degenerate,
+}
+
+impl Display for ErrPrism {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use ErrPrism::*;
+        match self {
+            TooFewCorners(n) => write!(f, "Prism outline needs at least 3 corners; got={n}"),
+            Degenerate => write!(f, "Prism outline encloses no area"),
+        }
+    }
+}
+
+/// A polygon in the xz plane, extruded along the y axis from 0 to 1. The polygon may be any
+/// simple shape, convex or not, like a star or the footprint of a building. By default the top
+/// and bottom are capped.
+#[derive(Debug, Clone)]
+pub struct Prism {
+    id: usize,
+    name: Option<String>,
+    /// The corners of the polygon, as x and z coordinates, in order around the outline.
+    corners: Vec<(f64, f64)>,
+    /// Whether the corners go counter-clockwise, drawn with x to the right and z upwards. This
+    /// decides which side of each edge is outside.
+    counter_clockwise: bool,
+    /// Whether the top and bottom are capped.
+    closed: bool,
+
+    transform: Tr,
+    inv_transform: Tr,
+    /// A matrix used in computing normals.
+    norm_transform: Matrix<3, 3>,
+
+    material: Material,
+}
+
+impl Default for Prism {
+    /// A square prism, from -1 to 1 in x and z.
+    fn default() -> Self {
+        Self::new(vec![(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)])
+            .expect("a square is a valid outline")
+    }
+}
+
+impl PartialEq for Prism {
+    fn eq(&self, other: &Self) -> bool {
+        self.corners == other.corners
+            && self.closed == other.closed
+            && self.transform == other.transform
+            && self.inv_transform == other.inv_transform
+            && self.norm_transform == other.norm_transform
+            && self.material == other.material
+    }
+}
+
+impl Shape for Prism {
+    fn transform(&self) -> Tr {
+        self.transform
+    }
+
+    fn inv_transform(&self) -> Tr {
+        self.inv_transform
+    }
+
+    fn norm_transform(&self) -> Matrix<3, 3> {
+        self.norm_transform
+    }
+
+    fn set_transform(&mut self, t: Tr) {
+        self.try_set_transform(t)
+            .expect("shape transformation should be invertible");
+    }
+
+    fn material(&self) -> Material {
+        self.material.clone()
+    }
+
+    fn set_material(&mut self, m: Material) {
+        self.material = m;
+    }
+
+    fn local_intersect_with(&self, r: Ray) -> Vec<Intersection> {
+        let (o, d) = (r.origin(), r.direction());
+        let mut ts = vec![];
+
+        // The walls. Each edge is intersected in the xz plane, then checked against the height.
+        for (a, b) in self.edges() {
+            let e = (b.0 - a.0, b.1 - a.1);
+            let denom = cross((d.x(), d.z()), e);
+            if denom.abs() < EPSILON {
+                continue; // the ray runs along the wall
+            }
+            let ao = (a.0 - o.x(), a.1 - o.z());
+            let t = cross(ao, e) / denom;
+            let s = cross(ao, (d.x(), d.z())) / denom;
+            let y = o.y() + t * d.y();
+            if (0.0..1.0).contains(&s) && 0.0 < y && y < 1.0 {
+                ts.push(t);
+            }
+        }
+
+        if self.closed && d.y().abs() > EPSILON {
+            for cap in [0.0, 1.0] {
+                let t = (cap - o.y()) / d.y();
+                if self.outline_contains(o.x() + t * d.x(), o.z() + t * d.z()) {
+                    ts.push(t);
+                }
+            }
+        }
+
+        ts.sort_by(|a, b| a.total_cmp(b));
+        ts.into_iter()
+            .map(|t| Intersection::new(t, Arc::new(self.clone())))
+            .collect()
+    }
+
+    /// Points on the caps face straight up or down. Anywhere else, the normal is that of the
+    /// closest wall.
+    fn local_normal_at(&self, p: Point) -> Vector {
+        if self.closed && p.y() >= 1.0 - EPSILON {
+            return v!(0, 1, 0);
+        }
+        if self.closed && p.y() <= EPSILON {
+            return v!(0, -1, 0);
+        }
+        let (a, b) = self
+            .edges()
+            .min_by(|(a1, b1), (a2, b2)| {
+                let d1 = distance_to_edge((p.x(), p.z()), *a1, *b1);
+                let d2 = distance_to_edge((p.x(), p.z()), *a2, *b2);
+                d1.total_cmp(&d2)
+            })
+            .expect("a prism has at least three walls");
+        let e = (b.0 - a.0, b.1 - a.1);
+        let n = if self.counter_clockwise {
+            v!(e.1, 0, -e.0)
+        } else {
+            v!(-e.1, 0, e.0)
+        };
+        n.normalize()
+    }
+
+    fn local_bounds(&self) -> Bounds {
+        let xs = self.corners.iter().map(|c| c.0);
+        let zs = self.corners.iter().map(|c| c.1);
+        Bounds::new(
+            Point::new(
+                xs.clone().fold(f64::INFINITY, f64::min),
+                0.0,
+                zs.clone().fold(f64::INFINITY, f64::min),
+            ),
+            Point::new(
+                xs.fold(f64::NEG_INFINITY, f64::max),
+                1.0,
+                zs.fold(f64::NEG_INFINITY, f64::max),
+            ),
+        )
+    }
+
+    fn is_solid(&self) -> bool {
+        self.closed
+    }
+
+    fn local_contains(&self, p: Point) -> bool {
+        0.0 < p.y() && p.y() < 1.0 && self.outline_contains(p.x(), p.z())
+    }
+
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn eqx(&self, other: &dyn std::any::Any) -> bool {
+        other.downcast_ref::<Self>().is_some_and(|a| a == self)
+    }
+}
+
+/// The z component of the cross product of two 2D vectors.
+fn cross(a: (f64, f64), b: (f64, f64)) -> f64 {
+    a.0 * b.1 - a.1 * b.0
+}
+
+/// How far a point is from the segment between two others, all in 2D.
+fn distance_to_edge(p: (f64, f64), a: (f64, f64), b: (f64, f64)) -> f64 {
+    let e = (b.0 - a.0, b.1 - a.1);
+    let ap = (p.0 - a.0, p.1 - a.1);
+    let s = ((ap.0 * e.0 + ap.1 * e.1) / (e.0 * e.0 + e.1 * e.1)).clamp(0.0, 1.0);
+    let (dx, dz) = (ap.0 - s * e.0, ap.1 - s * e.1);
+    (dx * dx + dz * dz).sqrt()
+}
+
+impl Prism {
+    /// Creates a prism from the corners of its outline, as x and z coordinates in order around
+    /// the outline. The outline is closed automatically, so the last corner should not repeat the
+    /// first.
+    pub fn new(corners: Vec<(f64, f64)>) -> Result<Self, ErrPrism> {
+        if corners.len() < 3 {
+            return Err(ErrPrism::TooFewCorners(corners.len()));
+        }
+        let n = corners.len();
+        let area: f64 = (0..n)
+            .map(|i| cross(corners[i], corners[(i + 1) % n]))
+            .sum();
+        if area.abs() < EPSILON {
+            return Err(ErrPrism::Degenerate);
+        }
+        Ok(Self {
+            id: get_uid(),
+            name: None,
+            corners,
+            counter_clockwise: area > 0.0,
+            closed: true,
+            transform: Tr::default(),
+            inv_transform: Tr::default(),
+            norm_transform: Matrix::<3, 3>::ident(),
+            material: Material::default(),
+        })
+    }
+
+    /// Creates a prism whose outline is a star with some number of points. The points reach out
+    /// to a radius of one, and the notches between them to the inner radius.
+    pub fn star(points: usize, inner: f64) -> Result<Self, ErrPrism> {
+        let corners = (0..points * 2)
+            .map(|i| {
+                let r = if i % 2 == 0 { 1.0 } else { inner };
+                let theta = std::f64::consts::PI * i as f64 / points as f64;
+                (r * theta.sin(), r * theta.cos())
+            })
+            .collect();
+        Self::new(corners)
+    }
+
+    pub fn corners(&self) -> &[(f64, f64)] {
+        &self.corners
+    }
+
+    pub fn closed(&self) -> bool {
+        self.closed
+    }
+
+    pub fn with_closed(mut self, closed: bool) -> Self {
+        self.closed = closed;
+        self
+    }
+
+    /// Whether a point in the xz plane is inside the outline, by counting how many edges a line
+    /// from it crosses.
+    fn outline_contains(&self, x: f64, z: f64) -> bool {
+        let mut inside = false;
+        for (a, b) in self.edges() {
+            if (a.1 > z) != (b.1 > z) && x < a.0 + (z - a.1) / (b.1 - a.1) * (b.0 - a.0) {
+                inside = !inside;
+            }
+        }
+        inside
+    }
+
+    /// Pairs of neighbouring corners, going around the outline.
+    fn edges(&self) -> impl Iterator<Item = ((f64, f64), (f64, f64))> + '_ {
+        let n = self.corners.len();
+        (0..n).map(move |i| (self.corners[i], self.corners[(i + 1) % n]))
+    }
+
+    pub fn with_transform(mut self, t: Tr) -> Self {
+        self.set_transform(t);
+        self
+    }
+
+    /// Like `with_transform`, but fails instead of panicking if the transformation is singular.
+    pub fn try_with_transform(mut self, t: Tr) -> Result<Self, transform::Error> {
+        self.try_set_transform(t)?;
+        Ok(self)
+    }
+
+    fn try_set_transform(&mut self, t: Tr) -> Result<(), transform::Error> {
+        (self.inv_transform, self.norm_transform) = derived_transforms(t)?;
+        self.transform = t;
+        Ok(())
+    }
+
+    pub fn with_material(mut self, m: Material) -> Self {
+        self.material = m;
+        self
+    }
+
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn as_object(self) -> Object {
+        Arc::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ErrPrism, Prism};
+    use crate::ray::Ray;
+    use crate::shapes::Shape;
+    use crate::{assert_f64_eq, p, v, EPSILON};
+    use std::f64::consts::PI;
+
+    fn ts(s: &Prism, r: Ray) -> Vec<f64> {
+        s.local_intersect_with(r).iter().map(|x| x.t()).collect()
+    }
+
+    #[test]
+    fn intersecting_walls_and_caps() {
+        let s = Prism::default();
+        assert_eq!(ts(&s, Ray::new(p!(0, 0.5, -5), v!(0, 0, 1))), [4.0, 6.0]);
+        assert_eq!(ts(&s, Ray::new(p!(0, 5, 0), v!(0, -1, 0))), [4.0, 5.0]);
+        assert!(ts(&s, Ray::new(p!(0, 1.5, -5), v!(0, 0, 1))).is_empty());
+        assert!(ts(&s, Ray::new(p!(2, 5, 0), v!(0, -1, 0))).is_empty());
+
+        // Going in through a wall and out through the top.
+        let xs = ts(&s, Ray::new(p!(-2, 0.25, 0), v!(1, 0.5, 0)));
+        assert_eq!(xs, [1.0, 1.5]);
+
+        let open = Prism::default().with_closed(false);
+        assert!(ts(&open, Ray::new(p!(0, 5, 0), v!(0, -1, 0))).is_empty());
+    }
+
+    #[test]
+    fn concave_outlines() {
+        let star = Prism::star(5, 0.4).unwrap();
+        assert_eq!(star.corners().len(), 10);
+        // Straight down through a notch between two points misses.
+        let notch = (0.7 * (PI / 5.0).sin(), 0.7 * (PI / 5.0).cos());
+        assert!(ts(&star, Ray::new(p!(notch.0, 5, notch.1), v!(0, -1, 0))).is_empty());
+        // Down through the tip of a point hits both caps.
+        assert_eq!(ts(&star, Ray::new(p!(0, 5, 0.9), v!(0, -1, 0))).len(), 2);
+        // Across the top point, the ray goes in and out once, but across the bottom two points it
+        // goes in and out of each.
+        assert_eq!(ts(&star, Ray::new(p!(-5, 0.5, 0.8), v!(1, 0, 0))).len(), 2);
+        assert_eq!(ts(&star, Ray::new(p!(-5, 0.5, -0.7), v!(1, 0, 0))).len(), 4);
+    }
+
+    #[test]
+    fn normals_point_out_either_way_round() {
+        let ccw = Prism::default();
+        let cw = Prism::new(vec![(-1.0, -1.0), (-1.0, 1.0), (1.0, 1.0), (1.0, -1.0)]).unwrap();
+        for s in [ccw, cw] {
+            assert_eq!(s.local_normal_at(p!(1, 0.5, 0.2)), v!(1, 0, 0));
+            assert_eq!(s.local_normal_at(p!(0.3, 0.5, -1)), v!(0, 0, -1));
+            assert_eq!(s.local_normal_at(p!(0.3, 1, 0.2)), v!(0, 1, 0));
+            assert_eq!(s.local_normal_at(p!(0.3, 0, 0.2)), v!(0, -1, 0));
+        }
+        let n = Prism::new(vec![(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)])
+            .unwrap()
+            .local_normal_at(p!(0.5, 0.5, 0.5));
+        assert_f64_eq!(n.x(), 0.5f64.sqrt(), EPSILON);
+        assert_f64_eq!(n.z(), 0.5f64.sqrt(), EPSILON);
+    }
+
+    #[test]
+    fn invalid_outlines() {
+        assert_eq!(
+            Prism::new(vec![(0.0, 0.0), (1.0, 0.0)]),
+            Err(ErrPrism::TooFewCorners(2))
+        );
+        assert_eq!(
+            Prism::new(vec![(0.0, 0.0), (1.0, 1.0), (2.0, 2.0)]),
+            Err(ErrPrism::Degenerate)
+        );
+        let b = Prism::star(4, 0.5).unwrap().bounds();
+        assert_eq!(b.max(), p!(1, 1, 1));
+    }
+}
@@ -0,0 +1,228 @@
+use crate::bounds::Bounds;
+use crate::get_uid;
+use crate::light::Material;
+use crate::matrix::Matrix;
+use crate::ray::{Intersection, Ray};
+use crate::shapes::plane::xz_plane_hit;
+use crate::shapes::{derived_transforms, Object, Shape};
+use crate::transform::{self, Tr};
+use crate::tuple::{Point, Vector};
+use crate::v;
+use std::sync::Arc;
+
+/// A flat rectangle in the xz plane, centred on the origin, with its width along x and its height
+/// along z. It lies flat like a floor; turn it about the x axis to stand it up as a wall.
+#[derive(Debug, Clone)]
+pub struct Quad {
+    id: usize,
+    name: Option<String>,
+    width: f64,
+    height: f64,
+
+    transform: Tr,
+    inv_transform: Tr,
+    /// A matrix used in computing normals.
+    norm_transform: Matrix<3, 3>,
+
+    material: Material,
+}
+
+impl Default for Quad {
+    /// A square spanning from -1 to 1 along both x and z.
+    fn default() -> Self {
+        Self {
+            id: get_uid(),
+            name: None,
+            width: 2.0,
+            height: 2.0,
+            transform: Tr::default(),
+            inv_transform: Tr::default(),
+            norm_transform: Matrix::<3, 3>::ident(),
+            material: Material::default(),
+        }
+    }
+}
+
+impl PartialEq for Quad {
+    fn eq(&self, other: &Self) -> bool {
+        self.width == other.width
+            && self.height == other.height
+            && self.transform == other.transform
+            && self.inv_transform == other.inv_transform
+            && self.norm_transform == other.norm_transform
+            && self.material == other.material
+    }
+}
+
+impl Shape for Quad {
+    fn transform(&self) -> Tr {
+        self.transform
+    }
+
+    fn inv_transform(&self) -> Tr {
+        self.inv_transform
+    }
+
+    fn norm_transform(&self) -> Matrix<3, 3> {
+        self.norm_transform
+    }
+
+    fn set_transform(&mut self, t: Tr) {
+        self.try_set_transform(t)
+            .expect("shape transformation should be invertible");
+    }
+
+    fn material(&self) -> Material {
+        self.material.clone()
+    }
+
+    fn set_material(&mut self, m: Material) {
+        self.material = m;
+    }
+
+    fn local_intersect_with(&self, r: Ray) -> Vec<Intersection> {
+        let Some(t) = xz_plane_hit(r) else {
+            return vec![];
+        };
+        let p = r.position_at(t);
+        if p.x().abs() > self.width / 2.0 || p.z().abs() > self.height / 2.0 {
+            return vec![];
+        }
+        vec![Intersection::new(t, Arc::new(self.clone()))]
+    }
+
+    fn local_normal_at(&self, _: Point) -> Vector {
+        v!(0.0, 1.0, 0.0)
+    }
+
+    fn local_bounds(&self) -> Bounds {
+        let (w, h) = (self.width / 2.0, self.height / 2.0);
+        Bounds::new(Point::new(-w, 0.0, -h), Point::new(w, 0.0, h))
+    }
+
+    /// Stretches the texture over the whole rectangle once, with u along x and v along z.
+    fn local_uv_at(&self, p: Point) -> (f64, f64) {
+        let u = (p.x() / self.width + 0.5).clamp(0.0, 1.0);
+        let v = (p.z() / self.height + 0.5).clamp(0.0, 1.0);
+        (u, v)
+    }
+
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn eqx(&self, other: &dyn std::any::Any) -> bool {
+        other.downcast_ref::<Self>().is_some_and(|a| a == self)
+    }
+}
+
+impl Quad {
+    pub fn new(width: f64, height: f64) -> Self {
+        Self::default().with_width(width).with_height(height)
+    }
+
+    pub fn width(&self) -> f64 {
+        self.width
+    }
+
+    pub fn height(&self) -> f64 {
+        self.height
+    }
+
+    pub fn with_width(mut self, width: f64) -> Self {
+        self.width = width;
+        self
+    }
+
+    pub fn with_height(mut self, height: f64) -> Self {
+        self.height = height;
+        self
+    }
+
+    pub fn with_transform(mut self, t: Tr) -> Self {
+        self.set_transform(t);
+        self
+    }
+
+    /// Like `with_transform`, but fails instead of panicking if the transformation is singular.
+    pub fn try_with_transform(mut self, t: Tr) -> Result<Self, transform::Error> {
+        self.try_set_transform(t)?;
+        Ok(self)
+    }
+
+    fn try_set_transform(&mut self, t: Tr) -> Result<(), transform::Error> {
+        (self.inv_transform, self.norm_transform) = derived_transforms(t)?;
+        self.transform = t;
+        Ok(())
+    }
+
+    pub fn with_material(mut self, m: Material) -> Self {
+        self.material = m;
+        self
+    }
+
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn as_object(self) -> Object {
+        Arc::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Quad;
+    use crate::ray::Ray;
+    use crate::shapes::Shape;
+    use crate::transform::Tr;
+    use crate::{assert_f64_eq, p, v, EPSILON};
+    use std::f64::consts::FRAC_PI_2;
+
+    #[test]
+    fn rays_only_hit_within_the_rectangle() {
+        let quad = Quad::new(4.0, 1.0);
+        let tests = vec![
+            (p!(0, 1, 0), true),
+            (p!(1.9, 1, 0.4), true),
+            (p!(-2, 1, -0.5), true),
+            (p!(2.1, 1, 0), false),
+            (p!(0, 1, 0.6), false),
+        ];
+        for (origin, hit) in tests {
+            let xs = quad.local_intersect_with(Ray::new(origin, v!(0, -1, 0)));
+            assert_eq!(xs.len(), hit as usize, "origin={origin:?}");
+        }
+        let r = Ray::new(p!(0, 1, 0), v!(1, 0, 0));
+        assert!(quad.local_intersect_with(r).is_empty());
+        assert_eq!(quad.bounds().max(), p!(2, 0, 0.5));
+    }
+
+    #[test]
+    fn standing_a_quad_up_as_a_wall() {
+        let wall = Quad::new(4.0, 2.0)
+            .with_transform(Tr::new().rotate_x(-FRAC_PI_2).translate(0.0, 1.0, 5.0));
+        let xs = wall.intersect_with(Ray::new(p!(0, 1.5, 0), v!(0, 0, 1)));
+        assert_eq!(xs.len(), 1);
+        assert_f64_eq!(xs[0].t(), 5.0, EPSILON);
+        assert!(wall
+            .intersect_with(Ray::new(p!(0, 2.5, 0), v!(0, 0, 1)))
+            .is_empty());
+        let n = wall.normal_at(p!(0, 1.5, 5));
+        assert_f64_eq!(n.z(), -1.0, EPSILON);
+
+        assert_eq!(
+            Quad::new(4.0, 2.0).local_uv_at(p!(-1, 0, 0.5)),
+            (0.25, 0.75)
+        );
+    }
+}
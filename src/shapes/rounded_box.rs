@@ -0,0 +1,272 @@
+use crate::bounds::Bounds;
+use crate::light::Material;
+use crate::matrix::Matrix;
+use crate::ray::{Intersection, Ray};
+use crate::shapes::{derived_transforms, Object, Sdf, Shape};
+use crate::transform::{self, Tr};
+use crate::tuple::{Point, Vector};
+use crate::{get_uid, v};
+use std::sync::Arc;
+
+/// A cube with rounded edges and corners, spanning from -1 to 1 along every axis like a
+/// regular cube. The radius is how far the rounding reaches in from each face, so a radius of
+/// zero gives sharp edges and a radius of one gives a sphere.
+#[derive(Debug, Clone)]
+pub struct RoundedBox {
+    id: usize,
+    name: Option<String>,
+    radius: f64,
+    /// Marches rays through the box's distance function.
+    marcher: Sdf,
+
+    transform: Tr,
+    inv_transform: Tr,
+    /// A matrix used in computing normals.
+    norm_transform: Matrix<3, 3>,
+
+    material: Material,
+}
+
+impl Default for RoundedBox {
+    fn default() -> Self {
+        Self::new(0.25)
+    }
+}
+
+impl PartialEq for RoundedBox {
+    fn eq(&self, other: &Self) -> bool {
+        self.radius == other.radius
+            && self.transform == other.transform
+            && self.inv_transform == other.inv_transform
+            && self.norm_transform == other.norm_transform
+            && self.material == other.material
+    }
+}
+
+impl Shape for RoundedBox {
+    fn transform(&self) -> Tr {
+        self.transform
+    }
+
+    fn inv_transform(&self) -> Tr {
+        self.inv_transform
+    }
+
+    fn norm_transform(&self) -> Matrix<3, 3> {
+        self.norm_transform
+    }
+
+    fn set_transform(&mut self, t: Tr) {
+        self.try_set_transform(t)
+            .expect("shape transformation should be invertible");
+    }
+
+    fn material(&self) -> Material {
+        self.material.clone()
+    }
+
+    fn set_material(&mut self, m: Material) {
+        self.material = m;
+    }
+
+    fn local_intersect_with(&self, r: Ray) -> Vec<Intersection> {
+        self.marcher
+            .local_intersect_with(r)
+            .into_iter()
+            .map(|x| Intersection::new(x.t(), Arc::new(self.clone())))
+            .collect()
+    }
+
+    /// The normal is worked out exactly, so it is smooth all the way around the rounded edges.
+    /// Flat faces point straight out, while around the edges and corners the normal points away
+    /// from the nearest point on the box the rounding is wrapped around.
+    fn local_normal_at(&self, p: Point) -> Vector {
+        let inner = 1.0 - self.radius;
+        let out = |c: f64| c.signum() * (c.abs() - inner).max(0.0);
+        let n = v!(out(p.x()), out(p.y()), out(p.z()));
+        if n.magnitude() > 0.0 {
+            return n.normalize();
+        }
+        // Only reachable when the point is not actually on the surface. Use the closest face.
+        let (x, y, z) = (p.x().abs(), p.y().abs(), p.z().abs());
+        if x >= y && x >= z {
+            v!(p.x().signum(), 0, 0)
+        } else if y >= z {
+            v!(0, p.y().signum(), 0)
+        } else {
+            v!(0, 0, p.z().signum())
+        }
+    }
+
+    fn local_bounds(&self) -> Bounds {
+        Bounds::new(Point::new(-1.0, -1.0, -1.0), Point::new(1.0, 1.0, 1.0))
+    }
+
+    fn is_solid(&self) -> bool {
+        true
+    }
+
+    fn local_contains(&self, p: Point) -> bool {
+        distance(p, self.radius) < 0.0
+    }
+
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn eqx(&self, other: &dyn std::any::Any) -> bool {
+        other.downcast_ref::<Self>().is_some_and(|a| a == self)
+    }
+}
+
+/// The signed distance from some point to a rounded box.
+fn distance(p: Point, radius: f64) -> f64 {
+    let inner = 1.0 - radius;
+    let q = v!(
+        p.x().abs() - inner,
+        p.y().abs() - inner,
+        p.z().abs() - inner
+    );
+    let outside = v!(q.x().max(0.0), q.y().max(0.0), q.z().max(0.0)).magnitude();
+    let inside = q.x().max(q.y()).max(q.z()).min(0.0);
+    outside + inside - radius
+}
+
+impl RoundedBox {
+    /// Creates a rounded box. The radius is clamped between zero and one.
+    pub fn new(radius: f64) -> Self {
+        let radius = radius.clamp(0.0, 1.0);
+        Self {
+            id: get_uid(),
+            name: None,
+            radius,
+            marcher: Sdf::new(
+                move |p| distance(p, radius),
+                Bounds::new(Point::new(-1.0, -1.0, -1.0), Point::new(1.0, 1.0, 1.0)),
+            ),
+            transform: Tr::default(),
+            inv_transform: Tr::default(),
+            norm_transform: Matrix::<3, 3>::ident(),
+            material: Material::default(),
+        }
+    }
+
+    pub fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    pub fn with_radius(self, radius: f64) -> Self {
+        Self {
+            id: self.id,
+            name: self.name,
+            transform: self.transform,
+            inv_transform: self.inv_transform,
+            norm_transform: self.norm_transform,
+            material: self.material,
+            ..Self::new(radius)
+        }
+    }
+
+    pub fn with_transform(mut self, t: Tr) -> Self {
+        self.set_transform(t);
+        self
+    }
+
+    /// Like `with_transform`, but fails instead of panicking if the transformation is singular.
+    pub fn try_with_transform(mut self, t: Tr) -> Result<Self, transform::Error> {
+        self.try_set_transform(t)?;
+        Ok(self)
+    }
+
+    fn try_set_transform(&mut self, t: Tr) -> Result<(), transform::Error> {
+        (self.inv_transform, self.norm_transform) = derived_transforms(t)?;
+        self.transform = t;
+        Ok(())
+    }
+
+    pub fn with_material(mut self, m: Material) -> Self {
+        self.material = m;
+        self
+    }
+
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn as_object(self) -> Object {
+        Arc::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RoundedBox;
+    use crate::ray::Ray;
+    use crate::shapes::Shape;
+    use crate::transform::Tr;
+    use crate::tuple::Point;
+    use crate::{assert_f64_eq, p, v, EPSILON};
+    use std::f64::consts::FRAC_1_SQRT_2;
+
+    #[test]
+    fn rays_hit_faces_and_rounded_corners() {
+        let b = RoundedBox::new(0.5);
+        let xs = b.local_intersect_with(Ray::new(p!(0, 0, -5), v!(0, 0, 1)));
+        assert_eq!(xs.len(), 2);
+        assert_f64_eq!(xs[0].t(), 4.0, EPSILON);
+        assert_f64_eq!(xs[1].t(), 6.0, EPSILON);
+
+        // Along the diagonal, the corner is cut off by the rounding.
+        let corner = 0.5 + 0.5 / 3f64.sqrt();
+        let r = Ray::new(p!(-5, -5, -5), v!(1, 1, 1).normalize());
+        let xs = b.local_intersect_with(r);
+        assert_eq!(xs.len(), 2);
+        assert_f64_eq!(xs[0].t(), 75f64.sqrt() - corner * 3f64.sqrt(), EPSILON);
+
+        // A ray just past a rounded edge misses, though it would hit a sharp cube.
+        let r = Ray::new(p!(0.95, 0.95, -5), v!(0, 0, 1));
+        assert!(b.local_intersect_with(r).is_empty());
+        assert_eq!(RoundedBox::new(0.0).local_intersect_with(r).len(), 2);
+    }
+
+    #[test]
+    fn normals_are_smooth_around_edges() {
+        let b = RoundedBox::new(0.5);
+        let tests = vec![
+            (p!(1, 0.2, -0.3), v!(1, 0, 0)),
+            (p!(0, -1, 0.5), v!(0, -1, 0)),
+            (
+                Point::new(0.5 + 0.5 * FRAC_1_SQRT_2, 0.5 + 0.5 * FRAC_1_SQRT_2, 0.0),
+                v!(FRAC_1_SQRT_2, FRAC_1_SQRT_2, 0),
+            ),
+        ];
+        for (point, want) in tests {
+            let n = b.local_normal_at(point);
+            assert_f64_eq!(n.x(), want.x(), EPSILON);
+            assert_f64_eq!(n.y(), want.y(), EPSILON);
+            assert_f64_eq!(n.z(), want.z(), EPSILON);
+        }
+    }
+
+    #[test]
+    fn transformed_rounded_boxes() {
+        let b = RoundedBox::default()
+            .with_radius(0.1)
+            .with_transform(Tr::new().scale(2.0, 1.0, 1.0));
+        assert_eq!(b.radius(), 0.1);
+        let xs = b.intersect_with(Ray::new(p!(-5, 0, 0), v!(1, 0, 0)));
+        assert_eq!(xs.len(), 2);
+        assert_f64_eq!(xs[0].t(), 3.0, EPSILON);
+        assert_eq!(b.bounds().max(), p!(2, 1, 1));
+        assert_eq!(RoundedBox::new(3.0).radius(), 1.0);
+    }
+}
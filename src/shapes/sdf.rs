@@ -0,0 +1,374 @@
+use crate::bounds::Bounds;
+use crate::light::Material;
+use crate::matrix::Matrix;
+use crate::ray::{march_settings, Intersection, Ray};
+use crate::shapes::{derived_transforms, Object, Shape};
+use crate::transform::{self, Tr};
+use crate::tuple::{Point, Vector};
+use crate::{get_uid, v, EPSILON};
+use std::fmt::Debug;
+use std::sync::Arc;
+
+/// A signed distance function, giving how far some point is from the nearest surface. Points
+/// inside the shape have negative distances.
+pub type DistanceFn = Arc<dyn Fn(Point) -> f64 + Send + Sync>;
+
+/// A shape given by a signed distance function, found by marching rays through it. This suits
+/// shapes with no neat equation for their intersections, like fractals or blobs blended
+/// together.
+///
+/// The function may underestimate the distance to the surface, which only makes rays take more
+/// steps, but it must never overestimate, or rays will skip straight through thin parts.
+#[derive(Clone)]
+pub struct Sdf {
+    id: usize,
+    name: Option<String>,
+    distance: DistanceFn,
+    /// The region rays are marched through. Nothing outside it is ever seen.
+    bounds: Bounds,
+    /// The most steps a ray may take before giving up, instead of the render settings' limit.
+    max_steps: Option<usize>,
+
+    transform: Tr,
+    inv_transform: Tr,
+    /// A matrix used in computing normals.
+    norm_transform: Matrix<3, 3>,
+
+    material: Material,
+}
+
+impl Default for Sdf {
+    /// A unit sphere.
+    fn default() -> Self {
+        Self {
+            id: get_uid(),
+            name: None,
+            distance: Arc::new(|p| (p - Point::origin()).magnitude() - 1.0),
+            bounds: Bounds::new(Point::new(-1.0, -1.0, -1.0), Point::new(1.0, 1.0, 1.0)),
+            max_steps: None,
+            transform: Tr::default(),
+            inv_transform: Tr::default(),
+            norm_transform: Matrix::<3, 3>::ident(),
+            material: Material::default(),
+        }
+    }
+}
+
+impl Debug for Sdf {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Sdf")
+            .field("id", &self.id)
+            .field("bounds", &self.bounds)
+            .field("max_steps", &self.max_steps)
+            .field("transform", &self.transform)
+            .field("material", &self.material)
+            .finish_non_exhaustive()
+    }
+}
+
+impl PartialEq for Sdf {
+    /// Two SDF shapes are only equal if they share the very same distance function, since
+    /// functions can't be compared.
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.distance, &other.distance)
+            && self.bounds == other.bounds
+            && self.max_steps == other.max_steps
+            && self.transform == other.transform
+            && self.inv_transform == other.inv_transform
+            && self.norm_transform == other.norm_transform
+            && self.material == other.material
+    }
+}
+
+/// Sphere traces a ray through a distance function, from where it enters some bounds to where it
+/// leaves them, and gives the t values of every surface it crosses. Each step moves as far as the
+/// distance function says is safe, which is the distance to the surface whether the ray is inside
+/// or outside, so every crossing is found.
+pub(crate) fn march(
+    distance: impl Fn(Point) -> f64,
+    bounds: Bounds,
+    r: Ray,
+    max_steps: usize,
+    precision: f64,
+) -> Vec<f64> {
+    let Some((start, end)) = bounds.clip(r) else {
+        return vec![];
+    };
+    if !(start.is_finite() && end.is_finite()) {
+        return vec![];
+    }
+    // The ray's direction is not normalized once it has been transformed, so distances in space
+    // have to be converted to distances along the ray.
+    let speed = r.direction().magnitude();
+    let mut ts = vec![];
+    let mut t = start;
+    // Whether the ray is still touching the surface it last hit, so as not to count that surface
+    // twice.
+    let mut touching = false;
+    for _ in 0..max_steps {
+        if t > end {
+            break;
+        }
+        let d = distance(r.position_at(t)).abs();
+        if d < precision {
+            if !touching {
+                ts.push(t);
+                touching = true;
+            }
+        } else {
+            touching = false;
+        }
+        t += d.max(precision) / speed;
+    }
+    ts
+}
+
+impl Shape for Sdf {
+    fn transform(&self) -> Tr {
+        self.transform
+    }
+
+    fn inv_transform(&self) -> Tr {
+        self.inv_transform
+    }
+
+    fn norm_transform(&self) -> Matrix<3, 3> {
+        self.norm_transform
+    }
+
+    fn set_transform(&mut self, t: Tr) {
+        self.try_set_transform(t)
+            .expect("shape transformation should be invertible");
+    }
+
+    fn material(&self) -> Material {
+        self.material.clone()
+    }
+
+    fn set_material(&mut self, m: Material) {
+        self.material = m;
+    }
+
+    /// Marches the ray through the distance function, as the render settings say.
+    fn local_intersect_with(&self, r: Ray) -> Vec<Intersection> {
+        let settings = march_settings();
+        let max_steps = self.max_steps.unwrap_or(settings.max_steps);
+        march(
+            &*self.distance,
+            self.bounds,
+            r,
+            max_steps,
+            settings.precision,
+        )
+        .into_iter()
+        .map(|t| Intersection::new(t, Arc::new(self.clone())))
+        .collect()
+    }
+
+    /// Estimates the gradient of the distance function with central differences.
+    fn local_normal_at(&self, p: Point) -> Vector {
+        let f = &self.distance;
+        let h = EPSILON;
+        let dx = v!(h, 0, 0);
+        let dy = v!(0, h, 0);
+        let dz = v!(0, 0, h);
+        v!(
+            f(p + dx) - f(p - dx),
+            f(p + dy) - f(p - dy),
+            f(p + dz) - f(p - dz)
+        )
+        .normalize()
+    }
+
+    fn local_bounds(&self) -> Bounds {
+        self.bounds
+    }
+
+    fn is_solid(&self) -> bool {
+        true
+    }
+
+    fn local_contains(&self, p: Point) -> bool {
+        self.bounds.contains(p) && (self.distance)(p) < 0.0
+    }
+
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn eqx(&self, other: &dyn std::any::Any) -> bool {
+        other.downcast_ref::<Self>().is_some_and(|a| a == self)
+    }
+}
+
+impl Sdf {
+    /// Creates a shape from a distance function, which is only marched within the given bounds.
+    pub fn new(distance: impl Fn(Point) -> f64 + Send + Sync + 'static, bounds: Bounds) -> Self {
+        Self {
+            distance: Arc::new(distance),
+            bounds,
+            ..Self::default()
+        }
+    }
+
+    /// Evaluates the distance function at some point in object space.
+    pub fn distance(&self, p: Point) -> f64 {
+        (self.distance)(p)
+    }
+
+    /// Hollows out this shape, leaving a shell reaching some thickness in from the surface, like
+    /// the walls of a glass. The outside of the shape stays exactly where it was.
+    pub fn hollow(self, thickness: f64) -> Self {
+        let outer = self.distance.clone();
+        Self {
+            distance: Arc::new(move |p| {
+                let d = outer(p);
+                d.max(-(d + thickness))
+            }),
+            ..self
+        }
+    }
+
+    /// The most steps a ray may take through this shape, if it overrides the render settings.
+    pub fn max_steps(&self) -> Option<usize> {
+        self.max_steps
+    }
+
+    /// Sets the most steps a ray may take, instead of the limit in the render settings. Rays
+    /// which run out of steps keep whatever hits they found so far.
+    pub fn with_max_steps(mut self, steps: usize) -> Self {
+        self.max_steps = Some(steps);
+        self
+    }
+
+    pub fn with_transform(mut self, t: Tr) -> Self {
+        self.set_transform(t);
+        self
+    }
+
+    /// Like `with_transform`, but fails instead of panicking if the transformation is singular.
+    pub fn try_with_transform(mut self, t: Tr) -> Result<Self, transform::Error> {
+        self.try_set_transform(t)?;
+        Ok(self)
+    }
+
+    fn try_set_transform(&mut self, t: Tr) -> Result<(), transform::Error> {
+        (self.inv_transform, self.norm_transform) = derived_transforms(t)?;
+        self.transform = t;
+        Ok(())
+    }
+
+    pub fn with_material(mut self, m: Material) -> Self {
+        self.material = m;
+        self
+    }
+
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn as_object(self) -> Object {
+        Arc::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Sdf;
+    use crate::bounds::Bounds;
+    use crate::ray::Ray;
+    use crate::shapes::Shape;
+    use crate::transform::Tr;
+    use crate::tuple::Point;
+    use crate::{assert_f64_eq, p, v, EPSILON};
+
+    #[test]
+    fn marching_through_a_sphere() {
+        let s = Sdf::default();
+        let xs = s.local_intersect_with(Ray::new(p!(0, 0, -5), v!(0, 0, 1)));
+        assert_eq!(xs.len(), 2);
+        assert_f64_eq!(xs[0].t(), 4.0, EPSILON);
+        assert_f64_eq!(xs[1].t(), 6.0, EPSILON);
+
+        // From inside, the surface behind is found too.
+        let xs = s.local_intersect_with(Ray::new(p!(0, 0, 0), v!(0, 0, 1)));
+        assert_eq!(xs.len(), 2);
+        assert_f64_eq!(xs[0].t(), -1.0, EPSILON);
+        assert_f64_eq!(xs[1].t(), 1.0, EPSILON);
+
+        assert!(s
+            .local_intersect_with(Ray::new(p!(0, 2, -5), v!(0, 0, 1)))
+            .is_empty());
+    }
+
+    #[test]
+    fn blended_shapes_and_transforms() {
+        // Two spheres side by side, each of radius one, give a ray along them four hits.
+        let twin = Sdf::new(
+            |p| {
+                let a = (p - p!(-1.5, 0, 0)).magnitude() - 1.0;
+                let b = (p - p!(1.5, 0, 0)).magnitude() - 1.0;
+                a.min(b)
+            },
+            Bounds::new(p!(-2.5, -1, -1), p!(2.5, 1, 1)),
+        )
+        .with_transform(Tr::new().scale(2.0, 2.0, 2.0));
+        let xs = twin.intersect_with(Ray::new(p!(-10, 0, 0), v!(1, 0, 0)));
+        let ts: Vec<f64> = xs.iter().map(|x| x.t()).collect();
+        assert_eq!(ts.len(), 4);
+        for (got, want) in ts.iter().zip([5.0, 9.0, 11.0, 15.0]) {
+            assert_f64_eq!(*got, want, EPSILON);
+        }
+    }
+
+    #[test]
+    fn normals_from_the_distance_function() {
+        let s = Sdf::default();
+        let tests = vec![
+            (p!(1, 0, 0), v!(1, 0, 0)),
+            (p!(0, -1, 0), v!(0, -1, 0)),
+            (
+                Point::new(3f64.sqrt() / 3.0, 3f64.sqrt() / 3.0, 3f64.sqrt() / 3.0),
+                v!(1, 1, 1).normalize(),
+            ),
+        ];
+        for (point, want) in tests {
+            let n = s.local_normal_at(point);
+            assert_f64_eq!(n.x(), want.x(), EPSILON);
+            assert_f64_eq!(n.y(), want.y(), EPSILON);
+            assert_f64_eq!(n.z(), want.z(), EPSILON);
+        }
+    }
+
+    #[test]
+    fn hollowing_out_distance_functions() {
+        let s = Sdf::default().hollow(0.25);
+        let xs = s.local_intersect_with(Ray::new(p!(0, 0, -5), v!(0, 0, 1)));
+        let got: Vec<f64> = xs.iter().map(|x| x.t()).collect();
+        assert_eq!(got.len(), 4);
+        for (got, want) in got.iter().zip([4.0, 4.25, 5.75, 6.0]) {
+            assert_f64_eq!(got, want, EPSILON);
+        }
+        assert!(s.distance(p!(0, 0, 0)) > 0.0);
+        assert!(s.distance(p!(0, 0.9, 0)) < 0.0);
+    }
+
+    #[test]
+    fn running_out_of_steps() {
+        let s = Sdf::default().with_max_steps(1);
+        assert!(s
+            .local_intersect_with(Ray::new(p!(0, 0.5, -5), v!(0, 0, 1)))
+            .is_empty());
+        assert_eq!(s.max_steps(), Some(1));
+        assert_eq!(s.distance(p!(0, 0, 0)), -1.0);
+    }
+}
@@ -1,15 +1,18 @@
+use crate::bounds::Bounds;
 use crate::light::Material;
 use crate::matrix::Matrix;
 use crate::ray::{Intersection, Ray};
-use crate::shapes::{Object, Shape};
-use crate::transform::Tr;
+use crate::shapes::{derived_transforms, Object, Shape};
+use crate::transform::{self, Tr};
 use crate::tuple::{Point, Vector};
 use crate::{get_uid, EPSILON};
+use std::f64::consts::{PI, TAU};
 use std::sync::Arc;
 
 #[derive(Debug, Clone)]
 pub struct Sphere {
     id: usize,
+    name: Option<String>,
     center: Point,
 
     transform: Tr,
@@ -24,6 +27,7 @@ impl Default for Sphere {
     fn default() -> Self {
         Self {
             id: get_uid(),
+            name: None,
             center: Point::new(0.0, 0.0, 0.0),
             transform: Tr::default(),
             inv_transform: Tr::default(),
@@ -58,9 +62,8 @@ impl Shape for Sphere {
     }
 
     fn set_transform(&mut self, t: Tr) {
-        self.transform = t;
-        self.inv_transform = t.inverse();
-        self.norm_transform = t.matrix().submatrix(3, 3).inverse().unwrap().transpose();
+        self.try_set_transform(t)
+            .expect("shape transformation should be invertible");
     }
 
     fn material(&self) -> Material {
@@ -77,10 +80,13 @@ impl Shape for Sphere {
         let b = 2.0 * r.direction().dot(sphere_to_ray);
         let c = sphere_to_ray.dot(sphere_to_ray) - 1.0;
         let mut discr = b * b - 4.0 * a * c;
-        if discr < -EPSILON {
+        // The discriminant grows with the square of the ray's length in object space, which
+        // shrinks as the sphere is scaled up, so the tolerance has to shrink with it.
+        let tol = EPSILON * a;
+        if discr < -tol {
             return vec![];
         }
-        discr = if discr.abs() < EPSILON { 0.0 } else { discr };
+        discr = if discr.abs() < tol { 0.0 } else { discr };
         let t1 = (-b - discr.sqrt()) / (2.0 * a);
         let t2 = (-b + discr.sqrt()) / (2.0 * a);
         let s = self.clone().as_object();
@@ -94,16 +100,42 @@ impl Shape for Sphere {
         p - self.center
     }
 
+    fn local_bounds(&self) -> Bounds {
+        Bounds::new(Point::new(-1.0, -1.0, -1.0), Point::new(1.0, 1.0, 1.0))
+    }
+
+    fn is_solid(&self) -> bool {
+        true
+    }
+
+    fn local_contains(&self, p: Point) -> bool {
+        (p - Point::origin()).magnitude() < 1.0
+    }
+
+    /// Wraps the texture around the sphere like a map of the world: u goes once around the
+    /// equator, starting and ending at -z, and v goes from the south pole up to the north.
+    fn local_uv_at(&self, p: Point) -> (f64, f64) {
+        let theta = p.x().atan2(p.z());
+        let radius = (p - Point::origin()).magnitude();
+        let phi = (p.y() / radius).clamp(-1.0, 1.0).acos();
+        let u = 1.0 - (theta / TAU + 0.5);
+        (u.rem_euclid(1.0), 1.0 - phi / PI)
+    }
+
     fn id(&self) -> usize {
         self.id
     }
 
+    fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
     fn as_any(&self) -> &dyn std::any::Any {
         self
     }
 
     fn eqx(&self, other: &dyn std::any::Any) -> bool {
-        other.downcast_ref::<Self>().map_or(false, |a| a == self)
+        other.downcast_ref::<Self>().is_some_and(|a| a == self)
     }
 }
 
@@ -117,6 +149,18 @@ impl Sphere {
         self
     }
 
+    /// Like `with_transform`, but fails instead of panicking if the transformation is singular.
+    pub fn try_with_transform(mut self, t: Tr) -> Result<Self, transform::Error> {
+        self.try_set_transform(t)?;
+        Ok(self)
+    }
+
+    fn try_set_transform(&mut self, t: Tr) -> Result<(), transform::Error> {
+        (self.inv_transform, self.norm_transform) = derived_transforms(t)?;
+        self.transform = t;
+        Ok(())
+    }
+
     pub fn with_material(mut self, m: Material) -> Self {
         self.material = m;
         self
@@ -130,6 +174,11 @@ impl Sphere {
         self
     }
 
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
     pub fn as_object(self) -> Object {
         Arc::new(self)
     }
@@ -141,9 +190,10 @@ mod tests {
     use crate::light::Material;
     use crate::ray::Ray;
     use crate::shapes::Shape;
-    use crate::transform::Tr;
+    use crate::transform::{Error, Tr};
     use crate::tuple::{Point, Vector};
-    use std::f64::consts::FRAC_PI_4;
+    use crate::{assert_f64_eq, EPSILON};
+    use std::f64::consts::{FRAC_1_SQRT_2, FRAC_PI_4};
 
     #[test]
     fn ray_intersecting_spheres() {
@@ -218,6 +268,19 @@ mod tests {
         assert_eq!(got, want);
     }
 
+    #[test]
+    fn ray_through_the_middle_of_a_huge_sphere() {
+        // The discriminant here is below the absolute tolerance, which used to make this a
+        // grazing hit at the sphere's center.
+        let r = Ray::new(Point::new(0.0, 0.0, -5000.0), Vector::new(0.0, 0.0, 1.0));
+        let s = Sphere::default().with_transform(Tr::default().scale(1000.0, 1000.0, 1000.0));
+
+        let got: Vec<f64> = s.intersect_with(r).into_iter().map(|x| x.t()).collect();
+        assert_eq!(got.len(), 2);
+        assert_f64_eq!(got[0], 4000.0, 1e-6);
+        assert_f64_eq!(got[1], 6000.0, 1e-6);
+    }
+
     #[test]
     fn intersecting_translated_sphere_with_ray() {
         let r = Ray::new(Point::new(0.0, 0.0, -5.0), Vector::new(0.0, 0.0, 1.0));
@@ -260,8 +323,8 @@ mod tests {
     fn normal_on_translated_sphere() {
         let s = Sphere::default().with_transform(Tr::default().translate(0.0, 1.0, 0.0));
 
-        let got = s.normal_at(Point::new(0.0, 1.70711, -0.70711));
-        let want = Vector::new(0.0, 0.70711, -0.70711);
+        let got = s.normal_at(Point::new(0.0, 1.0 + FRAC_1_SQRT_2, -FRAC_1_SQRT_2));
+        let want = Vector::new(0.0, FRAC_1_SQRT_2, -FRAC_1_SQRT_2);
         assert_eq!(got, want);
     }
 
@@ -280,4 +343,39 @@ mod tests {
         let s = Sphere::default();
         assert_eq!(s.material(), Material::default());
     }
+
+    #[test]
+    fn sphere_rejects_singular_transformation() {
+        let got = Sphere::default().try_with_transform(Tr::default().scale(0.0, 1.0, 1.0));
+        assert_eq!(got.err(), Some(Error::Uninvertible));
+
+        let t = Tr::default().scale(2.0, 1.0, 1.0);
+        let got = Sphere::default().try_with_transform(t).unwrap();
+        assert_eq!(got.inv_transform(), t.inverse());
+    }
+
+    #[test]
+    fn spherical_uv_mapping() {
+        let tests = vec![
+            (Point::new(0.0, 0.0, -1.0), (0.0, 0.5)),
+            (Point::new(1.0, 0.0, 0.0), (0.25, 0.5)),
+            (Point::new(0.0, 0.0, 1.0), (0.5, 0.5)),
+            (Point::new(-1.0, 0.0, 0.0), (0.75, 0.5)),
+            (Point::new(0.0, 1.0, 0.0), (0.5, 1.0)),
+            (Point::new(0.0, -1.0, 0.0), (0.5, 0.0)),
+            (Point::new(FRAC_1_SQRT_2, FRAC_1_SQRT_2, 0.0), (0.25, 0.75)),
+        ];
+        let s = Sphere::default();
+        for (p, (u, v)) in tests {
+            let got = s.local_uv_at(p);
+            assert_f64_eq!(got.0, u, EPSILON);
+            assert_f64_eq!(got.1, v, EPSILON);
+        }
+
+        // The mapping moves with the sphere.
+        let s = s.with_transform(Tr::new().scale(2.0, 2.0, 2.0).translate(5.0, 0.0, 0.0));
+        let got = s.uv_at(Point::new(7.0, 0.0, 0.0));
+        assert_f64_eq!(got.0, 0.25, EPSILON);
+        assert_f64_eq!(got.1, 0.5, EPSILON);
+    }
 }
@@ -0,0 +1,354 @@
+use crate::bounds::Bounds;
+use crate::light::Material;
+use crate::matrix::Matrix;
+use crate::ray::{march_settings, Intersection, Ray};
+use crate::roots::solve_quartic;
+use crate::shapes::sdf::march;
+use crate::shapes::{derived_transforms, Object, Shape};
+use crate::transform::{self, Tr};
+use crate::tuple::{Point, Vector};
+use crate::v;
+use crate::{get_uid, EPSILON};
+use std::sync::Arc;
+
+/// A torus lying in the xz plane and centred on the origin. The tube's centre line is a circle
+/// with the major radius, and the tube itself has the minor radius.
+#[derive(Debug, Clone)]
+pub struct Torus {
+    id: usize,
+    name: Option<String>,
+    major: f64,
+    minor: f64,
+    /// Whether rays are marched through the torus instead of solving for its intersections
+    /// exactly.
+    marched: bool,
+
+    transform: Tr,
+    inv_transform: Tr,
+    /// A matrix used in computing normals.
+    norm_transform: Matrix<3, 3>,
+
+    material: Material,
+}
+
+impl Default for Torus {
+    fn default() -> Self {
+        Self {
+            id: get_uid(),
+            name: None,
+            major: 1.0,
+            minor: 0.25,
+            marched: false,
+            transform: Tr::default(),
+            inv_transform: Tr::default(),
+            norm_transform: Matrix::<3, 3>::ident(),
+            material: Material::default(),
+        }
+    }
+}
+
+impl PartialEq for Torus {
+    fn eq(&self, other: &Self) -> bool {
+        self.major == other.major
+            && self.minor == other.minor
+            && self.marched == other.marched
+            && self.transform == other.transform
+            && self.inv_transform == other.inv_transform
+            && self.norm_transform == other.norm_transform
+            && self.material == other.material
+    }
+}
+
+impl Shape for Torus {
+    fn transform(&self) -> Tr {
+        self.transform
+    }
+
+    fn inv_transform(&self) -> Tr {
+        self.inv_transform
+    }
+
+    fn norm_transform(&self) -> Matrix<3, 3> {
+        self.norm_transform
+    }
+
+    fn set_transform(&mut self, t: Tr) {
+        self.try_set_transform(t)
+            .expect("shape transformation should be invertible");
+    }
+
+    fn material(&self) -> Material {
+        self.material.clone()
+    }
+
+    fn set_material(&mut self, m: Material) {
+        self.material = m;
+    }
+
+    fn local_intersect_with(&self, r: Ray) -> Vec<Intersection> {
+        if self.marched {
+            let settings = march_settings();
+            let (major, minor) = (self.major, self.minor);
+            let distance = |p: Point| {
+                let ring = (p.x() * p.x() + p.z() * p.z()).sqrt() - major;
+                (ring * ring + p.y() * p.y()).sqrt() - minor
+            };
+            return march(
+                distance,
+                self.local_bounds(),
+                r,
+                settings.max_steps,
+                settings.precision,
+            )
+            .into_iter()
+            .map(|t| Intersection::new(t, Arc::new(self.clone())))
+            .collect();
+        }
+
+        let (o, d) = (r.origin(), r.direction());
+        let dd = d.x() * d.x() + d.y() * d.y() + d.z() * d.z();
+        if dd < EPSILON * EPSILON {
+            return vec![];
+        }
+
+        // The quartic is badly conditioned when the origin is far away, so we solve it from the
+        // point on the ray closest to the centre instead, and shift the roots back afterwards.
+        let shift = -(o.x() * d.x() + o.y() * d.y() + o.z() * d.z()) / dd;
+        let (ox, oy, oz) = (
+            o.x() + shift * d.x(),
+            o.y() + shift * d.y(),
+            o.z() + shift * d.z(),
+        );
+        let bound = self.major + self.minor;
+        if ox * ox + oy * oy + oz * oz > bound * bound {
+            return vec![];
+        }
+
+        // Substituting the ray into (|p|^2 + R^2 - r^2)^2 = 4R^2 (x^2 + z^2).
+        let rr = 4.0 * self.major * self.major;
+        let f = ox * d.x() + oy * d.y() + oz * d.z();
+        let e = ox * ox + oy * oy + oz * oz + self.major * self.major - self.minor * self.minor;
+        let a4 = dd * dd;
+        let a3 = 4.0 * dd * f;
+        let a2 = 2.0 * dd * e + 4.0 * f * f - rr * (d.x() * d.x() + d.z() * d.z());
+        let a1 = 4.0 * f * e - 2.0 * rr * (ox * d.x() + oz * d.z());
+        let a0 = e * e - rr * (ox * ox + oz * oz);
+
+        solve_quartic(a4, a3, a2, a1, a0)
+            .into_iter()
+            .map(|t| Intersection::new(t + shift, Arc::new(self.clone())))
+            .collect()
+    }
+
+    fn local_normal_at(&self, p: Point) -> Vector {
+        // The gradient of the implicit surface, with a common factor of four dropped.
+        let s = p.x() * p.x() + p.y() * p.y() + p.z() * p.z() + self.major * self.major
+            - self.minor * self.minor;
+        let k = s - 2.0 * self.major * self.major;
+        v!(p.x() * k, p.y() * s, p.z() * k).normalize()
+    }
+
+    fn local_bounds(&self) -> Bounds {
+        let r = self.major + self.minor;
+        Bounds::new(
+            Point::new(-r, -self.minor, -r),
+            Point::new(r, self.minor, r),
+        )
+    }
+
+    fn is_solid(&self) -> bool {
+        true
+    }
+
+    fn local_contains(&self, p: Point) -> bool {
+        (p.x().hypot(p.z()) - self.major).hypot(p.y()) < self.minor
+    }
+
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn eqx(&self, other: &dyn std::any::Any) -> bool {
+        other.downcast_ref::<Self>().is_some_and(|a| a == self)
+    }
+}
+
+impl Torus {
+    pub fn new(major: f64, minor: f64) -> Self {
+        Self::default()
+            .with_major_radius(major)
+            .with_minor_radius(minor)
+    }
+
+    pub fn major_radius(&self) -> f64 {
+        self.major
+    }
+
+    pub fn minor_radius(&self) -> f64 {
+        self.minor
+    }
+
+    pub fn with_major_radius(mut self, major: f64) -> Self {
+        self.major = major;
+        self
+    }
+
+    pub fn with_minor_radius(mut self, minor: f64) -> Self {
+        self.minor = minor;
+        self
+    }
+
+    pub fn marched(&self) -> bool {
+        self.marched
+    }
+
+    /// Marches rays through the torus, as the render settings say, instead of solving for the
+    /// intersections exactly. Marching is slower, but doesn't suffer from the numerical trouble
+    /// the exact solution can have with very thin tori or rays from far away.
+    pub fn with_marching(mut self, marched: bool) -> Self {
+        self.marched = marched;
+        self
+    }
+
+    pub fn with_transform(mut self, t: Tr) -> Self {
+        self.set_transform(t);
+        self
+    }
+
+    /// Like `with_transform`, but fails instead of panicking if the transformation is singular.
+    pub fn try_with_transform(mut self, t: Tr) -> Result<Self, transform::Error> {
+        self.try_set_transform(t)?;
+        Ok(self)
+    }
+
+    fn try_set_transform(&mut self, t: Tr) -> Result<(), transform::Error> {
+        (self.inv_transform, self.norm_transform) = derived_transforms(t)?;
+        self.transform = t;
+        Ok(())
+    }
+
+    pub fn with_material(mut self, m: Material) -> Self {
+        self.material = m;
+        self
+    }
+
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn as_object(self) -> Object {
+        Arc::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Torus;
+    use crate::ray::Ray;
+    use crate::settings::{March, RenderSettings};
+    use crate::shapes::Shape;
+    use crate::transform::Tr;
+    use crate::world::World;
+    use crate::{assert_f64_eq, p, v, EPSILON};
+
+    #[test]
+    fn ray_through_the_tube_twice() {
+        let torus = Torus::new(1.0, 0.25);
+        let r = Ray::new(p!(-5, 0, 0), v!(1, 0, 0));
+        let xs = torus.local_intersect_with(r);
+        let ts: Vec<f64> = xs.iter().map(|x| x.t()).collect();
+        assert_eq!(ts.len(), 4);
+        for (got, want) in ts.iter().zip([3.75, 4.25, 5.75, 6.25]) {
+            assert_f64_eq!(*got, want, EPSILON);
+        }
+    }
+
+    #[test]
+    fn marching_finds_the_same_hits() {
+        let exact = Torus::new(1.0, 0.25).with_transform(Tr::new().rotate_x(0.3));
+        let marched = exact.clone().with_marching(true);
+        assert!(marched.marched());
+        for r in [
+            Ray::new(p!(-5, 0, 0), v!(1, 0, 0)),
+            Ray::new(p!(-5, 0.1, 0.3), v!(1, 0, 0)),
+            Ray::new(p!(0, 5, 0), v!(0, -1, 0)),
+        ] {
+            let want = exact.intersect_with(r);
+            let got = marched.intersect_with(r);
+            assert_eq!(got.len(), want.len());
+            for (g, w) in got.iter().zip(&want) {
+                assert_f64_eq!(g.t(), w.t(), EPSILON);
+            }
+        }
+    }
+
+    #[test]
+    fn marching_follows_the_render_settings() {
+        let torus = Torus::new(1.0, 0.25).with_marching(true).as_object();
+        let r = Ray::new(p!(-5, 0.1, 0.3), v!(1, 0, 0));
+        let w = World::new().with_objects(vec![torus]);
+        assert_eq!(r.when_intersect_world(&w).len(), 4);
+
+        let starved = March {
+            max_steps: 3,
+            ..March::default()
+        };
+        let w = w.with_settings(RenderSettings::new().with_march(starved));
+        assert_eq!(r.when_intersect_world(&w).len(), 0);
+    }
+
+    #[test]
+    fn ray_through_the_hole_misses() {
+        let torus = Torus::new(1.0, 0.25);
+        let r = Ray::new(p!(0, 5, 0), v!(0, -1, 0));
+        assert!(torus.local_intersect_with(r).is_empty());
+        let r = Ray::new(p!(0, 5, 0), v!(0, 0, 1));
+        assert!(torus.local_intersect_with(r).is_empty());
+    }
+
+    #[test]
+    fn ray_from_far_away_stays_accurate() {
+        let torus = Torus::new(1.0, 0.25);
+        let r = Ray::new(p!(1, 1e4, 0), v!(0, -1, 0));
+        let xs = torus.local_intersect_with(r);
+        assert_eq!(xs.len(), 2);
+        assert_f64_eq!(xs[0].t(), 1e4 - 0.25, EPSILON);
+        assert_f64_eq!(xs[1].t(), 1e4 + 0.25, EPSILON);
+    }
+
+    #[test]
+    fn normal_on_torus() {
+        let torus = Torus::new(1.0, 0.25);
+        let tests = vec![
+            (p!(1.25, 0, 0), v!(1, 0, 0)),
+            (p!(0.75, 0, 0), v!(-1, 0, 0)),
+            (p!(0, 0.25, 1), v!(0, 1, 0)),
+            (p!(0, 0, -1.25), v!(0, 0, -1)),
+        ];
+        for (point, want) in tests {
+            let n = torus.local_normal_at(point);
+            assert_f64_eq!(n.x(), want.x(), EPSILON);
+            assert_f64_eq!(n.y(), want.y(), EPSILON);
+            assert_f64_eq!(n.z(), want.z(), EPSILON);
+        }
+    }
+
+    #[test]
+    fn radii() {
+        let torus = Torus::default();
+        assert_eq!(torus.major_radius(), 1.0);
+        assert_eq!(torus.minor_radius(), 0.25);
+        let torus = Torus::new(3.0, 0.5);
+        assert_eq!(torus.major_radius(), 3.0);
+        assert_eq!(torus.minor_radius(), 0.5);
+    }
+}
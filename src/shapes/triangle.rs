@@ -0,0 +1,401 @@
+use crate::bounds::Bounds;
+use crate::light::Material;
+use crate::matrix::Matrix;
+use crate::ray::{Intersection, Ray};
+use crate::shapes::{derived_transforms, Object, Shape};
+use crate::transform::{self, Tr};
+use crate::tuple::{Point, Vector};
+use crate::{get_uid, EPSILON};
+use std::sync::Arc;
+
+/// A flat triangle between three points. This is what meshes are made of.
+#[derive(Debug, Clone)]
+pub struct Triangle {
+    id: usize,
+    name: Option<String>,
+    p1: Point,
+    p2: Point,
+    p3: Point,
+    /// The edge from p1 to p2.
+    e1: Vector,
+    /// The edge from p1 to p3.
+    e2: Vector,
+    normal: Vector,
+    /// Normals at p1, p2 and p3, which are blended across the triangle so that meshes shade
+    /// smoothly. Without them, the triangle is flat.
+    vertex_normals: Option<[Vector; 3]>,
+    /// Texture coordinates at p1, p2 and p3, which are blended across the triangle.
+    vertex_uvs: Option<[(f64, f64); 3]>,
+    /// Tangents and bitangents at p1, p2 and p3, pointing the way the texture coordinates grow.
+    /// Without them, they are worked out from the texture coordinates, if there are any.
+    vertex_tangents: Option<[(Vector, Vector); 3]>,
+    /// Whether the triangle belongs to a closed mesh.
+    closed_mesh: bool,
+
+    transform: Tr,
+    inv_transform: Tr,
+    /// A matrix used in computing normals.
+    norm_transform: Matrix<3, 3>,
+
+    material: Material,
+}
+
+impl PartialEq for Triangle {
+    fn eq(&self, other: &Self) -> bool {
+        self.p1 == other.p1
+            && self.p2 == other.p2
+            && self.p3 == other.p3
+            && self.vertex_normals == other.vertex_normals
+            && self.vertex_uvs == other.vertex_uvs
+            && self.vertex_tangents == other.vertex_tangents
+            && self.closed_mesh == other.closed_mesh
+            && self.transform == other.transform
+            && self.inv_transform == other.inv_transform
+            && self.norm_transform == other.norm_transform
+            && self.material == other.material
+    }
+}
+
+impl Shape for Triangle {
+    fn transform(&self) -> Tr {
+        self.transform
+    }
+
+    fn inv_transform(&self) -> Tr {
+        self.inv_transform
+    }
+
+    fn norm_transform(&self) -> Matrix<3, 3> {
+        self.norm_transform
+    }
+
+    fn set_transform(&mut self, t: Tr) {
+        self.try_set_transform(t)
+            .expect("shape transformation should be invertible");
+    }
+
+    fn material(&self) -> Material {
+        self.material.clone()
+    }
+
+    fn set_material(&mut self, m: Material) {
+        self.material = m;
+    }
+
+    /// Intersects using the Möller–Trumbore algorithm.
+    fn local_intersect_with(&self, r: Ray) -> Vec<Intersection> {
+        let dir_cross_e2 = r.direction().cross(self.e2);
+        let det = self.e1.dot(dir_cross_e2);
+        // The ray is parallel to the triangle.
+        if det.abs() < EPSILON {
+            return vec![];
+        }
+
+        let f = 1.0 / det;
+        let p1_to_origin = r.origin() - self.p1;
+        let u = f * p1_to_origin.dot(dir_cross_e2);
+        if !(0.0..=1.0).contains(&u) {
+            return vec![];
+        }
+
+        let origin_cross_e1 = p1_to_origin.cross(self.e1);
+        let v = f * r.direction().dot(origin_cross_e1);
+        if v < 0.0 || u + v > 1.0 {
+            return vec![];
+        }
+
+        let t = f * self.e2.dot(origin_cross_e1);
+        vec![Intersection::new(t, Arc::new(self.clone()))]
+    }
+
+    fn local_normal_at(&self, p: Point) -> Vector {
+        let Some([n1, n2, n3]) = self.vertex_normals else {
+            return self.normal;
+        };
+        let (u, v) = self.barycentric(p);
+        (n2 * u + n3 * v + n1 * (1.0 - u - v)).normalize()
+    }
+
+    fn is_closed_mesh(&self) -> bool {
+        self.closed_mesh
+    }
+
+    /// Blends the corners' texture coordinates, if there are any. Otherwise the point is
+    /// projected onto the xz plane, like shapes without a mapping of their own.
+    fn local_uv_at(&self, p: Point) -> (f64, f64) {
+        let Some([a, b, c]) = self.vertex_uvs else {
+            return (p.x().rem_euclid(1.0), p.z().rem_euclid(1.0));
+        };
+        let (u, v) = self.barycentric(p);
+        let w = 1.0 - u - v;
+        (a.0 * w + b.0 * u + c.0 * v, a.1 * w + b.1 * u + c.1 * v)
+    }
+
+    /// Blends the corners' tangents, then straightens them out against the normal, which may
+    /// itself be blended.
+    fn local_tangents_at(&self, p: Point) -> Option<(Vector, Vector)> {
+        let (t, b) = match (self.vertex_tangents, self.vertex_uvs) {
+            (Some([t1, t2, t3]), _) => {
+                let (u, v) = self.barycentric(p);
+                let w = 1.0 - u - v;
+                (
+                    t1.0 * w + t2.0 * u + t3.0 * v,
+                    t1.1 * w + t2.1 * u + t3.1 * v,
+                )
+            }
+            (None, Some(uvs)) => uv_tangents([self.p1, self.p2, self.p3], uvs)?,
+            (None, None) => return None,
+        };
+        let n = self.local_normal_at(p);
+        let t = t - n * n.dot(t);
+        if t.magnitude() < EPSILON {
+            return None;
+        }
+        let t = t.normalize();
+        let b = (b - n * n.dot(b) - t * t.dot(b)).normalize();
+        Some((t, b))
+    }
+
+    fn local_bounds(&self) -> Bounds {
+        Bounds::new(self.p1, self.p2).union(&Bounds::new(self.p3, self.p3))
+    }
+
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn eqx(&self, other: &dyn std::any::Any) -> bool {
+        other.downcast_ref::<Self>().is_some_and(|a| a == self)
+    }
+}
+
+impl Triangle {
+    pub fn new(p1: Point, p2: Point, p3: Point) -> Self {
+        let e1 = p2 - p1;
+        let e2 = p3 - p1;
+        Self {
+            id: get_uid(),
+            name: None,
+            p1,
+            p2,
+            p3,
+            e1,
+            e2,
+            normal: e2.cross(e1).normalize(),
+            vertex_normals: None,
+            vertex_uvs: None,
+            vertex_tangents: None,
+            closed_mesh: false,
+            transform: Tr::default(),
+            inv_transform: Tr::default(),
+            norm_transform: Matrix::<3, 3>::ident(),
+            material: Material::default(),
+        }
+    }
+
+    pub fn p1(&self) -> Point {
+        self.p1
+    }
+
+    pub fn p2(&self) -> Point {
+        self.p2
+    }
+
+    pub fn p3(&self) -> Point {
+        self.p3
+    }
+
+    pub fn e1(&self) -> Vector {
+        self.e1
+    }
+
+    pub fn e2(&self) -> Vector {
+        self.e2
+    }
+
+    pub fn normal(&self) -> Vector {
+        self.normal
+    }
+
+    /// Gives the triangle a normal at each of its corners, to be blended across it.
+    pub fn with_vertex_normals(mut self, n1: Vector, n2: Vector, n3: Vector) -> Self {
+        self.vertex_normals = Some([n1, n2, n3]);
+        self
+    }
+
+    pub fn vertex_normals(&self) -> Option<[Vector; 3]> {
+        self.vertex_normals
+    }
+
+    /// Gives the triangle texture coordinates at each of its corners, to be blended across it.
+    pub fn with_vertex_uvs(mut self, uv1: (f64, f64), uv2: (f64, f64), uv3: (f64, f64)) -> Self {
+        self.vertex_uvs = Some([uv1, uv2, uv3]);
+        self
+    }
+
+    pub fn vertex_uvs(&self) -> Option<[(f64, f64); 3]> {
+        self.vertex_uvs
+    }
+
+    /// Gives the triangle a tangent and bitangent at each of its corners, to be blended across
+    /// it, so that neighbouring triangles agree on them.
+    pub fn with_vertex_tangents(mut self, tangents: [(Vector, Vector); 3]) -> Self {
+        self.vertex_tangents = Some(tangents);
+        self
+    }
+
+    pub fn vertex_tangents(&self) -> Option<[(Vector, Vector); 3]> {
+        self.vertex_tangents
+    }
+
+    /// Finds how far some point on the triangle is towards p2 and p3 respectively, as barycentric
+    /// coordinates.
+    fn barycentric(&self, p: Point) -> (f64, f64) {
+        let to_p = p - self.p1;
+        let (d11, d12, d22) = (
+            self.e1.dot(self.e1),
+            self.e1.dot(self.e2),
+            self.e2.dot(self.e2),
+        );
+        let (dp1, dp2) = (to_p.dot(self.e1), to_p.dot(self.e2));
+        let denom = d11 * d22 - d12 * d12;
+        let u = (d22 * dp1 - d12 * dp2) / denom;
+        let v = (d11 * dp2 - d12 * dp1) / denom;
+        (u, v)
+    }
+
+    /// Marks the triangle as part of a closed mesh, with its normal facing out of the mesh. This
+    /// lets shadow rays skip it from behind.
+    pub fn with_closed_mesh(mut self, closed_mesh: bool) -> Self {
+        self.closed_mesh = closed_mesh;
+        self
+    }
+
+    pub fn with_transform(mut self, t: Tr) -> Self {
+        self.set_transform(t);
+        self
+    }
+
+    /// Like `with_transform`, but fails instead of panicking if the transformation is singular.
+    pub fn try_with_transform(mut self, t: Tr) -> Result<Self, transform::Error> {
+        self.try_set_transform(t)?;
+        Ok(self)
+    }
+
+    fn try_set_transform(&mut self, t: Tr) -> Result<(), transform::Error> {
+        (self.inv_transform, self.norm_transform) = derived_transforms(t)?;
+        self.transform = t;
+        Ok(())
+    }
+
+    pub fn with_material(mut self, m: Material) -> Self {
+        self.material = m;
+        self
+    }
+
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn as_object(self) -> Object {
+        Arc::new(self)
+    }
+}
+
+/// Finds the directions in which the texture coordinates grow across a triangle, as a tangent
+/// along U and a bitangent along V. Neither is normalized. Gives None if the texture coordinates
+/// don't span an area.
+pub(crate) fn uv_tangents(
+    [p1, p2, p3]: [Point; 3],
+    [uv1, uv2, uv3]: [(f64, f64); 3],
+) -> Option<(Vector, Vector)> {
+    let (e1, e2) = (p2 - p1, p3 - p1);
+    let (du1, dv1) = (uv2.0 - uv1.0, uv2.1 - uv1.1);
+    let (du2, dv2) = (uv3.0 - uv1.0, uv3.1 - uv1.1);
+    let det = du1 * dv2 - du2 * dv1;
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let f = 1.0 / det;
+    Some(((e1 * dv2 - e2 * dv1) * f, (e2 * du1 - e1 * du2) * f))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Triangle;
+    use crate::ray::Ray;
+    use crate::shapes::Shape;
+    use crate::{p, v};
+
+    fn triangle() -> Triangle {
+        Triangle::new(p!(0, 1, 0), p!(-1, 0, 0), p!(1, 0, 0))
+    }
+
+    #[test]
+    fn constructing_a_triangle() {
+        let t = triangle();
+        assert_eq!(t.p1(), p!(0, 1, 0));
+        assert_eq!(t.p2(), p!(-1, 0, 0));
+        assert_eq!(t.p3(), p!(1, 0, 0));
+        assert_eq!(t.e1(), v!(-1, -1, 0));
+        assert_eq!(t.e2(), v!(1, -1, 0));
+        assert_eq!(t.normal(), v!(0, 0, -1));
+    }
+
+    #[test]
+    fn normal_on_a_triangle() {
+        let t = triangle();
+        assert_eq!(t.local_normal_at(p!(0, 0.5, 0)), t.normal());
+        assert_eq!(t.local_normal_at(p!(-0.5, 0.75, 0)), t.normal());
+        assert_eq!(t.local_normal_at(p!(0.5, 0.25, 0)), t.normal());
+    }
+
+    #[test]
+    fn ray_parallel_to_triangle() {
+        let r = Ray::new(p!(0, -1, -2), v!(0, 1, 0));
+        assert!(triangle().local_intersect_with(r).is_empty());
+    }
+
+    #[test]
+    fn ray_misses_edges() {
+        let tests = vec![
+            // p1-p3 edge
+            p!(1, 1, -2),
+            // p1-p2 edge
+            p!(-1, 1, -2),
+            // p2-p3 edge
+            p!(0, -1, -2),
+        ];
+        for origin in tests {
+            let r = Ray::new(origin, v!(0, 0, 1));
+            assert!(triangle().local_intersect_with(r).is_empty());
+        }
+    }
+
+    #[test]
+    fn normal_on_smooth_triangle() {
+        let t = triangle().with_vertex_normals(v!(0, 1, 0), v!(-1, 0, 0), v!(1, 0, 0));
+        // 0.45 of the way to p2, and 0.25 of the way to p3.
+        let got = t.local_normal_at(p!(-0.2, 0.3, 0));
+        assert_eq!(got, v!(-0.5547, 0.83205, 0));
+        assert_eq!(t.local_normal_at(p!(0, 1, 0)), v!(0, 1, 0));
+        assert_eq!(t.local_normal_at(p!(1, 0, 0)), v!(1, 0, 0));
+    }
+
+    #[test]
+    fn ray_strikes_triangle() {
+        let r = Ray::new(p!(0, 0.5, -2), v!(0, 0, 1));
+        let xs = triangle().local_intersect_with(r);
+        assert_eq!(xs.len(), 1);
+        assert_eq!(xs[0].t(), 2.0);
+    }
+}
@@ -0,0 +1,252 @@
+use crate::camera::Camera;
+use crate::canvas::Canvas;
+use crate::color::Color;
+use crate::world::World;
+
+/// Lays out a bunch of renders in a labelled grid, to compare them side by side. Useful when
+/// tweaking some parameter and trying to find a value that looks right.
+pub struct ContactSheet {
+    columns: usize,
+    padding: usize,
+    label_scale: usize,
+    entries: Vec<(String, Canvas)>,
+}
+
+impl ContactSheet {
+    /// Creates an empty sheet which places at most `columns` images on each row.
+    pub fn new(columns: usize) -> Self {
+        Self {
+            columns: columns.max(1),
+            padding: 4,
+            label_scale: 1,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Sets the gap, in pixels, between images.
+    pub fn with_padding(mut self, padding: usize) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    /// Sets how many pixels each dot of the label font takes up.
+    pub fn with_label_scale(mut self, scale: usize) -> Self {
+        self.label_scale = scale.max(1);
+        self
+    }
+
+    /// Adds an image to the next free cell of the sheet.
+    pub fn add(&mut self, label: impl Into<String>, image: Canvas) {
+        self.entries.push((label.into(), image));
+    }
+
+    /// Renders the same scene once for every variant, and adds each result to the sheet. A
+    /// variant gets its own copy of the world to tweak as it sees fit.
+    pub fn render_each<L, F>(
+        mut self,
+        camera: &Camera,
+        world: &World,
+        variants: impl IntoIterator<Item = (L, F)>,
+    ) -> Self
+    where
+        L: Into<String>,
+        F: FnOnce(World) -> World,
+    {
+        for (label, f) in variants {
+            let w = f(world.clone());
+            self.add(label, camera.render(&w));
+        }
+        self
+    }
+
+    /// The height of the strip beneath each image which holds its label.
+    fn label_height(&self) -> usize {
+        GLYPH_HEIGHT * self.label_scale + self.padding
+    }
+
+    /// Assembles every image added so far into one canvas.
+    pub fn to_canvas(&self) -> Canvas {
+        let n = self.entries.len();
+        let cols = self.columns.min(n).max(1);
+        let rows = n.div_ceil(cols);
+        let tile_w = self
+            .entries
+            .iter()
+            .map(|(_, c)| c.width())
+            .max()
+            .unwrap_or(0);
+        let tile_h = self
+            .entries
+            .iter()
+            .map(|(_, c)| c.height())
+            .max()
+            .unwrap_or(0);
+        let cell_w = tile_w + self.padding;
+        let cell_h = tile_h + self.label_height() + self.padding;
+
+        let mut sheet = Canvas::new(cols * cell_w + self.padding, rows * cell_h + self.padding);
+        for (i, (label, image)) in self.entries.iter().enumerate() {
+            let x0 = self.padding + (i % cols) * cell_w;
+            let y0 = self.padding + (i / cols) * cell_h;
+            for y in 0..image.height() {
+                for x in 0..image.width() {
+                    sheet.write_to(x0 + x, y0 + y, image.pixel_at(x, y));
+                }
+            }
+            self.draw_label(&mut sheet, label, x0, y0 + tile_h + self.padding, tile_w);
+        }
+        sheet
+    }
+
+    /// Writes some text in white, starting from (x0, y0) at the top left. Anything which goes
+    /// beyond `max_width` is cut off.
+    fn draw_label(&self, canvas: &mut Canvas, label: &str, x0: usize, y0: usize, max_width: usize) {
+        let s = self.label_scale;
+        let advance = (GLYPH_WIDTH + 1) * s;
+        for (i, ch) in label.chars().enumerate() {
+            let gx = x0 + i * advance;
+            if (i + 1) * advance > max_width + s {
+                break;
+            }
+            for (row, bits) in glyph(ch).iter().enumerate() {
+                for col in 0..GLYPH_WIDTH {
+                    if bits & (1 << (GLYPH_WIDTH - 1 - col)) == 0 {
+                        continue;
+                    }
+                    for dy in 0..s {
+                        for dx in 0..s {
+                            canvas.write_to(gx + col * s + dx, y0 + row * s + dy, Color::white());
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+const GLYPH_WIDTH: usize = 3;
+const GLYPH_HEIGHT: usize = 5;
+
+/// A tiny 3x5 bitmap font. Each row is three bits, with the leftmost pixel in the highest bit.
+/// Letters are drawn in upper case, and anything without a glyph is left blank.
+fn glyph(c: char) -> [u8; GLYPH_HEIGHT] {
+    match c.to_ascii_uppercase() {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '+' => [0b000, 0b010, 0b111, 0b010, 0b000],
+        '=' => [0b000, 0b111, 0b000, 0b111, 0b000],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        _ => [0; GLYPH_HEIGHT],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ContactSheet;
+    use crate::camera::Camera;
+    use crate::canvas::Canvas;
+    use crate::color::Color;
+    use crate::settings::RenderSettings;
+    use crate::world::World;
+    use std::f64::consts::FRAC_PI_2;
+
+    fn filled(w: usize, h: usize, c: Color) -> Canvas {
+        let mut canvas = Canvas::new(w, h);
+        for px in canvas.pixels_mut() {
+            *px = c;
+        }
+        canvas
+    }
+
+    #[test]
+    fn lays_out_images_in_a_grid() {
+        let mut sheet = ContactSheet::new(2).with_padding(1);
+        sheet.add("a", filled(4, 3, Color::new(1.0, 0.0, 0.0)));
+        sheet.add("b", filled(4, 3, Color::new(0.0, 1.0, 0.0)));
+        sheet.add("c", filled(4, 3, Color::new(0.0, 0.0, 1.0)));
+        let got = sheet.to_canvas();
+
+        // Each cell is the image, a 5 pixel label plus padding, and padding after.
+        let cell_w = 4 + 1;
+        let cell_h = 3 + 5 + 1 + 1;
+        assert_eq!(got.width(), 2 * cell_w + 1);
+        assert_eq!(got.height(), 2 * cell_h + 1);
+        assert_eq!(got.pixel_at(1, 1), Color::new(1.0, 0.0, 0.0));
+        assert_eq!(got.pixel_at(1 + cell_w, 1), Color::new(0.0, 1.0, 0.0));
+        assert_eq!(got.pixel_at(1, 1 + cell_h), Color::new(0.0, 0.0, 1.0));
+        assert_eq!(got.pixel_at(0, 0), Color::black());
+    }
+
+    #[test]
+    fn draws_labels_under_images() {
+        let mut sheet = ContactSheet::new(1).with_padding(0);
+        sheet.add("1", filled(4, 2, Color::black()));
+        let got = sheet.to_canvas();
+
+        // The glyph for 1, drawn from the top left of the label strip.
+        let want = ["010", "110", "010", "010", "111"];
+        for (y, row) in want.iter().enumerate() {
+            for (x, bit) in row.chars().enumerate() {
+                let c = got.pixel_at(x, 2 + y);
+                assert_eq!(c == Color::white(), bit == '1', "at ({x}, {y})");
+            }
+        }
+    }
+
+    #[test]
+    fn renders_each_variant() {
+        let camera = Camera::new(5, 5, FRAC_PI_2);
+        let world = World::default();
+        let sheet = ContactSheet::new(3).render_each(
+            &camera,
+            &world,
+            [0, 1, 5].map(|n| {
+                (format!("bounce={n}"), move |w: World| {
+                    w.with_settings(RenderSettings::new().with_max_bounce(n))
+                })
+            }),
+        );
+        let got = sheet.to_canvas();
+        assert_eq!(got.width(), 3 * (5 + 4) + 4);
+        assert_eq!(got.height(), 5 + 5 + 4 + 4 + 4);
+    }
+}
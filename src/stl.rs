@@ -0,0 +1,191 @@
+use crate::mesh::Mesh;
+use crate::tuple::Point;
+use std::fmt::Display;
+use std::path::Path;
+
+/// The size of a binary STL header, including the triangle count.
+const HEADER_LEN: usize = 84;
+/// The size of each triangle in a binary STL file: a normal, three vertices, and two spare bytes.
+const TRIANGLE_LEN: usize = 50;
+
+/// Represents some problem reading an STL file.
+#[derive(Debug, PartialEq)]
+pub enum ErrParseStl {
+    /// When the file cannot be read at all.
+    Io(String),
+    /// When the file is neither ASCII nor binary STL.
+    NotStl,
+    InvalidNumber(String),
+    /// When a facet does not have exactly three vertices. Holds the facet's position in the file.
+    InvalidFacet(usize),
+    /// When the file ends partway through a facet.
+    Truncated,
+}
+
+impl Display for ErrParseStl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use ErrParseStl::*;
+        match self {
+            Io(msg) => write!(f, "Could not read STL file; {msg}"),
+            NotStl => write!(f, "Not an STL file"),
+            InvalidNumber(n) => write!(f, "Invalid number in STL file; n={n}"),
+            InvalidFacet(i) => write!(f, "STL facet does not have three vertices; facet={i}"),
+            Truncated => write!(f, "STL file is truncated"),
+        }
+    }
+}
+
+/// Parses an STL file, which may be either ASCII or binary, into a mesh. STL only stores loose
+/// triangles, so corners at the same position are welded together; the stored facet normals are
+/// ignored, since they are often missing or wrong.
+///
+/// Binary files may also begin with `solid`, so a file is only treated as ASCII if its size does
+/// not match the triangle count in a binary header.
+pub fn parse_stl(bytes: &[u8]) -> Result<Mesh, ErrParseStl> {
+    if is_binary(bytes) {
+        return Ok(Mesh::from_triangles(&parse_binary(bytes)));
+    }
+    match std::str::from_utf8(bytes) {
+        // The padding in a binary header is almost always zeroes, which text never has.
+        Ok(s) if s.trim_start().starts_with("solid") && !s.contains('\0') => {
+            Ok(Mesh::from_triangles(&parse_ascii(s)?))
+        }
+        _ if bytes.len() >= HEADER_LEN => Err(ErrParseStl::Truncated),
+        _ => Err(ErrParseStl::NotStl),
+    }
+}
+
+/// Reads and parses an STL file. See [`parse_stl`].
+pub fn read_stl(path: impl AsRef<Path>) -> Result<Mesh, ErrParseStl> {
+    parse_stl(&std::fs::read(path).map_err(|e| ErrParseStl::Io(e.to_string()))?)
+}
+
+fn is_binary(bytes: &[u8]) -> bool {
+    if bytes.len() < HEADER_LEN {
+        return false;
+    }
+    let count = u32::from_le_bytes(bytes[80..84].try_into().unwrap()) as usize;
+    count
+        .checked_mul(TRIANGLE_LEN)
+        .and_then(|n| n.checked_add(HEADER_LEN))
+        == Some(bytes.len())
+}
+
+/// Reads the triangles of a binary STL file, whose size has already been checked.
+fn parse_binary(bytes: &[u8]) -> Vec<[Point; 3]> {
+    let f32_at = |i: usize| f32::from_le_bytes(bytes[i..i + 4].try_into().unwrap()) as f64;
+    bytes[HEADER_LEN..]
+        .chunks_exact(TRIANGLE_LEN)
+        .enumerate()
+        .map(|(n, _)| {
+            // Skip the normal, which takes the first twelve bytes.
+            let start = HEADER_LEN + n * TRIANGLE_LEN + 12;
+            [0, 1, 2].map(|v| {
+                let i = start + v * 12;
+                Point::new(f32_at(i), f32_at(i + 4), f32_at(i + 8))
+            })
+        })
+        .collect()
+}
+
+fn parse_ascii(s: &str) -> Result<Vec<[Point; 3]>, ErrParseStl> {
+    let mut triangles = vec![];
+    let mut corners: Vec<Point> = vec![];
+    let mut words = s.split_whitespace();
+    while let Some(w) = words.next() {
+        match w {
+            "facet" => corners.clear(),
+            "vertex" => {
+                let mut coord = || -> Result<f64, ErrParseStl> {
+                    let w = words.next().ok_or(ErrParseStl::Truncated)?;
+                    w.parse()
+                        .map_err(|_| ErrParseStl::InvalidNumber(w.to_string()))
+                };
+                corners.push(Point::new(coord()?, coord()?, coord()?));
+            }
+            "endfacet" => match corners[..] {
+                [a, b, c] => triangles.push([a, b, c]),
+                _ => return Err(ErrParseStl::InvalidFacet(triangles.len())),
+            },
+            _ => {}
+        }
+    }
+    Ok(triangles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_stl, ErrParseStl};
+    use crate::light::Material;
+    use crate::p;
+
+    const ASCII: &str = "solid roof
+  facet normal 0 0 0
+    outer loop
+      vertex 0 0 0
+      vertex 0 0 1
+      vertex -1 -1 0
+    endloop
+  endfacet
+  facet normal 0 0 0
+    outer loop
+      vertex 0 0 0
+      vertex 1 -1 0
+      vertex 0 0 1.0e0
+    endloop
+  endfacet
+endsolid roof
+";
+
+    fn binary(triangles: &[[[f32; 3]; 3]]) -> Vec<u8> {
+        // Binary files often start with "solid" too, to trip up naive readers.
+        let mut bytes = b"solid roof".to_vec();
+        bytes.resize(80, 0);
+        bytes.extend((triangles.len() as u32).to_le_bytes());
+        for tri in triangles {
+            bytes.extend([0u8; 12]);
+            for c in tri.iter().flatten() {
+                bytes.extend(c.to_le_bytes());
+            }
+            bytes.extend([0u8; 2]);
+        }
+        bytes
+    }
+
+    #[test]
+    fn ascii_stl() {
+        let m = parse_stl(ASCII.as_bytes()).unwrap();
+        assert_eq!(m.vertices().len(), 4);
+        assert_eq!(m.faces(), &[[0, 1, 2], [0, 3, 1]]);
+        assert_eq!(m.vertices()[3], p!(1, -1, 0));
+        assert_eq!(m.to_group(&Material::default()).children().len(), 2);
+    }
+
+    #[test]
+    fn binary_stl() {
+        let bytes = binary(&[
+            [[0.0, 0.0, 0.0], [0.0, 0.0, 1.0], [-1.0, -1.0, 0.0]],
+            [[0.0, 0.0, 0.0], [1.0, -1.0, 0.0], [0.0, 0.0, 1.0]],
+        ]);
+        let m = parse_stl(&bytes).unwrap();
+        assert_eq!(m, parse_stl(ASCII.as_bytes()).unwrap());
+    }
+
+    #[test]
+    fn bad_stl_files() {
+        assert_eq!(parse_stl(b"hello"), Err(ErrParseStl::NotStl));
+        let mut bytes = binary(&[[[0.0; 3]; 3]]);
+        bytes.pop();
+        assert_eq!(parse_stl(&bytes), Err(ErrParseStl::Truncated));
+        let two_corners = ASCII.replace("      vertex -1 -1 0\n", "");
+        assert_eq!(
+            parse_stl(two_corners.as_bytes()),
+            Err(ErrParseStl::InvalidFacet(0))
+        );
+        let typo = ASCII.replace("1.0e0", "1.O");
+        assert_eq!(
+            parse_stl(typo.as_bytes()),
+            Err(ErrParseStl::InvalidNumber("1.O".to_string()))
+        );
+    }
+}
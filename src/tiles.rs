@@ -0,0 +1,208 @@
+/// A rectangular block of pixels, rendered as one unit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tile {
+    /// Left edge, in pixels.
+    pub x: usize,
+    /// Top edge, in pixels.
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// The order in which tiles get rendered. When the image is shown while rendering, this decides
+/// what the user sees first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TileOrder {
+    /// Left to right, top to bottom.
+    #[default]
+    Scanline,
+    /// Starts at the middle tile and winds outwards.
+    Spiral,
+    /// Nearest to the middle of the image first.
+    CenterOut,
+    /// Along a Hilbert curve, which keeps consecutive tiles next to each other.
+    Hilbert,
+}
+
+/// Splits an image into tiles of at most `size` pixels square, in the given order. Tiles along
+/// the right and bottom edges are cut short to fit the image.
+pub fn tiles(width: usize, height: usize, size: usize, order: TileOrder) -> Vec<Tile> {
+    if width == 0 || height == 0 {
+        return vec![];
+    }
+    let size = size.max(1);
+    let (cols, rows) = (width.div_ceil(size), height.div_ceil(size));
+    let tile = |(c, r): (usize, usize)| Tile {
+        x: c * size,
+        y: r * size,
+        width: size.min(width - c * size),
+        height: size.min(height - r * size),
+    };
+
+    let mut cells: Vec<(usize, usize)> = (0..rows)
+        .flat_map(|r| (0..cols).map(move |c| (c, r)))
+        .collect();
+    match order {
+        TileOrder::Scanline => {}
+        TileOrder::CenterOut => {
+            // Measure in half tiles so that everything stays an integer.
+            let (mx, my) = (cols as i64 - 1, rows as i64 - 1);
+            cells.sort_by_key(|&(c, r)| {
+                let (dx, dy) = (2 * c as i64 - mx, 2 * r as i64 - my);
+                dx * dx + dy * dy
+            });
+        }
+        TileOrder::Spiral => cells = spiral(cols, rows),
+        TileOrder::Hilbert => {
+            let n = cols.max(rows).next_power_of_two();
+            cells.sort_by_key(|&(c, r)| hilbert_index(n, c, r));
+        }
+    }
+    cells.into_iter().map(tile).collect()
+}
+
+/// Walks a square spiral out from the middle cell of a grid, keeping only the cells inside it.
+fn spiral(cols: usize, rows: usize) -> Vec<(usize, usize)> {
+    let total = cols * rows;
+    let mut res = Vec::with_capacity(total);
+    let (mut c, mut r) = (((cols - 1) / 2) as i64, ((rows - 1) / 2) as i64);
+    let push = |c: i64, r: i64, res: &mut Vec<(usize, usize)>| {
+        if (0..cols as i64).contains(&c) && (0..rows as i64).contains(&r) {
+            res.push((c as usize, r as usize));
+        }
+    };
+    push(c, r, &mut res);
+    // Right, down, left, up; the legs grow by one every two turns.
+    let dirs = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+    let mut leg = 1;
+    'walk: loop {
+        for (i, (dc, dr)) in dirs.iter().enumerate() {
+            for _ in 0..leg {
+                if res.len() == total {
+                    break 'walk;
+                }
+                c += dc;
+                r += dr;
+                push(c, r, &mut res);
+            }
+            if i % 2 == 1 {
+                leg += 1;
+            }
+        }
+    }
+    res
+}
+
+/// The position of (x, y) along a Hilbert curve filling an n by n grid, n being a power of two.
+fn hilbert_index(n: usize, mut x: usize, mut y: usize) -> usize {
+    let mut d = 0;
+    let mut s = n / 2;
+    while s > 0 {
+        let rx = usize::from(x & s > 0);
+        let ry = usize::from(y & s > 0);
+        d += s * s * ((3 * rx) ^ ry);
+        // Rotate the quadrant so that the curve lines up.
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - (x & (s - 1));
+                y = s - 1 - (y & (s - 1));
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        x &= s - 1;
+        y &= s - 1;
+        s /= 2;
+    }
+    d
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hilbert_index, tiles, Tile, TileOrder};
+    use std::collections::HashSet;
+
+    fn origins(ts: &[Tile]) -> Vec<(usize, usize)> {
+        ts.iter().map(|t| (t.x, t.y)).collect()
+    }
+
+    #[test]
+    fn tiles_cover_the_image_once() {
+        for order in [
+            TileOrder::Scanline,
+            TileOrder::Spiral,
+            TileOrder::CenterOut,
+            TileOrder::Hilbert,
+        ] {
+            let ts = tiles(50, 30, 16, order);
+            assert_eq!(ts.len(), 4 * 2);
+            let unique: HashSet<(usize, usize)> = origins(&ts).into_iter().collect();
+            assert_eq!(unique.len(), ts.len());
+            let area: usize = ts.iter().map(|t| t.width * t.height).sum();
+            assert_eq!(area, 50 * 30);
+        }
+        assert!(tiles(0, 10, 4, TileOrder::Spiral).is_empty());
+    }
+
+    #[test]
+    fn edge_tiles_are_cut_short() {
+        let ts = tiles(10, 5, 4, TileOrder::Scanline);
+        assert_eq!(
+            ts[2],
+            Tile {
+                x: 8,
+                y: 0,
+                width: 2,
+                height: 4
+            }
+        );
+        assert_eq!(
+            ts[5],
+            Tile {
+                x: 8,
+                y: 4,
+                width: 2,
+                height: 1
+            }
+        );
+    }
+
+    #[test]
+    fn spiral_starts_in_the_middle() {
+        let ts = tiles(3, 3, 1, TileOrder::Spiral);
+        let want = vec![
+            (1, 1),
+            (2, 1),
+            (2, 2),
+            (1, 2),
+            (0, 2),
+            (0, 1),
+            (0, 0),
+            (1, 0),
+            (2, 0),
+        ];
+        assert_eq!(origins(&ts), want);
+    }
+
+    #[test]
+    fn center_out_starts_in_the_middle() {
+        let ts = tiles(5, 5, 1, TileOrder::CenterOut);
+        assert_eq!((ts[0].x, ts[0].y), (2, 2));
+        let last = ts.last().unwrap();
+        assert!([0, 4].contains(&last.x) && [0, 4].contains(&last.y));
+    }
+
+    #[test]
+    fn hilbert_curve_moves_one_step_at_a_time() {
+        assert_eq!(
+            (0..4)
+                .map(|i| hilbert_index(2, i % 2, i / 2))
+                .collect::<Vec<_>>(),
+            vec![0, 3, 1, 2]
+        );
+        let ts = tiles(8, 8, 1, TileOrder::Hilbert);
+        for w in ts.windows(2) {
+            let d = w[0].x.abs_diff(w[1].x) + w[0].y.abs_diff(w[1].y);
+            assert_eq!(d, 1);
+        }
+    }
+}
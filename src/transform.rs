@@ -2,6 +2,7 @@ use serde::Deserialize;
 
 use crate::matrix::Matrix;
 use crate::tuple::{Point, Tuple, Vector};
+use std::collections::HashMap;
 use std::ops;
 
 fn translation(x: f64, y: f64, z: f64) -> Matrix<4, 4> {
@@ -85,6 +86,15 @@ impl ops::Mul<Vector> for Matrix<4, 4> {
     }
 }
 
+/// Represents some problem with constructing a transformation.
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    /// When the bottom row of a matrix is not (0, 0, 0, 1), so it cannot be an affine
+    /// transformation.
+    NotAffine,
+    Uninvertible,
+}
+
 /// A transformation.
 #[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
 #[serde(try_from = "crate::yaml::TransformRepr")]
@@ -105,6 +115,17 @@ impl Tr {
         self
     }
 
+    /// Wraps a matrix as a transformation. The matrix is not checked in any way; use `try_from`
+    /// if it comes from somewhere untrusted.
+    pub fn from_matrix(m: Matrix<4, 4>) -> Self {
+        Self(m)
+    }
+
+    /// Creates a transformation from the rows of its matrix.
+    pub fn from_rows(rows: [[f64; 4]; 4]) -> Self {
+        Self(Matrix::new(rows))
+    }
+
     pub fn translate(self, x: f64, y: f64, z: f64) -> Self {
         Self(translation(x, y, z) * self.0)
     }
@@ -133,19 +154,177 @@ impl Tr {
         Self(shearing(xy, xz, yx, yz, zx, zy) * self.0)
     }
 
-    // TODO maybe return Result instead of panicking.
+    /// Rotates about an axis parallel to the x axis, which passes through the point (cx, cy, cz).
+    pub fn rotate_x_about(self, rad: f64, cx: f64, cy: f64, cz: f64) -> Self {
+        self.translate(-cx, -cy, -cz)
+            .rotate_x(rad)
+            .translate(cx, cy, cz)
+    }
+
+    /// Rotates about an axis parallel to the y axis, which passes through the point (cx, cy, cz).
+    pub fn rotate_y_about(self, rad: f64, cx: f64, cy: f64, cz: f64) -> Self {
+        self.translate(-cx, -cy, -cz)
+            .rotate_y(rad)
+            .translate(cx, cy, cz)
+    }
+
+    /// Rotates about an axis parallel to the z axis, which passes through the point (cx, cy, cz).
+    pub fn rotate_z_about(self, rad: f64, cx: f64, cy: f64, cz: f64) -> Self {
+        self.translate(-cx, -cy, -cz)
+            .rotate_z(rad)
+            .translate(cx, cy, cz)
+    }
+
+    /// Scales such that the point (cx, cy, cz) stays where it is.
+    pub fn scale_about(self, x: f64, y: f64, z: f64, cx: f64, cy: f64, cz: f64) -> Self {
+        self.translate(-cx, -cy, -cz)
+            .scale(x, y, z)
+            .translate(cx, cy, cz)
+    }
+
+    /// Inverts this transformation.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the transformation is singular, e.g. when something is scaled by zero. Use
+    /// `try_inverse` when the transformation comes from user input.
     pub fn inverse(&self) -> Tr {
-        Tr(self.0.inverse().unwrap())
+        self.try_inverse()
+            .expect("transformation should be invertible")
+    }
+
+    /// Inverts this transformation, failing if it is singular.
+    pub fn try_inverse(&self) -> Result<Tr, Error> {
+        self.0.inverse().map(Tr).map_err(|_| Error::Uninvertible)
     }
 
     pub fn matrix(&self) -> Matrix<4, 4> {
         self.0
     }
 
+    /// The most this transformation stretches any of the axes, e.g. 2 for a uniform scale by 2.
+    /// Translations and rotations don't stretch anything.
+    pub fn max_scale(&self) -> f64 {
+        (0..3)
+            .map(|j| (0..3).map(|i| self.0.get(i, j).powi(2)).sum::<f64>().sqrt())
+            .fold(0.0, f64::max)
+    }
+
     /// Applies another transformation to the current one.
     pub fn and(self, other: Tr) -> Tr {
         Tr(other.0 * self.0)
     }
+
+    /// Applies another transformation to the current one, as if the pivot were the origin. The
+    /// pivot stays where it is, so rotating about the pivot swings things around it, like a
+    /// joint.
+    pub fn and_about(self, other: Tr, pivot: Point) -> Tr {
+        self.translate(-pivot.x(), -pivot.y(), -pivot.z())
+            .and(other)
+            .translate(pivot.x(), pivot.y(), pivot.z())
+    }
+}
+
+/// Represents some problem with looking up transformations in a [`TransformLibrary`].
+#[derive(Debug, PartialEq)]
+pub enum ErrTransformLibrary {
+    /// When no transformation has been defined with some name.
+    Unknown(String),
+}
+
+impl std::fmt::Display for ErrTransformLibrary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unknown(name) => write!(f, "Unknown transformation; name={name}"),
+        }
+    }
+}
+
+/// Transformations which are defined once by name and then used anywhere, like the `transforms`
+/// section of a YAML scene. Definitions may be built out of earlier ones, so a rig such as an
+/// articulated lamp can be put together joint by joint without repeating matrix chains.
+///
+/// Chains of names are applied in order, so the first transformation in a chain is applied
+/// first.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TransformLibrary {
+    defs: HashMap<String, Tr>,
+}
+
+impl TransformLibrary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Defines a transformation, replacing any earlier one with the same name. Definitions
+    /// already built out of the earlier one keep it.
+    pub fn define(&mut self, name: &str, t: Tr) {
+        self.defs.insert(name.to_string(), t);
+    }
+
+    pub fn with_definition(mut self, name: &str, t: Tr) -> Self {
+        self.define(name, t);
+        self
+    }
+
+    /// Defines a transformation as a chain of others, which must already be defined.
+    pub fn define_chain(&mut self, name: &str, chain: &[&str]) -> Result<(), ErrTransformLibrary> {
+        let t = self.compose(chain)?;
+        self.define(name, t);
+        Ok(())
+    }
+
+    /// Defines a transformation as another one applied about a pivot; see [`Tr::and_about`].
+    pub fn define_about(
+        &mut self,
+        name: &str,
+        of: &str,
+        pivot: Point,
+    ) -> Result<(), ErrTransformLibrary> {
+        let t = Tr::new().and_about(self.get(of)?, pivot);
+        self.define(name, t);
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Result<Tr, ErrTransformLibrary> {
+        self.defs
+            .get(name)
+            .copied()
+            .ok_or_else(|| ErrTransformLibrary::Unknown(name.to_string()))
+    }
+
+    /// Composes a chain of named transformations into one.
+    pub fn compose(&self, chain: &[&str]) -> Result<Tr, ErrTransformLibrary> {
+        chain
+            .iter()
+            .try_fold(Tr::new(), |acc, name| Ok(acc.and(self.get(name)?)))
+    }
+
+    /// The names of every transformation defined so far, in no particular order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.defs.keys().map(|k| k.as_str())
+    }
+}
+
+impl std::fmt::Display for Tr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl TryFrom<[[f64; 4]; 4]> for Tr {
+    type Error = Error;
+
+    /// Creates a transformation from the rows of its matrix, checking that the matrix is actually
+    /// an invertible affine transformation.
+    fn try_from(rows: [[f64; 4]; 4]) -> Result<Self, Self::Error> {
+        if rows[3] != [0.0, 0.0, 0.0, 1.0] {
+            return Err(Error::NotAffine);
+        }
+        let t = Self(Matrix::new(rows));
+        t.try_inverse()?;
+        Ok(t)
+    }
 }
 
 /// Creates a transformation for the camera.
@@ -168,10 +347,56 @@ mod tests {
     use std::f64::consts::PI;
 
     use super::{
-        rotation_x, rotation_y, rotation_z, scaling, shearing, translation, view_transform, Tr,
+        rotation_x, rotation_y, rotation_z, scaling, shearing, translation, view_transform,
+        ErrTransformLibrary, Error, Tr, TransformLibrary,
     };
     use crate::matrix::Matrix;
     use crate::tuple::{Point, Vector};
+    use crate::{assert_f64_eq, p, EPSILON};
+    use std::f64::consts::FRAC_PI_4;
+
+    #[test]
+    fn measuring_how_much_a_transformation_stretches() {
+        assert_eq!(Tr::new().max_scale(), 1.0);
+        let t = Tr::new()
+            .scale(0.5, 3.0, 2.0)
+            .rotate_y(FRAC_PI_4)
+            .translate(10.0, 0.0, -4.0);
+        assert_f64_eq!(t.max_scale(), 3.0, EPSILON);
+    }
+
+    #[test]
+    fn rigging_a_lamp_by_name() {
+        // The arm stands on the base, and bends at a joint one unit up. The head bends at the top
+        // of the arm, two units up.
+        let mut lib = TransformLibrary::new()
+            .with_definition("bend", Tr::new().rotate_z(FRAC_PI_4))
+            .with_definition("base", Tr::new().translate(5.0, 0.0, 0.0));
+        lib.define_about("elbow", "bend", p!(0, 1, 0)).unwrap();
+        lib.define_about("wrist", "bend", p!(0, 2, 0)).unwrap();
+        lib.define_chain("arm", &["elbow", "base"]).unwrap();
+        lib.define_chain("head", &["wrist", "elbow", "base"])
+            .unwrap();
+
+        let tip = lib.get("arm").unwrap().matrix() * p!(0, 2, 0);
+        assert_f64_eq!(tip.x(), 5.0 - FRAC_PI_4.sin(), EPSILON);
+        assert_f64_eq!(tip.y(), 1.0 + FRAC_PI_4.cos(), EPSILON);
+
+        // The head's joint moves with the arm, and the head bends a further 45 degrees there.
+        let head = lib.get("head").unwrap();
+        let joint = head.matrix() * p!(0, 2, 0);
+        assert_f64_eq!(joint.x(), tip.x(), EPSILON);
+        assert_f64_eq!(joint.y(), tip.y(), EPSILON);
+        let end = head.matrix() * p!(0, 3, 0);
+        assert_f64_eq!(end.x(), tip.x() - 1.0, EPSILON);
+        assert_f64_eq!(end.y(), tip.y(), EPSILON);
+
+        assert_eq!(lib.names().count(), 6);
+        assert_eq!(
+            lib.compose(&["arm", "leg"]),
+            Err(ErrTransformLibrary::Unknown("leg".to_string()))
+        );
+    }
 
     #[test]
     fn multiply_by_translation_matrix() {
@@ -424,4 +649,95 @@ mod tests {
         ]);
         assert_eq!(got.0, want);
     }
+
+    #[test]
+    fn rotating_about_a_point() {
+        let t = Tr::new().rotate_y_about(PI / 2.0, 1.0, 0.0, 1.0);
+        assert_eq!(
+            t.matrix() * Point::new(1.0, 5.0, 1.0),
+            Point::new(1.0, 5.0, 1.0)
+        );
+        assert_eq!(
+            t.matrix() * Point::new(1.0, 0.0, 2.0),
+            Point::new(2.0, 0.0, 1.0)
+        );
+
+        let t = Tr::new().rotate_x_about(PI / 2.0, 0.0, 1.0, 0.0);
+        assert_eq!(
+            t.matrix() * Point::new(0.0, 2.0, 0.0),
+            Point::new(0.0, 1.0, 1.0)
+        );
+
+        let t = Tr::new().rotate_z_about(PI / 2.0, 1.0, 0.0, 0.0);
+        assert_eq!(
+            t.matrix() * Point::new(2.0, 0.0, 0.0),
+            Point::new(1.0, 1.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn scaling_about_a_point() {
+        let t = Tr::new().scale_about(2.0, 3.0, 4.0, 1.0, 1.0, 1.0);
+        assert_eq!(
+            t.matrix() * Point::new(1.0, 1.0, 1.0),
+            Point::new(1.0, 1.0, 1.0)
+        );
+        assert_eq!(
+            t.matrix() * Point::new(2.0, 2.0, 2.0),
+            Point::new(3.0, 4.0, 5.0)
+        );
+    }
+
+    #[test]
+    fn transformation_from_rows() {
+        let rows = [
+            [1.0, 0.0, 0.0, 5.0],
+            [0.0, 1.0, 0.0, -3.0],
+            [0.0, 0.0, 1.0, 2.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ];
+        let want = Tr::new().translate(5.0, -3.0, 2.0);
+        assert_eq!(Tr::from_rows(rows), want);
+        assert_eq!(Tr::from_matrix(Matrix::new(rows)), want);
+        assert_eq!(Tr::try_from(rows), Ok(want));
+    }
+
+    #[test]
+    fn transformation_from_invalid_rows() {
+        let rows = [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [1.0, 0.0, 0.0, 1.0],
+        ];
+        assert_eq!(Tr::try_from(rows), Err(Error::NotAffine));
+
+        let rows = [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 0.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ];
+        assert_eq!(Tr::try_from(rows), Err(Error::Uninvertible));
+    }
+
+    #[test]
+    fn display_transformation() {
+        let t = Tr::new().translate(1.0, -2.0, 10.0);
+        let want = "\
+[1.00000  0.00000  0.00000   1.00000]
+[0.00000  1.00000  0.00000  -2.00000]
+[0.00000  0.00000  1.00000  10.00000]
+[0.00000  0.00000  0.00000   1.00000]";
+        assert_eq!(t.to_string(), want);
+    }
+
+    #[test]
+    fn checked_inverse() {
+        let t = Tr::new().translate(1.0, 2.0, 3.0);
+        assert_eq!(t.try_inverse(), Ok(Tr::new().translate(-1.0, -2.0, -3.0)));
+
+        let t = Tr::new().scale(1.0, 0.0, 1.0);
+        assert_eq!(t.try_inverse(), Err(Error::Uninvertible));
+    }
 }
@@ -1,6 +1,7 @@
+use serde::{Deserialize, Serialize};
 use std::ops;
 
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
 pub struct Tuple(pub f64, pub f64, pub f64, pub f64);
 
 impl PartialEq for Tuple {
@@ -80,7 +81,7 @@ impl ops::Div<f64> for Tuple {
     }
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub struct Point(pub Tuple);
 
 impl Point {
@@ -147,7 +148,7 @@ impl From<(f64, f64, f64)> for Point {
     }
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub struct Vector(Tuple);
 
 impl Vector {
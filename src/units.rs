@@ -0,0 +1,82 @@
+use crate::transform::Tr;
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+/// The unit of length a scene or model is measured in. Scenes default to meters, and anything
+/// measured in other units is scaled to match when it is brought into a scene.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Unit {
+    #[default]
+    #[serde(alias = "m")]
+    Meters,
+    #[serde(alias = "cm")]
+    Centimeters,
+    #[serde(alias = "mm")]
+    Millimeters,
+    #[serde(alias = "in")]
+    Inches,
+    #[serde(alias = "ft")]
+    Feet,
+}
+
+impl Display for Unit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Unit::Meters => "m",
+            Unit::Centimeters => "cm",
+            Unit::Millimeters => "mm",
+            Unit::Inches => "in",
+            Unit::Feet => "ft",
+        };
+        write!(f, "{s}")
+    }
+}
+
+impl Unit {
+    /// How many meters one of this unit is.
+    pub fn meters(&self) -> f64 {
+        match self {
+            Unit::Meters => 1.0,
+            Unit::Centimeters => 0.01,
+            Unit::Millimeters => 0.001,
+            Unit::Inches => 0.0254,
+            Unit::Feet => 0.3048,
+        }
+    }
+
+    /// How many of another unit one of this unit is, e.g. 100 from meters to centimeters.
+    pub fn scale_to(&self, to: Unit) -> f64 {
+        self.meters() / to.meters()
+    }
+
+    /// The transformation which takes something measured in this unit to the same size in
+    /// another unit.
+    pub fn conversion_to(&self, to: Unit) -> Tr {
+        let k = self.scale_to(to);
+        Tr::new().scale(k, k, k)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Unit;
+    use crate::p;
+
+    #[test]
+    fn converting_between_units() {
+        assert_eq!(Unit::Meters.scale_to(Unit::Centimeters), 100.0);
+        assert_eq!(Unit::Millimeters.scale_to(Unit::Millimeters), 1.0);
+        assert!((Unit::Feet.scale_to(Unit::Inches) - 12.0).abs() < 1e-12);
+
+        let p = Unit::Centimeters.conversion_to(Unit::Meters).matrix() * p!(150, 0, -20);
+        assert_eq!(p, p!(1.5, 0, -0.2));
+
+        let units: Vec<Unit> = serde_yaml::from_str("[ meters, cm, inches, ft ]").unwrap();
+        assert_eq!(
+            units,
+            vec![Unit::Meters, Unit::Centimeters, Unit::Inches, Unit::Feet]
+        );
+        assert_eq!(Unit::Millimeters.to_string(), "mm");
+    }
+}
@@ -1,13 +1,166 @@
 use crate::color::Color;
-use crate::light::{is_shadowed, lighting, reflected_color, refracted_color, Material, PointLight};
-use crate::ray::{hit, schlick, IntersectionVals, Ray};
-use crate::shapes::{Object, Sphere};
+use crate::decal::Decal;
+use crate::deep::DeepSample;
+use crate::light::{
+    is_shadowed, lighting, reflected_color, refracted_color, Material, PointLight, ShadingInput,
+};
+use crate::ray::{self, schlick, Intersection, IntersectionVals, Ray};
+use crate::rng::{point_seed, Rng};
+use crate::settings::{EnergyAudit, HitSearch, RenderSettings, Transparency};
+use crate::shapes::{Csg, Group, Instance, Object, Plane, Sphere};
 use crate::transform::Tr;
-use crate::tuple::Point;
+use crate::tuple::{Point, Vector};
+use crate::units::Unit;
+use crate::{v, EPSILON};
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Debug, Display};
 
+#[derive(Clone)]
 pub struct World {
     pub light: Option<PointLight>,
     pub objects: Vec<Object>,
+    pub settings: RenderSettings,
+    /// Named groups of objects, by their IDs, which can be rendered on their own.
+    pub passes: HashMap<String, HashSet<usize>>,
+    /// IDs of objects which block whatever is behind them, but show up as black themselves.
+    pub holdouts: HashSet<usize>,
+    /// Images and patterns projected onto the objects, applied in order.
+    pub decals: Vec<Decal>,
+    /// IDs of objects which are invisible themselves, but show the shadows and reflections which
+    /// fall on them, for compositing renders over photographs.
+    pub catchers: HashSet<usize>,
+    /// Labels on objects, by their IDs, which pick out variants of a scene to render.
+    pub tags: HashMap<String, HashSet<usize>>,
+    /// The unit of length everything in the world is measured in.
+    pub units: Unit,
+}
+
+/// Sums up the world rather than printing every object in full, since a single mesh can run to
+/// thousands of lines. Each object is listed with its ID, name and material.
+impl Debug for World {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("World")
+            .field("light", &self.light)
+            .field("objects", &ObjectSummaries(&self.objects))
+            .field("settings", &self.settings)
+            .field("passes", &self.passes)
+            .field("holdouts", &self.holdouts)
+            .field("catchers", &self.catchers)
+            .field("tags", &self.tags)
+            .field("units", &self.units)
+            .field("decals", &self.decals.len())
+            .finish()
+    }
+}
+
+/// A summary of the world, with the light and one line for each object, e.g.
+///
+/// ```text
+/// world with 2 objects (1 named) in 2 materials
+/// light 255 255 255 at (-10, 10, -10)
+///   #3 "lamp": color 255 255 255, ambient 1, diffuse 0, specular 0
+///   #4: color 255 255 255, ambient 0.1, diffuse 0.9, specular 0.9, reflective 0.5
+/// ```
+impl Display for World {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let named = self.objects.iter().filter(|o| o.name().is_some()).count();
+        let mut materials: Vec<Material> = vec![];
+        for o in &self.objects {
+            let m = o.material();
+            if !materials.contains(&m) {
+                materials.push(m);
+            }
+        }
+        let plural = |n: usize| if n == 1 { "" } else { "s" };
+        writeln!(
+            f,
+            "world with {} object{} ({named} named) in {} material{}",
+            self.objects.len(),
+            plural(self.objects.len()),
+            materials.len(),
+            plural(materials.len())
+        )?;
+        match &self.light {
+            Some(light) => write!(f, "{light}")?,
+            None => write!(f, "no light")?,
+        }
+        for o in &self.objects {
+            write!(f, "\n  #{}", o.id())?;
+            if let Some(name) = o.name() {
+                write!(f, " {name:?}")?;
+            }
+            write!(f, ": {}", o.material())?;
+        }
+        Ok(())
+    }
+}
+
+/// Lists objects by their ID, name, bounds and material, for [`World`]'s [`Debug`].
+struct ObjectSummaries<'a>(&'a [Object]);
+
+impl Debug for ObjectSummaries<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries(self.0.iter().map(ObjectSummary))
+            .finish()
+    }
+}
+
+struct ObjectSummary<'a>(&'a Object);
+
+impl Debug for ObjectSummary<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Object")
+            .field("id", &self.0.id())
+            .field("name", &self.0.name())
+            .field("bounds", &self.0.bounds())
+            .field("material", &format_args!("{}", self.0.material()))
+            .finish()
+    }
+}
+
+/// What happens to objects outside of the render pass being rendered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PassMode {
+    /// Leave them out of the world entirely.
+    Hide,
+    /// Turn them into holdout mattes, which cut a black hole in the pass wherever they would
+    /// cover it.
+    Holdout,
+}
+
+/// Represents some problem with placing an object in a world.
+#[derive(Debug, PartialEq)]
+pub enum ErrPlacement {
+    /// When no object in the world has the given ID.
+    UnknownObject(usize),
+    /// When the object goes on forever, like a plane, and so can't be moved onto anything.
+    Unbounded(usize),
+    /// When there is nothing for the object to land on.
+    NothingBelow(usize),
+}
+
+impl Display for ErrPlacement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use ErrPlacement::*;
+        match self {
+            UnknownObject(id) => write!(f, "No such object in world; id={id}"),
+            Unbounded(id) => write!(f, "Object is unbounded and can't be placed; id={id}"),
+            NothingBelow(id) => write!(f, "Nothing below object to place it on; id={id}"),
+        }
+    }
+}
+
+/// A single term of the shading, rendered on its own so that a compositor can adjust it over the
+/// beauty pass without rendering again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShadingPass {
+    /// How much of its direct light each surface keeps, to be multiplied over the beauty pass.
+    /// White wherever nothing is in shadow.
+    Shadow,
+    /// The light reflected off each surface, to be added over the beauty pass. Black wherever
+    /// nothing is reflected.
+    Reflection,
 }
 
 impl World {
@@ -16,6 +169,13 @@ impl World {
         Self {
             light: None,
             objects: Vec::new(),
+            settings: RenderSettings::default(),
+            passes: HashMap::new(),
+            holdouts: HashSet::new(),
+            decals: Vec::new(),
+            catchers: HashSet::new(),
+            tags: HashMap::new(),
+            units: Unit::default(),
         }
     }
 
@@ -24,6 +184,53 @@ impl World {
         self
     }
 
+    pub fn with_settings(mut self, settings: RenderSettings) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    /// Declares the unit this world is measured in. Nothing in the world is scaled; see
+    /// [`World::merge`] for bringing in worlds measured in other units.
+    pub fn with_units(mut self, units: Unit) -> Self {
+        self.units = units;
+        self
+    }
+
+    /// Brings everything in another world into this one: its objects, decals, passes, tags,
+    /// holdouts and shadow catchers. Objects keep their IDs. If the other world is measured in
+    /// different units, its contents are scaled to match this one's, about the origin. This
+    /// world keeps its own light and settings.
+    pub fn merge(&mut self, other: World) {
+        let k = other.units.scale_to(self.units);
+        let scale = other.units.conversion_to(self.units);
+        self.objects.extend(other.objects.into_iter().map(|obj| {
+            if k == 1.0 {
+                obj
+            } else {
+                Instance::in_place_of(obj).with_transform(scale).as_object()
+            }
+        }));
+        self.decals.extend(other.decals.into_iter().map(|d| {
+            let t = d.transform().and(scale);
+            d.with_transform(t)
+        }));
+        for (mine, theirs) in [
+            (&mut self.passes, other.passes),
+            (&mut self.tags, other.tags),
+        ] {
+            for (name, ids) in theirs {
+                mine.entry(name).or_default().extend(ids);
+            }
+        }
+        self.holdouts.extend(other.holdouts);
+        self.catchers.extend(other.catchers);
+    }
+
+    pub fn with_decal(mut self, decal: Decal) -> Self {
+        self.decals.push(decal);
+        self
+    }
+
     pub fn add_objects(&mut self, mut objects: Vec<Object>) {
         self.objects.append(&mut objects);
     }
@@ -41,6 +248,333 @@ impl World {
         self
     }
 
+    /// Finds an object by its name, looking inside groups too. If several objects have the name,
+    /// the first one found is given.
+    pub fn object_named(&self, name: &str) -> Option<&Object> {
+        self.objects_named(name).into_iter().next()
+    }
+
+    /// Finds every object with some name, looking inside groups too.
+    pub fn objects_named(&self, name: &str) -> Vec<&Object> {
+        fn find<'a>(objects: &'a [Object], name: &str, res: &mut Vec<&'a Object>) {
+            for obj in objects {
+                if obj.name() == Some(name) {
+                    res.push(obj);
+                }
+                if let Some(g) = obj.as_any().downcast_ref::<Group>() {
+                    find(g.children(), name, res);
+                }
+            }
+        }
+        let mut res = vec![];
+        find(&self.objects, name, &mut res);
+        res
+    }
+
+    /// Finds the transformation which takes the object with some ID from its own object space to
+    /// world space, through every group, instance and CSG it is in. Gives None if there is no such
+    /// object.
+    pub fn transform_of(&self, id: usize) -> Option<Tr> {
+        self.transform_along(&[], id)
+    }
+
+    /// Like [`World::transform_of`], but only for the copy of the object found inside the shapes
+    /// with the given IDs, innermost first. This tells apart the copies of a shape placed by
+    /// several instances. Shapes on the way which aren't in the list are passed through.
+    pub(crate) fn transform_along(&self, path: &[usize], id: usize) -> Option<Tr> {
+        fn find<'a>(
+            objects: impl IntoIterator<Item = &'a Object>,
+            path: &[usize],
+            id: usize,
+            outer: Tr,
+        ) -> Option<Tr> {
+            for obj in objects {
+                let t = obj.transform().and(outer);
+                let rest = match path.split_last() {
+                    Some((&last, rest)) if last == obj.id() => rest,
+                    _ => path,
+                };
+                let any = obj.as_any();
+                let inner = if let Some(g) = any.downcast_ref::<Group>() {
+                    find(g.children(), rest, id, t)
+                } else if let Some(inst) = any.downcast_ref::<Instance>() {
+                    // An instance may share its object's ID, in which case the object is meant.
+                    find([inst.object()], rest, id, t)
+                } else if let Some(csg) = any.downcast_ref::<Csg>() {
+                    find([csg.left(), csg.right()], rest, id, t)
+                } else {
+                    None
+                };
+                if inner.is_some() {
+                    return inner;
+                }
+                if path.is_empty() && obj.id() == id {
+                    return Some(t);
+                }
+            }
+            None
+        }
+        find(&self.objects, path, id, Tr::default())
+    }
+
+    /// Moves an object along some direction until it rests on whatever is below it, and gives how
+    /// far it moved. An object sunk into whatever is below it is lifted out instead, and moves a
+    /// negative distance. The object keeps its ID, so it stays in any passes it was in.
+    ///
+    /// A grid of rays is cast through the object, so small bumps on either surface may be missed.
+    pub fn drop_to_surface(&mut self, id: usize, down: Vector) -> Result<f64, ErrPlacement> {
+        const GRID: usize = 25;
+        let idx = self
+            .objects
+            .iter()
+            .position(|o| o.id() == id)
+            .ok_or(ErrPlacement::UnknownObject(id))?;
+        let obj = self.objects[idx].clone();
+        let b = obj.bounds();
+        if b.is_infinite() {
+            return Err(ErrPlacement::Unbounded(id));
+        }
+
+        let down = down.normalize();
+        let side = if down.x().abs() < 0.9 {
+            v!(1, 0, 0)
+        } else {
+            v!(0, 1, 0)
+        };
+        let (u, w) = (
+            down.cross(side).normalize(),
+            down.cross(down.cross(side)).normalize(),
+        );
+        let radius = b.size().magnitude() / 2.0;
+        let step = |i: usize| radius * (2.0 * i as f64 / (GRID - 1) as f64 - 1.0);
+
+        let mut gap: Option<f64> = None;
+        for i in 0..GRID {
+            for j in 0..GRID {
+                let origin = b.center() + u * step(i) + w * step(j) - down * (radius + 1.0);
+                let r = Ray::new(origin, down);
+                let ts: Vec<f64> = obj.intersect_with(r).iter().map(|x| x.t()).collect();
+                let Some(enter) = ts.iter().copied().reduce(f64::min) else {
+                    continue;
+                };
+                let exit = ts.iter().copied().fold(enter, f64::max);
+                // Anything the ray meets before the object is above it, not below.
+                let below = self
+                    .objects
+                    .iter()
+                    .filter(|o| o.id() != id)
+                    .flat_map(|o| o.intersect_with(r))
+                    .map(|x| x.t())
+                    .filter(|t| *t >= enter)
+                    .reduce(f64::min);
+                if let Some(t) = below {
+                    gap = Some(gap.map_or(t - exit, |g| g.min(t - exit)));
+                }
+            }
+        }
+
+        let gap = gap.ok_or(ErrPlacement::NothingBelow(id))?;
+        let d = down * gap;
+        self.objects[idx] = Instance::in_place_of(obj)
+            .with_transform(Tr::new().translate(d.x(), d.y(), d.z()))
+            .as_object();
+        Ok(gap)
+    }
+
+    /// Puts an object into a render pass, creating the pass if need be. An object may be in any
+    /// number of passes.
+    pub fn add_to_pass(&mut self, pass: &str, obj: &Object) {
+        self.passes
+            .entry(pass.to_string())
+            .or_default()
+            .insert(obj.id());
+    }
+
+    pub fn with_pass(mut self, pass: &str, objects: &[Object]) -> Self {
+        for obj in objects {
+            self.add_to_pass(pass, obj);
+        }
+        self
+    }
+
+    /// Creates a copy of this world for rendering a single pass. Objects outside the pass are
+    /// hidden or held out, and a pass which doesn't exist has nothing in it.
+    pub fn pass(&self, pass: &str, mode: PassMode) -> World {
+        let empty = HashSet::new();
+        let members = self.passes.get(pass).unwrap_or(&empty);
+        let mut res = self.clone();
+        match mode {
+            PassMode::Hide => res.objects.retain(|obj| members.contains(&obj.id())),
+            PassMode::Holdout => res.holdouts.extend(
+                self.objects
+                    .iter()
+                    .map(|obj| obj.id())
+                    .filter(|id| !members.contains(id)),
+            ),
+        }
+        res
+    }
+
+    /// Checks whether two worlds hold the same scene, even if their objects were built separately
+    /// or listed in a different order. Objects are compared like shapes are, ignoring their IDs,
+    /// and passes, tags, holdouts and shadow catchers must pick out matching objects in both
+    /// worlds. Any of them which picks out an object that isn't in its world makes the worlds
+    /// differ.
+    pub fn approx_eq(&self, other: &World) -> bool {
+        if self.light != other.light
+            || self.settings != other.settings
+            || self.units != other.units
+            || self.decals != other.decals
+            || self.objects.len() != other.objects.len()
+        {
+            return false;
+        }
+        // Everything an object is in, so that objects are only paired up with equal ones which are
+        // in the same passes, tags, holdouts and catchers. Equal objects which are in the same
+        // things can be swapped for each other, so pairing them up greedily is enough.
+        fn memberships(w: &World, id: usize) -> Vec<(&str, &str)> {
+            let mut res: Vec<_> = [("pass", &w.passes), ("tag", &w.tags)]
+                .into_iter()
+                .flat_map(|(kind, sets)| {
+                    sets.iter()
+                        .filter(move |(_, ids)| ids.contains(&id))
+                        .map(move |(name, _)| (kind, name.as_str()))
+                })
+                .collect();
+            if w.holdouts.contains(&id) {
+                res.push(("holdout", ""));
+            }
+            if w.catchers.contains(&id) {
+                res.push(("catcher", ""));
+            }
+            res.sort();
+            res
+        }
+        let theirs: Vec<_> = other
+            .objects
+            .iter()
+            .map(|o| memberships(other, o.id()))
+            .collect();
+        // Pair every object up with an equal one in the other world, using each only once.
+        let mut ids = HashMap::new();
+        let mut used = vec![false; other.objects.len()];
+        for a in &self.objects {
+            let mine = memberships(self, a.id());
+            let Some(i) = (0..other.objects.len())
+                .find(|&i| !used[i] && theirs[i] == mine && **a == *other.objects[i])
+            else {
+                return false;
+            };
+            used[i] = true;
+            ids.insert(a.id(), other.objects[i].id());
+        }
+        let same = |a: &HashSet<usize>, b: &HashSet<usize>| {
+            a.len() == b.len()
+                && a.iter()
+                    .all(|id| ids.get(id).is_some_and(|id| b.contains(id)))
+        };
+        same(&self.holdouts, &other.holdouts)
+            && same(&self.catchers, &other.catchers)
+            && [(&self.passes, &other.passes), (&self.tags, &other.tags)]
+                .iter()
+                .all(|(mine, theirs)| {
+                    mine.len() == theirs.len()
+                        && mine
+                            .iter()
+                            .all(|(name, a)| theirs.get(name).is_some_and(|b| same(a, b)))
+                })
+    }
+
+    /// Labels an object with a tag. An object may have any number of tags.
+    pub fn add_tag(&mut self, tag: &str, obj: &Object) {
+        self.tags
+            .entry(tag.to_string())
+            .or_default()
+            .insert(obj.id());
+    }
+
+    pub fn with_tag(mut self, tag: &str, objects: &[Object]) -> Self {
+        for obj in objects {
+            self.add_tag(tag, obj);
+        }
+        self
+    }
+
+    /// Every tag some object has, in no particular order.
+    pub fn tags_of(&self, obj: &Object) -> Vec<&str> {
+        self.tags
+            .iter()
+            .filter(|(_, ids)| ids.contains(&obj.id()))
+            .map(|(tag, _)| tag.as_str())
+            .collect()
+    }
+
+    /// Creates a copy of this world with only some of its objects, picked by their tags. If any
+    /// tags are included, an object needs at least one of them to stay; with none included,
+    /// every object stays unless it has an excluded tag. Exclusions win over inclusions.
+    pub fn filtered(&self, include_tags: &[&str], exclude_tags: &[&str]) -> World {
+        let empty = HashSet::new();
+        let ids = |tags: &[&str]| -> Vec<&HashSet<usize>> {
+            tags.iter()
+                .map(|tag| self.tags.get(*tag).unwrap_or(&empty))
+                .collect()
+        };
+        let (include, exclude) = (ids(include_tags), ids(exclude_tags));
+        let mut res = self.clone();
+        res.objects.retain(|obj| {
+            let id = obj.id();
+            (include_tags.is_empty() || include.iter().any(|ids| ids.contains(&id)))
+                && !exclude.iter().any(|ids| ids.contains(&id))
+        });
+        res
+    }
+
+    /// Adds an object which only catches shadows and reflections. Its material still decides
+    /// whether it casts shadows, and how much it reflects.
+    pub fn with_shadow_catcher(mut self, obj: Object) -> Self {
+        self.catchers.insert(obj.id());
+        self.objects.push(obj);
+        self
+    }
+
+    /// Adds an infinite floor at some height which only catches shadows and reflections, for
+    /// compositing renders over a photographed ground.
+    pub fn with_matte_floor(self, y: f64) -> Self {
+        let floor = Plane::default()
+            .with_material(Material::default().with_casts_shadow(false))
+            .with_transform(Tr::new().translate(0.0, y, 0.0));
+        self.with_shadow_catcher(floor.as_object())
+    }
+
+    /// Finds what a shadow catcher shows at some intersection: the reflections on it, and how
+    /// opaque it is. In shadow, it is as opaque as the direct light it loses; elsewhere it is only
+    /// as opaque as its reflections are bright.
+    fn catch(&self, c: &IntersectionVals, limit: u16) -> (Color, f64) {
+        let reflected = reflected_color(self, c, limit);
+        let shadow = if is_shadowed(self, c.over_point) {
+            1.0 - c.object.material().ambient().clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        let glint = reflected.luminance().clamp(0.0, 1.0);
+        (reflected, 1.0 - (1.0 - shadow) * (1.0 - glint))
+    }
+
+    /// Like [`World::color_at`], but also finds the alpha of whatever the ray hits. Colors are
+    /// premultiplied by their alpha. Rays which hit nothing are fully transparent, and so are
+    /// shadow catchers, apart from their shadows and reflections.
+    pub fn color_and_alpha_at(&self, r: Ray) -> (Color, f64) {
+        let intersections = r.when_intersect_world(self);
+        let Some(i) = intersections.hit_for_camera() else {
+            return (Color::black(), 0.0);
+        };
+        if !self.catchers.contains(&i.object().id()) {
+            return (self.color_at(r), 1.0);
+        }
+        let comps = self.prepare(i, r, Some(intersections.as_slice()));
+        self.catch(&comps, self.settings.max_bounce())
+    }
+
     /// Removes all objects from the world.
     pub fn clear_objects(&mut self) {
         self.objects.clear();
@@ -48,40 +582,313 @@ impl World {
 
     /// Computes the correct color at some point of intersection (between a ray and an object).
     /// This function takes into account reflection and reflection.
+    ///
+    /// Materials which give off more light than falls on them are checked or normalized according
+    /// to the settings' [`EnergyAudit`]. Light which a material emits is added on top, and is
+    /// left alone by the audit.
+    ///
+    /// With [`Transparency::Stochastic`], nothing is refracted, since rays which get through the
+    /// surface never hit it in the first place.
     pub fn shade_hit(&self, c: IntersectionVals, limit: u16) -> Color {
-        let surface = lighting(
-            c.object.material(),
-            &*c.object,
-            self.light
-                .unwrap_or(PointLight::new(Point::origin(), Color::black())),
-            c.over_point,
-            c.eyev,
-            c.normalv,
-            is_shadowed(self, c.over_point),
-        );
+        let stochastic = self.settings.transparency() == Transparency::Stochastic;
+        let surface = self.surface_color(&c);
         let reflected = reflected_color(self, &c, limit);
-        let refracted = refracted_color(self, &c, limit);
+        let refracted = if stochastic {
+            Color::black()
+        } else {
+            refracted_color(self, &c, limit)
+        };
 
         let material = c.object.material();
-        if material.refractive_index() > 0.0 && material.transparency() > 0.0 {
+        // Every term is proportional to its weight in the material, so scaling the result is the
+        // same as scaling all the weights.
+        let scale = self.energy_scale(&material);
+        let emitted = material.emissive();
+        if !stochastic && material.reflective() > 0.0 && material.transparency() > 0.0 {
             let reflectance = schlick(c);
-            return surface + reflected * reflectance + refracted * (1.0 - reflectance);
+            return (surface + reflected * reflectance + refracted * (1.0 - reflectance)) * scale
+                + emitted;
+        }
+        (surface + reflected + refracted) * scale + emitted
+    }
+
+    /// How much to scale the light off some material by, according to the settings'
+    /// [`EnergyAudit`].
+    fn energy_scale(&self, material: &Material) -> f64 {
+        match self.settings.energy_audit() {
+            EnergyAudit::Off => 1.0,
+            EnergyAudit::Check => {
+                debug_assert!(
+                    material.conserves_energy(),
+                    "material gives off more light than falls on it; energy={}, material={material:?}",
+                    material.energy()
+                );
+                1.0
+            }
+            EnergyAudit::Normalize => 1.0 / material.energy().max(1.0),
+        }
+    }
+
+    /// Prepares the computations for shading some intersection, with the over and under points
+    /// nudged off the surface by the bias for the object which was hit: its material's, if it has
+    /// one, or else the settings' scaled to the object.
+    fn prepare(&self, i: &Intersection, r: Ray, xs: Option<&[Intersection]>) -> IntersectionVals {
+        let object = i.object();
+        let bias = object
+            .material()
+            .bias()
+            .unwrap_or_else(|| self.settings.bias_for(object.transform()));
+        i.prepare_computations_with_bias(r, xs, bias)
+    }
+
+    /// Computes the color of a surface lit directly by the light, without any reflection or
+    /// refraction.
+    fn surface_color(&self, c: &IntersectionVals) -> Color {
+        self.surface_color_with(c, is_shadowed(self, c.over_point))
+    }
+
+    /// Like [`World::surface_color`], but with the shadow decided by the caller.
+    fn surface_color_with(&self, c: &IntersectionVals, in_shadow: bool) -> Color {
+        let mut m = c.object.material();
+        if !self.decals.is_empty() {
+            let base = m.color_on_object(&*c.object, c.over_point);
+            let painted = self
+                .decals
+                .iter()
+                .fold(base, |color, d| d.apply(color, c.point, c.normalv));
+            m = m.with_flat_color(painted);
+        }
+        if let (None, Some(bands)) = (m.toon_bands(), self.settings.toon_bands()) {
+            m = m.with_toon_bands(bands);
+        }
+        let dark = PointLight::new(Point::origin(), Color::black());
+        let light = self.light.as_ref().unwrap_or(&dark);
+        lighting(ShadingInput::from_comps(c, &m, light).with_in_shadow(in_shadow))
+    }
+
+    /// Finds a single term of the shading of whatever a camera ray hits, skipping objects hidden
+    /// from the camera. Rays which hit nothing, or hit holdouts, give the pass's neutral color.
+    pub fn shading_pass_at(&self, r: Ray, pass: ShadingPass) -> Color {
+        let neutral = match pass {
+            ShadingPass::Shadow => Color::white(),
+            ShadingPass::Reflection => Color::black(),
+        };
+        let xs = r.when_intersect_world(self);
+        let Some(i) = xs.hit_for_camera() else {
+            return neutral;
+        };
+        if self.holdouts.contains(&i.object().id()) {
+            return neutral;
+        }
+        let comps = self.prepare(i, r, Some(xs.as_slice()));
+        match pass {
+            ShadingPass::Shadow => {
+                if !is_shadowed(self, comps.over_point) {
+                    return neutral;
+                }
+                let lit = self.surface_color_with(&comps, false);
+                let dark = self.surface_color_with(&comps, true);
+                let keep = |dark: f64, lit: f64| if lit > 0.0 { dark / lit } else { 1.0 };
+                Color::new(
+                    keep(dark.r(), lit.r()),
+                    keep(dark.g(), lit.g()),
+                    keep(dark.b(), lit.b()),
+                )
+            }
+            ShadingPass::Reflection => {
+                let m = comps.object.material();
+                let reflected = reflected_color(self, &comps, self.settings.max_bounce())
+                    * self.energy_scale(&m);
+                if m.reflective() > 0.0 && m.transparency() > 0.0 {
+                    reflected * schlick(comps)
+                } else {
+                    reflected
+                }
+            }
+        }
+    }
+
+    /// Finds every surface a camera ray passes through, front to back, up to and including the
+    /// first opaque one. Each surface is shaded with its reflections but without what is
+    /// refracted through it, since the surfaces behind it get samples of their own. Surfaces
+    /// hidden from the camera are passed over.
+    pub fn deep_samples(&self, r: Ray) -> Vec<DeepSample> {
+        let xs = r.when_intersect_world(self);
+        let mut res = vec![];
+        for i in xs.ahead().filter(|x| x.material().visible_to_camera()) {
+            let comps = self.prepare(i, r, Some(xs.as_slice()));
+            let alpha = 1.0 - comps.object.material().transparency().clamp(0.0, 1.0);
+            let color = if self.holdouts.contains(&i.object().id()) {
+                Color::black()
+            } else {
+                self.surface_color(&comps)
+                    + reflected_color(self, &comps, self.settings.max_bounce())
+                    + comps.object.material().emissive()
+            };
+            res.push(DeepSample {
+                depth: i.t(),
+                color,
+                alpha,
+            });
+            if alpha >= 1.0 {
+                break;
+            }
+        }
+        res
+    }
+
+    /// Given a ray, computes the color of the point which the ray hits, following reflections and
+    /// refractions as far as this world's settings allow.
+    ///
+    /// The ray is taken to come from the camera, so it passes through objects hidden from the
+    /// camera. If the settings limit the work per ray, rays which go over the limit are shaded
+    /// orange.
+    pub fn color_at(&self, r: Ray) -> Color {
+        let Some(limit) = self.settings.work_limit() else {
+            return self.trace(r, self.settings.max_bounce(), true);
+        };
+        ray::reset_work();
+        let color = self.trace(r, self.settings.max_bounce(), true);
+        if ray::work_done() > limit {
+            Color::orange()
+        } else {
+            color
         }
-        surface + reflected + refracted
     }
 
     /// Given a ray, computes the color of the point which the ray hits. If the ray does not hit
-    /// any point it just returns black.
+    /// any point it just returns black. The ray may bounce at most `limit` more times.
     pub fn color_of_ray(&self, r: Ray, limit: u16) -> Color {
+        self.trace(r, limit, false)
+    }
+
+    /// Finds the nearest intersection ahead of a ray, without gathering and sorting every
+    /// intersection along it like [`Ray::when_intersect_world`] does. Objects whose bounds only
+    /// start past the nearest hit found so far are not intersected at all.
+    pub fn nearest_hit(&self, r: Ray) -> Option<Intersection> {
+        self.nearest_hit_where(r, |_| true)
+    }
+
+    /// Like [`World::nearest_hit`], but only counts the intersections which pass some test.
+    fn nearest_hit_where(
+        &self,
+        r: Ray,
+        keep: impl Fn(&Intersection) -> bool,
+    ) -> Option<Intersection> {
+        if !ray::enter_world(self) {
+            return None;
+        }
+        let mut res: Option<Intersection> = None;
+        let mut nearest = f64::INFINITY;
+        for obj in &self.objects {
+            // Leave some slack, since the bounds and the hits are computed differently.
+            match obj.bounds().clip(r) {
+                Some((start, end)) if start <= nearest + EPSILON && end >= -EPSILON => (),
+                _ => continue,
+            }
+            for x in obj.intersect_with(r) {
+                if x.t() >= 0.0 && x.t() < nearest && keep(&x) && !x.is_cut_out(r) {
+                    nearest = x.t();
+                    res = Some(x);
+                }
+            }
+        }
+        res
+    }
+
+    /// Whether a ray goes straight through the surface at some intersection, which only happens
+    /// with [`Transparency::Stochastic`]. Each point is decided by a coin toss of its own, which
+    /// comes up as often as the surface is transparent.
+    pub(crate) fn passes_through(&self, x: &Intersection, r: Ray) -> bool {
+        if self.settings.transparency() != Transparency::Stochastic {
+            return false;
+        }
+        let transparency = x.material().transparency();
+        transparency > 0.0 && Rng::new(point_seed(r.position_at(x.t()))).next_f64() < transparency
+    }
+
+    /// Like [`World::color_of_ray`], but rays from the camera skip objects hidden from it.
+    fn trace(&self, r: Ray, limit: u16, from_camera: bool) -> Color {
+        let visible = |x: &Intersection| !from_camera || x.material().visible_to_camera();
+        if self.settings.transparency() == Transparency::Stochastic {
+            return match self.nearest_hit_where(r, |x| visible(x) && !self.passes_through(x, r)) {
+                Some(i) => self.shade(&i, r, None, limit),
+                None => Color::black(),
+            };
+        }
+        if self.settings.hit_search() == HitSearch::Nearest {
+            match self.nearest_hit_where(r, visible) {
+                None => return Color::black(),
+                // The refractive indices on either side of an opaque surface don't matter, so
+                // there's no need for the rest of the intersections.
+                Some(i) if i.material().transparency() == 0.0 => {
+                    return self.shade(&i, r, None, limit)
+                }
+                Some(_) => (),
+            }
+        }
         let intersections = r.when_intersect_world(self);
-        if intersections.len() == 0 {
+        let hit = intersections.ahead().find(|x| visible(x));
+        match hit {
+            Some(i) => self.shade(i, r, Some(intersections.as_slice()), limit),
+            None => Color::black(),
+        }
+    }
+
+    /// Shades the hit a ray found, where `xs` are all of the ray's intersections if the hit's
+    /// refractive indices are needed.
+    fn shade(&self, i: &Intersection, r: Ray, xs: Option<&[Intersection]>, limit: u16) -> Color {
+        let comps = self.prepare(i, r, xs);
+        if self.holdouts.contains(&i.object().id()) {
             return Color::black();
         }
-        match hit(&intersections) {
-            Some(i) => self.shade_hit(i.prepare_computations(r, Some(&intersections)), limit),
-            None => Color::black(),
+        if self.catchers.contains(&i.object().id()) {
+            // Shadow catchers have nothing behind them, so only their reflections show.
+            return self.catch(&comps, limit).0;
+        }
+        match self.settings.acne_threshold() {
+            Some(d) if self.has_acne(&comps, d) => Color::magenta(),
+            _ => self.shade_hit(comps, limit),
         }
     }
+
+    /// Finds how much light falls on the first surface a camera ray hits, regardless of the
+    /// surface's material: the light's luminance, scaled by the cosine of the angle it arrives
+    /// at. Surfaces hidden from the camera are skipped. Points in shadow, facing away from the
+    /// light, or not on any surface get zero.
+    pub fn incident_light(&self, r: Ray) -> f64 {
+        let Some(hit) = r.when_intersect_world(self).hit_for_camera().cloned() else {
+            return 0.0;
+        };
+        let comps = self.prepare(&hit, r, None);
+        self.incident_light_at(comps.over_point, comps.normalv)
+    }
+
+    /// Finds how much light falls on a surface at some point, facing some way. The point should
+    /// already be nudged off the surface, so that it doesn't shadow itself.
+    pub fn incident_light_at(&self, p: Point, normalv: Vector) -> f64 {
+        let Some(light) = &self.light else {
+            return 0.0;
+        };
+        if is_shadowed(self, p) {
+            return 0.0;
+        }
+        let lightv = (light.position() - p).normalize();
+        light.intensity_towards(p).luminance() * lightv.dot(normalv).max(0.0)
+    }
+
+    /// Checks if a shadow ray leaving some intersection runs straight back into the same object,
+    /// within a distance of `d`. This is the telltale sign of shadow acne, and usually means the
+    /// bias is too small for the scale of the scene.
+    pub fn has_acne(&self, comps: &IntersectionVals, d: f64) -> bool {
+        let Some(light) = &self.light else {
+            return false;
+        };
+        let direction = (light.position() - comps.over_point).normalize();
+        let xs = Ray::new(comps.over_point, direction).when_intersect_world(self);
+        xs.shadow_hit(Ray::new(comps.over_point, direction))
+            .is_some_and(|h| h.t() < d && *h.object() == *comps.object)
+    }
 }
 
 /// Returns a sphere of radius one at the origin. Used for testing.
@@ -105,21 +912,263 @@ impl Default for World {
         Self {
             light: Some(light),
             objects: vec![stock_sphere_a().as_object(), stock_sphere_b().as_object()],
+            settings: RenderSettings::default(),
+            passes: HashMap::new(),
+            holdouts: HashSet::new(),
+            decals: Vec::new(),
+            catchers: HashSet::new(),
+            tags: HashMap::new(),
+            units: Unit::default(),
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::World;
+    use super::{ErrPlacement, PassMode, ShadingPass, World};
     use crate::color::Color;
-    use crate::light::{Material, PointLight};
+    use crate::decal::{Decal, DecalSource};
+    use crate::light::{is_shadowed, Material, PointLight};
+    use crate::patterns::Stripe;
     use crate::ray::{Intersection, Ray};
-    use crate::shapes::{Plane, Sphere};
+    use crate::settings::{EnergyAudit, HitSearch, RenderSettings, Transparency};
+    use crate::shapes::{Group, Instance, Plane, Shape, Sphere};
     use crate::transform::Tr;
     use crate::tuple::{Point, Vector};
-    use crate::{p, v, MAX_BOUNCE};
-    use std::f64::consts::SQRT_2;
+    use crate::units::Unit;
+    use crate::{assert_f64_eq, p, v, EPSILON, MAX_BOUNCE};
+    use std::f64::consts::{FRAC_PI_2, SQRT_2};
+    use std::sync::Arc;
+
+    #[test]
+    fn comparing_worlds_ignores_object_order() {
+        let build = |reversed: bool| {
+            let ball = Sphere::default()
+                .with_transform(Tr::new().translate(0.0, 1.0, 0.0))
+                .as_object();
+            let floor = Plane::default()
+                .with_material(Material::default().with_reflective(0.3))
+                .as_object();
+            let mut objects = vec![ball.clone(), floor.clone()];
+            if reversed {
+                objects.reverse();
+            }
+            World::new()
+                .with_light(PointLight::new(p!(-10, 10, -10), Color::white()))
+                .with_objects(objects)
+                .with_pass("hero", &[ball])
+        };
+        let (a, b) = (build(false), build(true));
+        assert!(a.approx_eq(&b));
+        assert!(b.approx_eq(&a));
+
+        // The pass has to pick out the matching object, not just any object.
+        let mut moved = build(false);
+        moved.passes.clear();
+        let floor = moved.objects[1].clone();
+        moved.add_to_pass("hero", &floor);
+        assert!(!a.approx_eq(&moved));
+
+        // Equal objects are told apart by what they are in.
+        let twins = |hero: usize| {
+            let balls: Vec<_> = (0..2).map(|_| Sphere::default().as_object()).collect();
+            World::new()
+                .with_objects(balls.clone())
+                .with_tag("hero", &balls[hero..=hero])
+                .with_pass("hero", &balls[hero..=hero])
+        };
+        assert!(twins(0).approx_eq(&twins(1)));
+        let mut split = twins(0);
+        let other = split.objects[1].clone();
+        split.tags.clear();
+        split.add_tag("hero", &other);
+        assert!(!twins(0).approx_eq(&split));
+
+        // Passes naming objects which aren't in the world never match.
+        let stray = |w: World| w.with_pass("stray", &[Sphere::default().as_object()]);
+        assert!(!stray(build(false)).approx_eq(&stray(build(false))));
+
+        let relit = build(false).with_light(PointLight::new(p!(0, 10, 0), Color::white()));
+        assert!(!a.approx_eq(&relit));
+        let extra = build(false).with_objects(vec![Sphere::default().as_object()]);
+        assert!(!a.approx_eq(&extra));
+    }
+
+    #[test]
+    fn filtering_objects_by_tag() {
+        let table = Sphere::default().as_object();
+        let vase = Sphere::default().as_object();
+        let lamp = Sphere::default().as_object();
+        let w = World::new()
+            .with_objects(vec![table.clone(), vase.clone(), lamp.clone()])
+            .with_tag("props", &[vase.clone(), lamp.clone()])
+            .with_tag("fragile", std::slice::from_ref(&vase));
+        assert_eq!(w.tags_of(&vase).len(), 2);
+        assert_eq!(w.tags_of(&lamp), vec!["props"]);
+        assert!(w.tags_of(&table).is_empty());
+
+        let ids = |w: World| w.objects.iter().map(|o| o.id()).collect::<Vec<_>>();
+        assert_eq!(
+            ids(w.filtered(&[], &[])),
+            vec![table.id(), vase.id(), lamp.id()]
+        );
+        assert_eq!(ids(w.filtered(&["props"], &[])), vec![vase.id(), lamp.id()]);
+        assert_eq!(ids(w.filtered(&[], &["props"])), vec![table.id()]);
+        assert_eq!(ids(w.filtered(&["props"], &["fragile"])), vec![lamp.id()]);
+        assert!(w.filtered(&["missing"], &[]).objects.is_empty());
+    }
+
+    #[test]
+    fn nearest_hits_match_the_sorted_list() {
+        let glass = Material::default()
+            .with_transparency(1.0)
+            .with_refractive_index(1.5);
+        let w = World::default().with_objects(vec![
+            Sphere::default().with_material(glass).as_object(),
+            Plane::default()
+                .with_transform(Tr::new().translate(0.0, -1.0, 0.0))
+                .as_object(),
+            Sphere::default()
+                .with_transform(Tr::new().translate(0.0, 0.0, 10.0))
+                .as_object(),
+        ]);
+        let rays = [
+            Ray::new(p!(0, 0, -5), v!(0, 0, 1)),
+            Ray::new(p!(0, 0, 0), v!(0, 0, 1)),
+            Ray::new(p!(3, 5, -5), v!(0, -1, 0.5).normalize()),
+            Ray::new(p!(0, 5, 0), v!(0, 1, 0)),
+        ];
+        for r in rays {
+            let want = r.when_intersect_world(&w).hit().cloned();
+            assert_eq!(w.nearest_hit(r), want);
+        }
+
+        let sorted = World {
+            settings: RenderSettings::new().with_hit_search(HitSearch::Sorted),
+            ..w.clone()
+        };
+        for r in rays {
+            assert_eq!(w.color_at(r), sorted.color_at(r));
+        }
+    }
+
+    #[test]
+    fn merging_worlds_measured_in_other_units() {
+        let mut room = World::new().with_units(Unit::Meters);
+        let cup = Sphere::default()
+            .with_transform(Tr::new().translate(0.0, 0.0, 100.0))
+            .as_object();
+        let props = World::new()
+            .with_units(Unit::Centimeters)
+            .with_objects(vec![cup.clone()])
+            .with_tag("props", std::slice::from_ref(&cup));
+        room.merge(props);
+
+        // The cup keeps its ID, but is a hundredth of the size and a meter away.
+        assert_eq!(room.objects.len(), 1);
+        let merged = &room.objects[0];
+        assert_eq!(merged.id(), cup.id());
+        assert_eq!(room.tags_of(merged), vec!["props"]);
+        let xs = Ray::new(p!(0, 0, -5), v!(0, 0, 1)).when_intersect_world(&room);
+        assert_f64_eq!(xs[0].t(), 5.99, EPSILON);
+
+        // Worlds in the same units are merged as they are.
+        let mut a = World::new();
+        a.merge(World::new().with_objects(vec![cup.clone()]));
+        assert!(Arc::ptr_eq(&a.objects[0], &cup));
+    }
+
+    #[test]
+    fn summarizing_worlds() {
+        let lamp = Sphere::default().with_name("lamp").as_object();
+        let mirror = Plane::default()
+            .with_material(Material::default().with_reflective(0.5))
+            .as_object();
+        let w = World::new()
+            .with_light(PointLight::new(p!(-10, 10, -10), Color::white()))
+            .with_objects(vec![lamp.clone(), mirror.clone()]);
+        let want = format!(
+            "world with 2 objects (1 named) in 2 materials
+light 255 255 255 at (-10, 10, -10)
+  #{} \"lamp\": color 255 255 255, ambient 0.1, diffuse 0.9, specular 0.9
+  #{}: color 255 255 255, ambient 0.1, diffuse 0.9, specular 0.9, reflective 0.5",
+            lamp.id(),
+            mirror.id()
+        );
+        assert_eq!(w.to_string(), want);
+        assert_eq!(
+            World::new().to_string(),
+            "world with 0 objects (0 named) in 0 materials\nno light"
+        );
+
+        let dbg = format!("{w:?}");
+        assert!(dbg.contains(&format!("id: {}, name: Some(\"lamp\")", lamp.id())));
+        assert!(dbg.contains("reflective 0.5"));
+    }
+
+    #[test]
+    fn bias_scales_with_objects() {
+        let huge = Sphere::default()
+            .with_transform(Tr::new().scale(1000.0, 1000.0, 1000.0))
+            .as_object();
+        let w = World::new().with_objects(vec![huge]);
+        let r = Ray::new(p!(0, 0, -5000), v!(0, 0, 1));
+        let xs = r.when_intersect_world(&w);
+        assert_f64_eq!(xs.hit().unwrap().t(), 4000.0, 1e-9);
+        let gap = |w: &World| {
+            let comps = w.prepare(xs.hit().unwrap(), r, None);
+            comps.point.z() - comps.over_point.z()
+        };
+        assert_f64_eq!(gap(&w), EPSILON * 1000.0, 1e-9);
+        let w = w.with_settings(RenderSettings::new().with_scaled_bias(false));
+        assert_f64_eq!(gap(&w), EPSILON, 1e-9);
+    }
+
+    #[test]
+    fn materials_override_the_bias() {
+        // A thin slab of a floor, which the scaled bias would push right through.
+        let slab = Sphere::default()
+            .with_transform(Tr::new().scale(100.0, 0.001, 100.0))
+            .with_material(Material::default().with_bias(1e-6))
+            .as_object();
+        let w = World::new().with_objects(vec![slab]);
+        let r = Ray::new(p!(0, 5, 0), v!(0, -1, 0));
+        let xs = r.when_intersect_world(&w);
+        let comps = w.prepare(xs.hit().unwrap(), r, None);
+        assert_f64_eq!(comps.over_point.y() - comps.point.y(), 1e-6, 1e-12);
+        assert!(comps.under_point.y() > 0.0);
+    }
+
+    #[test]
+    fn rays_pass_through_cutouts() {
+        // A wall across z = 0 with every other one-unit stripe cut away, and a ball behind it.
+        let wall = Plane::default()
+            .with_transform(Tr::new().rotate_x(FRAC_PI_2))
+            .with_material(
+                Material::default()
+                    .with_cutout(Arc::new(Stripe::new(Color::white(), Color::black()))),
+            )
+            .as_object();
+        let ball = Sphere::default()
+            .with_transform(Tr::new().translate(1.5, 0.0, 5.0))
+            .as_object();
+        let w = World::new()
+            .with_objects(vec![wall, ball.clone()])
+            .with_light(PointLight::new(p!(1.5, 0, -10), Color::white()));
+
+        let solid = Ray::new(p!(0.5, 0, -5), v!(0, 0, 1));
+        assert_f64_eq!(solid.when_intersect_world(&w).hit().unwrap().t(), 5.0);
+        let through = Ray::new(p!(1.5, 0, -5), v!(0, 0, 1));
+        let xs = through.when_intersect_world(&w);
+        let hit = xs.hit().unwrap();
+        assert_f64_eq!(hit.t(), 9.0);
+        assert_eq!(hit.object().id(), ball.id());
+
+        // Light gets through the holes too.
+        assert!(!is_shadowed(&w, p!(1.5, 0, 3.9)));
+        let w = w.with_light(PointLight::new(p!(-3, 0, -10), Color::white()));
+        assert!(is_shadowed(&w, p!(1.5, 0, 3.9)));
+    }
 
     #[test]
     fn creating_a_world() {
@@ -167,12 +1216,198 @@ mod tests {
     }
 
     #[test]
-    fn shading_an_intersection_from_the_inside() {
+    fn shading_an_intersection_with_a_decal() {
+        let red = Color::new(1.0, 0.0, 0.0);
+        let decal = Decal::new(DecalSource::Pattern(Arc::new(Stripe::new(red, red))));
+        let r = Ray::new(Point::new(0.0, 0.0, -5.0), Vector::new(0.0, 0.0, 1.0));
+        let shade = |w: &World| {
+            let comps = Intersection::new(4.0, w.objects[0].clone()).prepare_computations(r, None);
+            w.shade_hit(comps, MAX_BOUNCE)
+        };
+
+        let painted = World::default().map_objects(|o| {
+            Sphere::default()
+                .with_transform(o.transform())
+                .with_material(o.material().with_color(red))
+                .as_object()
+        });
+        let got = shade(&World::default().with_decal(decal.clone()));
+        assert_eq!(got, shade(&painted));
+
+        // Moved out of the way, the decal does nothing.
+        let decal = decal.with_transform(Tr::new().translate(5.0, 0.0, 0.0));
+        let got = shade(&World::default().with_decal(decal));
+        assert_eq!(got, shade(&World::default()));
+    }
+
+    #[test]
+    fn matte_floor_only_shows_shadows() {
+        let w = World::default().with_matte_floor(-1.0);
+        let down = v!(0, -1, 0);
+
+        // In the shadow of the spheres, the floor is as opaque as the light it loses.
+        let (c, a) = w.color_and_alpha_at(Ray::new(p!(1, 5, 1), down));
+        assert_eq!(c, Color::black());
+        assert_f64_eq!(a, 0.9);
+
+        // Out in the light, the floor is invisible.
+        assert_eq!(
+            w.color_and_alpha_at(Ray::new(p!(5, 5, 5), down)),
+            (Color::black(), 0.0)
+        );
+        assert_eq!(w.color_at(Ray::new(p!(5, 5, 5), down)), Color::black());
+
+        // Everything else is opaque, or transparent if there is nothing there.
+        let r = Ray::new(p!(0, 0, -5), v!(0, 0, 1));
+        assert_eq!(w.color_and_alpha_at(r), (w.color_at(r), 1.0));
+        let r = Ray::new(p!(0, 0, -5), v!(0, 0, -1));
+        assert_eq!(w.color_and_alpha_at(r), (Color::black(), 0.0));
+    }
+
+    #[test]
+    fn shadow_catchers_show_reflections() {
+        let floor = Plane::default()
+            .with_material(Material::default().with_reflective(0.5))
+            .with_transform(Tr::new().translate(0.0, -1.0, 0.0));
+        let w = World::default().with_shadow_catcher(floor.as_object());
+        let r = Ray::new(p!(0, 0, -3), v!(0, -SQRT_2 / 2.0, SQRT_2 / 2.0));
+        let (c, a) = w.color_and_alpha_at(r);
+        assert_eq!(c, Color::new(0.19033, 0.23792, 0.14275));
+        assert!(a > 0.0 && a < 1.0);
+        assert_eq!(w.color_at(r), c);
+    }
+
+    #[test]
+    fn emitters_hidden_from_the_camera() {
+        let glow = |visible: bool| {
+            Sphere::default()
+                .with_material(
+                    Material::default()
+                        .with_ambient(1.0)
+                        .with_diffuse(0.0)
+                        .with_specular(0.0)
+                        .with_casts_shadow(false)
+                        .with_visible_to_camera(visible),
+                )
+                .with_transform(Tr::new().scale(0.5, 0.5, 0.5).translate(0.0, 2.0, 1.0))
+                .as_object()
+        };
+        let mirror = Plane::default()
+            .with_material(
+                Material::default()
+                    .with_color(Color::black())
+                    .with_ambient(0.0)
+                    .with_diffuse(0.0)
+                    .with_specular(0.0)
+                    .with_reflective(1.0),
+            )
+            .with_transform(Tr::new().translate(0.0, -1.0, 0.0))
+            .as_object();
+        let world = |visible: bool| {
+            World::new()
+                .with_light(PointLight::new(p!(-10, 10, -10), Color::white()))
+                .with_objects(vec![glow(visible), mirror.clone()])
+        };
+
+        let straight = Ray::new(p!(0, 2, -5), v!(0, 0, 1));
+        assert_eq!(world(true).color_at(straight), Color::white());
+        assert_eq!(world(false).color_at(straight), Color::black());
+        assert_eq!(world(false).color_and_alpha_at(straight).1, 0.0);
+
+        // The hidden emitter still shows in the mirror.
+        let bounced = Ray::new(p!(0, 0, -3), v!(0, -SQRT_2 / 2.0, SQRT_2 / 2.0));
+        assert_eq!(world(false).color_at(bounced), Color::white());
+        assert_eq!(
+            world(false).color_at(bounced),
+            world(true).color_at(bounced)
+        );
+    }
+
+    /// The default world, behind a reflective ball which the camera can't see.
+    fn veiled() -> World {
+        let veil = Sphere::default()
+            .with_material(
+                Material::default()
+                    .with_reflective(0.5)
+                    .with_casts_shadow(false)
+                    .with_visible_to_camera(false),
+            )
+            .with_transform(Tr::new().scale(0.5, 0.5, 0.5).translate(0.0, 0.0, -3.0))
+            .as_object();
         let mut w = World::default();
-        w.light = Some(PointLight::new(
-            Point::new(0.0, 0.25, 0.0),
-            Color::new(1.0, 1.0, 1.0),
-        ));
+        w.add_objects(vec![veil]);
+        w
+    }
+
+    #[test]
+    fn shading_passes_skip_objects_hidden_from_the_camera() {
+        // A wall behind the camera, for the veil to reflect.
+        let wall = Plane::default()
+            .with_transform(Tr::new().rotate_x(FRAC_PI_2).translate(0.0, 0.0, -10.0))
+            .as_object();
+        let with_wall = |mut w: World| {
+            w.add_objects(vec![wall.clone()]);
+            w
+        };
+        let r = Ray::new(p!(0, 0, -5), v!(0, 0, 1));
+        for pass in [ShadingPass::Shadow, ShadingPass::Reflection] {
+            assert_eq!(
+                with_wall(veiled()).shading_pass_at(r, pass),
+                with_wall(World::default()).shading_pass_at(r, pass)
+            );
+        }
+        assert_eq!(
+            with_wall(veiled()).shading_pass_at(r, ShadingPass::Reflection),
+            Color::black()
+        );
+    }
+
+    #[test]
+    fn deep_samples_skip_objects_hidden_from_the_camera() {
+        let r = Ray::new(p!(0, 0, -5), v!(0, 0, 1));
+        let got = veiled().deep_samples(r);
+        assert_eq!(got.len(), 1);
+        assert_f64_eq!(got[0].depth, 4.0);
+        assert_eq!(got, World::default().deep_samples(r));
+    }
+
+    #[test]
+    fn incident_light_skips_objects_hidden_from_the_camera() {
+        // Seen from below, the veil would face away from the light.
+        let r = Ray::new(p!(0, -0.4, -5), v!(0, 0, 1));
+        assert!(World::default().incident_light(r) > 0.0);
+        assert_eq!(
+            veiled().incident_light(r),
+            World::default().incident_light(r)
+        );
+    }
+
+    #[test]
+    fn rays_over_the_work_limit_are_flagged() {
+        let r = Ray::new(p!(0, 0, -5), v!(0, 0, 1));
+        let w = World::default().with_settings(RenderSettings::new().with_work_limit(100));
+        assert_eq!(w.color_at(r), World::default().color_at(r));
+
+        // A thousand spheres in the same place.
+        let pile = (0..1000).map(|_| Sphere::default().as_object()).collect();
+        let w = w.with_objects(pile);
+        assert_eq!(w.color_at(r), Color::orange());
+        // Rays which miss everything still had to test everything.
+        assert_eq!(
+            w.color_at(Ray::new(p!(0, 5, -5), v!(0, 0, 1))),
+            Color::orange()
+        );
+    }
+
+    #[test]
+    fn shading_an_intersection_from_the_inside() {
+        let w = World {
+            light: Some(PointLight::new(
+                Point::new(0.0, 0.25, 0.0),
+                Color::new(1.0, 1.0, 1.0),
+            )),
+            ..World::default()
+        };
         let r = Ray::new(Point::new(0.0, 0.0, 0.0), Vector::new(0.0, 0.0, 1.0));
         let shape = w.objects[1].clone();
         let i = Intersection::new(0.5, shape);
@@ -183,6 +1418,126 @@ mod tests {
         assert_eq!(got, want);
     }
 
+    #[test]
+    fn total_internal_reflection_does_not_brighten_glass() {
+        // A ray inside a ball of glass meets its surface at 45 degrees, which is past the
+        // critical angle, so nothing is refracted out. The glass itself has no color and doesn't
+        // reflect, so there's nothing to see at all.
+        let glass = Material::default()
+            .with_ambient(0.0)
+            .with_diffuse(0.0)
+            .with_specular(0.0)
+            .with_transparency(1.0)
+            .with_refractive_index(1.5);
+        let w = World::new()
+            .with_light(PointLight::new(p!(-10, 10, -10), Color::white()))
+            .with_objects(vec![Sphere::default().with_material(glass).as_object()]);
+        let r = Ray::new(p!(0, 0, SQRT_2 / 2.0), v!(0, 1, 0));
+        assert_eq!(w.color_at(r), Color::black());
+    }
+
+    #[test]
+    fn normalizing_materials_which_glow() {
+        let bright = Material::default().with_reflective(0.1);
+        let w = World::new()
+            .with_light(PointLight::new(p!(-10, 10, -10), Color::white()))
+            .with_objects(vec![Sphere::default().with_material(bright).as_object()]);
+        let r = Ray::new(p!(0, 0, -5), v!(0, 0, 1));
+        let before = w.color_at(r);
+        let w = w.with_settings(RenderSettings::new().with_energy_audit(EnergyAudit::Normalize));
+        assert_eq!(w.color_at(r), before * 0.5);
+
+        // Materials which already conserve energy are left alone.
+        let dim = Material::default().with_diffuse(0.5).with_specular(0.0);
+        let w = w.map_objects(|_| Sphere::default().with_material(dim.clone()).as_object());
+        let want = w.clone().with_settings(RenderSettings::new()).color_at(r);
+        assert_eq!(w.color_at(r), want);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "material gives off more light than falls on it")]
+    fn checking_materials_for_energy() {
+        let w = World::new()
+            .with_objects(vec![Sphere::default().as_object()])
+            .with_settings(RenderSettings::new().with_energy_audit(EnergyAudit::Check));
+        w.color_at(Ray::new(p!(0, 0, -5), v!(0, 0, 1)));
+    }
+
+    #[test]
+    fn glowing_objects_show_up_in_mirrors() {
+        let light = PointLight::new(p!(0, 1, 1), Color::new(1.0, 0.9, 0.8));
+        let mirror = Plane::default().with_material(
+            Material::default()
+                .with_color(Color::black())
+                .with_specular(0.0)
+                .with_reflective(1.0),
+        );
+        let w = World::new()
+            .with_light(light.clone())
+            .with_objects(vec![mirror.as_object(), light.bulb(0.5).as_object()]);
+
+        // Looking straight at the bulb, all there is to see is its glow.
+        let got = w.color_at(Ray::new(p!(0, 1, -5), v!(0, 0, 1)));
+        assert_eq!(got, Color::new(1.0, 0.9, 0.8));
+        // The mirror shows the bulb, which does not shade the spot it sits above.
+        let got = w.color_at(Ray::new(p!(0, 1, -1), v!(0, -1, 1).normalize()));
+        assert_eq!(got, Color::new(1.0, 0.9, 0.8));
+        assert!(!is_shadowed(&w, p!(0, 0.01, 1)));
+
+        let m = Material::default().with_emissive(Color::white());
+        // Glowing is not reflecting more light than falls on the surface.
+        assert!(m.is_emissive());
+        assert_eq!(m.energy(), Material::default().energy());
+        assert!(m.to_string().ends_with("emissive 255 255 255"));
+    }
+
+    #[test]
+    fn stochastic_transparency_lets_rays_through_some_of_the_time() {
+        let flat = |c: Color| {
+            Material::default()
+                .with_color(c)
+                .with_ambient(1.0)
+                .with_diffuse(0.0)
+                .with_specular(0.0)
+        };
+        let wall = |z: f64, m: Material| {
+            Plane::default()
+                .with_transform(Tr::new().rotate_x(FRAC_PI_2).translate(0.0, 0.0, z))
+                .with_material(m)
+                .as_object()
+        };
+        let blue = Color::new(0.0, 0.0, 1.0);
+        let red = Color::new(1.0, 0.0, 0.0);
+        let settings = RenderSettings::new().with_transparency(Transparency::Stochastic);
+        let w = World::new()
+            .with_light(PointLight::new(p!(0, 0, -10), Color::white()))
+            .with_settings(settings)
+            .with_objects(vec![
+                wall(0.0, flat(blue).with_transparency(0.5)),
+                wall(1.0, flat(red)),
+            ]);
+        let colors: Vec<Color> = (0..1000)
+            .map(|i| w.color_at(Ray::new(p!(i as f64 * 0.013, 0, -5), v!(0, 0, 1))))
+            .collect();
+        // Each ray sees one wall or the other, never a mix of both.
+        assert!(colors.iter().all(|&c| c == red || c == blue));
+        let through = colors.iter().filter(|&&c| c == red).count();
+        assert!((430..570).contains(&through), "through={through}");
+
+        // However many clear layers there are, rays don't run out of bounces getting through.
+        let mut layers: Vec<_> = (0..40)
+            .map(|i| wall(i as f64 * 0.1, flat(blue).with_transparency(1.0)))
+            .collect();
+        layers.push(wall(5.0, flat(red)));
+        let w = w.with_objects(layers);
+        assert_eq!(w.color_at(Ray::new(p!(0, 0, -5), v!(0, 0, 1))), red);
+        // Shadows fall through them too.
+        assert!(!is_shadowed(&w, p!(0, 0, 4.9)));
+        let w = w.with_settings(RenderSettings::new());
+        assert!(is_shadowed(&w, p!(0, 0, 4.9)));
+    }
+
     #[test]
     fn color_when_ray_misses() {
         let w = World::default();
@@ -203,6 +1558,201 @@ mod tests {
         assert_eq!(got, want);
     }
 
+    #[test]
+    fn acne_diagnostics_flag_points_below_the_surface() {
+        let r = Ray::new(p!(0, 0, -5), v!(0, 0, 1));
+        let settings = RenderSettings::new().with_acne_diagnostics(1000.0);
+
+        let w = World::default().with_settings(settings);
+        assert_eq!(w.color_at(r), Color::new(0.38066, 0.47583, 0.2855));
+
+        // A negative bias sinks the shading point into the sphere, so the shadow ray hits the
+        // sphere again on its way out.
+        let w = World::default().with_settings(settings.with_bias(-0.001));
+        assert_eq!(w.color_at(r), Color::magenta());
+    }
+
+    #[test]
+    fn incident_light_on_a_surface() {
+        let w = World::default().with_light(PointLight::new(p!(0, 0, -10), Color::white()));
+        // Straight on, the light arrives at full strength.
+        let got = w.incident_light(Ray::new(p!(0, 0, -5), v!(0, 0, 1)));
+        assert_f64_eq!(got, 1.0, EPSILON);
+        // At the edge of the sphere it arrives at a grazing angle.
+        let got = w.incident_light(Ray::new(p!(0, 0.9, -5), v!(0, 0, 1)));
+        assert!(got > 0.0 && got < 0.5);
+        // Missing everything means no light.
+        assert_eq!(w.incident_light(Ray::new(p!(0, 0, -5), v!(0, 1, 0))), 0.0);
+    }
+
+    #[test]
+    fn rendering_a_single_pass() {
+        let mut w = World::default();
+        let back = w.objects[1].clone();
+        let outer = w.objects[..1].to_vec();
+        w = w.with_pass("outer", &outer);
+        w.add_to_pass("inner", &back);
+
+        let hidden = w.pass("inner", PassMode::Hide);
+        assert_eq!(hidden.objects.len(), 1);
+        assert_eq!(*hidden.objects[0], *back);
+
+        // Held out, the outer sphere still covers the inner one, but shows up black.
+        let r = Ray::new(p!(0, 0, -5), v!(0, 0, 1));
+        let held = w.pass("inner", PassMode::Holdout);
+        assert_eq!(held.objects.len(), 2);
+        assert_eq!(held.color_at(r), Color::black());
+        let got = w.pass("outer", PassMode::Holdout).color_at(r);
+        assert_eq!(got, w.color_at(r));
+
+        assert!(w.pass("nothing", PassMode::Hide).objects.is_empty());
+    }
+
+    #[test]
+    fn dropping_objects_onto_surfaces() {
+        let ball = |y: f64, r: f64| {
+            Sphere::default()
+                .with_transform(Tr::new().scale(r, r, r).translate(0.0, y, 0.0))
+                .as_object()
+        };
+        let (floor, big, small) = (Plane::default().as_object(), ball(1.0, 1.0), ball(6.0, 0.5));
+        let mut w = World::new().with_objects(vec![floor.clone(), big.clone(), small.clone()]);
+        w.add_to_pass("balls", &small);
+
+        // The small ball lands on top of the big one, which already rests on the floor.
+        let down = v!(0, -1, 0);
+        assert_eq!(w.drop_to_surface(big.id(), down), Ok(0.0));
+        assert_f64_eq!(w.drop_to_surface(small.id(), down).unwrap(), 3.5, EPSILON);
+        let xs = Ray::new(p!(0, 10, 0), down).when_intersect_world(&w);
+        assert_f64_eq!(xs.hit().unwrap().t(), 7.0, EPSILON);
+        assert_eq!(w.objects[2].id(), small.id());
+        assert_eq!(w.pass("balls", PassMode::Hide).objects.len(), 1);
+
+        // A ball sunk into the floor is lifted out.
+        let sunk = ball(0.25, 1.0);
+        w.add_objects(vec![sunk.clone()]);
+        w.objects.remove(1);
+        assert_f64_eq!(w.drop_to_surface(sunk.id(), down).unwrap(), -0.75, EPSILON);
+
+        assert_eq!(
+            w.drop_to_surface(small.id(), v!(0, 1, 0)),
+            Err(ErrPlacement::NothingBelow(small.id()))
+        );
+        assert_eq!(
+            w.drop_to_surface(floor.id(), down),
+            Err(ErrPlacement::Unbounded(floor.id()))
+        );
+        assert_eq!(
+            w.drop_to_surface(12345678, down),
+            Err(ErrPlacement::UnknownObject(12345678))
+        );
+    }
+
+    #[test]
+    fn isolating_shadows_and_reflections() {
+        let mut w = World::default().with_light(PointLight::new(p!(0, 0, -10), Color::white()));
+        w.add_objects(vec![Sphere::default()
+            .with_transform(Tr::new().translate(0.0, 0.0, 10.0))
+            .as_object()]);
+        // Behind the first sphere, the second only keeps its ambient light.
+        let r = Ray::new(p!(0, 0, 5), v!(0, 0, 1));
+        let got = w.shading_pass_at(r, ShadingPass::Shadow);
+        assert_eq!(got, Color::new(0.1, 0.1, 0.1) * (1.0 / 1.9));
+        let lit = Ray::new(p!(0, 0, -5), v!(0, 0, 1));
+        assert_eq!(w.shading_pass_at(lit, ShadingPass::Shadow), Color::white());
+        let miss = Ray::new(p!(0, 5, -5), v!(0, 0, 1));
+        assert_eq!(w.shading_pass_at(miss, ShadingPass::Shadow), Color::white());
+
+        let mut w = World::default();
+        w.add_objects(vec![Plane::default()
+            .with_material(Material::default().with_reflective(0.5))
+            .with_transform(Tr::new().translate(0.0, -1.0, 0.0))
+            .as_object()]);
+        let r = Ray::new(p!(0, 0, -3), v!(0, -SQRT_2 / 2.0, SQRT_2 / 2.0));
+        let got = w.shading_pass_at(r, ShadingPass::Reflection);
+        assert_eq!(got, Color::new(0.19033, 0.23792, 0.14275));
+        assert_eq!(
+            w.shading_pass_at(lit, ShadingPass::Reflection),
+            Color::black()
+        );
+    }
+
+    #[test]
+    fn finding_objects_by_name() {
+        let pillar = |x: f64| {
+            Sphere::default()
+                .with_name("pillar")
+                .with_transform(Tr::new().translate(x, 0.0, 0.0))
+                .as_object()
+        };
+        let floor = Plane::default().with_name("floor").as_object();
+        let w = World::new().with_objects(vec![
+            floor.clone(),
+            Group::new(vec![pillar(-3.0), pillar(3.0)]).as_object(),
+        ]);
+        assert_eq!(w.object_named("floor").map(|o| o.id()), Some(floor.id()));
+        let pillars = w.objects_named("pillar");
+        assert_eq!(pillars.len(), 2);
+        assert_eq!(pillars[0].transform(), Tr::new().translate(-3.0, 0.0, 0.0));
+        assert!(w.object_named("roof").is_none());
+    }
+
+    #[test]
+    fn finding_the_transformation_of_nested_objects() {
+        let ball = Sphere::default().with_transform(Tr::new().scale(2.0, 2.0, 2.0));
+        let id = ball.id();
+        let group = Group::new(vec![Instance::new(ball.as_object())
+            .with_transform(Tr::new().rotate_y(FRAC_PI_2))
+            .as_object()])
+        .with_transform(Tr::new().translate(0.0, 1.0, 0.0));
+        let w = World::new().with_objects(vec![group.as_object()]);
+        let want = Tr::new()
+            .scale(2.0, 2.0, 2.0)
+            .rotate_y(FRAC_PI_2)
+            .translate(0.0, 1.0, 0.0);
+        assert_eq!(w.transform_of(id), Some(want));
+        assert_eq!(w.transform_of(usize::MAX), None);
+    }
+
+    #[test]
+    fn finding_the_transformation_of_one_copy_of_a_shape() {
+        let ball = Sphere::default().as_object();
+        let place = |x: f64| {
+            Instance::new(ball.clone())
+                .with_transform(Tr::new().translate(x, 0.0, 0.0))
+                .as_object()
+        };
+        let (left, right) = (place(-2.0), place(2.0));
+        let w = World::new().with_objects(vec![left.clone(), right.clone()]);
+        assert_eq!(
+            w.transform_along(&[right.id()], ball.id()),
+            Some(Tr::new().translate(2.0, 0.0, 0.0))
+        );
+        assert_eq!(
+            w.transform_along(&[left.id()], ball.id()),
+            Some(Tr::new().translate(-2.0, 0.0, 0.0))
+        );
+        assert_eq!(w.transform_along(&[usize::MAX], ball.id()), None);
+    }
+
+    #[test]
+    fn color_at_uses_world_settings() {
+        let r = Ray::new(p!(0, 0, -5), v!(0, 0, 1));
+        let w = World::default();
+        assert_eq!(w.color_at(r), w.color_of_ray(r, MAX_BOUNCE));
+
+        let w = World::default()
+            .map_objects(|o| {
+                Sphere::default()
+                    .with_transform(o.transform())
+                    .with_material(o.material().with_reflective(0.5))
+                    .as_object()
+            })
+            .with_settings(RenderSettings::new().with_max_bounce(0));
+        assert_eq!(w.color_at(r), w.color_of_ray(r, 0));
+        assert_ne!(w.color_at(r), w.color_of_ray(r, MAX_BOUNCE));
+    }
+
     #[test]
     fn color_when_intersection_behind_ray() {
         let w = World::default().with_objects(vec![
@@ -1,35 +1,225 @@
-use crate::camera::Camera;
-use crate::light::{Material, PointLight};
-use crate::shapes::{Object, Plane, Sphere};
-use crate::transform::Tr;
-use serde::Deserialize;
+use crate::cache::hash_bytes;
+use crate::camera::{Camera, Projection};
+use crate::light::Material;
+use crate::mesh::{BvhLayout, Mesh};
+use crate::obj::parse_obj;
+use crate::rng::Rng;
+use crate::shapes::{Group, Instance, Object, Plane, Sphere};
+use crate::stl::parse_stl;
+use crate::transform::{view_transform, Tr};
+use crate::units::Unit;
+use crate::world::World;
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
 use std::collections::{HashMap, HashSet};
+use std::f64::consts::TAU;
 use std::fmt::Display;
+use std::path::Path;
 
-struct Data {
-    camera: Camera,
-    light: PointLight,
-    materials: HashMap<String, Material>,
-    transforms: HashMap<String, Vec<Tr>>,
+/// The version of the scene format which this build of the ray tracer understands. Scene files
+/// without a `version` key are assumed to be version 1.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Migrations between successive schema versions. The migration at index `i` upgrades a scene
+/// from version `i + 1` to version `i + 2`.
+const MIGRATIONS: [fn(&mut Value); (SCHEMA_VERSION - 1) as usize] = [migrate_v1_to_v2];
+
+/// What a scene file looks like, after it has been migrated to the current schema version.
+#[derive(Deserialize, Debug)]
+struct SceneRepr {
+    camera: CameraRepr,
+    light: PointLightRepr,
+    /// The unit of length the whole scene is measured in.
+    #[serde(default)]
+    units: Unit,
+    #[serde(default)]
+    materials: Materials,
+    #[serde(default)]
+    transforms: Transforms,
+    #[serde(default)]
+    objects: Vec<ObjectRepr>,
+    #[serde(default)]
+    scatter: Vec<ScatterRepr>,
 }
 
-#[derive(Deserialize, Debug, PartialEq)]
+/// A fully parsed scene, ready to be rendered.
+pub struct Scene {
+    pub camera: Camera,
+    pub world: World,
+}
+
+/// A scene where every named material and transformation has been substituted into the objects
+/// which use them. This is the form in which scenes are cached.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct ResolvedScene {
+    camera: CameraRepr,
+    light: PointLightRepr,
+    #[serde(default)]
+    units: Unit,
+    objects: Vec<ResolvedObject>,
+    #[serde(default)]
+    scatters: Vec<ResolvedScatter>,
+}
+
+impl ResolvedScene {
+    /// Builds the actual camera and world described by this scene. Relative mesh paths are taken
+    /// from the working directory.
+    pub fn build(self) -> Result<Scene, ErrParseYaml> {
+        self.build_in(Path::new(""))
+    }
+
+    /// Reads the mesh of every scatter directive which doesn't have one yet, and works out its
+    /// bounding volume hierarchy, so that both are kept when the scene is cached. Relative mesh
+    /// paths are taken from some directory.
+    pub fn load_meshes(&mut self, dir: &Path) -> Result<(), ErrParseYaml> {
+        for x in self.scatters.iter_mut().filter(|x| x.loaded.is_none()) {
+            let (hash, mesh) = read_mesh(&dir.join(&x.source))
+                .map_err(|msg| ErrParseYaml::Mesh(format!("{msg}; source={}", x.source)))?;
+            let (_, bvh) = mesh.to_bvh(&Material::default(), x.bvh_threshold);
+            x.loaded = Some(LoadedMesh { hash, mesh, bvh });
+        }
+        Ok(())
+    }
+
+    /// The path of every mesh which has been read into the scene, as written in the scene, along
+    /// with the hash of the file it was read from.
+    pub fn mesh_hashes(&self) -> impl Iterator<Item = (&str, u64)> {
+        self.scatters
+            .iter()
+            .filter_map(|x| Some((x.source.as_str(), x.loaded.as_ref()?.hash)))
+    }
+
+    /// Builds the actual camera and world described by this scene, taking relative mesh paths
+    /// from some directory.
+    pub fn build_in(self, dir: &Path) -> Result<Scene, ErrParseYaml> {
+        let mut tagged: Vec<(Vec<Object>, Vec<String>)> = vec![];
+        let tags: Vec<_> = self.objects.iter().map(|x| x.tags.clone()).collect();
+        for (obj, tags) in build_objects(self.objects)?.into_iter().zip(tags) {
+            tagged.push((vec![obj], tags));
+        }
+        for x in self.scatters {
+            let tags = x.tags.clone();
+            tagged.push((build_scatters(vec![x], self.units, dir)?, tags));
+        }
+
+        let mut world = World::new()
+            .with_light(self.light.into())
+            .with_units(self.units);
+        for (objects, tags) in tagged {
+            for tag in &tags {
+                world = world.with_tag(tag, &objects);
+            }
+            world.add_objects(objects);
+        }
+        Ok(Scene {
+            camera: self.camera.into(),
+            world,
+        })
+    }
+}
+
+/// Parses a scene from a YAML string. Scenes written for older versions of the format are
+/// migrated before being parsed.
+pub fn parse_scene(s: &str) -> Result<Scene, ErrParseYaml> {
+    resolve_scene(s)?.build()
+}
+
+/// Parses a scene from a YAML string, without building any of its objects.
+pub fn resolve_scene(s: &str) -> Result<ResolvedScene, ErrParseYaml> {
+    let mut value: Value =
+        serde_yaml::from_str(s).map_err(|e| ErrParseYaml::Syntax(e.to_string()))?;
+    migrate(&mut value)?;
+    let repr: SceneRepr =
+        serde_yaml::from_value(value).map_err(|e| ErrParseYaml::Syntax(e.to_string()))?;
+    // Catch cameras which can't be placed, e.g. looking straight along `up`, before they are built.
+    repr.camera.view_transform().try_inverse().map_err(|e| {
+        ErrParseYaml::InvalidTransformation(format!("{e:?}; camera={:?}", repr.camera))
+    })?;
+    Ok(ResolvedScene {
+        camera: repr.camera,
+        light: repr.light,
+        units: repr.units,
+        objects: resolve_objects(&repr.objects, &repr.materials.0, &repr.transforms.0)?,
+        scatters: resolve_scatters(&repr.scatter, &repr.materials.0, &repr.transforms.0)?,
+    })
+}
+
+/// Upgrades a scene, in place, to the current schema version.
+fn migrate(value: &mut Value) -> Result<(), ErrParseYaml> {
+    let version = match value.get("version") {
+        None => 1,
+        Some(v) => v
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .filter(|v| *v > 0)
+            .ok_or(ErrParseYaml::InvalidVersion(format!("{v:?}")))?,
+    };
+    if version > SCHEMA_VERSION {
+        return Err(ErrParseYaml::UnsupportedVersion(version));
+    }
+    for migration in &MIGRATIONS[(version - 1) as usize..] {
+        migration(value);
+    }
+    if let Value::Mapping(m) = value {
+        m.insert("version".into(), SCHEMA_VERSION.into());
+    }
+    Ok(())
+}
+
+/// Version 1 allowed transformation names to be written in kebab case, e.g. `rotate-x`. From
+/// version 2 onwards they are always snake case.
+fn migrate_v1_to_v2(value: &mut Value) {
+    fn fix_transforms(trs: &mut Value) {
+        let Some(trs) = trs.as_sequence_mut() else {
+            return;
+        };
+        for tr in trs {
+            if let Some(Value::String(name)) = tr.as_sequence_mut().and_then(|xs| xs.first_mut()) {
+                *name = name.replace('-', "_");
+            }
+        }
+    }
+
+    if let Some(Value::Mapping(trs)) = value.get_mut("transforms") {
+        for (_, tr) in trs.iter_mut() {
+            fix_transforms(tr);
+        }
+    }
+    if let Some(Value::Sequence(objects)) = value.get_mut("objects") {
+        for obj in objects {
+            if let Some(tr) = obj.get_mut("transform") {
+                fix_transforms(tr);
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub struct CameraRepr {
     pub width: usize,
     pub height: usize,
     pub field_of_view: f64,
+    #[serde(default)]
+    pub projection: Projection,
     pub from: (f64, f64, f64),
     pub to: (f64, f64, f64),
     pub up: (f64, f64, f64),
 }
 
-#[derive(Deserialize, Debug, PartialEq)]
+impl CameraRepr {
+    /// The transformation which looks from `from` to `to`, which may be singular.
+    pub(crate) fn view_transform(&self) -> Tr {
+        view_transform(self.from.into(), self.to.into(), self.up.into())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub struct PointLightRepr {
     pub at: (f64, f64, f64),
     pub color: (f64, f64, f64),
 }
 
-#[derive(Deserialize, Debug, PartialEq, Clone)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 #[serde(untagged)]
 pub enum MaterialRepr {
     Complete {
@@ -53,7 +243,7 @@ pub enum MaterialRepr {
 pub struct MaterialReprs(pub HashMap<String, MaterialRepr>);
 
 /// A map of material names to materials.
-#[derive(Deserialize, Debug, PartialEq)]
+#[derive(Deserialize, Debug, PartialEq, Default)]
 #[serde(try_from = "MaterialReprs")]
 pub struct Materials(pub HashMap<String, Material>);
 
@@ -65,7 +255,19 @@ pub enum ErrParseYaml {
     KeyNotExists(String),
     Unsupported,
     UnknownTransformation(String),
+    /// When a transformation is known, but its parameters don't make sense.
+    InvalidTransformation(String),
     UnknownMaterial(String),
+    /// When the YAML itself is malformed, or does not fit the expected layout.
+    Syntax(String),
+    /// When the `version` key is not a positive integer.
+    InvalidVersion(String),
+    /// When the scene was written for a newer version of the format than we understand.
+    UnsupportedVersion(u32),
+    /// When a mesh file referenced by the scene cannot be loaded.
+    Mesh(String),
+    /// When the parameters of a scatter directive don't make sense.
+    InvalidScatter(String),
 }
 
 impl Display for ErrParseYaml {
@@ -76,7 +278,16 @@ impl Display for ErrParseYaml {
             KeyNotExists(key) => write!(f, "Key does not exist; key={key}"),
             Unsupported => write!(f, "An unsupported operation"),
             UnknownTransformation(tr) => write!(f, "Unknown transformation; tr={tr}"),
+            InvalidTransformation(tr) => write!(f, "Invalid transformation; {tr}"),
             UnknownMaterial(mat) => write!(f, "Unknown material; mat={mat}"),
+            Syntax(msg) => write!(f, "Invalid yaml; {msg}"),
+            InvalidVersion(v) => write!(f, "Invalid scene version; version={v}"),
+            UnsupportedVersion(v) => write!(
+                f,
+                "Scene version is newer than supported; version={v}, supported={SCHEMA_VERSION}"
+            ),
+            Mesh(msg) => write!(f, "Could not load mesh; {msg}"),
+            InvalidScatter(msg) => write!(f, "Invalid scatter; {msg}"),
         }
     }
 }
@@ -178,7 +389,7 @@ impl TryFrom<MaterialRepr> for Material {
     }
 }
 
-#[derive(Deserialize, Debug, PartialEq, Clone)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 #[serde(untagged)]
 pub enum TransformRepr {
     /// References another transformation.
@@ -187,16 +398,28 @@ pub enum TransformRepr {
     OneParam(String, f64),
     /// Transformations involving three parameters, i.e. translation and scaling.
     ThreeParam(String, f64, f64, f64),
-    /// Transformations involving six parameters, i.e. shearing.
+    /// Transformations involving four parameters, i.e. rotations about a point.
+    FourParam(String, f64, f64, f64, f64),
+    /// Transformations involving six parameters, i.e. shearing and scaling about a point.
     SixParam(String, f64, f64, f64, f64, f64, f64),
+    /// A transformation given directly by the rows of its matrix.
+    Matrix { matrix: [[f64; 4]; 4] },
 }
 
 #[derive(Deserialize, Debug)]
 struct TransformReprs(pub HashMap<String, Vec<TransformRepr>>);
 
-#[derive(Deserialize, Debug, PartialEq)]
+/// A map of transformation names to the chain of transformations they stand for. References to
+/// other named transformations are expanded, so a chain only contains concrete transformations.
+#[derive(Deserialize, Debug, PartialEq, Default)]
 #[serde(try_from = "TransformReprs")]
-struct Transforms(pub HashMap<String, Tr>);
+struct Transforms(pub HashMap<String, Vec<TransformRepr>>);
+
+/// Composes a chain of concrete transformations into a single transformation.
+fn compose_transforms(trs: &[TransformRepr]) -> Result<Tr, ErrParseYaml> {
+    trs.iter()
+        .try_fold(Tr::new(), |acc, tr| Ok(acc.and(Tr::try_from(tr.clone())?)))
+}
 
 pub fn complete_transform(
     key: &str,
@@ -237,16 +460,11 @@ impl TryFrom<TransformReprs> for Transforms {
         for key in keys {
             complete_transform(&key, &mut v.0, &mut HashSet::new())?;
         }
-        let mut res = HashMap::new();
-        for (name, trs) in v.0 {
-            res.insert(
-                name,
-                trs.into_iter()
-                    .map(|tr| Tr::try_from(tr).unwrap())
-                    .fold(Tr::new(), |x, y| x.and(y)),
-            );
+        // Make sure that every chain is made of transformations we actually know about.
+        for trs in v.0.values() {
+            compose_transforms(trs)?;
         }
-        Ok(Transforms(res))
+        Ok(Transforms(v.0))
     }
 }
 
@@ -267,16 +485,26 @@ impl TryFrom<TransformRepr> for Tr {
                 "scale" => Ok(Tr::new().scale(x, y, z)),
                 _ => Err(ErrParseYaml::UnknownTransformation(name)),
             },
+            FourParam(name, v, cx, cy, cz) => match name.as_str() {
+                "rotate_x_about" => Ok(Tr::new().rotate_x_about(v, cx, cy, cz)),
+                "rotate_y_about" => Ok(Tr::new().rotate_y_about(v, cx, cy, cz)),
+                "rotate_z_about" => Ok(Tr::new().rotate_z_about(v, cx, cy, cz)),
+                _ => Err(ErrParseYaml::UnknownTransformation(name)),
+            },
             SixParam(name, x1, x2, x3, y1, y2, y3) => match name.as_str() {
                 "shear" => Ok(Tr::new().shear(x1, x2, x3, y1, y2, y3)),
+                "scale_about" => Ok(Tr::new().scale_about(x1, x2, x3, y1, y2, y3)),
                 _ => Err(ErrParseYaml::UnknownTransformation(name)),
             },
+            Matrix { matrix } => Tr::try_from(matrix).map_err(|e| {
+                ErrParseYaml::InvalidTransformation(format!("{e:?}; matrix={matrix:?}"))
+            }),
         }
     }
 }
 
 /// The kinds of objects which can be expressed in YAML.
-#[derive(Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
 #[serde(rename_all = "snake_case")]
 enum Shape {
     Sphere,
@@ -302,84 +530,362 @@ enum MaterialDefn {
 struct ObjectRepr {
     #[serde(rename = "type")]
     typ: Shape,
+    #[serde(default)]
+    name: Option<String>,
+    /// Labels for picking out variants of the scene; see [`World::filtered`].
+    #[serde(default)]
+    tags: Vec<String>,
     material: MaterialDefn,
     transform: Vec<TransformRepr>,
 }
 
-/// Generates a list of objects from their representations.
-fn generate_objects(
+/// An object whose material and transformation no longer reference any named definitions.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct ResolvedObject {
+    typ: Shape,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    /// This is always a complete material.
+    material: MaterialRepr,
+    /// This never contains references to named transformations.
+    transform: Vec<TransformRepr>,
+}
+
+/// Places many copies of a mesh at random within some region, e.g. trees in a forest. Every copy
+/// shares the same geometry, so the mesh is only held in memory once however many are placed.
+#[derive(Deserialize, Debug, PartialEq)]
+struct ScatterRepr {
+    /// Path to an OBJ or STL file. Relative paths are taken from the directory of the scene file,
+    /// or from the working directory for scenes which aren't read from a file.
+    source: String,
+    count: usize,
+    region: RegionRepr,
+    /// How much each copy's size may vary, as a fraction of the original.
+    #[serde(default)]
+    scale_jitter: f64,
+    #[serde(default)]
+    seed: u64,
+    #[serde(default = "default_material_defn")]
+    material: MaterialDefn,
+    /// Applied to the mesh before it is placed, e.g. to stand it upright.
+    #[serde(default)]
+    transform: Vec<TransformRepr>,
+    /// The most triangles left in one box of the mesh's bounding volume hierarchy, and the most
+    /// copies left in one box of the hierarchy around them; see
+    /// [`Group::divide`](crate::shapes::Group::divide). Zero turns both hierarchies off.
+    #[serde(default = "default_bvh_threshold")]
+    bvh_threshold: usize,
+    /// Given to every copy.
+    #[serde(default)]
+    tags: Vec<String>,
+    /// The unit the mesh was modelled in, if it differs from the scene's. The mesh is scaled to
+    /// the scene's units before anything else is done to it.
+    #[serde(default)]
+    units: Option<Unit>,
+}
+
+fn default_bvh_threshold() -> usize {
+    8
+}
+
+fn default_material_defn() -> MaterialDefn {
+    MaterialDefn::Defined {
+        color: None,
+        diffuse: None,
+        ambient: None,
+        specular: None,
+        reflective: None,
+    }
+}
+
+/// An axis aligned box, within which copies of a mesh are placed.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+struct RegionRepr {
+    min: (f64, f64, f64),
+    max: (f64, f64, f64),
+}
+
+/// A mesh read in for a scatter directive, along with how its faces are arranged into a bounding
+/// volume hierarchy.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct LoadedMesh {
+    /// The hash of the file the mesh was read from.
+    hash: u64,
+    mesh: Mesh,
+    bvh: BvhLayout,
+}
+
+/// A scatter directive whose material and transformation no longer reference any named
+/// definitions. The mesh itself is only read when the scene is built, unless it was loaded
+/// beforehand with [`ResolvedScene::load_meshes`].
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct ResolvedScatter {
+    source: String,
+    count: usize,
+    region: RegionRepr,
+    scale_jitter: f64,
+    seed: u64,
+    /// This is always a complete material.
+    material: MaterialRepr,
+    /// This never contains references to named transformations.
+    transform: Vec<TransformRepr>,
+    bvh_threshold: usize,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    units: Option<Unit>,
+    #[serde(default)]
+    loaded: Option<LoadedMesh>,
+}
+
+/// Substitutes named materials and transformations into a list of object representations.
+fn resolve_objects(
     xs: &[ObjectRepr],
     mats: &HashMap<String, Material>,
-    trs: &HashMap<String, Tr>,
-) -> Result<Vec<Object>, ErrParseYaml> {
-    let mut res: Vec<Object> = vec![];
+    trs: &HashMap<String, Vec<TransformRepr>>,
+) -> Result<Vec<ResolvedObject>, ErrParseYaml> {
+    let mut res = vec![];
     for x in xs {
-        // Get the material.
-        let mat = match &x.material {
-            MaterialDefn::Ref(name) => mats
-                .get(name)
-                .cloned()
-                .ok_or(ErrParseYaml::UnknownMaterial(name.to_string()))?,
-            MaterialDefn::Defined {
-                color,
-                diffuse,
-                ambient,
-                specular,
-                reflective,
-            } => {
-                let mat = Material::default();
-                Material::default()
-                    .with_color(color.map(|c| c.into()).unwrap_or(mat.color().into()))
-                    .with_diffuse(diffuse.unwrap_or(mat.diffuse()))
-                    .with_ambient(ambient.unwrap_or(mat.ambient()))
-                    .with_specular(specular.unwrap_or(mat.specular()))
-                    .with_reflective(reflective.unwrap_or(mat.reflective()))
-            }
-        };
+        res.push(ResolvedObject {
+            typ: x.typ,
+            name: x.name.clone(),
+            tags: x.tags.clone(),
+            material: resolve_material(&x.material, mats)?,
+            transform: resolve_transform(&x.transform, trs)?,
+        });
+    }
+    Ok(res)
+}
+
+/// Substitutes named materials and transformations into a list of scatter directives.
+fn resolve_scatters(
+    xs: &[ScatterRepr],
+    mats: &HashMap<String, Material>,
+    trs: &HashMap<String, Vec<TransformRepr>>,
+) -> Result<Vec<ResolvedScatter>, ErrParseYaml> {
+    let mut res = vec![];
+    for x in xs {
+        // Anything past this could shrink a copy to nothing, or turn it inside out.
+        if !(0.0..1.0).contains(&x.scale_jitter) {
+            return Err(ErrParseYaml::InvalidScatter(format!(
+                "scale_jitter must be in [0, 1); scale_jitter={}",
+                x.scale_jitter
+            )));
+        }
+        res.push(ResolvedScatter {
+            source: x.source.clone(),
+            count: x.count,
+            region: x.region,
+            scale_jitter: x.scale_jitter,
+            seed: x.seed,
+            material: resolve_material(&x.material, mats)?,
+            transform: resolve_transform(&x.transform, trs)?,
+            bvh_threshold: x.bvh_threshold,
+            tags: x.tags.clone(),
+            units: x.units,
+            loaded: None,
+        });
+    }
+    Ok(res)
+}
+
+/// Turns a material definition into a complete material, looking it up if it is a reference.
+fn resolve_material(
+    defn: &MaterialDefn,
+    mats: &HashMap<String, Material>,
+) -> Result<MaterialRepr, ErrParseYaml> {
+    let mat = match defn {
+        MaterialDefn::Ref(name) => mats
+            .get(name)
+            .cloned()
+            .ok_or(ErrParseYaml::UnknownMaterial(name.to_string()))?,
+        MaterialDefn::Defined {
+            color,
+            diffuse,
+            ambient,
+            specular,
+            reflective,
+        } => {
+            let mat = Material::default();
+            Material::default()
+                .with_color(color.map(|c| c.into()).unwrap_or(mat.color()))
+                .with_diffuse(diffuse.unwrap_or(mat.diffuse()))
+                .with_ambient(ambient.unwrap_or(mat.ambient()))
+                .with_specular(specular.unwrap_or(mat.specular()))
+                .with_reflective(reflective.unwrap_or(mat.reflective()))
+        }
+    };
+    Ok(MaterialRepr::Complete {
+        color: (mat.color().r(), mat.color().g(), mat.color().b()),
+        diffuse: mat.diffuse(),
+        ambient: mat.ambient(),
+        specular: mat.specular(),
+        reflective: mat.reflective(),
+    })
+}
 
-        // Get the transformation.
-        let mut transform = Tr::new();
-        for tr in &x.transform {
-            let x = match tr {
-                TransformRepr::Ref(name) => trs
-                    .get(name)
+/// Expands the named transformations in a chain, and checks that the whole chain is invertible.
+fn resolve_transform(
+    chain: &[TransformRepr],
+    trs: &HashMap<String, Vec<TransformRepr>>,
+) -> Result<Vec<TransformRepr>, ErrParseYaml> {
+    let mut transform = vec![];
+    for tr in chain {
+        match tr {
+            TransformRepr::Ref(name) => transform.extend(
+                trs.get(name)
                     .cloned()
                     .ok_or(ErrParseYaml::UnknownTransformation(name.to_string()))?,
-                _ => Tr::try_from(tr.clone())?,
-            };
-            transform = transform.and(x);
+            ),
+            _ => {
+                Tr::try_from(tr.clone())?;
+                transform.push(tr.clone());
+            }
         }
+    }
+
+    // Catch singular transformations now, rather than when the objects are built.
+    compose_transforms(&transform)?
+        .try_inverse()
+        .map_err(|e| ErrParseYaml::InvalidTransformation(format!("{e:?}; tr={transform:?}")))?;
+    Ok(transform)
+}
 
-        // Finally, get the shape.
+/// Builds the actual objects from their resolved representations.
+fn build_objects(xs: Vec<ResolvedObject>) -> Result<Vec<Object>, ErrParseYaml> {
+    let mut res: Vec<Object> = vec![];
+    for x in xs {
+        let mat = Material::try_from(x.material)?;
+        let transform = compose_transforms(&x.transform)?;
+        let invalid = |e| ErrParseYaml::InvalidTransformation(format!("{e:?}; tr={transform:?}"));
         let shape = match x.typ {
-            Shape::Plane => Plane::default()
+            Shape::Plane => x
+                .name
+                .into_iter()
+                .fold(Plane::default(), Plane::with_name)
                 .with_material(mat)
-                .with_transform(transform)
+                .try_with_transform(transform)
+                .map_err(invalid)?
                 .as_object(),
-            Shape::Sphere => Sphere::default()
+            Shape::Sphere => x
+                .name
+                .into_iter()
+                .fold(Sphere::default(), Sphere::with_name)
                 .with_material(mat)
-                .with_transform(transform)
+                .try_with_transform(transform)
+                .map_err(invalid)?
                 .as_object(),
         };
-
         res.push(shape);
     }
+    Ok(res)
+}
 
+/// Loads the mesh of every scatter directive, and places its copies in a group divided into a
+/// bounding volume hierarchy, so rays only test the copies near them. Each copy gets a random
+/// position in the region, a random turn about the y axis, and a jittered size; the same seed
+/// always gives the same placements. Meshes modelled in other units are first scaled to the
+/// scene's units. Meshes which weren't loaded beforehand are read here, taking relative paths
+/// from `dir`.
+fn build_scatters(
+    xs: Vec<ResolvedScatter>,
+    units: Unit,
+    dir: &Path,
+) -> Result<Vec<Object>, ErrParseYaml> {
+    let mut res: Vec<Object> = vec![];
+    for x in xs {
+        let mat = Material::try_from(x.material)?;
+        let transform = x
+            .units
+            .map_or(Tr::new(), |u| u.conversion_to(units))
+            .and(compose_transforms(&x.transform)?);
+        let loaded = x
+            .loaded
+            .as_ref()
+            .and_then(|m| m.mesh.to_group_with_layout(&mat, &m.bvh));
+        let group = match loaded {
+            Some(g) => g,
+            None => read_mesh(&dir.join(&x.source))
+                .map_err(|msg| ErrParseYaml::Mesh(format!("{msg}; source={}", x.source)))?
+                .1
+                .to_group(&mat)
+                .divide(x.bvh_threshold),
+        };
+        let shared = group
+            .try_with_transform(transform)
+            .map_err(|e| ErrParseYaml::InvalidTransformation(format!("{e:?}; tr={transform:?}")))?
+            .as_object();
+
+        let mut rng = Rng::new(x.seed);
+        let (min, max) = (x.region.min, x.region.max);
+        let mut copies = vec![];
+        for _ in 0..x.count {
+            let s = 1.0 + x.scale_jitter * rng.range(-1.0, 1.0);
+            let place = Tr::new()
+                .scale(s, s, s)
+                .rotate_y(rng.range(0.0, TAU))
+                .translate(
+                    rng.range(min.0, max.0),
+                    rng.range(min.1, max.1),
+                    rng.range(min.2, max.2),
+                );
+            copies.push(
+                Instance::new(shared.clone())
+                    .with_transform(place)
+                    .as_object(),
+            );
+        }
+        res.push(Group::new(copies).divide(x.bvh_threshold).as_object());
+    }
     Ok(res)
 }
 
+/// Reads a mesh file, choosing the format from its extension. Anything which isn't STL is read as
+/// OBJ. Also gives the hash of the file.
+fn read_mesh(path: &Path) -> Result<(u64, Mesh), String> {
+    let bytes = std::fs::read(path).map_err(|e| e.to_string())?;
+    let is_stl = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("stl"));
+    let mesh = if is_stl {
+        parse_stl(&bytes).map_err(|e| e.to_string())?
+    } else {
+        let s = std::str::from_utf8(&bytes).map_err(|e| e.to_string())?;
+        parse_obj(s).map_err(|e| e.to_string())?
+    };
+    Ok((hash_bytes(&bytes), mesh))
+}
+
+/// Generates a list of objects from their representations.
+#[cfg(test)]
+fn generate_objects(
+    xs: &[ObjectRepr],
+    mats: &HashMap<String, Material>,
+    trs: &HashMap<String, Vec<TransformRepr>>,
+) -> Result<Vec<Object>, ErrParseYaml> {
+    build_objects(resolve_objects(xs, mats, trs)?)
+}
+
 #[cfg(test)]
 mod tests {
     use crate::camera::Camera;
     use crate::color::Color;
     use crate::light::{Material, PointLight};
-    use crate::shapes::{Plane, Sphere};
+    use crate::shapes::{Group, Instance, Object, Plane, Sphere};
     use crate::transform::{view_transform, Tr};
+    use crate::units::Unit;
     use crate::yaml::Materials;
     use crate::{p, v};
     use std::collections::HashMap;
+    use std::f64::consts::FRAC_PI_2;
+    use std::sync::Arc;
 
-    use super::{generate_objects, MaterialDefn, ObjectRepr, Shape, TransformRepr, Transforms};
+    use super::{
+        compose_transforms, generate_objects, parse_scene, ErrParseYaml, MaterialDefn, ObjectRepr,
+        Shape, TransformRepr, Transforms, SCHEMA_VERSION,
+    };
 
     #[test]
     fn deserialize_camera() {
@@ -465,12 +971,11 @@ large:
     - [ scale, 3.5, 3.5, 3.5 ]"#;
         let got: Transforms =
             serde_yaml::from_str(yaml).expect("deserializes multiple transform definitions");
-        let mut want = HashMap::new();
         let standard = Tr::new().translate(1.0, -1.0, 1.0).scale(0.5, 0.5, 0.5);
         let large = standard.scale(3.5, 3.5, 3.5);
-        want.insert("standard".to_string(), standard);
-        want.insert("large".to_string(), large);
-        assert_eq!(got, Transforms(want));
+        assert_eq!(got.0.len(), 2);
+        assert_eq!(compose_transforms(&got.0["standard"]).unwrap(), standard);
+        assert_eq!(compose_transforms(&got.0["large"]).unwrap(), large);
     }
 
     #[test]
@@ -507,11 +1012,15 @@ large:
         let want = vec![
             ObjectRepr {
                 typ: Shape::Sphere,
+                name: None,
+                tags: vec![],
                 material: MaterialDefn::Ref("white".to_string()),
                 transform: vec![TransformRepr::Ref("large".to_string())],
             },
             ObjectRepr {
                 typ: Shape::Plane,
+                name: None,
+                tags: vec![],
                 material: MaterialDefn::Defined {
                     color: Some((1.0, 1.0, 1.0)),
                     ambient: Some(1.0),
@@ -520,7 +1029,7 @@ large:
                     reflective: None,
                 },
                 transform: vec![
-                    TransformRepr::OneParam("rotate_x".to_string(), 1.5707963267948966),
+                    TransformRepr::OneParam("rotate_x".to_string(), FRAC_PI_2),
                     TransformRepr::ThreeParam("translate".to_string(), 0.0, 0.0, 500.0),
                 ],
             },
@@ -561,7 +1070,14 @@ large:
             .translate(1.0, -1.0, 1.0)
             .scale(0.5, 0.5, 0.5)
             .scale(3.5, 3.5, 3.5);
-        trs.insert("large".to_string(), large);
+        trs.insert(
+            "large".to_string(),
+            vec![
+                TransformRepr::ThreeParam("translate".to_string(), 1.0, -1.0, 1.0),
+                TransformRepr::ThreeParam("scale".to_string(), 0.5, 0.5, 0.5),
+                TransformRepr::ThreeParam("scale".to_string(), 3.5, 3.5, 3.5),
+            ],
+        );
 
         let got = generate_objects(&xs, &mats, &trs).expect("should generate objects");
 
@@ -578,14 +1094,308 @@ large:
                         .with_diffuse(0.0)
                         .with_specular(0.0),
                 )
-                .with_transform(
-                    Tr::new()
-                        .rotate_x(1.5707963267948966)
-                        .translate(0.0, 0.0, 500.0),
-                )
+                .with_transform(Tr::new().rotate_x(FRAC_PI_2).translate(0.0, 0.0, 500.0))
                 .as_object(),
         ];
 
         assert_eq!(got, want);
     }
+
+    #[test]
+    fn parse_spec_scene() {
+        let scene = parse_scene(include_str!("spec.yml")).expect("parses the spec scene");
+        assert_eq!(scene.world.objects.len(), 2);
+        assert_eq!(
+            scene.world.light,
+            Some(PointLight::new(p!(50, 100, -50), Color::white()))
+        );
+    }
+
+    #[test]
+    fn migrates_unversioned_scene() {
+        let yaml = r#"
+camera:
+  width: 10
+  height: 10
+  field_of_view: 0.785
+  from: [ 0, 0, -5 ]
+  to: [ 0, 0, 0 ]
+  up: [ 0, 1, 0 ]
+light:
+  at: [ 0, 10, -10 ]
+  color: [ 1, 1, 1 ]
+transforms:
+  tilted:
+    - [ rotate-x, 1.5707963267948966 ]
+objects:
+  - type: plane
+    material: {}
+    transform:
+      - tilted
+      - [ rotate-z, 0.5 ]"#;
+        let scene = parse_scene(yaml).expect("migrates and parses the scene");
+        let want = Plane::default()
+            .with_transform(Tr::new().rotate_x(FRAC_PI_2).rotate_z(0.5))
+            .as_object();
+        assert_eq!(scene.world.objects, vec![want]);
+    }
+
+    #[test]
+    fn fails_on_newer_scene_version() {
+        let yaml = format!("version: {}\n", SCHEMA_VERSION + 1);
+        let got = parse_scene(&yaml);
+        assert!(matches!(got, Err(ErrParseYaml::UnsupportedVersion(v)) if v == SCHEMA_VERSION + 1));
+
+        let got = parse_scene("version: -1\n");
+        assert!(matches!(got, Err(ErrParseYaml::InvalidVersion(_))));
+    }
+
+    #[test]
+    fn deserialize_transforms_about_a_point() {
+        let yaml = r#"
+- [ rotate_y_about, 1.5707963267948966, 1, 0, 1 ]
+- [ scale_about, 2, 3, 4, 1, 1, 1 ]"#;
+        let got: Vec<TransformRepr> = serde_yaml::from_str(yaml).expect("deserializes transforms");
+        assert_eq!(
+            got[0],
+            TransformRepr::FourParam("rotate_y_about".to_string(), FRAC_PI_2, 1.0, 0.0, 1.0)
+        );
+        let got = compose_transforms(&got).expect("composes transforms");
+        let want = Tr::new()
+            .rotate_y_about(FRAC_PI_2, 1.0, 0.0, 1.0)
+            .scale_about(2.0, 3.0, 4.0, 1.0, 1.0, 1.0);
+        assert_eq!(got, want);
+    }
+
+    #[test]
+    fn deserialize_matrix_transform() {
+        let yaml = r#"
+- matrix:
+    - [ 1, 0, 0, 5 ]
+    - [ 0, 1, 0, -3 ]
+    - [ 0, 0, 1, 2 ]
+    - [ 0, 0, 0, 1 ]
+- [ scale, 2, 2, 2 ]"#;
+        let got: Vec<TransformRepr> = serde_yaml::from_str(yaml).expect("deserializes transforms");
+        let got = compose_transforms(&got).expect("composes transforms");
+        let want = Tr::new().translate(5.0, -3.0, 2.0).scale(2.0, 2.0, 2.0);
+        assert_eq!(got, want);
+
+        let yaml = r#"
+- matrix:
+    - [ 1, 0, 0, 0 ]
+    - [ 0, 1, 0, 0 ]
+    - [ 0, 0, 1, 0 ]
+    - [ 1, 1, 1, 1 ]"#;
+        let got: Vec<TransformRepr> = serde_yaml::from_str(yaml).expect("deserializes transforms");
+        assert!(matches!(
+            compose_transforms(&got),
+            Err(ErrParseYaml::InvalidTransformation(_))
+        ));
+    }
+
+    #[test]
+    fn fails_on_singular_object_transform() {
+        let yaml = r#"
+camera:
+  width: 10
+  height: 10
+  field_of_view: 0.785
+  from: [ 0, 0, -5 ]
+  to: [ 0, 0, 0 ]
+  up: [ 0, 1, 0 ]
+light:
+  at: [ 0, 10, -10 ]
+  color: [ 1, 1, 1 ]
+objects:
+  - type: sphere
+    material: {}
+    transform:
+      - [ scale, 1, 0, 1 ]"#;
+        let got = parse_scene(yaml);
+        assert!(matches!(got, Err(ErrParseYaml::InvalidTransformation(_))));
+    }
+
+    #[test]
+    fn fails_on_singular_camera_transform() {
+        let scene = |from: &str, up: &str| {
+            format!(
+                r#"
+camera:
+  width: 10
+  height: 10
+  field_of_view: 0.785
+  from: {from}
+  to: [ 0, 0, 0 ]
+  up: {up}
+light:
+  at: [ 0, 10, -10 ]
+  color: [ 1, 1, 1 ]"#
+            )
+        };
+        // Looking straight down along the up vector.
+        let got = parse_scene(&scene("[ 0, 5, 0 ]", "[ 0, 1, 0 ]"));
+        assert!(matches!(got, Err(ErrParseYaml::InvalidTransformation(_))));
+        // Looking from the point being looked at.
+        let got = parse_scene(&scene("[ 0, 0, 0 ]", "[ 0, 1, 0 ]"));
+        assert!(matches!(got, Err(ErrParseYaml::InvalidTransformation(_))));
+        assert!(parse_scene(&scene("[ 0, 5, 0 ]", "[ 0, 0, 1 ]")).is_ok());
+    }
+
+    #[test]
+    fn objects_can_be_named() {
+        let yaml = r#"
+camera:
+  width: 10
+  height: 10
+  field_of_view: 0.785
+  from: [ 0, 0, -5 ]
+  to: [ 0, 0, 0 ]
+  up: [ 0, 1, 0 ]
+light:
+  at: [ 0, 10, -10 ]
+  color: [ 1, 1, 1 ]
+objects:
+  - type: sphere
+    name: left pillar
+    material: {}
+    transform:
+      - [ translate, -3, 0, 0 ]
+  - type: plane
+    material: {}
+    transform: []"#;
+        let scene = parse_scene(yaml).expect("parses named objects");
+        let pillar = scene.world.object_named("left pillar").unwrap();
+        assert_eq!(pillar.transform(), Tr::new().translate(-3.0, 0.0, 0.0));
+        assert_eq!(scene.world.objects[1].name(), None);
+        assert!(scene.world.object_named("right pillar").is_none());
+    }
+
+    #[test]
+    fn objects_can_be_tagged() {
+        let yaml = r#"
+camera:
+  width: 10
+  height: 10
+  field_of_view: 0.785
+  from: [ 0, 0, -5 ]
+  to: [ 0, 0, 0 ]
+  up: [ 0, 1, 0 ]
+light:
+  at: [ 0, 10, -10 ]
+  color: [ 1, 1, 1 ]
+objects:
+  - type: sphere
+    name: vase
+    tags: [ props, fragile ]
+    material: {}
+    transform: []
+  - type: plane
+    name: floor
+    material: {}
+    transform: []"#;
+        let world = parse_scene(yaml).expect("parses tagged objects").world;
+        let vase = world.object_named("vase").unwrap();
+        assert_eq!(world.tags_of(vase).len(), 2);
+        let bare = world.filtered(&[], &["props"]);
+        assert_eq!(bare.objects.len(), 1);
+        assert!(bare.object_named("floor").is_some());
+    }
+
+    /// Every copy placed by a scatter, from the hierarchy they were put in.
+    fn copies(objects: &[Object]) -> Vec<Object> {
+        objects
+            .iter()
+            .flat_map(|o| match o.as_any().downcast_ref::<Group>() {
+                Some(g) => copies(g.children()),
+                None => vec![o.clone()],
+            })
+            .collect()
+    }
+
+    /// A scene which scatters copies of a single triangle, read from a temporary OBJ file.
+    fn scatter_scene(name: &str, extra: &str) -> String {
+        let path = std::env::temp_dir().join(format!("toytracer-{name}.obj"));
+        std::fs::write(&path, "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n").unwrap();
+        format!(
+            r#"
+camera:
+  width: 10
+  height: 10
+  field_of_view: 0.785
+  from: [0, 1.5, -5]
+  to: [0, 1, 0]
+  up: [0, 1, 0]
+light:
+  at: [-10, 10, -10]
+  color: [1, 1, 1]
+scatter:
+  - source: {}
+    count: 20
+    region: {{ min: [-5, 0, -5], max: [5, 0, 5] }}
+    seed: 7
+{extra}"#,
+            path.display()
+        )
+    }
+
+    #[test]
+    fn scattered_copies_share_one_mesh() {
+        let scene = parse_scene(&scatter_scene("shared", "    scale_jitter: 0.2")).unwrap();
+        // The copies are kept together, in a hierarchy of their own.
+        assert_eq!(scene.world.objects.len(), 1);
+        let objects = copies(&scene.world.objects);
+        assert_eq!(objects.len(), 20);
+        let top = scene.world.objects[0].as_any().downcast_ref::<Group>();
+        assert!(top.is_some_and(|g| g.children().len() < 20));
+        let mesh = |o: &crate::shapes::Object| {
+            o.as_any()
+                .downcast_ref::<Instance>()
+                .expect("copies are instances")
+                .object()
+                .clone()
+        };
+        for o in &objects[1..] {
+            assert!(Arc::ptr_eq(&mesh(&objects[0]), &mesh(o)));
+            assert_ne!(o.transform(), objects[0].transform());
+        }
+    }
+
+    #[test]
+    fn scatter_is_deterministic() {
+        let src = scatter_scene("deterministic", "");
+        let a = copies(&parse_scene(&src).unwrap().world.objects);
+        let b = copies(&parse_scene(&src).unwrap().world.objects);
+        let transforms = |xs: &[crate::shapes::Object]| -> Vec<Tr> {
+            xs.iter().map(|o| o.transform()).collect()
+        };
+        assert_eq!(transforms(&a), transforms(&b));
+    }
+
+    #[test]
+    fn scattered_meshes_are_converted_to_scene_units() {
+        let size = |extra: &str| {
+            let scene = parse_scene(&scatter_scene("units", extra)).unwrap();
+            let copy = copies(&scene.world.objects)[0].clone();
+            let mesh = copy.as_any().downcast_ref::<Instance>().unwrap().object();
+            (scene.world.units, mesh.bounds().max())
+        };
+        assert_eq!(size(""), (Unit::Meters, p!(1, 1, 0)));
+        assert_eq!(size("    units: cm"), (Unit::Meters, p!(0.01, 0.01, 0)));
+        assert_eq!(
+            size("    units: mm\nunits: cm"),
+            (Unit::Centimeters, p!(0.1, 0.1, 0))
+        );
+    }
+
+    #[test]
+    fn fails_on_bad_scatter() {
+        let src = scatter_scene("jitter", "    scale_jitter: 1.5");
+        assert!(matches!(
+            parse_scene(&src),
+            Err(ErrParseYaml::InvalidScatter(_))
+        ));
+        let src = scatter_scene("missing", "").replace("missing.obj", "nonexistent.obj");
+        assert!(matches!(parse_scene(&src), Err(ErrParseYaml::Mesh(_))));
+    }
 }